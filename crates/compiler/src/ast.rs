@@ -1,10 +1,12 @@
+use crate::diagnostics::{self, Warning, WarningKind};
+use crate::types;
 use core::fmt;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use reader::Sexpr;
+use reader::{Sexpr, Span};
 use unwrap_enum::{EnumAs, EnumIs};
 
-static BUILT_INS: &[&str] = &[
+pub static BUILT_INS: &[&str] = &[
     "+",
     "-",
     "*",
@@ -25,6 +27,7 @@ static BUILT_INS: &[&str] = &[
     "lambda",
     "defmacro",
     "def",
+    "defconst",
     "set!",
     "eval-when-compile",
     "quote",
@@ -34,10 +37,23 @@ static BUILT_INS: &[&str] = &[
     "<",
     "assert",
     "decl",
+    "declaim",
     "map-create",
     "map-insert!",
     "map-retrieve",
     "map-items",
+    "gensym",
+    "documentation",
+    "match",
+    "case",
+    "dolist",
+    "dotimes",
+    "->",
+    "->>",
+    "deftest",
+    "defbench",
+    "macroexpand",
+    "macroexpand-1",
     "module",
     "export",
     "require",
@@ -45,13 +61,32 @@ static BUILT_INS: &[&str] = &[
 
 #[derive(Clone, Debug, thiserror::Error)]
 pub struct Error {
-    sexpr: &'static Sexpr<'static>,
+    span: Span,
     message: String,
 }
 
 #[derive(Clone, Debug)]
 pub struct Compiler {
-    macros: HashSet<String>,
+    // Macros by the module that defined them, None for the top level. Top level
+    // macros are visible everywhere, the rest only in their own module and in
+    // those that imported them.
+    macros: HashMap<Option<String>, HashSet<String>>,
+    exported_macros: HashMap<String, HashSet<String>>,
+    // What each module, or the top level, required, as far as macros go.
+    macro_imports: HashMap<Option<String>, MacroImports>,
+    current_module: Option<String>,
+    patterns: usize,
+    warnings: Vec<Warning>,
+    errors: Vec<Error>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct MacroImports {
+    aliases: HashMap<String, String>,
+    // Modules required with :only, and the macros that can be used from them.
+    only: HashMap<String, HashSet<String>>,
+    // Macros usable without a module prefix, and the module they came from.
+    names: HashMap<String, String>,
 }
 
 #[derive(Clone, Debug, EnumAs, EnumIs)]
@@ -62,7 +97,9 @@ pub enum Ast {
     DefMacro(DefMacro),
     Lambda(Lambda),
     Def(Def),
+    DefConst(DefConst),
     Decl(Decl),
+    Declaim(Declaim),
     Set(Set),
     If(If),
     Apply(Apply),
@@ -72,6 +109,8 @@ pub enum Ast {
     Cons(Cons),
     Car(Car),
     Cdr(Cdr),
+    SetCar(SetCar),
+    SetCdr(SetCdr),
     FnCall(FnCall),
     MacroCall(MacroCall),
     Quote(Quote),
@@ -80,6 +119,12 @@ pub enum Ast {
     MapInsert(MapInsert),
     MapRetrieve(MapRetrieve),
     MapItems(MapItems),
+    Gensym(Gensym),
+    Documentation(Documentation),
+    Match(Match),
+    Case(Case),
+    Loop(Loop),
+    MacroExpand(MacroExpand),
     Variable(Variable),
     Constant(Constant),
     Assert(Assert),
@@ -88,31 +133,17 @@ pub enum Ast {
 
 #[derive(Clone, Debug)]
 pub struct EvalWhenCompile {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub exprs: Vec<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub enum Constant {
-    String {
-        source: &'static Sexpr<'static>,
-        string: String,
-    },
-    Char {
-        source: &'static Sexpr<'static>,
-        char: char,
-    },
-    Int {
-        source: &'static Sexpr<'static>,
-        int: i64,
-    },
-    Bool {
-        source: &'static Sexpr<'static>,
-        bool: bool,
-    },
-    Nil {
-        source: &'static Sexpr<'static>,
-    },
+    String { source: Span, string: String },
+    Char { source: Span, char: char },
+    Int { source: Span, int: i64 },
+    Bool { source: Span, bool: bool },
+    Nil { source: Span },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -124,11 +155,11 @@ pub enum Type {
 #[derive(Clone, Debug)]
 pub enum Variable {
     WithoutModule {
-        source: &'static Sexpr<'static>,
+        source: Span,
         name: String,
     },
     WithModule {
-        source: &'static Sexpr<'static>,
+        source: Span,
         name: String,
         module: String,
     },
@@ -144,54 +175,81 @@ pub struct Parameter {
 pub enum Parameters {
     Normal(Vec<Parameter>),
     Rest(Vec<Parameter>, Parameter),
+    Optional(Vec<Parameter>, Vec<(Parameter, Ast)>),
 }
 
 #[derive(Clone, Debug)]
 pub struct Require {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub module: String,
+    pub alias: Option<String>,
+    pub only: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug)]
 pub struct DefMacro {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub name: String,
     pub parameters: Parameters,
+    pub doc: Option<String>,
     pub body: Vec<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Lambda {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub r#type: Option<Type>,
     pub parameters: Parameters,
+    pub doc: Option<String>,
     pub body: Vec<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Def {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub parameter: Parameter,
+    pub doc: Option<String>,
+    pub body: Box<Ast>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DefConst {
+    pub source: Span,
+    pub name: String,
+    pub doc: Option<String>,
     pub body: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Decl {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub parameter: Parameter,
     pub body: Box<Ast>,
 }
 
+#[derive(Clone, Debug)]
+pub struct Declaim {
+    pub source: Span,
+    pub pragmas: Vec<(Pragma, bool)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pragma {
+    StrictArity,
+    WarningsAsErrors,
+    RequireDecl,
+}
+
 #[derive(Clone, Debug)]
 pub struct Set {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub variable: Variable,
     pub body: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct If {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub predicate: Box<Ast>,
     pub then: Box<Ast>,
     pub r#else: Box<Ast>,
@@ -199,7 +257,7 @@ pub struct If {
 
 #[derive(Clone, Debug)]
 pub struct Apply {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub function: Box<Ast>,
     pub list: Box<Ast>,
 }
@@ -214,7 +272,7 @@ pub enum BinaryArithmeticOperator {
 
 #[derive(Clone, Debug)]
 pub struct BinaryArithmeticOperation {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub operator: BinaryArithmeticOperator,
     pub lhs: Box<Ast>,
     pub rhs: Box<Ast>,
@@ -229,7 +287,7 @@ pub enum ComparisonOperator {
 
 #[derive(Clone, Debug)]
 pub struct ComparisonOperation {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub operator: ComparisonOperator,
     pub lhs: Box<Ast>,
     pub rhs: Box<Ast>,
@@ -237,46 +295,67 @@ pub struct ComparisonOperation {
 
 #[derive(Clone, Debug)]
 pub struct List {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub exprs: Vec<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Cons {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub lhs: Box<Ast>,
     pub rhs: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Car {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub body: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Cdr {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
+    pub body: Box<Ast>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SetCar {
+    pub source: Span,
+    pub cons: Box<Ast>,
+    pub body: Box<Ast>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SetCdr {
+    pub source: Span,
+    pub cons: Box<Ast>,
     pub body: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct FnCall {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub function: Box<Ast>,
     pub exprs: Vec<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct MacroCall {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub r#macro: String,
     pub args: Vec<Quoted>,
 }
 
+#[derive(Clone, Debug)]
+pub struct MacroExpand {
+    pub source: Span,
+    pub form: Sexpr,
+    pub once: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct Quote {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub body: Quoted,
 }
 
@@ -294,25 +373,82 @@ pub enum IsTypeParameter {
 
 #[derive(Clone, Debug)]
 pub struct IsType {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub parameter: IsTypeParameter,
     pub body: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Assert {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub body: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct MapCreate {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct Gensym {
+    pub source: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub source: Span,
+    pub scrutinee: Box<Ast>,
+    pub clauses: Vec<MatchClause>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchClause {
+    pub source: Span,
+    pub pattern: Pattern,
+    pub guard: Option<Ast>,
+    pub body: Ast,
+}
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Wildcard,
+    Literal(Box<Ast>),
+    Cons(Box<Pattern>, Box<Pattern>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub source: Span,
+    pub scrutinee: Box<Ast>,
+    pub clauses: Vec<CaseClause>,
+    pub default: Option<Box<Ast>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CaseClause {
+    pub source: Span,
+    pub keys: Vec<Quoted>,
+    pub body: Ast,
+}
+
+// Only produced by lowering other forms, evaluates body for as long as test is
+// true and then evaluates to nil.
+#[derive(Clone, Debug)]
+pub struct Loop {
+    pub source: Span,
+    pub test: Box<Ast>,
+    pub body: Vec<Ast>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Documentation {
+    pub source: Span,
+    pub function: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct MapInsert {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub map: Box<Ast>,
     pub key: Box<Ast>,
     pub value: Box<Ast>,
@@ -320,68 +456,239 @@ pub struct MapInsert {
 
 #[derive(Clone, Debug)]
 pub struct MapRetrieve {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub map: Box<Ast>,
     pub key: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub struct MapItems {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub map: Box<Ast>,
 }
 
 #[derive(Clone, Debug)]
 pub enum Quoted {
     List {
-        source: &'static Sexpr<'static>,
+        source: Span,
+        list: Vec<Quoted>,
+    },
+    DottedList {
+        source: Span,
         list: Vec<Quoted>,
+        tail: Box<Quoted>,
     },
     Symbol {
-        source: &'static Sexpr<'static>,
+        source: Span,
         symbol: String,
     },
     String {
-        source: &'static Sexpr<'static>,
+        source: Span,
         string: String,
     },
     Char {
-        source: &'static Sexpr<'static>,
+        source: Span,
         char: char,
     },
     Int {
-        source: &'static Sexpr<'static>,
+        source: Span,
         int: i64,
     },
     Bool {
-        source: &'static Sexpr<'static>,
+        source: Span,
         bool: bool,
     },
     Nil {
-        source: &'static Sexpr<'static>,
+        source: Span,
+    },
+    // Built when it's evaluated, there's no constant for a map.
+    Map {
+        source: Span,
+        entries: Vec<(Quoted, Quoted)>,
     },
 }
 
 #[derive(Clone, Debug)]
 pub struct Export {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub symbol: String,
+    pub r#macro: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct Module {
-    pub source: &'static Sexpr<'static>,
+    pub source: Span,
     pub name: String,
 }
 
 impl Compiler {
     pub fn new() -> Self {
         Self {
-            macros: HashSet::new(),
+            macros: HashMap::new(),
+            exported_macros: HashMap::new(),
+            macro_imports: HashMap::new(),
+            current_module: None,
+            patterns: 0,
+            warnings: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn is_macro(&self, name: &str) -> bool {
+        self.resolve_macro(name).is_some()
+    }
+
+    // The global a macro's function is defined as, if name is a macro visible
+    // from the current module. A macro another module exports can be named
+    // module::name, or alias::name, like any of its other exports.
+    pub fn resolve_macro(&self, name: &str) -> Option<String> {
+        let imports = self.macro_imports.get(&self.current_module);
+
+        if let Some((module, name)) = name.split_once("::") {
+            let module = imports
+                .and_then(|imports| imports.aliases.get(module))
+                .map_or(module, String::as_str);
+
+            let visible = if self.current_module.as_deref() == Some(module) {
+                self.macros
+                    .get(&self.current_module)
+                    .is_some_and(|macros| macros.contains(name))
+            } else {
+                self.exports_macro(module, name)
+                    && imports
+                        .and_then(|imports| imports.only.get(module))
+                        .is_none_or(|only| only.contains(name))
+            };
+
+            visible.then(|| macro_global(Some(module), name))
+        } else if self
+            .macros
+            .get(&self.current_module)
+            .is_some_and(|macros| macros.contains(name))
+        {
+            Some(macro_global(self.current_module.as_deref(), name))
+        } else if let Some(module) = imports.and_then(|imports| imports.names.get(name)) {
+            Some(macro_global(Some(module), name))
+        } else if self
+            .macros
+            .get(&None)
+            .is_some_and(|macros| macros.contains(name))
+        {
+            Some(name.to_string())
+        } else {
+            None
+        }
+    }
+
+    pub fn set_current_module(&mut self, module: Option<&str>) {
+        self.current_module = module.map(str::to_string);
+    }
+
+    // Brings the macros a required module exports into the current module.
+    // They're usable without a prefix unless the module was required :as an
+    // alias, when only those listed with :only are. With :only, the ones
+    // left out aren't usable at all.
+    pub fn import(&mut self, require: &Require) {
+        let imports = self
+            .macro_imports
+            .entry(self.current_module.clone())
+            .or_default();
+
+        if let Some(alias) = &require.alias {
+            imports
+                .aliases
+                .insert(alias.clone(), require.module.clone());
+        }
+
+        if let Some(only) = &require.only {
+            imports
+                .only
+                .insert(require.module.clone(), only.iter().cloned().collect());
+        }
+
+        if require.alias.is_some() && require.only.is_none() {
+            return;
+        }
+
+        let Some(exported) = self.exported_macros.get(&require.module) else {
+            return;
+        };
+
+        for name in exported {
+            if require.only.as_ref().is_none_or(|only| only.contains(name)) {
+                imports.names.insert(name.clone(), require.module.clone());
+            }
+        }
+    }
+
+    pub(crate) fn module_macros(&self, module: &str) -> Vec<String> {
+        self.macros
+            .get(&Some(module.to_string()))
+            .map(|macros| macros.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn exported_module_macros(&self, module: &str) -> Vec<String> {
+        self.exported_macros
+            .get(module)
+            .map(|macros| macros.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn load_module_macros(
+        &mut self,
+        module: &str,
+        macros: &[String],
+        exported: &[String],
+    ) {
+        self.macros
+            .entry(Some(module.to_string()))
+            .or_default()
+            .extend(macros.iter().cloned());
+
+        self.exported_macros
+            .entry(module.to_string())
+            .or_default()
+            .extend(exported.iter().cloned());
+    }
+
+    pub fn exports_macro(&self, module: &str, name: &str) -> bool {
+        self.exported_macros
+            .get(module)
+            .is_some_and(|exported| exported.contains(name))
+    }
+
+    // Compiles a top level form. An error in one expression doesn't stop the
+    // rest of the form from being compiled, so every error in it is returned.
+    pub fn compile(&mut self, sexpr: &Sexpr) -> Result<Ast, Vec<Error>> {
+        let ast = self.compile_expr(sexpr);
+
+        if self.errors.is_empty() {
+            Ok(ast)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    // Errors are recorded and nil stands in for the expression, so compiling
+    // can carry on past it.
+    fn compile_expr(&mut self, sexpr: &Sexpr) -> Ast {
+        match self.compile_form(sexpr) {
+            Ok(ast) => ast,
+            Err(error) => {
+                self.errors.push(error);
+                Ast::Constant(Constant::Nil {
+                    source: sexpr.source_span(),
+                })
+            }
         }
     }
 
-    pub fn compile(&mut self, sexpr: &'static Sexpr<'static>) -> Result<Ast, Error> {
+    fn compile_form(&mut self, sexpr: &Sexpr) -> Result<Ast, Error> {
         use Sexpr::*;
         Ok(match sexpr {
             Sexpr::List { list, .. }
@@ -394,10 +701,10 @@ impl Compiler {
                     [Symbol { symbol, .. }, Symbol { symbol: name, .. }] if symbol == "module" => {
                         self.compile_module(sexpr, name)?
                     }
-                    [Symbol { symbol, .. }, Symbol { symbol: module, .. }]
+                    [Symbol { symbol, .. }, Symbol { symbol: module, .. }, options @ ..]
                         if symbol == "require" =>
                     {
-                        self.compile_require(sexpr, module)?
+                        self.compile_require(sexpr, module, options)?
                     }
                     [Symbol { symbol, .. }, rest @ ..] if symbol == "eval-when-compile" => {
                         self.compile_eval_when_compile(sexpr, rest)?
@@ -415,12 +722,30 @@ impl Compiler {
                     [Symbol { symbol, .. }, parameters, rest @ ..] if symbol == "lambda" => {
                         self.compile_lambda(sexpr, parameters, None, rest)?
                     }
+                    [Symbol { symbol, .. }, parameter, String { string: doc, .. }, body]
+                        if symbol == "def" =>
+                    {
+                        self.compile_def(sexpr, parameter, Some(doc), body)?
+                    }
                     [Symbol { symbol, .. }, parameter, body] if symbol == "def" => {
-                        self.compile_def(sexpr, parameter, body)?
+                        self.compile_def(sexpr, parameter, None, body)?
+                    }
+                    [Symbol { symbol, .. }, Symbol { symbol: name, .. }, String { string: doc, .. }, body]
+                        if symbol == "defconst" =>
+                    {
+                        self.compile_defconst(sexpr, name, Some(doc), body)
+                    }
+                    [Symbol { symbol, .. }, Symbol { symbol: name, .. }, body]
+                        if symbol == "defconst" =>
+                    {
+                        self.compile_defconst(sexpr, name, None, body)
                     }
                     [Symbol { symbol, .. }, parameter, body] if symbol == "decl" => {
                         self.compile_decl(sexpr, parameter, body)?
                     }
+                    [Symbol { symbol, .. }, pragmas @ ..] if symbol == "declaim" => {
+                        self.compile_declaim(sexpr, pragmas)?
+                    }
                     [Symbol { symbol, .. }, parameter, body] if symbol == "set!" => {
                         self.compile_set(sexpr, parameter, body)?
                     }
@@ -474,7 +799,49 @@ impl Compiler {
                         self.compile_assert(sexpr, body)?
                     }
                     [Symbol { symbol, .. }] if symbol == "map-create" => {
-                        Ast::MapCreate(MapCreate { source: sexpr })
+                        Ast::MapCreate(MapCreate {
+                            source: sexpr.source_span(),
+                        })
+                    }
+                    [Symbol { symbol, .. }, entries @ ..] if symbol == "map-create" => {
+                        self.compile_map_literal(sexpr, entries)?
+                    }
+                    [Symbol { symbol, .. }, form]
+                        if matches!(symbol.as_str(), "macroexpand" | "macroexpand-1") =>
+                    {
+                        self.compile_macroexpand(sexpr, symbol, form)?
+                    }
+                    [Symbol { symbol, .. }] if symbol == "gensym" => Ast::Gensym(Gensym {
+                        source: sexpr.source_span(),
+                    }),
+                    [Symbol { symbol, .. }, expr, clauses @ ..] if symbol == "match" => {
+                        self.compile_match(sexpr, expr, clauses)?
+                    }
+                    [Symbol { symbol, .. }, expr, clauses @ ..] if symbol == "case" => {
+                        self.compile_case(sexpr, expr, clauses)?
+                    }
+                    [Symbol { symbol, .. }, spec, body @ ..]
+                        if matches!(symbol.as_str(), "dolist" | "dotimes") =>
+                    {
+                        self.compile_iteration(sexpr, symbol, spec, body)?
+                    }
+                    [Symbol { symbol, .. }, init, steps @ ..]
+                        if matches!(symbol.as_str(), "->" | "->>") =>
+                    {
+                        self.compile_threading(symbol, init, steps)?
+                    }
+                    [Symbol { symbol, .. }, name @ Symbol { .. }, body @ ..]
+                        if symbol == "deftest" =>
+                    {
+                        self.compile_registered(sexpr, "register-test", name, body)?
+                    }
+                    [Symbol { symbol, .. }, name @ Symbol { .. }, body @ ..]
+                        if symbol == "defbench" =>
+                    {
+                        self.compile_registered(sexpr, "register-bench", name, body)?
+                    }
+                    [Symbol { symbol, .. }, function] if symbol == "documentation" => {
+                        self.compile_documentation(sexpr, function)?
                     }
                     [Symbol { symbol, .. }, map, key, value] if symbol == "map-insert!" => {
                         self.compile_map_insert(sexpr, map, key, value)?
@@ -490,7 +857,7 @@ impl Compiler {
                     }
                     _ => {
                         return Err(Error {
-                            sexpr,
+                            span: sexpr.source_span(),
                             message: "invalid expression".to_string(),
                         })
                     }
@@ -500,254 +867,1013 @@ impl Compiler {
                 if list
                     .first()
                     .and_then(|first| first.as_symbol())
-                    .is_some_and(|symbol| self.macros.contains(symbol)) =>
+                    .is_some_and(|symbol| self.is_macro(symbol)) =>
             {
                 self.compile_macro_call(
                     sexpr,
-                    list.first().unwrap().as_symbol().unwrap(),
+                    self.resolve_macro(list.first().unwrap().as_symbol().unwrap())
+                        .unwrap()
+                        .as_str(),
                     &list.as_slice()[1..],
                 )?
             }
             List { list, .. } if !list.is_empty() => {
                 self.compile_fncall(sexpr, list.first().unwrap(), &list.as_slice()[1..])?
             }
+            // keywords like :mode evaluate to themselves
+            Symbol { symbol, .. } if symbol.len() > 1 && symbol.starts_with(':') => {
+                let source = sexpr.source_span();
+
+                Ast::Quote(Quote {
+                    body: quote(&source, sexpr),
+                    source,
+                })
+            }
             Symbol { symbol, .. } => {
                 Ast::Variable(parse_variable(sexpr, symbol.as_str()).map_err(|_| Error {
-                    sexpr,
+                    span: sexpr.source_span(),
                     message: "failed to parse variable".to_string(),
                 })?)
             }
             String { string, .. } => Ast::Constant(Constant::String {
-                source: sexpr,
+                source: sexpr.source_span(),
                 string: string.clone(),
             }),
             Char { char, .. } => Ast::Constant(Constant::Char {
-                source: sexpr,
+                source: sexpr.source_span(),
                 char: *char,
             }),
             Int { int, .. } => Ast::Constant(Constant::Int {
-                source: sexpr,
+                source: sexpr.source_span(),
                 int: *int,
             }),
             Bool { bool, .. } => Ast::Constant(Constant::Bool {
-                source: sexpr,
+                source: sexpr.source_span(),
                 bool: *bool,
             }),
-            Nil { .. } => Ast::Constant(Constant::Nil { source: sexpr }),
+            Nil { .. } => Ast::Constant(Constant::Nil {
+                source: sexpr.source_span(),
+            }),
+            DottedList { .. } => {
+                return Err(Error {
+                    span: sexpr.source_span(),
+                    message: "dotted list can't be evaluated, quote it".to_string(),
+                })
+            }
+            Sexpr::Vector { list, .. } => self.compile_list(sexpr, list)?,
+            Sexpr::Map { list, .. } if list.is_empty() => Ast::MapCreate(MapCreate {
+                source: sexpr.source_span(),
+            }),
+            Sexpr::Map { list, .. } => self.compile_map_literal(sexpr, list)?,
             _ => unreachable!(),
         })
     }
 
-    fn compile_module(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        name: &str,
-    ) -> Result<Ast, Error> {
+    fn compile_module(&mut self, source: &Sexpr, name: &str) -> Result<Ast, Error> {
+        self.current_module = Some(name.to_string());
+
         Ok(Ast::Module(Module {
-            source,
+            source: source.source_span(),
             name: name.to_string(),
         }))
     }
 
     fn compile_require(
         &mut self,
-        source: &'static Sexpr<'static>,
+        source: &Sexpr,
         module: &str,
+        options: &[Sexpr],
     ) -> Result<Ast, Error> {
+        use Sexpr::{List, Symbol};
+
+        let mut alias = None;
+        let mut only = None;
+
+        for option in options.chunks(2) {
+            match option {
+                [Symbol { symbol, .. }, Symbol { symbol: name, .. }] if symbol == ":as" => {
+                    alias = Some(name.to_string());
+                }
+                [Symbol { symbol, .. }, List { list: names, .. }] if symbol == ":only" => {
+                    only = Some(
+                        names
+                            .iter()
+                            .map(|name| match name {
+                                Symbol { symbol, .. } => Ok(symbol.to_string()),
+                                _ => Err(Error {
+                                    span: name.source_span(),
+                                    message: "expected symbol".to_string(),
+                                }),
+                            })
+                            .collect::<Result<_, _>>()?,
+                    );
+                }
+                _ => {
+                    return Err(Error {
+                        span: option[0].source_span(),
+                        message: "expected :as <alias> or :only (<name>...)".to_string(),
+                    })
+                }
+            }
+        }
+
         Ok(Ast::Require(Require {
-            source,
+            source: source.source_span(),
             module: module.to_string(),
+            alias,
+            only,
         }))
     }
 
-    fn compile_eval_when_compile(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        args: &'static [Sexpr<'static>],
-    ) -> Result<Ast, Error> {
+    fn compile_eval_when_compile(&mut self, source: &Sexpr, args: &[Sexpr]) -> Result<Ast, Error> {
         Ok(Ast::EvalWhenCompile(EvalWhenCompile {
-            source,
+            source: source.source_span(),
             exprs: args
                 .iter()
-                .map(|arg| self.compile(arg))
-                .collect::<Result<Vec<_>, _>>()?,
+                .map(|arg| self.compile_expr(arg))
+                .collect::<Vec<_>>(),
         }))
     }
 
     fn compile_defmacro(
         &mut self,
-        source: &'static Sexpr<'static>,
+        source: &Sexpr,
         name: &str,
-        parameters: &'static Sexpr<'static>,
-        rest: &'static [Sexpr<'static>],
+        parameters: &Sexpr,
+        rest: &[Sexpr],
     ) -> Result<Ast, Error> {
-        self.macros.insert(name.to_string());
+        self.macros
+            .entry(self.current_module.clone())
+            .or_default()
+            .insert(name.to_string());
+
+        let mut patterns = Vec::new();
+        let (doc, rest) = split_docstring(rest);
 
         Ok(Ast::DefMacro(DefMacro {
-            source,
+            source: source.source_span(),
             name: name.to_string(),
             parameters: match parameters {
                 Sexpr::List { list, .. } => {
-                    parse_parameters(source, list.as_slice()).map_err(|_| Error {
-                        sexpr: source,
-                        message: "failed to parse parameters".to_string(),
-                    })?
+                    self.parse_parameters(source, list.as_slice(), &mut patterns)?
                 }
                 Sexpr::Nil { .. } => Parameters::Normal(Vec::new()),
                 _ => {
                     return Err(Error {
-                        sexpr: source,
+                        span: source.source_span(),
                         message: "expected list for parameters".to_string(),
                     })
                 }
             },
-            body: rest
-                .iter()
-                .map(|arg| self.compile(arg))
-                .collect::<Result<Vec<_>, _>>()?,
+            doc,
+            body: self.compile_body(source, rest, patterns.as_slice())?,
         }))
     }
 
     fn compile_lambda(
         &mut self,
-        source: &'static Sexpr<'static>,
-        parameters: &'static Sexpr<'static>,
-        r#type: Option<&'static Sexpr<'static>>,
-        rest: &'static [Sexpr<'static>],
+        source: &Sexpr,
+        parameters: &Sexpr,
+        r#type: Option<&Sexpr>,
+        rest: &[Sexpr],
     ) -> Result<Ast, Error> {
+        let mut patterns = Vec::new();
+        let (doc, rest) = split_docstring(rest);
+
+        let parameters = match parameters {
+            Sexpr::List { list, .. } => {
+                self.parse_parameters(source, list.as_slice(), &mut patterns)?
+            }
+            Sexpr::Nil { .. } => Parameters::Normal(Vec::new()),
+            _ => {
+                return Err(Error {
+                    span: source.source_span(),
+                    message: "expectes list for parameters".to_string(),
+                })
+            }
+        };
+
+        let mut seen = HashSet::new();
+
+        for name in parameters.names() {
+            if !seen.insert(name) && !diagnostics::is_exempt(name) {
+                self.warnings.push(Warning {
+                    kind: WarningKind::Shadowing,
+                    span: source.source_span(),
+                    message: format!("{name} appears more than once in the parameter list"),
+                });
+            }
+        }
+
         Ok(Ast::Lambda(Lambda {
-            source,
+            source: source.source_span(),
             r#type: match r#type.map(Type::from_sexpr) {
                 Some(Ok(t)) => Some(t),
                 Some(Err(_)) => {
                     return Err(Error {
-                        sexpr: source,
+                        span: source.source_span(),
                         message: "failed to parse type".to_string(),
                     })
                 }
                 None => None,
             },
-            parameters: match parameters {
-                Sexpr::List { list, .. } => {
-                    parse_parameters(source, list.as_slice()).map_err(|_| Error {
-                        sexpr: source,
-                        message: "failed to parse parameters".to_string(),
-                    })?
+            parameters,
+            doc,
+            body: self.compile_body(source, rest, patterns.as_slice())?,
+        }))
+    }
+
+    fn compile_body(
+        &mut self,
+        source: &Sexpr,
+        body: &[Sexpr],
+        patterns: &[(String, &Sexpr)],
+    ) -> Result<Vec<Ast>, Error> {
+        let body = body
+            .iter()
+            .map(|arg| self.compile_expr(arg))
+            .collect::<Vec<_>>();
+
+        if patterns.is_empty() {
+            Ok(body)
+        } else {
+            Ok(vec![destructure_patterns(source, patterns, body)?])
+        }
+    }
+
+    // Destructuring patterns are replaced by generated names and pushed onto
+    // patterns so the caller can bind them at the start of the body.
+    fn parse_parameters<'a>(
+        &mut self,
+        source: &Sexpr,
+        list: &'a [Sexpr],
+        patterns: &mut Vec<(String, &'a Sexpr)>,
+    ) -> Result<Parameters, Error> {
+        let error = |message: &str| Error {
+            span: source.source_span(),
+            message: message.to_string(),
+        };
+
+        let mut required = Vec::new();
+        let mut optional = Vec::new();
+        let mut rest = None;
+        let mut is_optional = false;
+        let mut iter = list.iter();
+
+        while let Some(parameter) = iter.next() {
+            match parameter {
+                Sexpr::Symbol { symbol, .. } if symbol == "&optional" => {
+                    if is_optional {
+                        return Err(error("&optional may only appear once"));
+                    }
+                    is_optional = true;
                 }
-                Sexpr::Nil { .. } => Parameters::Normal(Vec::new()),
-                _ => {
-                    return Err(Error {
-                        sexpr: source,
-                        message: "expectes list for parameters".to_string(),
-                    })
+                Sexpr::Symbol { symbol, .. } if symbol == "&rest" => {
+                    if is_optional {
+                        return Err(error("&optional can not be combined with &rest"));
+                    }
+                    rest = match (iter.next(), iter.next()) {
+                        (Some(parameter), None) => Some(
+                            Parameter::from_sexpr(parameter)
+                                .map_err(|_| error("failed to parse parameter"))?,
+                        ),
+                        _ => return Err(error("failed to parse parameters")),
+                    };
                 }
-            },
-            body: rest
-                .iter()
-                .map(|arg| self.compile(arg))
-                .collect::<Result<Vec<_>, _>>()?,
-        }))
+                _ if is_optional => {
+                    let (parameter, default) = match parameter {
+                        Sexpr::List { list, .. } if list.len() == 2 => {
+                            (&list[0], self.compile_expr(&list[1]))
+                        }
+                        _ => (
+                            parameter,
+                            Ast::Constant(Constant::Nil {
+                                source: parameter.source_span(),
+                            }),
+                        ),
+                    };
+
+                    optional.push((
+                        Parameter::from_sexpr(parameter)
+                            .map_err(|_| error("failed to parse parameter"))?,
+                        default,
+                    ));
+                }
+                Sexpr::List { list, .. } if !is_typed_parameter(list) => {
+                    let name = format!("#:pattern{}", self.patterns);
+                    self.patterns += 1;
+                    patterns.push((name.clone(), parameter));
+                    required.push(Parameter { name, r#type: None });
+                }
+                _ => required.push(
+                    Parameter::from_sexpr(parameter)
+                        .map_err(|_| error("failed to parse parameter"))?,
+                ),
+            }
+        }
+
+        Ok(match rest {
+            Some(rest) => Parameters::Rest(required, rest),
+            None if is_optional => Parameters::Optional(required, optional),
+            None => Parameters::Normal(required),
+        })
     }
 
     fn compile_def(
         &mut self,
-        source: &'static Sexpr<'static>,
-        parameter: &'static Sexpr<'static>,
-        body: &'static Sexpr<'static>,
+        source: &Sexpr,
+        parameter: &Sexpr,
+        doc: Option<&str>,
+        body: &Sexpr,
     ) -> Result<Ast, Error> {
+        let mut body = self.compile_expr(body);
+
+        // the docstring of a def naming a function is also stored on the function
+        if let (Ast::Lambda(lambda), Some(doc)) = (&mut body, doc) {
+            lambda.doc.get_or_insert_with(|| doc.to_string());
+        }
+
         Ok(Ast::Def(Def {
-            source,
+            source: source.source_span(),
             parameter: Parameter::from_sexpr(parameter).map_err(|_| Error {
-                sexpr: source,
+                span: source.source_span(),
                 message: "failed to parse parameter".to_string(),
             })?,
-            body: Box::new(self.compile(body)?),
+            doc: doc.map(|doc| doc.to_string()),
+            body: Box::new(body),
         }))
     }
 
-    fn compile_decl(
+    // The scrutinee is bound to a parameter of an immediately applied lambda so
+    // that it's only evaluated once, each clause then binds its pattern's names
+    // with another lambda around its guard and body.
+    fn compile_match(
         &mut self,
-        source: &'static Sexpr<'static>,
-        parameter: &'static Sexpr<'static>,
-        body: &'static Sexpr<'static>,
+        source: &Sexpr,
+        expr: &Sexpr,
+        clauses: &[Sexpr],
     ) -> Result<Ast, Error> {
-        Ok(Ast::Decl(Decl {
-            source,
-            parameter: Parameter::from_sexpr(parameter).map_err(|_| Error {
-                sexpr: source,
-                message: "failed to parse parameter".to_string(),
-            })?,
-            body: Box::new(self.compile(body)?),
+        let name = format!("#:match{}", self.patterns);
+        self.patterns += 1;
+
+        let scrutinee = Ast::Variable(Variable::WithoutModule {
+            source: expr.source_span(),
+            name: name.clone(),
+        });
+
+        let clauses = clauses
+            .iter()
+            .map(|clause| self.compile_match_clause(clause, &scrutinee))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Ast::FnCall(FnCall {
+            source: source.source_span(),
+            function: Box::new(Ast::Lambda(Lambda {
+                source: source.source_span(),
+                r#type: None,
+                parameters: Parameters::Normal(vec![Parameter { name, r#type: None }]),
+                doc: None,
+                body: vec![Ast::Match(Match {
+                    source: source.source_span(),
+                    scrutinee: Box::new(scrutinee),
+                    clauses,
+                })],
+            })),
+            exprs: vec![self.compile_expr(expr)],
         }))
     }
 
-    fn compile_set(
+    fn compile_match_clause(
         &mut self,
-        source: &'static Sexpr<'static>,
-        parameter: &'static Sexpr<'static>,
-        body: &'static Sexpr<'static>,
-    ) -> Result<Ast, Error> {
-        Ok(Ast::Set(Set {
-            source,
-            variable: match parameter
-                .as_symbol()
-                .map(|symbol| parse_variable(source, symbol))
+        clause: &Sexpr,
+        scrutinee: &Ast,
+    ) -> Result<MatchClause, Error> {
+        let (pattern, guard, body) = match clause.as_list() {
+            Some([pattern, Sexpr::Symbol { symbol, .. }, guard, body @ ..])
+                if symbol == ":when" && !body.is_empty() =>
             {
-                Some(Ok(variable)) => variable,
-                Some(Err(())) => {
-                    return Err(Error {
-                        sexpr: source,
-                        message: "failed to parse variable".to_string(),
-                    })
+                (pattern, Some(guard), body)
+            }
+            Some([pattern, body @ ..]) if !body.is_empty() => (pattern, None, body),
+            _ => {
+                return Err(Error {
+                    span: clause.source_span(),
+                    message: "expected (pattern body...) in match".to_string(),
+                })
+            }
+        };
+
+        let mut bindings = Vec::new();
+        let pattern = self.compile_pattern(pattern, scrutinee.clone(), &mut bindings)?;
+
+        let guard = match guard {
+            Some(guard) => Some(bind(
+                clause,
+                bindings.clone(),
+                vec![self.compile_expr(guard)],
+            )?),
+            None => None,
+        };
+
+        let body = body
+            .iter()
+            .map(|expr| self.compile_expr(expr))
+            .collect::<Vec<_>>();
+
+        Ok(MatchClause {
+            source: clause.source_span(),
+            pattern,
+            guard,
+            body: bind(clause, bindings, body)?,
+        })
+    }
+
+    fn compile_pattern(
+        &mut self,
+        pattern: &Sexpr,
+        value: Ast,
+        bindings: &mut Vec<(String, Ast)>,
+    ) -> Result<Pattern, Error> {
+        match pattern {
+            Sexpr::Symbol { symbol, .. } if symbol == "_" => Ok(Pattern::Wildcard),
+            Sexpr::Symbol { symbol, .. } => {
+                bindings.push((symbol.clone(), value));
+                Ok(Pattern::Wildcard)
+            }
+            Sexpr::List { list, .. } => match list.as_slice() {
+                [Sexpr::Symbol { symbol, .. }, _] if symbol == "quote" => {
+                    Ok(Pattern::Literal(Box::new(self.compile_expr(pattern))))
                 }
-                None => {
-                    return Err(Error {
-                        sexpr: source,
-                        message: "expected symbol".to_string(),
-                    })
+                [Sexpr::Symbol { symbol, .. }, car, cdr] if symbol == "cons" => {
+                    let (car_value, cdr_value) = car_and_cdr(pattern, value);
+
+                    Ok(Pattern::Cons(
+                        Box::new(self.compile_pattern(car, car_value, bindings)?),
+                        Box::new(self.compile_pattern(cdr, cdr_value, bindings)?),
+                    ))
                 }
+                [Sexpr::Symbol { symbol, .. }, elements @ ..] if symbol == "list" => {
+                    self.compile_list_pattern(pattern, elements, value, bindings)
+                }
+                _ => Err(Error {
+                    span: pattern.source_span(),
+                    message: "malformed pattern: expected quote, cons or list".to_string(),
+                }),
             },
-            body: Box::new(self.compile(body)?),
-        }))
+            Sexpr::Vector { list, .. } => {
+                self.compile_list_pattern(pattern, list, value, bindings)
+            }
+            _ => Ok(Pattern::Literal(Box::new(self.compile_expr(pattern)))),
+        }
     }
 
-    fn compile_if(
+    fn compile_list_pattern(
         &mut self,
-        source: &'static Sexpr<'static>,
-        predicate: &'static Sexpr<'static>,
-        then: &'static Sexpr<'static>,
-        r#else: &'static Sexpr<'static>,
-    ) -> Result<Ast, Error> {
-        Ok(Ast::If(If {
-            source,
-            predicate: Box::new(self.compile(predicate)?),
-            then: Box::new(self.compile(then)?),
-            r#else: Box::new(self.compile(r#else)?),
-        }))
-    }
+        pattern: &Sexpr,
+        elements: &[Sexpr],
+        value: Ast,
+        bindings: &mut Vec<(String, Ast)>,
+    ) -> Result<Pattern, Error> {
+        match elements {
+            [] => Ok(Pattern::Literal(Box::new(Ast::Constant(Constant::Nil {
+                source: pattern.source_span(),
+            })))),
+            [Sexpr::Symbol { symbol, .. }, rest] if symbol == "&rest" => {
+                self.compile_pattern(rest, value, bindings)
+            }
+            [Sexpr::Symbol { symbol, .. }, ..] if symbol == "&rest" => Err(Error {
+                span: pattern.source_span(),
+                message: "malformed pattern: &rest must be followed by a single pattern"
+                    .to_string(),
+            }),
+            [first, rest @ ..] => {
+                let (car_value, cdr_value) = car_and_cdr(pattern, value);
+
+                Ok(Pattern::Cons(
+                    Box::new(self.compile_pattern(first, car_value, bindings)?),
+                    Box::new(self.compile_list_pattern(pattern, rest, cdr_value, bindings)?),
+                ))
+            }
+        }
+    }
+
+    // Keys are compared against unevaluated, a clause may list several of them
+    // and the last clause may be (else body...). Anything other than a variable
+    // is bound first so the scrutinee is only evaluated once.
+    fn compile_case(
+        &mut self,
+        source: &Sexpr,
+        expr: &Sexpr,
+        clauses: &[Sexpr],
+    ) -> Result<Ast, Error> {
+        let (clauses, default) = match clauses {
+            [rest @ .., last] => match last.as_list() {
+                Some([Sexpr::Symbol { symbol, .. }, body @ ..]) if symbol == "else" => {
+                    (rest, Some(Box::new(self.compile_case_body(last, body)?)))
+                }
+                _ => (clauses, None),
+            },
+            [] => (clauses, None),
+        };
+
+        let clauses = clauses
+            .iter()
+            .map(|clause| self.compile_case_clause(clause))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Sexpr::Symbol { .. } = expr {
+            return Ok(Ast::Case(Case {
+                source: source.source_span(),
+                scrutinee: Box::new(self.compile_expr(expr)),
+                clauses,
+                default,
+            }));
+        }
+
+        let name = format!("#:case{}", self.patterns);
+        self.patterns += 1;
+
+        Ok(Ast::FnCall(FnCall {
+            source: source.source_span(),
+            function: Box::new(Ast::Lambda(Lambda {
+                source: source.source_span(),
+                r#type: None,
+                parameters: Parameters::Normal(vec![Parameter {
+                    name: name.clone(),
+                    r#type: None,
+                }]),
+                doc: None,
+                body: vec![Ast::Case(Case {
+                    source: source.source_span(),
+                    scrutinee: Box::new(Ast::Variable(Variable::WithoutModule {
+                        source: expr.source_span(),
+                        name,
+                    })),
+                    clauses,
+                    default,
+                })],
+            })),
+            exprs: vec![self.compile_expr(expr)],
+        }))
+    }
+
+    fn compile_case_clause(&mut self, clause: &Sexpr) -> Result<CaseClause, Error> {
+        let (keys, body) = match clause.as_list() {
+            Some([Sexpr::Symbol { symbol, .. }, ..]) if symbol == "else" => {
+                return Err(Error {
+                    span: clause.source_span(),
+                    message: "else must be the last clause in case".to_string(),
+                })
+            }
+            Some([keys, body @ ..]) => (keys, body),
+            _ => {
+                return Err(Error {
+                    span: clause.source_span(),
+                    message: "expected (keys body...) in case".to_string(),
+                })
+            }
+        };
+
+        let source = clause.source_span();
+        let keys = match keys {
+            Sexpr::List { list, .. } => list.iter().map(|key| quote(&source, key)).collect(),
+            key => vec![quote(&source, key)],
+        };
+
+        Ok(CaseClause {
+            source,
+            keys,
+            body: self.compile_case_body(clause, body)?,
+        })
+    }
+
+    fn compile_case_body(&mut self, clause: &Sexpr, body: &[Sexpr]) -> Result<Ast, Error> {
+        match body {
+            [] => Err(Error {
+                span: clause.source_span(),
+                message: "expected (keys body...) in case".to_string(),
+            }),
+            [expr] => Ok(self.compile_expr(expr)),
+            _ => bind(
+                clause,
+                Vec::new(),
+                body.iter()
+                    .map(|expr| self.compile_expr(expr))
+                    .collect::<Vec<_>>(),
+            ),
+        }
+    }
+
+    // (-> x (f a) g) is rewritten to (g (f x a)) before compiling, and ->>
+    // threads into the last argument instead.
+    fn compile_threading(
+        &mut self,
+        form: &str,
+        init: &Sexpr,
+        steps: &[Sexpr],
+    ) -> Result<Ast, Error> {
+        let threaded = steps.iter().try_fold(init.clone(), |acc, step| {
+            let list = match step {
+                Sexpr::List { list, .. } if !list.is_empty() => {
+                    let mut list = list.clone();
+                    if form == "->" {
+                        list.insert(1, acc);
+                    } else {
+                        list.push(acc);
+                    }
+                    list
+                }
+                Sexpr::Symbol { .. } => vec![step.clone(), acc],
+                _ => {
+                    return Err(Error {
+                        span: step.source_span(),
+                        message: format!("expected a function call or symbol in {form}"),
+                    })
+                }
+            };
+
+            Ok(Sexpr::List {
+                list,
+                context: step.context().clone(),
+                span: step.span(),
+            })
+        })?;
+
+        self.compile_form(&threaded)
+    }
+
+    // (deftest name body...) is rewritten to
+    // (def name (register-test 'name (lambda () body...))) before compiling,
+    // and defbench the same with register-bench. Doing it here rather than in a
+    // macro keeps the body's spans, so a failed assert points at the test file
+    // rather than at an expansion.
+    fn compile_registered(
+        &mut self,
+        source: &Sexpr,
+        register: &str,
+        name: &Sexpr,
+        body: &[Sexpr],
+    ) -> Result<Ast, Error> {
+        let symbol = |symbol: &str| Sexpr::Symbol {
+            symbol: symbol.to_string(),
+            context: source.context().clone(),
+            span: source.span(),
+        };
+        let list = |list: Vec<Sexpr>| Sexpr::List {
+            list,
+            context: source.context().clone(),
+            span: source.span(),
+        };
+
+        let lambda = list(
+            [
+                symbol("lambda"),
+                Sexpr::Nil {
+                    context: source.context().clone(),
+                    span: source.span(),
+                },
+            ]
+            .into_iter()
+            .chain(body.iter().cloned())
+            .collect(),
+        );
+
+        let def = list(vec![
+            symbol("def"),
+            name.clone(),
+            list(vec![symbol(register), name.quote(), lambda]),
+        ]);
+
+        self.compile_form(&def)
+    }
+
+    // (dolist (x list [result]) body...) and (dotimes (i n [result]) body...)
+    // are lowered to a loop inside of a lambda that binds the loop variable next
+    // to a hidden one holding the rest of the list or the count. The loop
+    // variable is updated in place rather than rebound on every iteration.
+    fn compile_iteration(
+        &mut self,
+        source: &Sexpr,
+        form: &str,
+        spec: &Sexpr,
+        body: &[Sexpr],
+    ) -> Result<Ast, Error> {
+        let (variable, init, result) = match spec.as_list() {
+            Some([Sexpr::Symbol { symbol, .. }, init]) => (symbol, init, None),
+            Some([Sexpr::Symbol { symbol, .. }, init, result]) => (symbol, init, Some(result)),
+            _ => {
+                return Err(Error {
+                    span: spec.source_span(),
+                    message: format!("expected ({form} (variable expr [result]) body...)"),
+                })
+            }
+        };
+
+        let hidden = format!("#:{form}{}", self.patterns);
+        self.patterns += 1;
+
+        let get = |name: &str| {
+            Box::new(Ast::Variable(Variable::WithoutModule {
+                source: source.source_span(),
+                name: name.to_string(),
+            }))
+        };
+
+        let set = |name: &str, body: Ast| {
+            Ast::Set(Set {
+                source: source.source_span(),
+                variable: Variable::WithoutModule {
+                    source: source.source_span(),
+                    name: name.to_string(),
+                },
+                body: Box::new(body),
+            })
+        };
+
+        let mut body = body
+            .iter()
+            .map(|expr| self.compile_expr(expr))
+            .collect::<Vec<_>>();
+
+        let (test, initial) = if form == "dolist" {
+            body.insert(
+                0,
+                set(
+                    variable,
+                    Ast::Car(Car {
+                        source: source.source_span(),
+                        body: get(&hidden),
+                    }),
+                ),
+            );
+            body.push(set(
+                &hidden,
+                Ast::Cdr(Cdr {
+                    source: source.source_span(),
+                    body: get(&hidden),
+                }),
+            ));
+
+            (
+                Ast::IsType(IsType {
+                    source: source.source_span(),
+                    parameter: IsTypeParameter::Cons,
+                    body: get(&hidden),
+                }),
+                Ast::Constant(Constant::Nil {
+                    source: source.source_span(),
+                }),
+            )
+        } else {
+            body.push(set(
+                variable,
+                Ast::BinaryArithemticOperation(BinaryArithmeticOperation {
+                    source: source.source_span(),
+                    operator: BinaryArithmeticOperator::Add,
+                    lhs: get(variable),
+                    rhs: Box::new(Ast::Constant(Constant::Int {
+                        source: source.source_span(),
+                        int: 1,
+                    })),
+                }),
+            ));
+
+            (
+                Ast::ComparisonOperation(ComparisonOperation {
+                    source: source.source_span(),
+                    operator: ComparisonOperator::Lt,
+                    lhs: get(variable),
+                    rhs: get(&hidden),
+                }),
+                Ast::Constant(Constant::Int {
+                    source: source.source_span(),
+                    int: 0,
+                }),
+            )
+        };
+
+        let mut lambda_body = vec![Ast::Loop(Loop {
+            source: source.source_span(),
+            test: Box::new(test),
+            body,
+        })];
+
+        if let Some(result) = result {
+            lambda_body.push(self.compile_expr(result));
+        }
+
+        Ok(Ast::FnCall(FnCall {
+            source: source.source_span(),
+            function: Box::new(Ast::Lambda(Lambda {
+                source: source.source_span(),
+                r#type: None,
+                parameters: Parameters::Normal(vec![
+                    Parameter {
+                        name: variable.clone(),
+                        r#type: None,
+                    },
+                    Parameter {
+                        name: hidden,
+                        r#type: None,
+                    },
+                ]),
+                doc: None,
+                body: lambda_body,
+            })),
+            exprs: vec![initial, self.compile_expr(init)],
+        }))
+    }
+
+    // (documentation 'foo) refers to whatever foo is bound to where it appears,
+    // so a quoted symbol is compiled as a reference to it.
+    fn compile_documentation(&mut self, source: &Sexpr, function: &Sexpr) -> Result<Ast, Error> {
+        let function = match function.as_list() {
+            Some([Sexpr::Symbol { symbol, .. }, name @ Sexpr::Symbol { .. }])
+                if symbol == "quote" =>
+            {
+                name
+            }
+            _ => function,
+        };
+
+        Ok(Ast::Documentation(Documentation {
+            source: source.source_span(),
+            function: Box::new(self.compile_expr(function)),
+        }))
+    }
+
+    fn compile_defconst(
+        &mut self,
+        source: &Sexpr,
+        name: &str,
+        doc: Option<&str>,
+        body: &Sexpr,
+    ) -> Ast {
+        Ast::DefConst(DefConst {
+            source: source.source_span(),
+            name: name.to_string(),
+            doc: doc.map(|doc| doc.to_string()),
+            body: Box::new(self.compile_expr(body)),
+        })
+    }
+
+    fn compile_decl(
+        &mut self,
+        source: &Sexpr,
+        parameter: &Sexpr,
+        body: &Sexpr,
+    ) -> Result<Ast, Error> {
+        Ok(Ast::Decl(Decl {
+            source: source.source_span(),
+            parameter: Parameter::from_sexpr(parameter).map_err(|_| Error {
+                span: source.source_span(),
+                message: "failed to parse parameter".to_string(),
+            })?,
+            body: Box::new(self.compile_expr(body)),
+        }))
+    }
+
+    // (declaim (strict-arity t) (require-decl nil) ...)
+    fn compile_declaim(&mut self, source: &Sexpr, pragmas: &[Sexpr]) -> Result<Ast, Error> {
+        Ok(Ast::Declaim(Declaim {
+            source: source.source_span(),
+            pragmas: pragmas
+                .iter()
+                .map(|pragma| match pragma.as_list() {
+                    Some([Sexpr::Symbol { symbol, .. }, value]) => Ok((
+                        match symbol.as_str() {
+                            "strict-arity" => Pragma::StrictArity,
+                            "warnings-as-errors" => Pragma::WarningsAsErrors,
+                            "require-decl" => Pragma::RequireDecl,
+                            _ => {
+                                return Err(Error {
+                                    span: pragma.source_span(),
+                                    message: format!("unknown pragma: {symbol}"),
+                                })
+                            }
+                        },
+                        match value {
+                            Sexpr::Symbol { symbol, .. } if symbol == "t" => true,
+                            Sexpr::Bool { bool, .. } => *bool,
+                            Sexpr::Nil { .. } => false,
+                            _ => {
+                                return Err(Error {
+                                    span: value.source_span(),
+                                    message: "expected t or nil".to_string(),
+                                })
+                            }
+                        },
+                    )),
+                    _ => Err(Error {
+                        span: pragma.source_span(),
+                        message: "expected (pragma t) or (pragma nil)".to_string(),
+                    }),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        }))
+    }
+
+    // Besides variables, set! can assign to the places (car x), (cdr x) and
+    // (map-retrieve map key).
+    fn compile_set(
+        &mut self,
+        source: &Sexpr,
+        parameter: &Sexpr,
+        body: &Sexpr,
+    ) -> Result<Ast, Error> {
+        if let Some(place) = parameter.as_list() {
+            return match place {
+                [Sexpr::Symbol { symbol, .. }, cons] if symbol == "car" => {
+                    Ok(Ast::SetCar(SetCar {
+                        source: source.source_span(),
+                        cons: Box::new(self.compile_expr(cons)),
+                        body: Box::new(self.compile_expr(body)),
+                    }))
+                }
+                [Sexpr::Symbol { symbol, .. }, cons] if symbol == "cdr" => {
+                    Ok(Ast::SetCdr(SetCdr {
+                        source: source.source_span(),
+                        cons: Box::new(self.compile_expr(cons)),
+                        body: Box::new(self.compile_expr(body)),
+                    }))
+                }
+                [Sexpr::Symbol { symbol, .. }, map, key] if symbol == "map-retrieve" => {
+                    Ok(Ast::MapInsert(MapInsert {
+                        source: source.source_span(),
+                        map: Box::new(self.compile_expr(map)),
+                        key: Box::new(self.compile_expr(key)),
+                        value: Box::new(self.compile_expr(body)),
+                    }))
+                }
+                _ => Err(Error {
+                    span: parameter.source_span(),
+                    message: "expected a variable, (car x), (cdr x) or (map-retrieve map key)"
+                        .to_string(),
+                }),
+            };
+        }
+
+        Ok(Ast::Set(Set {
+            source: source.source_span(),
+            variable: match parameter
+                .as_symbol()
+                .map(|symbol| parse_variable(source, symbol))
+            {
+                Some(Ok(variable)) => variable,
+                Some(Err(())) => {
+                    return Err(Error {
+                        span: source.source_span(),
+                        message: "failed to parse variable".to_string(),
+                    })
+                }
+                None => {
+                    return Err(Error {
+                        span: source.source_span(),
+                        message: "expected symbol".to_string(),
+                    })
+                }
+            },
+            body: Box::new(self.compile_expr(body)),
+        }))
+    }
+
+    fn compile_if(
+        &mut self,
+        source: &Sexpr,
+        predicate: &Sexpr,
+        then: &Sexpr,
+        r#else: &Sexpr,
+    ) -> Result<Ast, Error> {
+        Ok(Ast::If(If {
+            source: source.source_span(),
+            predicate: Box::new(self.compile_expr(predicate)),
+            then: Box::new(self.compile_expr(then)),
+            r#else: Box::new(self.compile_expr(r#else)),
+        }))
+    }
 
     fn compile_apply(
         &mut self,
-        source: &'static Sexpr<'static>,
-        function: &'static Sexpr<'static>,
-        list: &'static Sexpr<'static>,
+        source: &Sexpr,
+        function: &Sexpr,
+        list: &Sexpr,
     ) -> Result<Ast, Error> {
         Ok(Ast::Apply(Apply {
-            source,
-            function: Box::new(self.compile(function)?),
-            list: Box::new(self.compile(list)?),
+            source: source.source_span(),
+            function: Box::new(self.compile_expr(function)),
+            list: Box::new(self.compile_expr(list)),
         }))
     }
 
     fn compile_binary_arithmetic_op(
         &mut self,
-        source: &'static Sexpr<'static>,
+        source: &Sexpr,
         operator: &str,
-        lhs: &'static Sexpr<'static>,
-        rhs: &'static Sexpr<'static>,
+        lhs: &Sexpr,
+        rhs: &Sexpr,
     ) -> Result<Ast, Error> {
         Ok(Ast::BinaryArithemticOperation(BinaryArithmeticOperation {
-            source,
+            source: source.source_span(),
             operator: match operator {
                 "+" => BinaryArithmeticOperator::Add,
                 "-" => BinaryArithmeticOperator::Sub,
@@ -755,128 +1881,120 @@ impl Compiler {
                 "/" => BinaryArithmeticOperator::Div,
                 _ => unreachable!(),
             },
-            lhs: Box::new(self.compile(lhs)?),
-            rhs: Box::new(self.compile(rhs)?),
+            lhs: Box::new(self.compile_expr(lhs)),
+            rhs: Box::new(self.compile_expr(rhs)),
         }))
     }
 
     fn compile_comparison_op(
         &mut self,
-        source: &'static Sexpr<'static>,
+        source: &Sexpr,
         operator: &str,
-        lhs: &'static Sexpr<'static>,
-        rhs: &'static Sexpr<'static>,
+        lhs: &Sexpr,
+        rhs: &Sexpr,
     ) -> Result<Ast, Error> {
         Ok(Ast::ComparisonOperation(ComparisonOperation {
-            source,
+            source: source.source_span(),
             operator: match operator {
                 "=" => ComparisonOperator::Eq,
                 "<" => ComparisonOperator::Lt,
                 ">" => ComparisonOperator::Gt,
                 _ => unreachable!(),
             },
-            lhs: Box::new(self.compile(lhs)?),
-            rhs: Box::new(self.compile(rhs)?),
+            lhs: Box::new(self.compile_expr(lhs)),
+            rhs: Box::new(self.compile_expr(rhs)),
         }))
     }
 
-    fn compile_list(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        args: &'static [Sexpr<'static>],
-    ) -> Result<Ast, Error> {
+    fn compile_list(&mut self, source: &Sexpr, args: &[Sexpr]) -> Result<Ast, Error> {
         Ok(Ast::List(List {
-            source,
+            source: source.source_span(),
             exprs: args
                 .iter()
-                .map(|arg| self.compile(arg))
-                .collect::<Result<Vec<_>, _>>()?,
+                .map(|arg| self.compile_expr(arg))
+                .collect::<Vec<_>>(),
         }))
     }
 
-    fn compile_cons(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        lhs: &'static Sexpr<'static>,
-        rhs: &'static Sexpr<'static>,
-    ) -> Result<Ast, Error> {
+    fn compile_cons(&mut self, source: &Sexpr, lhs: &Sexpr, rhs: &Sexpr) -> Result<Ast, Error> {
         Ok(Ast::Cons(Cons {
-            source,
-            lhs: Box::new(self.compile(lhs)?),
-            rhs: Box::new(self.compile(rhs)?),
+            source: source.source_span(),
+            lhs: Box::new(self.compile_expr(lhs)),
+            rhs: Box::new(self.compile_expr(rhs)),
         }))
     }
 
-    fn compile_car(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        body: &'static Sexpr<'static>,
-    ) -> Result<Ast, Error> {
+    fn compile_car(&mut self, source: &Sexpr, body: &Sexpr) -> Result<Ast, Error> {
         Ok(Ast::Car(Car {
-            source,
-            body: Box::new(self.compile(body)?),
+            source: source.source_span(),
+            body: Box::new(self.compile_expr(body)),
         }))
     }
 
-    fn compile_cdr(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        body: &'static Sexpr<'static>,
-    ) -> Result<Ast, Error> {
+    fn compile_cdr(&mut self, source: &Sexpr, body: &Sexpr) -> Result<Ast, Error> {
         Ok(Ast::Cdr(Cdr {
-            source,
-            body: Box::new(self.compile(body)?),
+            source: source.source_span(),
+            body: Box::new(self.compile_expr(body)),
         }))
     }
 
     fn compile_fncall(
         &mut self,
-        source: &'static Sexpr<'static>,
-        function: &'static Sexpr<'static>,
-        args: &'static [Sexpr<'static>],
+        source: &Sexpr,
+        function: &Sexpr,
+        args: &[Sexpr],
     ) -> Result<Ast, Error> {
         Ok(Ast::FnCall(FnCall {
-            source,
-            function: Box::new(self.compile(function)?),
+            source: source.source_span(),
+            function: Box::new(self.compile_expr(function)),
             exprs: args
                 .iter()
-                .map(|arg| self.compile(arg))
-                .collect::<Result<Vec<_>, _>>()?,
+                .map(|arg| self.compile_expr(arg))
+                .collect::<Vec<_>>(),
         }))
     }
 
     fn compile_macro_call(
         &mut self,
-        source: &'static Sexpr<'static>,
+        source: &Sexpr,
         r#macro: &str,
-        args: &'static [Sexpr<'static>],
+        args: &[Sexpr],
     ) -> Result<Ast, Error> {
+        let source = source.source_span();
+        // quasiquote's own argument is the template rather than code
+        let quoting = if r#macro == "quasiquote" {
+            Quoting::QuasiQuoted
+        } else {
+            Quoting::Code
+        };
+
         Ok(Ast::MacroCall(MacroCall {
-            source,
             r#macro: r#macro.to_string(),
-            args: args.iter().map(|arg| quote(source, arg)).collect(),
+            args: args
+                .iter()
+                .map(|arg| quote_macro_arg(&source, arg, quoting))
+                .collect(),
+            source,
         }))
     }
 
-    fn compile_quote(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        body: &'static Sexpr<'static>,
-    ) -> Result<Ast, Error> {
+    fn compile_quote(&mut self, source: &Sexpr, body: &Sexpr) -> Result<Ast, Error> {
+        let source = source.source_span();
+
         Ok(Ast::Quote(Quote {
+            body: quote(&source, body),
             source,
-            body: quote(source, body),
         }))
     }
 
     fn compile_is_type(
         &mut self,
-        source: &'static Sexpr<'static>,
+        source: &Sexpr,
         parameter: &str,
-        body: &'static Sexpr<'static>,
+        body: &Sexpr,
     ) -> Result<Ast, Error> {
         Ok(Ast::IsType(IsType {
-            source,
+            source: source.source_span(),
             parameter: match parameter {
                 "function?" => IsTypeParameter::Function,
                 "cons?" => IsTypeParameter::Cons,
@@ -888,94 +2006,166 @@ impl Compiler {
                 "nil?" => IsTypeParameter::Nil,
                 _ => unreachable!(),
             },
-            body: Box::new(self.compile(body)?),
+            body: Box::new(self.compile_expr(body)),
         }))
     }
 
-    fn compile_assert(
+    fn compile_macroexpand(
         &mut self,
-        source: &'static Sexpr<'static>,
-        body: &'static Sexpr<'static>,
+        source: &Sexpr,
+        symbol: &str,
+        form: &Sexpr,
     ) -> Result<Ast, Error> {
+        match form.as_list() {
+            Some([Sexpr::Symbol { symbol: quote, .. }, form]) if quote == "quote" => {
+                Ok(Ast::MacroExpand(MacroExpand {
+                    source: source.source_span(),
+                    form: form.clone(),
+                    once: symbol == "macroexpand-1",
+                }))
+            }
+            _ => Err(Error {
+                span: source.source_span(),
+                message: format!("{symbol} expects a quoted form"),
+            }),
+        }
+    }
+
+    fn compile_assert(&mut self, source: &Sexpr, body: &Sexpr) -> Result<Ast, Error> {
         Ok(Ast::Assert(Assert {
-            source,
-            body: Box::new(self.compile(body)?),
+            source: source.source_span(),
+            body: Box::new(self.compile_expr(body)),
         }))
     }
 
     fn compile_map_insert(
         &mut self,
-        source: &'static Sexpr<'static>,
-        map: &'static Sexpr<'static>,
-        key: &'static Sexpr<'static>,
-        value: &'static Sexpr<'static>,
+        source: &Sexpr,
+        map: &Sexpr,
+        key: &Sexpr,
+        value: &Sexpr,
     ) -> Result<Ast, Error> {
         Ok(Ast::MapInsert(MapInsert {
-            source,
-            map: Box::new(self.compile(map)?),
-            key: Box::new(self.compile(key)?),
-            value: Box::new(self.compile(value)?),
+            source: source.source_span(),
+            map: Box::new(self.compile_expr(map)),
+            key: Box::new(self.compile_expr(key)),
+            value: Box::new(self.compile_expr(value)),
         }))
     }
 
     fn compile_map_retrieve(
         &mut self,
-        source: &'static Sexpr<'static>,
-        map: &'static Sexpr<'static>,
-        key: &'static Sexpr<'static>,
+        source: &Sexpr,
+        map: &Sexpr,
+        key: &Sexpr,
     ) -> Result<Ast, Error> {
         Ok(Ast::MapRetrieve(MapRetrieve {
-            source,
-            map: Box::new(self.compile(map)?),
-            key: Box::new(self.compile(key)?),
+            source: source.source_span(),
+            map: Box::new(self.compile_expr(map)),
+            key: Box::new(self.compile_expr(key)),
         }))
     }
 
-    fn compile_map_items(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        map: &'static Sexpr<'static>,
-    ) -> Result<Ast, Error> {
+    // (map-create k v ...), which {k v ...} reads as, creates the map and
+    // inserts each entry into it in order.
+    fn compile_map_literal(&mut self, source: &Sexpr, entries: &[Sexpr]) -> Result<Ast, Error> {
+        if !entries.len().is_multiple_of(2) {
+            return Err(Error {
+                span: source.source_span(),
+                message: "map literal has a key without a value".to_string(),
+            });
+        }
+
+        let name = format!("#:map{}", self.patterns);
+        self.patterns += 1;
+
+        let entries = entries
+            .chunks(2)
+            .map(|entry| {
+                (
+                    entry[0].source_span(),
+                    self.compile_expr(&entry[0]),
+                    self.compile_expr(&entry[1]),
+                )
+            })
+            .collect();
+
+        Ok(build_map(&source.source_span(), name, entries))
+    }
+
+    fn compile_map_items(&mut self, source: &Sexpr, map: &Sexpr) -> Result<Ast, Error> {
         Ok(Ast::MapItems(MapItems {
-            source,
-            map: Box::new(self.compile(map)?),
+            source: source.source_span(),
+            map: Box::new(self.compile_expr(map)),
         }))
     }
 
-    fn compile_export(
-        &mut self,
-        source: &'static Sexpr<'static>,
-        item: &str,
-    ) -> Result<Ast, Error> {
+    fn compile_export(&mut self, source: &Sexpr, item: &str) -> Result<Ast, Error> {
+        let symbol = match parse_variable(source, item) {
+            Ok(Variable::WithoutModule { name, .. }) => name,
+            Ok(_) => {
+                return Err(Error {
+                    span: source.source_span(),
+                    message: "expected non-module variable".to_string(),
+                })
+            }
+            Err(()) => {
+                return Err(Error {
+                    span: source.source_span(),
+                    message: "failed to parse variable".to_string(),
+                })
+            }
+        };
+
+        let r#macro = match &self.current_module {
+            Some(module)
+                if self
+                    .macros
+                    .get(&self.current_module)
+                    .is_some_and(|macros| macros.contains(&symbol)) =>
+            {
+                self.exported_macros
+                    .entry(module.clone())
+                    .or_default()
+                    .insert(symbol.clone());
+                true
+            }
+            _ => false,
+        };
+
         Ok(Ast::Export(Export {
-            source,
-            symbol: match parse_variable(source, item) {
-                Ok(Variable::WithoutModule { name, .. }) => name,
-                Ok(_) => {
-                    return Err(Error {
-                        sexpr: source,
-                        message: "expected non-module variable".to_string(),
-                    })
-                }
-                Err(()) => {
-                    return Err(Error {
-                        sexpr: source,
-                        message: "failed to parse variable".to_string(),
-                    })
-                }
-            },
+            source: source.source_span(),
+            symbol,
+            r#macro,
         }))
     }
 }
 
+// Macros are functions defined as globals while compiling. Ones defined in a
+// module are qualified with it, which no user global can be.
+pub(crate) fn macro_global(module: Option<&str>, name: &str) -> String {
+    match module {
+        Some(module) => format!("{module}::{name}"),
+        None => name.to_string(),
+    }
+}
+
+impl Error {
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "error: {}\n{}", self.message, self.sexpr)
+        let message = format!("error: {}", self.message);
+
+        write!(f, "{}", self.span.render_diagnostic(&message))
     }
 }
 
 impl Ast {
-    pub fn source_sexpr(&self) -> &'static Sexpr<'static> {
+    pub fn source_span(&self) -> &Span {
         match self {
             Self::Module(Module { source, .. })
             | Self::Require(Require { source, .. })
@@ -983,7 +2173,9 @@ impl Ast {
             | Self::DefMacro(DefMacro { source, .. })
             | Self::Lambda(Lambda { source, .. })
             | Self::Def(Def { source, .. })
+            | Self::DefConst(DefConst { source, .. })
             | Self::Decl(Decl { source, .. })
+            | Self::Declaim(Declaim { source, .. })
             | Self::Set(Set { source, .. })
             | Self::If(If { source, .. })
             | Self::Apply(Apply { source, .. })
@@ -993,6 +2185,8 @@ impl Ast {
             | Self::Cons(Cons { source, .. })
             | Self::Car(Car { source, .. })
             | Self::Cdr(Cdr { source, .. })
+            | Self::SetCar(SetCar { source, .. })
+            | Self::SetCdr(SetCdr { source, .. })
             | Self::FnCall(FnCall { source, .. })
             | Self::MacroCall(MacroCall { source, .. })
             | Self::Quote(Quote { source, .. })
@@ -1002,6 +2196,12 @@ impl Ast {
             | Self::MapInsert(MapInsert { source, .. })
             | Self::MapRetrieve(MapRetrieve { source, .. })
             | Self::MapItems(MapItems { source, .. })
+            | Self::Gensym(Gensym { source, .. })
+            | Self::Documentation(Documentation { source, .. })
+            | Self::Match(Match { source, .. })
+            | Self::Case(Case { source, .. })
+            | Self::Loop(Loop { source, .. })
+            | Self::MacroExpand(MacroExpand { source, .. })
             | Self::Export(Export { source, .. })
             | Self::Variable(Variable::WithoutModule { source, .. })
             | Self::Variable(Variable::WithModule { source, .. })
@@ -1023,11 +2223,30 @@ impl Type {
                     .collect::<Result<Vec<_>, _>>()?,
             ),
             Sexpr::Symbol { symbol, .. } => Type::Scalar(symbol.clone()),
+            Sexpr::Nil { .. } => Type::Scalar("nil".to_string()),
             _ => return Err(()),
         })
     }
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Scalar(scalar) => write!(f, "{scalar}"),
+            Type::Composite(composite) => {
+                write!(f, "(")?;
+                for (i, t) in composite.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{t}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
 impl Parameter {
     pub fn from_sexpr(sexpr: &Sexpr) -> Result<Self, ()> {
         Ok(match sexpr {
@@ -1056,109 +2275,313 @@ impl Parameters {
         match self {
             Parameters::Normal(params) => params.len(),
             Parameters::Rest(params, _) => params.len() + 1,
+            Parameters::Optional(params, optional) => params.len() + optional.len(),
         }
     }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let (required, rest, optional): (&[Parameter], Option<&Parameter>, &[_]) = match self {
+            Parameters::Normal(params) => (params, None, &[]),
+            Parameters::Rest(params, rest) => (params, Some(rest), &[]),
+            Parameters::Optional(params, optional) => (params, None, optional),
+        };
+
+        required
+            .iter()
+            .chain(optional.iter().map(|(parameter, _)| parameter))
+            .chain(rest)
+            .map(|parameter| parameter.name.as_str())
+    }
 }
 
-fn parse_parameters(
-    source: &'static Sexpr<'static>,
-    list: &'static [Sexpr<'static>],
-) -> Result<Parameters, Error> {
-    let parameters = list
-        .iter()
-        .map(Parameter::from_sexpr)
-        .collect::<Result<Vec<_>, ()>>()
-        .map_err(|_| Error {
-            sexpr: source,
-            message: "failed to parse parameter".to_string(),
-        })?;
+// A string before the rest of a body is a docstring, a lone string is the value
+// being returned.
+fn split_docstring(body: &[Sexpr]) -> (Option<String>, &[Sexpr]) {
+    match body {
+        [Sexpr::String { string, .. }, rest @ ..] if !rest.is_empty() => {
+            (Some(string.clone()), rest)
+        }
+        _ => (None, body),
+    }
+}
 
-    let with_rest = micro_nom::map(
-        micro_nom::separated(
-            micro_nom::take_while::<&[Parameter], _>(|parameter: &Parameter| {
-                parameter.name != "&rest"
-            }),
-            micro_nom::take_one_if::<&[Parameter], _>(|parameter: &&Parameter| {
-                parameter.name == "&rest"
-            }),
-            micro_nom::take_one,
-        ),
-        |(parameters, rest)| Parameters::Rest(parameters.to_vec(), rest.clone()),
-    );
+// A two element list whose second element names a type is a typed parameter,
+// any other list in parameter position is a destructuring pattern.
+fn is_typed_parameter(list: &[Sexpr]) -> bool {
+    matches!(list, [Sexpr::Symbol { .. }, r#type]
+        if Type::from_sexpr(r#type).is_ok_and(|t| types::Type::from_ast(&t).is_ok()))
+}
 
-    let without_rest = micro_nom::map(
-        micro_nom::take_while::<&[Parameter], _>(|_| true),
-        |parameters| Parameters::Normal(parameters.to_vec()),
-    );
+// Lowers destructured parameters by wrapping the body in a lambda that binds
+// each name in the patterns to the matching car/cdr of its argument.
+fn destructure_patterns(
+    source: &Sexpr,
+    patterns: &[(String, &Sexpr)],
+    body: Vec<Ast>,
+) -> Result<Ast, Error> {
+    let mut bindings = Vec::new();
+
+    for (name, pattern) in patterns {
+        let value = Ast::Variable(Variable::WithoutModule {
+            source: pattern.source_span(),
+            name: name.clone(),
+        });
+
+        destructure(pattern, value, &mut bindings)?;
+    }
+
+    bind(source, bindings, body)
+}
 
-    let p = match micro_nom::branch(with_rest, without_rest)(parameters.as_slice()) {
-        Ok((_, p)) => p,
-        Err(_) => {
+// Applies a lambda taking each name in bindings to the matching values.
+fn bind(source: &Sexpr, bindings: Vec<(String, Ast)>, body: Vec<Ast>) -> Result<Ast, Error> {
+    for (i, (name, _)) in bindings.iter().enumerate() {
+        if bindings[..i].iter().any(|(other, _)| other == name) {
             return Err(Error {
-                sexpr: source,
-                message: "failed to parse parameters".to_string(),
-            })
+                span: source.source_span(),
+                message: format!("{name} is bound more than once in pattern"),
+            });
         }
+    }
+
+    let (names, values): (Vec<_>, Vec<_>) = bindings.into_iter().unzip();
+
+    Ok(Ast::FnCall(FnCall {
+        source: source.source_span(),
+        function: Box::new(Ast::Lambda(Lambda {
+            source: source.source_span(),
+            r#type: None,
+            parameters: Parameters::Normal(
+                names
+                    .into_iter()
+                    .map(|name| Parameter { name, r#type: None })
+                    .collect(),
+            ),
+            doc: None,
+            body,
+        })),
+        exprs: values,
+    }))
+}
+
+fn car_and_cdr(source: &Sexpr, value: Ast) -> (Ast, Ast) {
+    (
+        Ast::Car(Car {
+            source: source.source_span(),
+            body: Box::new(value.clone()),
+        }),
+        Ast::Cdr(Cdr {
+            source: source.source_span(),
+            body: Box::new(value),
+        }),
+    )
+}
+
+fn destructure(
+    pattern: &Sexpr,
+    mut value: Ast,
+    bindings: &mut Vec<(String, Ast)>,
+) -> Result<(), Error> {
+    let malformed = |message: &str| Error {
+        span: pattern.source_span(),
+        message: format!("malformed pattern: {message}"),
+    };
+
+    let Sexpr::List { list, .. } = pattern else {
+        return Err(malformed("expected a list"));
     };
 
-    Ok(p)
+    let mut elements = list.iter();
+
+    while let Some(element) = elements.next() {
+        let car = Ast::Car(Car {
+            source: element.source_span(),
+            body: Box::new(value.clone()),
+        });
+
+        match element {
+            Sexpr::Symbol { symbol, .. } if symbol == "&rest" => {
+                return match (elements.next(), elements.next()) {
+                    (Some(Sexpr::Symbol { symbol, .. }), None) if symbol != "&rest" => {
+                        bindings.push((symbol.clone(), value));
+                        Ok(())
+                    }
+                    _ => Err(malformed("&rest must be followed by a single name")),
+                };
+            }
+            Sexpr::Symbol { symbol, .. } => bindings.push((symbol.clone(), car)),
+            Sexpr::List { .. } => destructure(element, car, bindings)?,
+            _ => return Err(malformed("expected a name or a nested pattern")),
+        }
+
+        value = Ast::Cdr(Cdr {
+            source: element.source_span(),
+            body: Box::new(value),
+        });
+    }
+
+    Ok(())
 }
 
-fn quote(source: &'static Sexpr<'static>, sexpr: &'static Sexpr<'static>) -> Quoted {
+// A fresh map filled in by a lambda that takes it as the named parameter,
+// there being no other way to get at the map between inserts.
+pub(crate) fn build_map(source: &Span, name: String, entries: Vec<(Span, Ast, Ast)>) -> Ast {
+    let map = Ast::Variable(Variable::WithoutModule {
+        source: source.clone(),
+        name: name.clone(),
+    });
+
+    let mut body = entries
+        .into_iter()
+        .map(|(source, key, value)| {
+            Ast::MapInsert(MapInsert {
+                source,
+                map: Box::new(map.clone()),
+                key: Box::new(key),
+                value: Box::new(value),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    body.push(map);
+
+    Ast::FnCall(FnCall {
+        source: source.clone(),
+        function: Box::new(Ast::Lambda(Lambda {
+            source: source.clone(),
+            r#type: None,
+            parameters: Parameters::Normal(vec![Parameter { name, r#type: None }]),
+            doc: None,
+            body,
+        })),
+        exprs: vec![Ast::MapCreate(MapCreate {
+            source: source.clone(),
+        })],
+    })
+}
+
+pub(crate) fn quote(source: &Span, sexpr: &Sexpr) -> Quoted {
     match sexpr {
         Sexpr::List { list, .. } => quote_list(source, list.as_slice()),
+        Sexpr::DottedList { list, tail, .. } => quote_dotted_list(source, list.as_slice(), tail),
         Sexpr::Symbol { symbol, .. } => Quoted::Symbol {
-            source,
+            source: source.clone(),
             symbol: symbol.clone(),
         },
         Sexpr::String { string, .. } => Quoted::String {
-            source,
+            source: source.clone(),
             string: string.clone(),
         },
         Sexpr::Char { char, .. } => Quoted::Char {
-            source,
+            source: source.clone(),
             char: *char,
         },
-        Sexpr::Int { int, .. } => Quoted::Int { source, int: *int },
+        Sexpr::Int { int, .. } => Quoted::Int {
+            source: source.clone(),
+            int: *int,
+        },
         Sexpr::Bool { bool, .. } => Quoted::Bool {
-            source,
+            source: source.clone(),
             bool: *bool,
         },
-        Sexpr::Nil { .. } => Quoted::Nil { source },
+        Sexpr::Nil { .. } => Quoted::Nil {
+            source: source.clone(),
+        },
+        Sexpr::Vector { list, .. } if list.is_empty() => Quoted::Nil {
+            source: source.clone(),
+        },
+        Sexpr::Vector { list, .. } => quote_list(source, list.as_slice()),
+        Sexpr::Map { list, .. } => Quoted::Map {
+            source: source.clone(),
+            entries: list
+                .chunks_exact(2)
+                .map(|entry| (quote(source, &entry[0]), quote(source, &entry[1])))
+                .collect(),
+        },
+    }
+}
+
+// How much of a macro argument is code. The literals in code are handed over
+// as the (list ...) and (map-create ...) forms they evaluate as, so that the
+// expansion still builds them, while quoted ones are data like any other
+// quoted form. Inside a quasiquote only what's unquoted is code.
+#[derive(Clone, Copy, PartialEq)]
+enum Quoting {
+    Code,
+    QuasiQuoted,
+}
+
+fn quote_macro_arg(source: &Span, sexpr: &Sexpr, quoting: Quoting) -> Quoted {
+    let head = sexpr
+        .as_list()
+        .and_then(|list| list.first())
+        .and_then(Sexpr::as_symbol);
+
+    let quoting = match (quoting, head) {
+        (Quoting::Code, Some("quote")) => return quote(source, sexpr),
+        (Quoting::Code, Some("quasiquote")) => Quoting::QuasiQuoted,
+        (Quoting::QuasiQuoted, Some("unquote" | "unquote-splice")) => Quoting::Code,
+        _ => quoting,
+    };
+
+    let elements = |list: &[Sexpr]| {
+        list.iter()
+            .map(|sexpr| quote_macro_arg(source, sexpr, quoting))
+            .collect::<Vec<_>>()
+    };
+
+    match sexpr {
+        Sexpr::List { list, .. } => Quoted::List {
+            source: source.clone(),
+            list: elements(list),
+        },
+        Sexpr::DottedList { list, tail, .. } => Quoted::DottedList {
+            source: source.clone(),
+            list: elements(list),
+            tail: Box::new(quote_macro_arg(source, tail, quoting)),
+        },
+        Sexpr::Vector { list, .. } | Sexpr::Map { list, .. } if quoting == Quoting::Code => {
+            let head = Quoted::Symbol {
+                source: source.clone(),
+                symbol: if sexpr.is_vector() { "list" } else { "map-create" }.to_string(),
+            };
+
+            Quoted::List {
+                source: source.clone(),
+                list: std::iter::once(head).chain(elements(list)).collect(),
+            }
+        }
+        Sexpr::Vector { list, .. } if !list.is_empty() => Quoted::List {
+            source: source.clone(),
+            list: elements(list),
+        },
+        Sexpr::Map { list, .. } => Quoted::Map {
+            source: source.clone(),
+            entries: elements(list)
+                .chunks_exact(2)
+                .map(|entry| (entry[0].clone(), entry[1].clone()))
+                .collect(),
+        },
+        _ => quote(source, sexpr),
     }
 }
 
-fn quote_list(source: &'static Sexpr<'static>, list: &'static [Sexpr<'static>]) -> Quoted {
+fn quote_dotted_list(source: &Span, list: &[Sexpr], tail: &Sexpr) -> Quoted {
+    Quoted::DottedList {
+        source: source.clone(),
+        list: list.iter().map(|sexpr| quote(source, sexpr)).collect(),
+        tail: Box::new(quote(source, tail)),
+    }
+}
+
+fn quote_list(source: &Span, list: &[Sexpr]) -> Quoted {
     Quoted::List {
-        source,
-        list: list
-            .iter()
-            .map(|sexpr| match sexpr {
-                Sexpr::List { list, .. } => quote_list(source, list.as_slice()),
-                Sexpr::Symbol { symbol, .. } => Quoted::Symbol {
-                    source,
-                    symbol: symbol.clone(),
-                },
-                Sexpr::String { string, .. } => Quoted::String {
-                    source,
-                    string: string.clone(),
-                },
-                Sexpr::Char { char, .. } => Quoted::Char {
-                    source,
-                    char: *char,
-                },
-                Sexpr::Int { int, .. } => Quoted::Int { source, int: *int },
-                Sexpr::Bool { bool, .. } => Quoted::Bool {
-                    source,
-                    bool: *bool,
-                },
-                Sexpr::Nil { .. } => Quoted::Nil { source },
-            })
-            .collect(),
+        source: source.clone(),
+        list: list.iter().map(|sexpr| quote(source, sexpr)).collect(),
     }
 }
 
-fn parse_variable(source: &'static Sexpr<'static>, variable: &str) -> Result<Variable, ()> {
+fn parse_variable(source: &Sexpr, variable: &str) -> Result<Variable, ()> {
     use micro_nom::{branch, map, pair, separated, take_one_if, take_while1};
 
     let separator = pair::<&str, _, _, _, _>(
@@ -1173,7 +2596,7 @@ fn parse_variable(source: &'static Sexpr<'static>, variable: &str) -> Result<Var
             take_while1(|_| true),
         ),
         |(module, name)| Variable::WithModule {
-            source,
+            source: source.source_span(),
             name: name.to_string(),
             module: module.to_string(),
         },
@@ -1181,7 +2604,7 @@ fn parse_variable(source: &'static Sexpr<'static>, variable: &str) -> Result<Var
 
     let without_module = map(take_while1::<&str, _>(|_| true), |name| {
         Variable::WithoutModule {
-            source,
+            source: source.source_span(),
             name: name.to_string(),
         }
     });
@@ -1196,20 +2619,20 @@ fn parse_variable(source: &'static Sexpr<'static>, variable: &str) -> Result<Var
 mod tests {
 
     use reader::Reader;
+    use std::rc::Rc;
 
     use super::*;
 
     #[test]
     fn test_parse_parameters() {
         let input = "(a b &rest c)";
-        let context = Box::leak(Box::new(reader::Context::new(
-            input,
-            "test_parse_parameters",
-        )));
-        let mut reader = Reader::new(context);
-        let sexpr = Box::leak(Box::new(reader.next().unwrap().unwrap()));
+        let context = Rc::new(reader::Context::new(input, "test_parse_parameters"));
+        let mut reader = Reader::new(&context);
+        let sexpr = reader.next().unwrap().unwrap();
         let list = sexpr.as_list().unwrap();
-        let parameters = parse_parameters(sexpr, list).unwrap();
+        let parameters = Compiler::new()
+            .parse_parameters(&sexpr, list, &mut Vec::new())
+            .unwrap();
 
         match parameters {
             Parameters::Rest(..) => (),
@@ -1220,18 +2643,34 @@ mod tests {
     #[test]
     fn test_parse_only_rest_parameters() {
         let input = "(&rest c)";
-        let context = Box::leak(Box::new(reader::Context::new(
-            input,
-            "test_parse_parameters",
-        )));
-        let mut reader = Reader::new(context);
-        let sexpr = Box::leak(Box::new(reader.next().unwrap().unwrap()));
+        let context = Rc::new(reader::Context::new(input, "test_parse_parameters"));
+        let mut reader = Reader::new(&context);
+        let sexpr = reader.next().unwrap().unwrap();
         let list = sexpr.as_list().unwrap();
-        let parameters = parse_parameters(sexpr, list).unwrap();
+        let parameters = Compiler::new()
+            .parse_parameters(&sexpr, list, &mut Vec::new())
+            .unwrap();
 
         match parameters {
             Parameters::Rest(params, _) if params.is_empty() => (),
             _ => panic!(),
         };
     }
+
+    #[test]
+    fn test_parse_optional_parameters() {
+        let input = "(a &optional b (c 1))";
+        let context = Rc::new(reader::Context::new(input, "test_parse_parameters"));
+        let mut reader = Reader::new(&context);
+        let sexpr = reader.next().unwrap().unwrap();
+        let list = sexpr.as_list().unwrap();
+        let parameters = Compiler::new()
+            .parse_parameters(&sexpr, list, &mut Vec::new())
+            .unwrap();
+
+        assert!(matches!(
+            parameters,
+            Parameters::Optional(params, optional) if params.len() == 1 && optional.len() == 2
+        ));
+    }
 }
@@ -3,24 +3,32 @@ mod optimizer;
 use crate::il::{self, Il};
 use core::fmt;
 use gc::Gc;
-use reader::Sexpr;
+use reader::Span;
 use vm::{OpCode, OpCodeTable};
 
 #[derive(Clone, Debug)]
 pub struct Error {
-    il: Il,
+    span: Span,
     message: String,
 }
 
+impl Error {
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
 impl fmt::Display for Error {
-    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
-        todo!()
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = format!("error: {}", self.message);
+
+        write!(f, "{}", self.span.render_diagnostic(&message))
     }
 }
 
 impl std::error::Error for Error {}
 
-pub fn compile(il: &Il, opcodes: &mut OpCodeTable<&'static Sexpr<'static>>) -> Result<(), Error> {
+pub fn compile(il: &Il, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     match il {
         Il::Module(module) => compile_module(module, opcodes),
         Il::Lambda(lambda) => compile_lambda(lambda, opcodes),
@@ -30,6 +38,8 @@ pub fn compile(il: &Il, opcodes: &mut OpCodeTable<&'static Sexpr<'static>>) -> R
         Il::Cons(cons) => compile_cons(cons, opcodes),
         Il::Car(car) => compile_car(car, opcodes),
         Il::Cdr(cdr) => compile_cdr(cdr, opcodes),
+        Il::SetCar(set_car) => compile_set_car(set_car, opcodes),
+        Il::SetCdr(set_cdr) => compile_set_cdr(set_cdr, opcodes),
         Il::VarRef(varref) => compile_varref(varref, opcodes),
         Il::Constant(constant) => compile_constant(constant, opcodes),
         Il::List(list) => compile_list(list, opcodes),
@@ -43,49 +53,92 @@ pub fn compile(il: &Il, opcodes: &mut OpCodeTable<&'static Sexpr<'static>>) -> R
         Il::MapInsert(map_insert) => compile_map_insert(map_insert, opcodes),
         Il::MapRetrieve(map_retrieve) => compile_map_retrieve(map_retrieve, opcodes),
         Il::MapItems(map_items) => compile_map_items(map_items, opcodes),
+        Il::Gensym(gensym) => compile_gensym(gensym, opcodes),
+        Il::Documentation(documentation) => compile_documentation(documentation, opcodes),
+        Il::Match(r#match) => compile_match(r#match, opcodes),
+        Il::Case(case) => compile_case(case, opcodes),
+        Il::Loop(r#loop) => compile_loop(r#loop, opcodes),
     }
 }
 
-fn compile_module(
-    module: &il::Module,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+// Checks that every jump in freshly compiled bytecode lands inside the table it
+// belongs to. Top level code may jump to the end of its table and fall through
+// to the next form, lambda bodies must always land on an opcode.
+pub fn verify(il: &Il, opcodes: &[OpCode<Span>]) -> Result<(), Error> {
+    verify_table(il, opcodes, opcodes.len())
+}
+
+fn verify_table(il: &Il, opcodes: &[OpCode<Span>], end: usize) -> Result<(), Error> {
+    for (index, opcode) in opcodes.iter().enumerate() {
+        let offsets = match opcode {
+            OpCode::Branch(offset) => vec![*offset as isize],
+            OpCode::Jmp(offset) => vec![*offset],
+            OpCode::JumpTable(table) => table
+                .offsets
+                .iter()
+                .chain([&table.default])
+                .map(|offset| *offset as isize)
+                .collect(),
+            OpCode::Lambda { body, .. } => {
+                verify_table(il, body.opcodes(), body.len() - 1)?;
+                continue;
+            }
+            _ => continue,
+        };
+
+        for offset in offsets {
+            if !(0..=end as isize).contains(&(index as isize + 1 + offset)) {
+                return Err(Error {
+                    span: il.source_ast().source_span().clone(),
+                    message: format!("jump at {index} lands outside of its table"),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn compile_module(module: &il::Module, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     opcodes.push(
         OpCode::CreateModule(Gc::new(module.name.clone())),
-        module.source.source_sexpr(),
+        module.source.source_span().clone(),
     );
 
     opcodes.push(
         OpCode::DefGlobal(Gc::new(module.name.clone())),
-        module.source.source_sexpr(),
+        module.source.source_span().clone(),
     );
 
     Ok(())
 }
 
-fn compile_varref(
-    varref: &il::VarRef,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_varref(varref: &il::VarRef, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     match varref {
         il::VarRef::Local { index, .. } => {
-            opcodes.push(OpCode::GetLocal(*index), varref.source().source_sexpr());
+            opcodes.push(
+                OpCode::GetLocal(*index),
+                varref.source().source_span().clone(),
+            );
         }
         il::VarRef::UpValue { index, .. } => {
-            opcodes.push(OpCode::GetUpValue(*index), varref.source().source_sexpr());
+            opcodes.push(
+                OpCode::GetUpValue(*index),
+                varref.source().source_span().clone(),
+            );
         }
         il::VarRef::Global { name, .. } => opcodes.push(
             OpCode::GetGlobal(Gc::new(name.clone())),
-            varref.source().source_sexpr(),
+            varref.source().source_span().clone(),
         ),
         il::VarRef::Module { name, module, .. } => {
             opcodes.push(
                 OpCode::GetGlobal(Gc::new(module.clone())),
-                varref.source().source_sexpr(),
+                varref.source().source_span().clone(),
             );
             opcodes.push(
                 OpCode::GetModuleVar(Gc::new(name.clone())),
-                varref.source().source_sexpr(),
+                varref.source().source_span().clone(),
             );
         }
     };
@@ -93,10 +146,7 @@ fn compile_varref(
     Ok(())
 }
 
-fn compile_constant(
-    constant: &il::Constant,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_constant(constant: &il::Constant, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     let op = match constant {
         il::Constant::Symbol { symbol, .. } => OpCode::PushSymbol(Gc::new(symbol.clone())),
         il::Constant::String { string, .. } => OpCode::PushString(Gc::new(string.clone())),
@@ -106,22 +156,35 @@ fn compile_constant(
         il::Constant::Nil { .. } => OpCode::PushNil,
     };
 
-    opcodes.push(op, constant.source().source_sexpr());
+    opcodes.push(op, constant.source().source_span().clone());
 
     Ok(())
 }
 
-fn compile_lambda(
-    lambda: &il::Lambda,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_lambda(lambda: &il::Lambda, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     let mut lambda_opcode_table = OpCodeTable::new();
 
+    // missing optional arguments are nil until the prologue stores their defaults
+    for (index, default) in &lambda.optional {
+        let source = lambda.source.source_span();
+        let mut default_opcodes = OpCodeTable::new();
+
+        compile(default, &mut default_opcodes)?;
+
+        lambda_opcode_table.push(OpCode::PushInt(*index as i64 + 1), source.clone());
+        lambda_opcode_table.push(OpCode::ArgCount, source.clone());
+        lambda_opcode_table.push(OpCode::Gt, source.clone());
+        lambda_opcode_table.push(OpCode::Branch(default_opcodes.len() + 2), source.clone());
+        lambda_opcode_table.append(default_opcodes);
+        lambda_opcode_table.push(OpCode::SetLocal(*index), source.clone());
+        lambda_opcode_table.push(OpCode::Pop, source.clone());
+    }
+
     for expr in &lambda.body {
         compile(expr, &mut lambda_opcode_table)?;
     }
 
-    lambda_opcode_table.push(OpCode::Return, lambda.source.source_sexpr());
+    lambda_opcode_table.push(OpCode::Return, lambda.source.source_span().clone());
 
     let optimized_opcode_table = optimizer::optimize(&lambda_opcode_table);
 
@@ -129,24 +192,284 @@ fn compile_lambda(
         OpCode::Lambda {
             arity: lambda.arity,
             body: Gc::new(optimized_opcode_table),
+            doc: lambda.doc.clone().map(Gc::new),
         },
-        lambda.source.source_sexpr(),
+        lambda.source.source_span().clone(),
     );
 
     for upvalue in &lambda.upvalues {
         opcodes.push(
             vm::OpCode::CreateUpValue(*upvalue),
-            lambda.source.source_sexpr(),
+            lambda.source.source_span().clone(),
         );
     }
 
     Ok(())
 }
 
-fn compile_if(
-    r#if: &il::If,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
+// Clauses are assembled back to front so that every test knows how far it has
+// to branch to reach the next clause, and every body how far to jump to the end.
+fn compile_match(r#match: &il::Match, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
+    let source = r#match.source.source_span();
+    let mut rest = OpCodeTable::new();
+
+    rest.push(OpCode::PushNil, source.clone());
+
+    for clause in r#match.clauses.iter().rev() {
+        let mut block = OpCodeTable::new();
+
+        compile(&clause.body, &mut block)?;
+        block.push(OpCode::Jmp(rest.len() as isize), source.clone());
+
+        if let Some(guard) = &clause.guard {
+            let mut guarded = OpCodeTable::new();
+            compile(guard, &mut guarded)?;
+            guarded.push(OpCode::Branch(block.len()), source.clone());
+            guarded.append(block);
+            block = guarded;
+        }
+
+        let mut tests = Vec::new();
+
+        compile_pattern(
+            &r#match.scrutinee,
+            &clause.pattern,
+            &mut Vec::new(),
+            &mut tests,
+        )?;
+
+        for mut test in tests.into_iter().rev() {
+            test.push(OpCode::Branch(block.len()), source.clone());
+            test.append(block);
+            block = test;
+        }
+
+        block.append(rest);
+        rest = block;
+    }
+
+    opcodes.append(rest);
+
+    Ok(())
+}
+
+// Each test loads the part of the scrutinee at path and leaves a bool, a cons
+// pattern is checked to be a cons before anything inside of it is tested.
+fn compile_pattern(
+    scrutinee: &Il,
+    pattern: &il::Pattern,
+    path: &mut Vec<OpCode<Span>>,
+    tests: &mut Vec<OpCodeTable<Span>>,
+) -> Result<(), Error> {
+    let source = scrutinee.source_ast().source_span();
+
+    match pattern {
+        il::Pattern::Wildcard => (),
+        il::Pattern::Literal(literal) => {
+            let mut test = compile_path(scrutinee, path)?;
+            compile(literal, &mut test)?;
+            test.push(OpCode::Eq, source.clone());
+            tests.push(test);
+        }
+        il::Pattern::Cons(car, cdr) => {
+            let mut test = compile_path(scrutinee, path)?;
+            test.push(OpCode::IsType(vm::object::Type::Cons), source.clone());
+            tests.push(test);
+
+            path.push(OpCode::Car);
+            compile_pattern(scrutinee, car, path, tests)?;
+            path.pop();
+
+            path.push(OpCode::Cdr);
+            compile_pattern(scrutinee, cdr, path, tests)?;
+            path.pop();
+        }
+    }
+
+    Ok(())
+}
+
+fn compile_path(scrutinee: &Il, path: &[OpCode<Span>]) -> Result<OpCodeTable<Span>, Error> {
+    let mut opcodes = OpCodeTable::new();
+
+    compile(scrutinee, &mut opcodes)?;
+
+    for opcode in path {
+        opcodes.push(opcode.clone(), scrutinee.source_ast().source_span().clone());
+    }
+
+    Ok(opcodes)
+}
+
+// Cases with enough int keys that are close together are dispatched through a
+// jump table, anything else compares the scrutinee against each key in turn.
+fn compile_case(case: &il::Case, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
+    let source = case.source.source_span();
+    let mut default = OpCodeTable::new();
+
+    match &case.default {
+        Some(body) => compile(body, &mut default)?,
+        None => default.push(OpCode::PushNil, source.clone()),
+    }
+
+    match jump_table_range(case) {
+        Some((min, len)) => compile_jump_table(case, min, len, default, opcodes),
+        None => compile_case_chain(case, default, opcodes),
+    }
+}
+
+const MIN_JUMP_TABLE_KEYS: usize = 4;
+
+fn jump_table_range(case: &il::Case) -> Option<(i64, usize)> {
+    let keys = case
+        .clauses
+        .iter()
+        .flat_map(|clause| clause.keys.iter())
+        .map(|key| match key {
+            Il::Constant(il::Constant::Int { int, .. }) => Some(*int),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let min = *keys.iter().min()?;
+    let max = *keys.iter().max()?;
+    let len = usize::try_from(max.checked_sub(min)?).ok()? + 1;
+
+    (keys.len() >= MIN_JUMP_TABLE_KEYS && len <= keys.len() * 2).then_some((min, len))
+}
+
+fn compile_jump_table(
+    case: &il::Case,
+    min: i64,
+    len: usize,
+    default: OpCodeTable<Span>,
+    opcodes: &mut OpCodeTable<Span>,
+) -> Result<(), Error> {
+    let source = case.source.source_span();
+    let mut offsets = vec![None; len];
+    let mut bodies = Vec::new();
+    let mut start = 0;
+
+    for clause in &case.clauses {
+        for key in &clause.keys {
+            if let Il::Constant(il::Constant::Int { int, .. }) = key {
+                // the first clause with a key wins
+                offsets[(int - min) as usize].get_or_insert(start);
+            }
+        }
+
+        let mut body = OpCodeTable::new();
+        compile(&clause.body, &mut body)?;
+
+        // each body is followed by a jump past everything after it
+        start += body.len() + 1;
+        bodies.push(body);
+    }
+
+    let end = start + default.len();
+
+    compile(&case.scrutinee, opcodes)?;
+    opcodes.push(
+        OpCode::JumpTable(Gc::new(vm::JumpTable {
+            min,
+            offsets: offsets
+                .into_iter()
+                .map(|offset| offset.unwrap_or(start))
+                .collect(),
+            default: start,
+        })),
+        source.clone(),
+    );
+
+    let mut start = 0;
+
+    for body in bodies {
+        start += body.len() + 1;
+        opcodes.append(body);
+        opcodes.push(OpCode::Jmp((end - start) as isize), source.clone());
+    }
+
+    opcodes.append(default);
+
+    Ok(())
+}
+
+fn compile_case_chain(
+    case: &il::Case,
+    default: OpCodeTable<Span>,
+    opcodes: &mut OpCodeTable<Span>,
 ) -> Result<(), Error> {
+    let source = case.source.source_span();
+    let mut rest = default;
+
+    for clause in case.clauses.iter().rev() {
+        if clause.keys.is_empty() {
+            continue;
+        }
+
+        let mut block = OpCodeTable::new();
+
+        compile(&clause.body, &mut block)?;
+        block.push(OpCode::Jmp(rest.len() as isize), source.clone());
+
+        // the last key skips the body when it doesn't match, the ones before it
+        // jump over the remaining keys into the body when they do
+        let mut tests = OpCodeTable::new();
+
+        for (i, key) in clause.keys.iter().rev().enumerate() {
+            let mut test = OpCodeTable::new();
+
+            compile(&case.scrutinee, &mut test)?;
+            compile(key, &mut test)?;
+            test.push(OpCode::Eq, source.clone());
+
+            if i == 0 {
+                test.push(OpCode::Branch(block.len()), source.clone());
+            } else {
+                test.push(OpCode::Branch(1), source.clone());
+                test.push(OpCode::Jmp(tests.len() as isize), source.clone());
+            }
+
+            test.append(tests);
+            tests = test;
+        }
+
+        tests.append(block);
+        tests.append(rest);
+        rest = tests;
+    }
+
+    opcodes.append(rest);
+
+    Ok(())
+}
+
+// The value of each expression in the body is popped so that the stack doesn't
+// grow with every iteration.
+fn compile_loop(r#loop: &il::Loop, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
+    let source = r#loop.source.source_span();
+    let mut test = OpCodeTable::new();
+    let mut body = OpCodeTable::new();
+
+    compile(&r#loop.test, &mut test)?;
+
+    for expr in &r#loop.body {
+        compile(expr, &mut body)?;
+        body.push(OpCode::Pop, source.clone());
+    }
+
+    let back = -((test.len() + body.len() + 2) as isize);
+
+    test.push(OpCode::Branch(body.len() + 1), source.clone());
+    opcodes.append(test);
+    opcodes.append(body);
+    opcodes.push(OpCode::Jmp(back), source.clone());
+    opcodes.push(OpCode::PushNil, source.clone());
+
+    Ok(())
+}
+
+fn compile_if(r#if: &il::If, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     let mut then_opcodes = OpCodeTable::new();
     let mut else_opcodes = OpCodeTable::new();
 
@@ -156,12 +479,12 @@ fn compile_if(
 
     opcodes.push(
         OpCode::Branch(then_opcodes.len() + 1),
-        r#if.source.source_sexpr(),
+        (r#if.source.source_span()).clone(),
     );
 
     then_opcodes.push(
         OpCode::Jmp(else_opcodes.len() as isize),
-        r#if.source.source_sexpr(),
+        (r#if.source.source_span()).clone(),
     );
 
     opcodes.append(then_opcodes);
@@ -170,27 +493,24 @@ fn compile_if(
     Ok(())
 }
 
-fn compile_def(
-    def: &il::Def,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_def(def: &il::Def, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(def.body(), opcodes)?;
 
     match def {
         il::Def::Global { parameter, .. } => opcodes.push(
             OpCode::DefGlobal(Gc::new(parameter.name.clone())),
-            def.source().source_sexpr(),
+            def.source().source_span().clone(),
         ),
         il::Def::Module {
             parameter, module, ..
         } => {
             opcodes.push(
                 OpCode::GetGlobal(Gc::new(module.clone())),
-                def.source().source_sexpr(),
+                def.source().source_span().clone(),
             );
             opcodes.push(
                 OpCode::DefModuleVar(Gc::new(parameter.name.clone())),
-                def.source().source_sexpr(),
+                def.source().source_span().clone(),
             );
         }
     }
@@ -198,33 +518,30 @@ fn compile_def(
     Ok(())
 }
 
-fn compile_set(
-    set: &il::Set,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_set(set: &il::Set, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(&set.body, opcodes)?;
 
     match &set.target {
         il::VarRef::Local { index, .. } => {
-            opcodes.push(OpCode::SetLocal(*index), set.source.source_sexpr());
+            opcodes.push(OpCode::SetLocal(*index), set.source.source_span().clone());
         }
         il::VarRef::UpValue { index, .. } => {
-            opcodes.push(OpCode::SetUpValue(*index), set.source.source_sexpr());
+            opcodes.push(OpCode::SetUpValue(*index), set.source.source_span().clone());
         }
         il::VarRef::Global { name, .. } => {
             opcodes.push(
                 OpCode::SetGlobal(Gc::new(name.clone())),
-                set.source.source_sexpr(),
+                set.source.source_span().clone(),
             );
         }
         il::VarRef::Module { name, module, .. } => {
             opcodes.push(
                 OpCode::GetGlobal(Gc::new(module.clone())),
-                set.source.source_sexpr(),
+                set.source.source_span().clone(),
             );
             opcodes.push(
                 OpCode::SetModuleVar(Gc::new(name.clone())),
-                set.source.source_sexpr(),
+                set.source.source_span().clone(),
             );
         }
     };
@@ -234,19 +551,23 @@ fn compile_set(
 
 fn compile_arithmetic_operation(
     arithmetic_op: &il::ArithmeticOperation,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
+    opcodes: &mut OpCodeTable<Span>,
 ) -> Result<(), Error> {
     compile(&arithmetic_op.lhs, opcodes)?;
     compile(&arithmetic_op.rhs, opcodes)?;
 
     opcodes.push(
-        match arithmetic_op.operator {
-            il::ArithmeticOperator::Add => OpCode::Add,
-            il::ArithmeticOperator::Sub => OpCode::Sub,
-            il::ArithmeticOperator::Mul => OpCode::Mul,
-            il::ArithmeticOperator::Div => OpCode::Div,
+        match (&arithmetic_op.operator, arithmetic_op.ints.get()) {
+            (il::ArithmeticOperator::Add, false) => OpCode::Add,
+            (il::ArithmeticOperator::Sub, false) => OpCode::Sub,
+            (il::ArithmeticOperator::Mul, false) => OpCode::Mul,
+            (il::ArithmeticOperator::Div, false) => OpCode::Div,
+            (il::ArithmeticOperator::Add, true) => OpCode::AddInt,
+            (il::ArithmeticOperator::Sub, true) => OpCode::SubInt,
+            (il::ArithmeticOperator::Mul, true) => OpCode::MulInt,
+            (il::ArithmeticOperator::Div, true) => OpCode::DivInt,
         },
-        arithmetic_op.source.source_sexpr(),
+        arithmetic_op.source.source_span().clone(),
     );
 
     Ok(())
@@ -254,7 +575,7 @@ fn compile_arithmetic_operation(
 
 fn compile_comparison_operation(
     comparison_op: &il::ComparisonOperation,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
+    opcodes: &mut OpCodeTable<Span>,
 ) -> Result<(), Error> {
     compile(&comparison_op.lhs, opcodes)?;
     compile(&comparison_op.rhs, opcodes)?;
@@ -265,29 +586,26 @@ fn compile_comparison_operation(
             il::ComparisonOperator::Lt => OpCode::Lt,
             il::ComparisonOperator::Gt => OpCode::Gt,
         },
-        comparison_op.source.source_sexpr(),
+        comparison_op.source.source_span().clone(),
     );
 
     Ok(())
 }
 
-fn compile_list(
-    list: &il::List,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_list(list: &il::List, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     for expr in &list.exprs {
         compile(expr, opcodes)?;
     }
 
-    opcodes.push(OpCode::List(list.exprs.len()), list.source.source_sexpr());
+    opcodes.push(
+        OpCode::List(list.exprs.len()),
+        list.source.source_span().clone(),
+    );
 
     Ok(())
 }
 
-fn compile_fncall(
-    fncall: &il::FnCall,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_fncall(fncall: &il::FnCall, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(&fncall.function, opcodes)?;
 
     for arg in &fncall.args {
@@ -296,50 +614,56 @@ fn compile_fncall(
 
     opcodes.push(
         OpCode::Call(fncall.args.len()),
-        fncall.source.source_sexpr(),
+        fncall.source.source_span().clone(),
     );
 
     Ok(())
 }
 
-fn compile_cons(
-    cons: &il::Cons,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_cons(cons: &il::Cons, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(&cons.lhs, opcodes)?;
     compile(&cons.rhs, opcodes)?;
 
-    opcodes.push(OpCode::Cons, cons.source.source_sexpr());
+    opcodes.push(OpCode::Cons, cons.source.source_span().clone());
 
     Ok(())
 }
 
-fn compile_car(
-    car: &il::Car,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_car(car: &il::Car, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(&car.body, opcodes)?;
 
-    opcodes.push(OpCode::Car, car.source.source_sexpr());
+    opcodes.push(OpCode::Car, car.source.source_span().clone());
 
     Ok(())
 }
 
-fn compile_cdr(
-    cdr: &il::Cdr,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_set_car(set_car: &il::SetCar, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
+    compile(&set_car.cons, opcodes)?;
+    compile(&set_car.body, opcodes)?;
+
+    opcodes.push(OpCode::SetCar, set_car.source.source_span().clone());
+
+    Ok(())
+}
+
+fn compile_set_cdr(set_cdr: &il::SetCdr, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
+    compile(&set_cdr.cons, opcodes)?;
+    compile(&set_cdr.body, opcodes)?;
+
+    opcodes.push(OpCode::SetCdr, set_cdr.source.source_span().clone());
+
+    Ok(())
+}
+
+fn compile_cdr(cdr: &il::Cdr, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(&cdr.body, opcodes)?;
 
-    opcodes.push(OpCode::Cdr, cdr.source.source_sexpr());
+    opcodes.push(OpCode::Cdr, cdr.source.source_span().clone());
 
     Ok(())
 }
 
-fn compile_is_type(
-    is_type: &il::IsType,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_is_type(is_type: &il::IsType, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(&is_type.body, opcodes)?;
 
     let vm_type = match is_type.r#type {
@@ -353,75 +677,95 @@ fn compile_is_type(
         il::IsTypeParameter::Nil => vm::object::Type::Nil,
     };
 
-    opcodes.push(OpCode::IsType(vm_type), is_type.source.source_sexpr());
+    opcodes.push(
+        OpCode::IsType(vm_type),
+        is_type.source.source_span().clone(),
+    );
 
     Ok(())
 }
 
-fn compile_apply(
-    apply: &il::Apply,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_apply(apply: &il::Apply, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(&apply.function, opcodes)?;
     compile(&apply.list, opcodes)?;
 
-    opcodes.push(OpCode::Apply, apply.source.source_sexpr());
+    opcodes.push(OpCode::Apply, apply.source.source_span().clone());
 
     Ok(())
 }
 
-fn compile_assert(
-    assert: &il::Assert,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
-) -> Result<(), Error> {
+fn compile_assert(assert: &il::Assert, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
     compile(&assert.body, opcodes)?;
 
-    opcodes.push(OpCode::Assert, assert.source.source_sexpr());
+    opcodes.push(OpCode::Assert, assert.source.source_span().clone());
 
     Ok(())
 }
 
 fn compile_map_create(
     map_create: &il::MapCreate,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
+    opcodes: &mut OpCodeTable<Span>,
 ) -> Result<(), Error> {
-    opcodes.push(OpCode::MapCreate, map_create.source.source_sexpr());
+    opcodes.push(OpCode::MapCreate, map_create.source.source_span().clone());
+
+    Ok(())
+}
+
+fn compile_gensym(gensym: &il::Gensym, opcodes: &mut OpCodeTable<Span>) -> Result<(), Error> {
+    opcodes.push(OpCode::Gensym, gensym.source.source_span().clone());
 
     Ok(())
 }
 
 fn compile_map_insert(
     map_insert: &il::MapInsert,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
+    opcodes: &mut OpCodeTable<Span>,
 ) -> Result<(), Error> {
     compile(&map_insert.map, opcodes)?;
     compile(&map_insert.key, opcodes)?;
     compile(&map_insert.value, opcodes)?;
 
-    opcodes.push(OpCode::MapInsert, map_insert.source.source_sexpr());
+    opcodes.push(OpCode::MapInsert, map_insert.source.source_span().clone());
 
     Ok(())
 }
 
 fn compile_map_retrieve(
     map_retrieve: &il::MapRetrieve,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
+    opcodes: &mut OpCodeTable<Span>,
 ) -> Result<(), Error> {
     compile(&map_retrieve.map, opcodes)?;
     compile(&map_retrieve.key, opcodes)?;
 
-    opcodes.push(OpCode::MapRetrieve, map_retrieve.source.source_sexpr());
+    opcodes.push(
+        OpCode::MapRetrieve,
+        map_retrieve.source.source_span().clone(),
+    );
 
     Ok(())
 }
 
 fn compile_map_items(
     map_items: &il::MapItems,
-    opcodes: &mut OpCodeTable<&'static Sexpr<'static>>,
+    opcodes: &mut OpCodeTable<Span>,
 ) -> Result<(), Error> {
     compile(&map_items.map, opcodes)?;
 
-    opcodes.push(OpCode::MapItems, map_items.source.source_sexpr());
+    opcodes.push(OpCode::MapItems, map_items.source.source_span().clone());
+
+    Ok(())
+}
+
+fn compile_documentation(
+    documentation: &il::Documentation,
+    opcodes: &mut OpCodeTable<Span>,
+) -> Result<(), Error> {
+    compile(&documentation.function, opcodes)?;
+
+    opcodes.push(
+        OpCode::Documentation,
+        documentation.source.source_span().clone(),
+    );
 
     Ok(())
 }
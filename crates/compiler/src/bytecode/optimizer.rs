@@ -21,9 +21,9 @@ pub(super) fn tco<D: Clone>(opcode_table: &OpCodeTable<D>) -> OpCodeTable<D> {
                 optimized.push(OpCode::Tail(*args), opcode_table.debug()[index].clone());
             }
             (OpCode::Call(args), Some(OpCode::Jmp(jmp)))
-                if opcode_table
-                    .opcodes()
-                    .get(index + *jmp as usize + 2)
+                if (index + 2)
+                    .checked_add_signed(*jmp)
+                    .and_then(|target| opcode_table.opcodes().get(target))
                     .is_some_and(|opcode| opcode.is_return()) =>
             {
                 optimized.push(OpCode::Tail(*args), opcode_table.debug()[index].clone())
@@ -0,0 +1,61 @@
+use reader::Span;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WarningKind {
+    UnusedVariable,
+    UnusedParameter,
+    Shadowing,
+    Inference,
+}
+
+// Something that compiled but probably isn't what was meant. Compilers collect
+// these as they go and callers drain them with take_warnings, separately from
+// errors which stop compilation.
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn file(&self) -> &str {
+        self.span.context().display()
+    }
+
+    pub fn line_and_column(&self) -> (usize, usize) {
+        self.span.context().line_and_column(self.span.range().start)
+    }
+}
+
+impl WarningKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningKind::UnusedVariable => "unused-variable",
+            WarningKind::UnusedParameter => "unused-parameter",
+            WarningKind::Shadowing => "shadowing",
+            WarningKind::Inference => "inference",
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (line, column) = self.line_and_column();
+
+        write!(
+            f,
+            "{}:{line}:{column}: warning: {} [{}]",
+            self.file(),
+            self.message,
+            self.kind.name()
+        )
+    }
+}
+
+// Bindings that are unused or shadowed on purpose start with an underscore,
+// and ones a macro introduced were renamed to a gensym the user never wrote.
+pub(crate) fn is_exempt(name: &str) -> bool {
+    name.starts_with('_') || name.starts_with("#:")
+}
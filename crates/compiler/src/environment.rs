@@ -1,12 +1,12 @@
 use crate::types::Type;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use vm::UpValue;
 
 #[derive(Clone, Debug)]
 pub(crate) enum Variable {
     Local(usize, Option<Type>),
     Upvalue(usize, Option<Type>),
-    Module(Option<Type>),
+    Module(String, Option<Type>),
     Global(Option<Type>),
 }
 
@@ -25,11 +25,22 @@ pub(crate) struct ModuleVar {
 #[derive(Clone, Debug)]
 struct Module(HashMap<String, ModuleVar>);
 
+// What require brought into a module, or into the top level.
+#[derive(Clone, Debug, Default)]
+struct Imports {
+    aliases: HashMap<String, String>,
+    // Modules required with :only, and the names that can be used from them.
+    only: HashMap<String, HashSet<String>>,
+    // Names usable without a module prefix, and the module they came from.
+    names: HashMap<String, String>,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct Environment {
     globals: HashMap<String, Option<Type>>,
     modules: HashMap<String, Module>,
     current_module: Option<String>,
+    imports: HashMap<Option<String>, Imports>,
     scopes: Vec<Scope>,
 }
 
@@ -69,6 +80,7 @@ impl Environment {
             globals: HashMap::new(),
             modules: HashMap::new(),
             current_module: None,
+            imports: HashMap::new(),
             scopes: Vec::new(),
         }
     }
@@ -88,6 +100,14 @@ impl Environment {
         self.scopes.pop().unwrap();
     }
 
+    // Whether name is bound by any enclosing lambda, unlike resolve this
+    // doesn't capture it.
+    pub(crate) fn is_local(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope.get_local(name).is_some())
+    }
+
     #[allow(clippy::manual_map)]
     pub(crate) fn resolve(&mut self, name: &str) -> Option<Variable> {
         if let Some(scope) = self.scopes.last()
@@ -111,18 +131,14 @@ impl Environment {
             let i = self.scopes.last().unwrap().upvalues.len() - 1;
 
             Some(Variable::Upvalue(i, r#type))
-        } else if let Some(module) = &self.current_module {
-            self.modules
-                .get(module)
-                .unwrap()
-                .0
-                .get(name)
-                .map(|module_var| Variable::Module(module_var.r#type.clone()))
-                .or_else(|| {
-                    self.globals
-                        .get(name)
-                        .map(|r#type| Variable::Global(r#type.clone()))
-                })
+        } else if let Some(module) = &self.current_module
+            && let Some(module_var) = self.modules.get(module).unwrap().0.get(name)
+        {
+            Some(Variable::Module(module.clone(), module_var.r#type.clone()))
+        } else if let Some(module) = self.imports().and_then(|imports| imports.names.get(name))
+            && let Some(module_var) = self.resolve_module_var(module, name)
+        {
+            Some(Variable::Module(module.clone(), module_var.r#type))
         } else {
             self.globals
                 .get(name)
@@ -165,14 +181,80 @@ impl Environment {
             .and_then(|module| module.0.get(name).cloned())
     }
 
-    pub(crate) fn export_module_var(&mut self, module: &str, name: &str) {
-        self.modules
-            .get_mut(module)
-            .unwrap()
-            .0
-            .get_mut(name)
-            .unwrap()
-            .visible = true;
+    pub(crate) fn global_type(&self, name: &str) -> Option<Type> {
+        self.globals.get(name).cloned().flatten()
+    }
+
+    pub(crate) fn module_vars(&self, module: &str) -> Option<Vec<(String, ModuleVar)>> {
+        self.modules.get(module).map(|module| {
+            module
+                .0
+                .iter()
+                .map(|(name, module_var)| (name.clone(), module_var.clone()))
+                .collect()
+        })
+    }
+
+    // Every (module, name) a module has exported.
+    pub(crate) fn exports(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.modules.iter().flat_map(|(module, vars)| {
+            vars.0
+                .iter()
+                .filter(|(_, var)| var.visible)
+                .map(move |(name, _)| (module.as_str(), name.as_str()))
+        })
+    }
+
+    pub(crate) fn has_module(&self, module: &str) -> bool {
+        self.modules.contains_key(module)
+    }
+
+    // The module a prefix refers to, after aliases.
+    pub(crate) fn resolve_module_name<'a>(&'a self, module: &'a str) -> &'a str {
+        self.imports()
+            .and_then(|imports| imports.aliases.get(module))
+            .map_or(module, |module| module.as_str())
+    }
+
+    // Whether the current module can use a module's exported name, which it
+    // can unless it required the module with :only and left the name out.
+    pub(crate) fn is_imported(&self, module: &str, name: &str) -> bool {
+        self.imports()
+            .and_then(|imports| imports.only.get(module))
+            .is_none_or(|names| names.contains(name))
+    }
+
+    pub(crate) fn import(&mut self, module: &str, alias: Option<&str>, only: Option<&[String]>) {
+        let imports = self.imports.entry(self.current_module.clone()).or_default();
+
+        if let Some(alias) = alias {
+            imports.aliases.insert(alias.to_string(), module.to_string());
+        }
+
+        if let Some(only) = only {
+            imports
+                .only
+                .insert(module.to_string(), only.iter().cloned().collect());
+
+            for name in only {
+                imports.names.insert(name.clone(), module.to_string());
+            }
+        }
+    }
+
+    fn imports(&self) -> Option<&Imports> {
+        self.imports.get(&self.current_module)
+    }
+
+    // Returns false if the module doesn't define the name.
+    pub(crate) fn export_module_var(&mut self, module: &str, name: &str) -> bool {
+        match self.modules.get_mut(module).unwrap().0.get_mut(name) {
+            Some(module_var) => {
+                module_var.visible = true;
+                true
+            }
+            None => false,
+        }
     }
 
     pub(crate) fn set_current_module(&mut self, module: Option<&str>) {
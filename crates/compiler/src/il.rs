@@ -1,21 +1,52 @@
 use crate::{
     ast::{self, Ast, Quoted},
     bytecode,
+    diagnostics::{self, Warning, WarningKind},
     environment::{self, Environment, ModuleVar, Variable},
     il,
-    types::Type,
+    interface::Interface,
+    macros,
+    typecheck,
+    types::{Signature, Type},
 };
-use reader::{Reader, Sexpr};
+use gc::Gc;
+use reader::{Reader, Sexpr, Span};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 use unwrap_enum::{EnumAs, EnumIs};
-use vm::{Arity, OpCodeTable, UpValue, Vm};
+use vm::{Arity, OpCode, OpCodeTable, UpValue, Vm};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("il compiler error: {message}")]
-    Il { ast: Ast, message: String },
+    #[error("{}", .span.render_diagnostic(&format!("error: {message}")))]
+    Il { span: Span, message: String },
+
+    #[error("{}", .span.render_diagnostic(&format!("type error: {message}")))]
+    Type { span: Span, message: String },
+
+    // A call with the wrong number of arguments for the function it calls,
+    // shown along with where that was defined.
+    #[error(
+        "{}\n{}",
+        .span.render_diagnostic(&format!("error: {message}")),
+        .definition.render_diagnostic("note: defined here")
+    )]
+    Arity {
+        span: Span,
+        definition: Span,
+        message: String,
+    },
+
+    // A set! of a global that was never defined or declared, which lint
+    // reports as a rule of its own.
+    #[error("{}", .span.render_diagnostic("error: unknown variable"))]
+    UndeclaredSet { span: Span, name: String },
 
-    #[error("reader error: {0}")]
-    Reader(#[from] reader::Error<'static>),
+    #[error("{0}")]
+    Reader(#[from] reader::Error),
 
     #[error("ast error: {0}")]
     Ast(#[from] ast::Error),
@@ -26,11 +57,25 @@ pub enum Error {
     #[error("vm error: {0}")]
     Vm(#[from] vm::Error),
 
-    #[error("vm error: {sexpr}")]
-    VmWithDebug {
-        error: vm::Error,
-        sexpr: &'static Sexpr<'static>,
-    },
+    #[error("{}", .span.render_diagnostic(&format!("error: {error}")))]
+    VmWithDebug { error: vm::Error, span: Span },
+}
+
+impl Error {
+    // Where in the source the error is, None for a vm error without debug info.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Self::Il { span, .. }
+            | Self::Type { span, .. }
+            | Self::Arity { span, .. }
+            | Self::UndeclaredSet { span, .. }
+            | Self::VmWithDebug { span, .. } => Some(span),
+            Self::Reader(error) => error.span(),
+            Self::Ast(error) => Some(error.span()),
+            Self::Bytecode(error) => Some(error.span()),
+            Self::Vm(_) => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, EnumAs, EnumIs)]
@@ -48,10 +93,17 @@ pub enum Il {
     Cons(Cons),
     Car(Car),
     Cdr(Cdr),
+    SetCar(SetCar),
+    SetCdr(SetCdr),
     MapCreate(MapCreate),
     MapInsert(MapInsert),
     MapRetrieve(MapRetrieve),
     MapItems(MapItems),
+    Gensym(Gensym),
+    Documentation(Documentation),
+    Match(Match),
+    Case(Case),
+    Loop(Loop),
     IsType(IsType),
     Assert(Assert),
     VarRef(VarRef),
@@ -74,6 +126,17 @@ pub enum Constant {
     Nil { source: Ast },
 }
 
+// The value of a defconst, which is put in place of every reference to it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConstantValue {
+    Symbol(String),
+    String(String),
+    Char(char),
+    Int(i64),
+    Bool(bool),
+    Nil,
+}
+
 #[derive(Clone, Debug)]
 pub enum VarRef {
     Local {
@@ -121,6 +184,8 @@ pub struct Lambda {
     pub r#type: Option<Type>,
     pub arity: Arity,
     pub upvalues: Vec<UpValue>,
+    pub optional: Vec<(usize, Il)>,
+    pub doc: Option<String>,
     pub body: Vec<Il>,
 }
 
@@ -171,6 +236,20 @@ pub struct Cdr {
     pub body: Box<Il>,
 }
 
+#[derive(Clone, Debug)]
+pub struct SetCar {
+    pub source: Ast,
+    pub cons: Box<Il>,
+    pub body: Box<Il>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SetCdr {
+    pub source: Ast,
+    pub cons: Box<Il>,
+    pub body: Box<Il>,
+}
+
 #[derive(Clone, Debug)]
 pub enum ArithmeticOperator {
     Add,
@@ -185,6 +264,8 @@ pub struct ArithmeticOperation {
     pub operator: ArithmeticOperator,
     pub lhs: Box<Il>,
     pub rhs: Box<Il>,
+    // Set by the type checker once it has proven both operands are ints.
+    pub ints: Cell<bool>,
 }
 
 #[derive(Clone, Debug)]
@@ -229,6 +310,59 @@ pub struct MapCreate {
     pub source: Ast,
 }
 
+#[derive(Clone, Debug)]
+pub struct Gensym {
+    pub source: Ast,
+}
+
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub source: Ast,
+    pub scrutinee: Box<Il>,
+    pub clauses: Vec<MatchClause>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MatchClause {
+    pub pattern: Pattern,
+    pub guard: Option<Il>,
+    pub body: Il,
+}
+
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    Wildcard,
+    Literal(Box<Il>),
+    Cons(Box<Pattern>, Box<Pattern>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Case {
+    pub source: Ast,
+    pub scrutinee: Box<Il>,
+    pub clauses: Vec<CaseClause>,
+    pub default: Option<Box<Il>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CaseClause {
+    pub keys: Vec<Il>,
+    pub body: Il,
+}
+
+#[derive(Clone, Debug)]
+pub struct Loop {
+    pub source: Ast,
+    pub test: Box<Il>,
+    pub body: Vec<Il>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Documentation {
+    pub source: Ast,
+    pub function: Box<Il>,
+}
+
 #[derive(Clone, Debug)]
 pub struct MapInsert {
     pub source: Ast,
@@ -275,8 +409,109 @@ pub struct Assert {
     pub body: Box<Il>,
 }
 
+// Stricter checks a file opts into with declaim. They're reset at the start of
+// every file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pragmas {
+    pub strict_arity: bool,
+    pub warnings_as_errors: bool,
+    pub require_decl: bool,
+}
+
 pub struct Compiler {
     environment: Environment,
+    stats: Stats,
+    definitions: Vec<Definition>,
+    pragmas: Pragmas,
+    // Functions defined or declared at the top level, keyed by module, for
+    // strict-arity and the type checker.
+    signatures: HashMap<(Option<String>, String), Signature>,
+    // Where the functions with signatures from a def were defined, their calls
+    // are checked against it whether or not strict-arity is on.
+    definition_sites: HashMap<(Option<String>, String), Span>,
+    decls: HashSet<String>,
+    constants: HashMap<String, ConstantValue>,
+    warnings: Vec<Warning>,
+    // The outermost macro call being compiled. Code a macro expanded to has no
+    // location of its own, so warnings about it point here instead.
+    expansion_site: Option<Ast>,
+    // Set while a guarded match clause compiles. Its guard and its body each
+    // bind the pattern's names with a lambda of their own, so what those leave
+    // unused is kept here, a name only has to be used by one of them.
+    unused_bindings: Option<Vec<(String, Ast)>>,
+    errors: Vec<Error>,
+    // Directories require looks in for <module>.lisp, in order.
+    search_path: Vec<PathBuf>,
+    // Modules require has already compiled, so each is only compiled once.
+    required: HashSet<String>,
+    cache_dir: Option<PathBuf>,
+    // Part of the key of every module cached, so that modules compiled after a
+    // different prelude, which may have had different macros, aren't reused.
+    cache_key: u64,
+    recording: Option<Recording>,
+    // Everything run at compile time, nested modules and all, while kept.
+    kept: Option<Vec<OpCodeTable<Span>>>,
+    options: CompilerOptions,
+    dumps: Vec<Dump>,
+}
+
+// What compiling a file did besides producing its bytecode, kept while a module
+// is compiled so that it can be cached and loaded again without compiling it.
+#[derive(Debug, Default)]
+pub struct Recording {
+    // Code that was run while compiling, such as macro definitions.
+    pub compile_time: Vec<OpCodeTable<Span>>,
+    // Globals the file declared or defined outside of a module.
+    pub globals: Vec<String>,
+    // The modules the file required, and where the code of those compiled
+    // along the way sits in the opcode table, since it isn't part of the file.
+    pub requires: Vec<String>,
+    pub nested: Vec<std::ops::Range<usize>>,
+}
+
+// Everything defined while compiling, after macro expansion, drained by tools
+// that index source files.
+#[derive(Clone, Debug)]
+pub struct Definition {
+    pub source: Ast,
+    pub kind: DefinitionKind,
+    pub name: String,
+    pub module: Option<String>,
+    pub r#type: Option<ast::Type>,
+    pub parameters: Option<ast::Parameters>,
+    pub return_type: Option<ast::Type>,
+    pub doc: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Function,
+    Variable,
+    Macro,
+    Decl,
+    Constant,
+}
+
+// Counters accumulated while compiling, drained by callers that want to report
+// on where compile time goes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub macro_expansion: Duration,
+    pub nodes: usize,
+}
+
+// What to keep of each top level form as it's compiled, for tools that show
+// the intermediate representations. Kept until take_dumps.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompilerOptions {
+    pub dump_ast: bool,
+    pub dump_il: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum Dump {
+    Ast(String),
+    Il(String),
 }
 
 impl VarRef {
@@ -320,10 +555,17 @@ impl Il {
             | Self::Cons(Cons { source, .. })
             | Self::Car(Car { source, .. })
             | Self::Cdr(Cdr { source, .. })
+            | Self::SetCar(SetCar { source, .. })
+            | Self::SetCdr(SetCdr { source, .. })
             | Self::MapCreate(MapCreate { source, .. })
             | Self::MapInsert(MapInsert { source, .. })
             | Self::MapRetrieve(MapRetrieve { source, .. })
             | Self::MapItems(MapItems { source, .. })
+            | Self::Gensym(Gensym { source, .. })
+            | Self::Documentation(Documentation { source, .. })
+            | Self::Match(Match { source, .. })
+            | Self::Case(Case { source, .. })
+            | Self::Loop(Loop { source, .. })
             | Self::IsType(IsType { source, .. })
             | Self::Assert(Assert { source, .. })
             | Self::VarRef(VarRef::Local { source, .. })
@@ -338,6 +580,115 @@ impl Il {
             | Self::Constant(Constant::Nil { source, .. }) => source,
         }
     }
+
+    // The nodes directly under this one, in evaluation order where there is one.
+    pub fn children(&self) -> Vec<&Il> {
+        match self {
+            Self::Module(_)
+            | Self::MapCreate(_)
+            | Self::Gensym(_)
+            | Self::VarRef(_)
+            | Self::Constant(_) => Vec::new(),
+            Self::Lambda(lambda) => lambda
+                .optional
+                .iter()
+                .map(|(_, default)| default)
+                .chain(lambda.body.iter())
+                .collect(),
+            Self::If(r#if) => vec![&r#if.predicate, &r#if.then, &r#if.r#else],
+            Self::Apply(apply) => vec![&apply.function, &apply.list],
+            Self::Def(Def::Global { body, .. } | Def::Module { body, .. })
+            | Self::Set(Set { body, .. })
+            | Self::Car(Car { body, .. })
+            | Self::Cdr(Cdr { body, .. })
+            | Self::IsType(IsType { body, .. })
+            | Self::Assert(Assert { body, .. }) => vec![body],
+            Self::FnCall(fncall) => std::iter::once(&*fncall.function)
+                .chain(fncall.args.iter())
+                .collect(),
+            Self::ArithmeticOperation(ArithmeticOperation { lhs, rhs, .. })
+            | Self::ComparisonOperation(ComparisonOperation { lhs, rhs, .. })
+            | Self::Cons(Cons { lhs, rhs, .. }) => vec![lhs, rhs],
+            Self::List(list) => list.exprs.iter().collect(),
+            Self::SetCar(SetCar { cons, body, .. }) | Self::SetCdr(SetCdr { cons, body, .. }) => {
+                vec![cons, body]
+            }
+            Self::MapInsert(map_insert) => {
+                vec![&map_insert.map, &map_insert.key, &map_insert.value]
+            }
+            Self::MapRetrieve(map_retrieve) => vec![&map_retrieve.map, &map_retrieve.key],
+            Self::MapItems(map_items) => vec![&map_items.map],
+            Self::Documentation(documentation) => vec![&documentation.function],
+            Self::Match(r#match) => {
+                let mut children = vec![&*r#match.scrutinee];
+                for clause in &r#match.clauses {
+                    children.extend(clause.pattern.literals());
+                    children.extend(clause.guard.as_ref());
+                    children.push(&clause.body);
+                }
+                children
+            }
+            Self::Case(case) => {
+                let mut children = vec![&*case.scrutinee];
+                for clause in &case.clauses {
+                    children.extend(clause.keys.iter());
+                    children.push(&clause.body);
+                }
+                children.extend(case.default.as_deref());
+                children
+            }
+            Self::Loop(r#loop) => std::iter::once(&*r#loop.test)
+                .chain(r#loop.body.iter())
+                .collect(),
+        }
+    }
+}
+
+impl Lambda {
+    // Indices of the locals that are read somewhere in the lambda, including
+    // by the lambdas nested in it that capture them.
+    fn used_locals(&self) -> HashSet<usize> {
+        fn walk(il: &Il, used: &mut HashSet<usize>) {
+            match il {
+                Il::VarRef(VarRef::Local { index, .. }) => {
+                    used.insert(*index);
+                }
+                Il::Lambda(nested) => {
+                    used.extend(nested.upvalues.iter().filter_map(|upvalue| match upvalue {
+                        UpValue::Local(i) => Some(*i),
+                        UpValue::UpValue(_) => None,
+                    }));
+                }
+                il => {
+                    for child in il.children() {
+                        walk(child, used);
+                    }
+                }
+            }
+        }
+
+        let mut used = HashSet::new();
+
+        for (_, default) in &self.optional {
+            walk(default, &mut used);
+        }
+
+        for il in &self.body {
+            walk(il, &mut used);
+        }
+
+        used
+    }
+}
+
+impl Pattern {
+    fn literals(&self) -> Vec<&Il> {
+        match self {
+            Self::Wildcard => Vec::new(),
+            Self::Literal(literal) => vec![literal],
+            Self::Cons(car, cdr) => car.literals().into_iter().chain(cdr.literals()).collect(),
+        }
+    }
 }
 
 impl Parameter {
@@ -370,6 +721,13 @@ impl Parameters {
                     .map(|param| Parameter::from_ast(source, param))
                     .collect::<Result<Vec<Parameter>, _>>()?,
             ),
+            ast::Parameters::Optional(params, optional) => Parameters::Nary(
+                params
+                    .iter()
+                    .chain(optional.iter().map(|(param, _)| param))
+                    .map(|param| Parameter::from_ast(source, param))
+                    .collect::<Result<Vec<Parameter>, _>>()?,
+            ),
         })
     }
 
@@ -392,189 +750,640 @@ impl Def {
     }
 }
 
+impl ConstantValue {
+    pub(crate) fn to_constant(&self, source: Ast) -> Constant {
+        match self {
+            Self::Symbol(symbol) => Constant::Symbol {
+                source,
+                symbol: symbol.clone(),
+            },
+            Self::String(string) => Constant::String {
+                source,
+                string: string.clone(),
+            },
+            Self::Char(char) => Constant::Char {
+                source,
+                char: *char,
+            },
+            Self::Int(int) => Constant::Int { source, int: *int },
+            Self::Bool(bool) => Constant::Bool {
+                source,
+                bool: *bool,
+            },
+            Self::Nil => Constant::Nil { source },
+        }
+    }
+
+    pub(crate) fn r#type(&self) -> Type {
+        match self {
+            Self::Symbol(_) => Type::Symbol,
+            Self::String(_) => Type::String,
+            Self::Char(_) => Type::Char,
+            Self::Int(_) => Type::Int,
+            Self::Bool(_) => Type::Bool,
+            Self::Nil => Type::Nil,
+        }
+    }
+}
+
+impl From<&Constant> for ConstantValue {
+    fn from(constant: &Constant) -> Self {
+        match constant {
+            Constant::Symbol { symbol, .. } => Self::Symbol(symbol.clone()),
+            Constant::String { string, .. } => Self::String(string.clone()),
+            Constant::Char { char, .. } => Self::Char(*char),
+            Constant::Int { int, .. } => Self::Int(*int),
+            Constant::Bool { bool, .. } => Self::Bool(*bool),
+            Constant::Nil { .. } => Self::Nil,
+        }
+    }
+}
+
+impl<D> TryFrom<&vm::Object<D>> for ConstantValue {
+    type Error = ();
+
+    fn try_from(object: &vm::Object<D>) -> Result<Self, Self::Error> {
+        Ok(match object {
+            vm::Object::Symbol(symbol) => Self::Symbol(symbol.to_string()),
+            vm::Object::String(string) => Self::String(string.to_string()),
+            vm::Object::Char(char) => Self::Char(*char),
+            vm::Object::Int(int) => Self::Int(*int),
+            vm::Object::Bool(bool) => Self::Bool(*bool),
+            vm::Object::Nil => Self::Nil,
+            _ => return Err(()),
+        })
+    }
+}
+
 impl Compiler {
     pub fn new() -> Self {
         Self {
             environment: Environment::new(),
+            stats: Stats::default(),
+            definitions: Vec::new(),
+            pragmas: Pragmas::default(),
+            signatures: HashMap::new(),
+            definition_sites: HashMap::new(),
+            decls: HashSet::new(),
+            constants: HashMap::new(),
+            warnings: Vec::new(),
+            expansion_site: None,
+            unused_bindings: None,
+            errors: Vec::new(),
+            search_path: Vec::new(),
+            required: HashSet::new(),
+            cache_dir: None,
+            cache_key: 0,
+            recording: None,
+            kept: None,
+            options: CompilerOptions::default(),
+            dumps: Vec::new(),
         }
     }
 
-    pub fn set_current_module(&mut self, module: Option<&str>) {
-        self.environment.set_current_module(module);
+    pub fn set_cache_dir(&mut self, dir: Option<&Path>) {
+        self.cache_dir = dir.map(Path::to_path_buf);
     }
 
-    pub fn compile(
-        &mut self,
-        ast: &Ast,
-        vm: &mut Vm<&'static Sexpr<'static>>,
-        ast_compiler: &mut ast::Compiler,
-    ) -> Result<Il, Error> {
-        match ast {
-            Ast::Module(module) => self.compile_module(ast, module),
-            Ast::Require(_) => panic!("requires should be handled outside of the il compiler"),
-            Ast::EvalWhenCompile(eval_when_compile) => {
-                self.eval_when_compile(ast, eval_when_compile, vm, ast_compiler)
-            }
-            Ast::DefMacro(defmacro) => self.compile_defmacro(ast, defmacro, vm, ast_compiler),
-            Ast::Lambda(lambda) => self.compile_lambda(ast, lambda, vm, ast_compiler),
-            Ast::Def(def) => self.compile_def(ast, def, vm, ast_compiler),
-            Ast::Decl(decl) => self.compile_decl(ast, decl),
-            Ast::Set(set) => self.compile_set(ast, set, vm, ast_compiler),
-            Ast::If(r#if) => self.compile_if(ast, r#if, vm, ast_compiler),
-            Ast::MacroCall(macro_call) => self.eval_macro(ast, macro_call, vm, ast_compiler),
-            Ast::FnCall(fncall) => self.compile_fncall(ast, fncall, vm, ast_compiler),
-            Ast::Quote(quote) => self.compile_quoted(ast, &quote.body),
-            Ast::Apply(apply) => self.compile_apply(ast, apply, vm, ast_compiler),
-            Ast::BinaryArithemticOperation(op) => {
-                self.compile_arithmetic_operation(ast, op, vm, ast_compiler)
-            }
-            Ast::ComparisonOperation(op) => {
-                self.compile_comparison_operation(ast, op, vm, ast_compiler)
-            }
-            Ast::List(list) => self.compile_list(ast, list, vm, ast_compiler),
-            Ast::Cons(cons) => self.compile_cons(ast, cons, vm, ast_compiler),
-            Ast::Car(car) => self.compile_car(ast, car, vm, ast_compiler),
-            Ast::Cdr(cdr) => self.compile_cdr(ast, cdr, vm, ast_compiler),
-            Ast::IsType(is_type) => self.compile_is_type(ast, is_type, vm, ast_compiler),
-            Ast::MapCreate(_) => self.compile_map_create(ast),
-            Ast::MapInsert(map_insert) => {
-                self.compile_map_insert(ast, map_insert, vm, ast_compiler)
-            }
-            Ast::MapRetrieve(map_retrieve) => {
-                self.compile_map_retrieve(ast, map_retrieve, vm, ast_compiler)
-            }
-            Ast::MapItems(map_items) => self.compile_map_items(ast, map_items, vm, ast_compiler),
-            Ast::Assert(assert) => self.compile_assert(ast, assert, vm, ast_compiler),
-            Ast::Export(export) => self.compile_export(ast, export),
-            Ast::Constant(constant) => self.compile_constant(ast, constant),
-            Ast::Variable(variable) => self.compile_variable_reference(ast, variable),
-        }
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
     }
 
-    fn compile_module(&mut self, source: &Ast, module: &ast::Module) -> Result<Il, Error> {
-        self.environment.create_module(module.name.as_str());
-        self.set_current_module(Some(module.name.as_str()));
+    pub fn set_cache_key(&mut self, key: u64) {
+        self.cache_key = key;
+    }
 
-        Ok(Il::Module(Module {
-            source: source.clone(),
-            name: module.name.clone(),
-        }))
+    pub fn cache_key(&self) -> u64 {
+        self.cache_key
     }
 
-    fn eval_when_compile(
-        &mut self,
-        source: &Ast,
-        eval_when_compile: &ast::EvalWhenCompile,
-        vm: &mut Vm<&'static Sexpr<'static>>,
-        ast_compiler: &mut ast::Compiler,
-    ) -> Result<Il, Error> {
-        let mut opcode_table = OpCodeTable::new();
+    // Starts recording what compiling does into a new recording, or stops, and
+    // returns the one that was in progress.
+    pub fn replace_recording(&mut self, recording: Option<Recording>) -> Option<Recording> {
+        std::mem::replace(&mut self.recording, recording)
+    }
 
-        for expr in &eval_when_compile.exprs {
-            let il = Box::leak(Box::new(self.compile(expr, vm, ast_compiler)?));
+    pub fn recording_mut(&mut self) -> Option<&mut Recording> {
+        self.recording.as_mut()
+    }
+
+    // Starts keeping a copy of everything run at compile time, or stops. Unlike
+    // a recording this isn't per file, it's for writing out a whole program.
+    pub fn set_keep_compile_time(&mut self, keep: bool) {
+        self.kept = keep.then(Vec::new);
+    }
 
-            bytecode::compile(il, &mut opcode_table)?;
+    pub fn keep_compile_time(&mut self, opcodes: &OpCodeTable<Span>) {
+        if let Some(kept) = &mut self.kept {
+            kept.push(opcodes.clone());
         }
+    }
 
-        vm.eval(&opcode_table)
-            .map_err(|(error, sexpr)| Error::VmWithDebug { error, sexpr })?;
+    pub fn take_compile_time(&mut self) -> Vec<OpCodeTable<Span>> {
+        self.kept.as_mut().map(std::mem::take).unwrap_or_default()
+    }
 
-        Ok(Il::Constant(Constant::Nil {
-            source: source.clone(),
-        }))
+    // Runs code at compile time, keeping it if a recording is in progress.
+    fn eval_now(&mut self, opcodes: OpCodeTable<Span>, vm: &mut Vm<Span>) -> Result<(), Error> {
+        vm.eval(&opcodes)
+            .map_err(|(error, span)| Error::VmWithDebug { error, span })?;
+
+        self.keep_compile_time(&opcodes);
+
+        if let Some(recording) = &mut self.recording {
+            recording.compile_time.push(opcodes);
+        }
+
+        Ok(())
     }
 
-    fn compile_constant(&mut self, source: &Ast, constant: &ast::Constant) -> Result<Il, Error> {
-        Ok(match constant {
-            ast::Constant::String { string, .. } => Il::Constant(Constant::String {
-                source: source.clone(),
-                string: string.clone(),
-            }),
-            ast::Constant::Char { char, .. } => Il::Constant(Constant::Char {
-                source: source.clone(),
-                char: *char,
-            }),
-            ast::Constant::Int { int, .. } => Il::Constant(Constant::Int {
-                source: source.clone(),
-                int: *int,
-            }),
-            ast::Constant::Bool { bool, .. } => Il::Constant(Constant::Bool {
-                source: source.clone(),
-                bool: *bool,
-            }),
-            ast::Constant::Nil { .. } => Il::Constant(Constant::Nil {
-                source: source.clone(),
-            }),
+    fn insert_global(&mut self, name: &str, r#type: Option<Type>) {
+        self.environment.insert_global(name, r#type);
+
+        if let Some(recording) = &mut self.recording {
+            recording.globals.push(name.to_string());
+        }
+    }
+
+    // Everything another file needs to compile against a module, None if there
+    // is no such module.
+    pub fn interface(
+        &self,
+        module: &str,
+        recording: &Recording,
+        ast_compiler: &ast::Compiler,
+    ) -> Option<Interface> {
+        let globals = recording
+            .globals
+            .iter()
+            .map(|name| (name.clone(), self.environment.global_type(name)))
+            .collect::<Vec<_>>();
+
+        Some(Interface {
+            module: module.to_string(),
+            vars: self.environment.module_vars(module)?,
+            signatures: self
+                .signatures
+                .iter()
+                .filter(|((key_module, name), _)| match key_module {
+                    Some(key_module) => key_module == module,
+                    None => globals.iter().any(|(global, _)| global == name),
+                })
+                .map(|(key, signature)| (key.clone(), signature.clone()))
+                .collect(),
+            constants: globals
+                .iter()
+                .filter_map(|(name, _)| Some((name.clone(), self.constants.get(name)?.clone())))
+                .collect(),
+            globals,
+            macros: ast_compiler.module_macros(module),
+            exported_macros: ast_compiler.exported_module_macros(module),
         })
     }
 
-    fn compile_variable_reference(
-        &mut self,
-        source: &Ast,
-        variable: &ast::Variable,
-    ) -> Result<Il, Error> {
-        Ok(match variable {
-            ast::Variable::WithoutModule { name, .. } => {
-                match self.environment.resolve(name.as_str()) {
-                    Some(environment::Variable::Local(index, r#type)) => {
-                        Il::VarRef(VarRef::Local {
-                            source: source.clone(),
-                            name: name.clone(),
-                            index,
-                            r#type,
-                        })
-                    }
-                    Some(environment::Variable::Upvalue(index, r#type)) => {
-                        Il::VarRef(VarRef::UpValue {
-                            source: source.clone(),
+    // The reverse of interface, for a module loaded from the cache.
+    pub fn load_interface(&mut self, interface: &Interface, ast_compiler: &mut ast::Compiler) {
+        let module = interface.module.as_str();
+
+        self.environment.create_module(module);
+
+        for (name, module_var) in &interface.vars {
+            self.environment
+                .insert_module_var(module, name, module_var.r#type.clone());
+
+            if module_var.visible {
+                self.environment.export_module_var(module, name);
+            }
+        }
+
+        for (name, r#type) in &interface.globals {
+            self.environment.insert_global(name, r#type.clone());
+        }
+
+        self.constants.extend(interface.constants.iter().cloned());
+
+        self.signatures.extend(interface.signatures.iter().cloned());
+
+        ast_compiler.load_module_macros(module, &interface.macros, &interface.exported_macros);
+    }
+
+    pub fn add_search_path(&mut self, path: &Path) {
+        self.search_path.push(path.to_path_buf());
+    }
+
+    pub fn search_path(&self) -> &[PathBuf] {
+        &self.search_path
+    }
+
+    // Returns false if the module was already required.
+    pub fn mark_required(&mut self, module: &str) -> bool {
+        self.required.insert(module.to_string())
+    }
+
+    // Every module required so far, sorted.
+    pub fn required_modules(&self) -> Vec<&str> {
+        let mut modules = self.required.iter().map(String::as_str).collect::<Vec<_>>();
+        modules.sort();
+        modules
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn warn(&mut self, kind: WarningKind, ast: &Ast, message: String) -> Result<(), Error> {
+        let ast = self.expansion_site.as_ref().unwrap_or(ast);
+
+        if self.pragmas.warnings_as_errors {
+            return Err(Error::Il {
+                span: ast.source_span().clone(),
+                message,
+            });
+        }
+
+        self.warnings.push(Warning {
+            kind,
+            span: ast.source_span().clone(),
+            message,
+        });
+
+        Ok(())
+    }
+
+    // Checks annotated call sites, arithmetic and branches in il, which should
+    // be a top-level form this compiler produced.
+    pub fn check_types(&self, il: &Il) -> Result<(), Error> {
+        typecheck::check(il, &self.signatures).map(|_| ())
+    }
+
+    // The type check_types works out for il, written the way declaim writes
+    // it, or None if it's unknown.
+    pub fn type_of(&self, il: &Il) -> Result<Option<String>, Error> {
+        typecheck::check(il, &self.signatures)
+            .map(|r#type| r#type.map(|r#type| r#type.to_string()))
+    }
+
+    pub fn pragmas(&self) -> Pragmas {
+        self.pragmas
+    }
+
+    // Returns the pragmas that were in effect so they can be restored once a
+    // nested file is done compiling.
+    pub fn replace_pragmas(&mut self, pragmas: Pragmas) -> Pragmas {
+        std::mem::replace(&mut self.pragmas, pragmas)
+    }
+
+    pub fn take_definitions(&mut self) -> Vec<Definition> {
+        std::mem::take(&mut self.definitions)
+    }
+
+    pub fn take_stats(&mut self) -> Stats {
+        std::mem::take(&mut self.stats)
+    }
+
+    pub fn set_options(&mut self, options: CompilerOptions) {
+        self.options = options;
+    }
+
+    pub fn take_dumps(&mut self) -> Vec<Dump> {
+        std::mem::take(&mut self.dumps)
+    }
+
+    // The arity of a function as last defined or declared, module is None for
+    // globals.
+    pub fn signature_arity(&self, module: Option<&str>, name: &str) -> Option<Arity> {
+        self.signatures
+            .get(&(module.map(str::to_string), name.to_string()))
+            .map(|signature| signature.arity)
+    }
+
+    // module::name for everything every module has exported.
+    pub fn exports(&self) -> Vec<String> {
+        self.environment
+            .exports()
+            .map(|(module, name)| format!("{module}::{name}"))
+            .collect()
+    }
+
+    pub fn set_current_module(&mut self, module: Option<&str>) {
+        self.environment.set_current_module(module);
+    }
+
+    pub fn current_module(&self) -> Option<&str> {
+        self.environment.current_module()
+    }
+
+    // Makes a module's alias, :only names and exported macros usable from the
+    // current module, once the module has been compiled.
+    pub fn import(
+        &mut self,
+        require: &ast::Require,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<(), Error> {
+        let module = require.module.as_str();
+        let error = |message| Error::Il {
+            span: require.source.clone(),
+            message,
+        };
+
+        if (require.alias.is_some() || require.only.is_some())
+            && !self.environment.has_module(module)
+        {
+            return Err(error(format!("{module}.lisp doesn't define a module called {module}")));
+        }
+
+        for name in require.only.iter().flatten() {
+            if !ast_compiler.exports_macro(module, name)
+                && !self
+                    .environment
+                    .resolve_module_var(module, name)
+                    .is_some_and(|module_var| module_var.visible)
+            {
+                return Err(error(format!("{module} doesn't export {name}")));
+            }
+        }
+
+        self.environment
+            .import(module, require.alias.as_deref(), require.only.as_deref());
+        ast_compiler.import(require);
+
+        if let Some(recording) = &mut self.recording {
+            recording.requires.push(module.to_string());
+        }
+
+        Ok(())
+    }
+
+    // Compiles a top level form, carrying on past errors in its expressions so
+    // that all of them are returned.
+    pub fn compile(
+        &mut self,
+        ast: &Ast,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Vec<Error>> {
+        if self.options.dump_ast {
+            self.dumps.push(Dump::Ast(ast.to_pretty_string()));
+        }
+
+        let il = self.compile_expr(ast, vm, ast_compiler);
+
+        if self.errors.is_empty() {
+            if self.options.dump_il {
+                self.dumps.push(Dump::Il(il.to_pretty_string()));
+            }
+
+            Ok(il)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    // Errors are recorded and nil stands in for the expression.
+    fn compile_expr(
+        &mut self,
+        ast: &Ast,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Il {
+        match self.compile_form(ast, vm, ast_compiler) {
+            Ok(il) => il,
+            Err(error) => {
+                self.errors.push(error);
+                Il::Constant(Constant::Nil {
+                    source: ast.clone(),
+                })
+            }
+        }
+    }
+
+    // For code that has to run while compiling, which can't go ahead once any
+    // of it failed to compile. The rest of the errors stay recorded.
+    fn failed_since(&mut self, errors: usize) -> Result<(), Error> {
+        match self.errors.len() > errors {
+            true => Err(self.errors.pop().unwrap()),
+            false => Ok(()),
+        }
+    }
+
+    fn compile_ast(
+        &mut self,
+        sexpr: &Sexpr,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Ast, Error> {
+        ast_compiler.compile(sexpr).map_err(|errors| {
+            let mut errors = errors.into_iter().map(Error::from).collect::<Vec<_>>();
+            let last = errors.pop().unwrap();
+            self.errors.extend(errors);
+            last
+        })
+    }
+
+    fn compile_form(
+        &mut self,
+        ast: &Ast,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        self.stats.nodes += 1;
+
+        match ast {
+            Ast::Module(module) => self.compile_module(ast, module),
+            Ast::Require(_) => panic!("requires should be handled outside of the il compiler"),
+            Ast::EvalWhenCompile(eval_when_compile) => {
+                self.eval_when_compile(ast, eval_when_compile, vm, ast_compiler)
+            }
+            Ast::DefMacro(defmacro) => self.compile_defmacro(ast, defmacro, vm, ast_compiler),
+            Ast::Lambda(lambda) => {
+                self.compile_lambda(ast, lambda, WarningKind::UnusedParameter, vm, ast_compiler)
+            }
+            Ast::Def(def) => self.compile_def(ast, def, vm, ast_compiler),
+            Ast::DefConst(defconst) => self.compile_defconst(ast, defconst, vm, ast_compiler),
+            Ast::Decl(decl) => self.compile_decl(ast, decl),
+            Ast::Declaim(declaim) => self.compile_declaim(ast, declaim),
+            Ast::Set(set) => self.compile_set(ast, set, vm, ast_compiler),
+            Ast::If(r#if) => self.compile_if(ast, r#if, vm, ast_compiler),
+            Ast::MacroCall(macro_call) => self.eval_macro(ast, macro_call, vm, ast_compiler),
+            Ast::FnCall(fncall) => self.compile_fncall(ast, fncall, vm, ast_compiler),
+            Ast::Quote(quote) => self.compile_quoted(ast, &quote.body, vm, ast_compiler),
+            Ast::Apply(apply) => self.compile_apply(ast, apply, vm, ast_compiler),
+            Ast::BinaryArithemticOperation(op) => {
+                self.compile_arithmetic_operation(ast, op, vm, ast_compiler)
+            }
+            Ast::ComparisonOperation(op) => {
+                self.compile_comparison_operation(ast, op, vm, ast_compiler)
+            }
+            Ast::List(list) => self.compile_list(ast, list, vm, ast_compiler),
+            Ast::Cons(cons) => self.compile_cons(ast, cons, vm, ast_compiler),
+            Ast::Car(car) => self.compile_car(ast, car, vm, ast_compiler),
+            Ast::Cdr(cdr) => self.compile_cdr(ast, cdr, vm, ast_compiler),
+            Ast::SetCar(set_car) => self.compile_set_car(ast, set_car, vm, ast_compiler),
+            Ast::SetCdr(set_cdr) => self.compile_set_cdr(ast, set_cdr, vm, ast_compiler),
+            Ast::IsType(is_type) => self.compile_is_type(ast, is_type, vm, ast_compiler),
+            Ast::MapCreate(_) => self.compile_map_create(ast),
+            Ast::Gensym(_) => self.compile_gensym(ast),
+            Ast::Match(r#match) => self.compile_match(ast, r#match, vm, ast_compiler),
+            Ast::Case(case) => self.compile_case(ast, case, vm, ast_compiler),
+            Ast::Loop(r#loop) => self.compile_loop(ast, r#loop, vm, ast_compiler),
+            Ast::Documentation(documentation) => {
+                self.compile_documentation(ast, documentation, vm, ast_compiler)
+            }
+            Ast::MacroExpand(macroexpand) => {
+                self.compile_macroexpand(ast, macroexpand, vm, ast_compiler)
+            }
+            Ast::MapInsert(map_insert) => {
+                self.compile_map_insert(ast, map_insert, vm, ast_compiler)
+            }
+            Ast::MapRetrieve(map_retrieve) => {
+                self.compile_map_retrieve(ast, map_retrieve, vm, ast_compiler)
+            }
+            Ast::MapItems(map_items) => self.compile_map_items(ast, map_items, vm, ast_compiler),
+            Ast::Assert(assert) => self.compile_assert(ast, assert, vm, ast_compiler),
+            Ast::Export(export) => self.compile_export(ast, export),
+            Ast::Constant(constant) => self.compile_constant(ast, constant),
+            Ast::Variable(variable) => self.compile_variable_reference(ast, variable),
+        }
+    }
+
+    fn compile_module(&mut self, source: &Ast, module: &ast::Module) -> Result<Il, Error> {
+        self.environment.create_module(module.name.as_str());
+        self.set_current_module(Some(module.name.as_str()));
+
+        Ok(Il::Module(Module {
+            source: source.clone(),
+            name: module.name.clone(),
+        }))
+    }
+
+    fn eval_when_compile(
+        &mut self,
+        source: &Ast,
+        eval_when_compile: &ast::EvalWhenCompile,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        let mut opcode_table = OpCodeTable::new();
+
+        for expr in &eval_when_compile.exprs {
+            let errors = self.errors.len();
+            let il = self.compile_expr(expr, vm, ast_compiler);
+            self.failed_since(errors)?;
+
+            bytecode::compile(&il, &mut opcode_table)?;
+        }
+
+        self.eval_now(opcode_table, vm)?;
+
+        Ok(Il::Constant(Constant::Nil {
+            source: source.clone(),
+        }))
+    }
+
+    fn compile_constant(&mut self, source: &Ast, constant: &ast::Constant) -> Result<Il, Error> {
+        Ok(match constant {
+            ast::Constant::String { string, .. } => Il::Constant(Constant::String {
+                source: source.clone(),
+                string: string.clone(),
+            }),
+            ast::Constant::Char { char, .. } => Il::Constant(Constant::Char {
+                source: source.clone(),
+                char: *char,
+            }),
+            ast::Constant::Int { int, .. } => Il::Constant(Constant::Int {
+                source: source.clone(),
+                int: *int,
+            }),
+            ast::Constant::Bool { bool, .. } => Il::Constant(Constant::Bool {
+                source: source.clone(),
+                bool: *bool,
+            }),
+            ast::Constant::Nil { .. } => Il::Constant(Constant::Nil {
+                source: source.clone(),
+            }),
+        })
+    }
+
+    fn compile_variable_reference(
+        &mut self,
+        source: &Ast,
+        variable: &ast::Variable,
+    ) -> Result<Il, Error> {
+        Ok(match variable {
+            ast::Variable::WithoutModule { name, .. } => {
+                match self.environment.resolve(name.as_str()) {
+                    Some(environment::Variable::Local(index, r#type)) => {
+                        Il::VarRef(VarRef::Local {
+                            source: source.clone(),
+                            name: name.clone(),
+                            index,
+                            r#type,
+                        })
+                    }
+                    Some(environment::Variable::Upvalue(index, r#type)) => {
+                        Il::VarRef(VarRef::UpValue {
+                            source: source.clone(),
                             name: name.clone(),
                             index,
                             r#type,
                         })
                     }
+                    Some(environment::Variable::Global(_)) if self.constants.contains_key(name) => {
+                        Il::Constant(self.constants[name].to_constant(source.clone()))
+                    }
                     Some(environment::Variable::Global(r#type)) => Il::VarRef(VarRef::Global {
                         source: source.clone(),
                         name: name.clone(),
                         r#type,
                     }),
-                    Some(environment::Variable::Module(r#type)) => Il::VarRef(VarRef::Module {
-                        source: source.clone(),
-                        name: name.clone(),
-                        module: self
-                            .environment
-                            .current_module()
-                            .map(|s| s.to_string())
-                            .unwrap(),
-                        r#type,
-                    }),
+                    Some(environment::Variable::Module(module, r#type)) => {
+                        Il::VarRef(VarRef::Module {
+                            source: source.clone(),
+                            name: name.clone(),
+                            module,
+                            r#type,
+                        })
+                    }
                     None => {
                         return Err(Error::Il {
-                            ast: source.clone(),
+                            span: source.source_span().clone(),
                             message: format!("unknown variable referenced: {}", name),
                         })
                     }
                 }
             }
             ast::Variable::WithModule { name, module, .. } => {
+                let module = self.environment.resolve_module_name(module).to_string();
+                // a module can refer to its own private variables by their full name
+                let own_module = self.environment.current_module() == Some(module.as_str());
+                let imported = self.environment.is_imported(module.as_str(), name.as_str());
+
                 match self
                     .environment
                     .resolve_module_var(module.as_str(), name.as_str())
                 {
-                    Some(ModuleVar { r#type, visible }) if visible => Il::VarRef(VarRef::Module {
-                        source: source.clone(),
-                        name: name.clone(),
-                        module: module.to_string(),
-                        r#type,
-                    }),
+                    Some(ModuleVar { r#type, visible }) if own_module || visible && imported => {
+                        Il::VarRef(VarRef::Module {
+                            source: source.clone(),
+                            name: name.clone(),
+                            module,
+                            r#type,
+                        })
+                    }
+                    Some(ModuleVar { visible: true, .. }) => {
+                        return Err(Error::Il {
+                            span: source.source_span().clone(),
+                            message: format!("{module} was required without {name}"),
+                        })
+                    }
                     Some(ModuleVar { .. }) => {
                         return Err(Error::Il {
-                            ast: source.clone(),
+                            span: source.source_span().clone(),
                             message: "private module variable referenced".to_string(),
                         })
                     }
                     None => {
                         return Err(Error::Il {
-                            ast: source.clone(),
+                            span: source.source_span().clone(),
                             message: "unknown module variable referenced".to_string(),
                         })
                     }
@@ -587,47 +1396,70 @@ impl Compiler {
         &mut self,
         source: &Ast,
         defmacro: &ast::DefMacro,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
-        let arity = match &defmacro.parameters {
-            ast::Parameters::Normal(_) if defmacro.parameters.len() == 0 => Arity::Nullary,
-            ast::Parameters::Normal(_) => Arity::Nary(defmacro.parameters.len()),
-            ast::Parameters::Rest(..) => Arity::Variadic(defmacro.parameters.len() - 1),
-        };
+        let arity = arity(&defmacro.parameters);
+
+        self.definitions.push(Definition {
+            source: source.clone(),
+            kind: DefinitionKind::Macro,
+            name: defmacro.name.clone(),
+            module: None,
+            r#type: None,
+            parameters: Some(defmacro.parameters.clone()),
+            return_type: None,
+            doc: defmacro.doc.clone(),
+        });
 
         let parameters =
             Parameters::from_ast(source, &defmacro.parameters).map_err(|_| Error::Il {
-                ast: source.clone(),
+                span: source.source_span().clone(),
                 message: "failed to compile parameters".to_string(),
             })?;
 
+        let errors = self.errors.len();
+
         self.environment
             .push_scope(parameters.iter().map(|param| (param.name, param.r#type)));
 
+        let optional = self.compile_optional(&defmacro.parameters, vm, ast_compiler);
+
         let body = defmacro
             .body
             .iter()
-            .map(|ast| self.compile(ast, vm, ast_compiler))
-            .collect::<Result<Vec<Il>, Error>>()?;
+            .map(|ast| self.compile_expr(ast, vm, ast_compiler))
+            .collect::<Vec<Il>>();
+
+        self.environment.pop_scope();
+
+        // the macro runs while compiling, so it can't be defined with errors in it
+        self.failed_since(errors)?;
 
-        let lambda = Box::leak(Box::new(Il::Lambda(il::Lambda {
+        let lambda = Il::Lambda(il::Lambda {
             source: source.clone(),
             parameters,
             r#type: None,
             upvalues: Vec::new(),
             arity,
+            optional,
+            doc: defmacro.doc.clone(),
             body,
-        })));
+        });
 
         let mut opcodes = OpCodeTable::new();
 
-        bytecode::compile(lambda, &mut opcodes)?;
+        bytecode::compile(&lambda, &mut opcodes)?;
 
-        vm.eval(&opcodes)
-            .map_err(|(error, sexpr)| Error::VmWithDebug { error, sexpr })?;
+        opcodes.push(
+            OpCode::DefGlobal(Gc::new(ast::macro_global(
+                self.environment.current_module(),
+                defmacro.name.as_str(),
+            ))),
+            source.source_span().clone(),
+        );
 
-        vm.def_global(defmacro.name.as_str())?;
+        self.eval_now(opcodes, vm)?;
 
         Ok(Il::Constant(Constant::Nil {
             source: source.clone(),
@@ -638,180 +1470,516 @@ impl Compiler {
         &mut self,
         source: &Ast,
         macro_call: &ast::MacroCall,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
+        let start = Instant::now();
+
+        let args = self.eval_macro_args(source, macro_call, vm, ast_compiler)?;
+        let expansion = macros::call_macro(macro_call.r#macro.as_str(), &args, vm)?;
+        let expansion = macros::expand_all(expansion, vm, ast_compiler)?;
+        let object = macros::rename_introduced_bindings(expansion, &args, vm);
+        let sexpr = read_expansion(source, &object)?;
+        let ast = self.compile_ast(&sexpr, ast_compiler)?;
+
+        self.stats.macro_expansion += start.elapsed();
+
+        let outermost = self.expansion_site.is_none();
+
+        if outermost {
+            self.expansion_site = Some(source.clone());
+        }
+
+        let il = self.compile_form(&ast, vm, ast_compiler);
+
+        if outermost {
+            self.expansion_site = None;
+        }
+
+        il
+    }
+
+    fn eval_macro_args(
+        &mut self,
+        source: &Ast,
+        macro_call: &ast::MacroCall,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Vec<vm::Object<Span>>, Error> {
         let mut opcode_table = OpCodeTable::new();
 
         for arg in &macro_call.args {
-            let il = Box::leak(Box::new(self.compile_quoted(source, arg)?));
-            bytecode::compile(Box::leak(Box::new(il)), &mut opcode_table).unwrap();
+            let il = self.compile_quoted(source, arg, vm, ast_compiler)?;
+            bytecode::compile(&il, &mut opcode_table).unwrap();
         }
 
-        vm.get_global(macro_call.r#macro.as_str())?;
-
         vm.eval(&opcode_table)
-            .map_err(|(error, sexpr)| Error::VmWithDebug { error, sexpr })?;
+            .map_err(|(error, span)| Error::VmWithDebug { error, span })?;
 
-        vm.call(macro_call.args.len())?;
+        let mut args = (0..macro_call.args.len())
+            .map(|_| vm.pop().unwrap().into_object())
+            .collect::<Vec<_>>();
 
-        vm.eval(&OpCodeTable::new())
-            .map_err(|(error, sexpr)| Error::VmWithDebug { error, sexpr })?;
+        args.reverse();
 
-        let Some(object) = vm.pop().map(|local| local.into_object()) else {
-            return Ok(Il::Constant(Constant::Nil {
-                source: source.clone(),
-            }));
+        Ok(args)
+    }
+
+    // Expands sexpr once if it is a macro call, exactly as the macro produced
+    // it. Anything else is returned unchanged.
+    pub fn macroexpand_1(
+        &mut self,
+        sexpr: &Sexpr,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Sexpr, Error> {
+        Ok(self
+            .expand_once(sexpr, vm, ast_compiler)?
+            .unwrap_or_else(|| sexpr.clone()))
+    }
+
+    // Expands sexpr until it is no longer a macro call. Subforms are left as they
+    // are.
+    pub fn macroexpand(
+        &mut self,
+        sexpr: &Sexpr,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Sexpr, Error> {
+        let mut sexpr = sexpr.clone();
+
+        while let Some(expansion) = self.expand_once(&sexpr, vm, ast_compiler)? {
+            sexpr = expansion;
+        }
+
+        Ok(sexpr)
+    }
+
+    // None if sexpr isn't a macro call.
+    fn expand_once(
+        &mut self,
+        sexpr: &Sexpr,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Option<Sexpr>, Error> {
+        if !sexpr
+            .as_list()
+            .and_then(|list| list.first())
+            .and_then(|first| first.as_symbol())
+            .is_some_and(|symbol| ast_compiler.is_macro(symbol))
+        {
+            return Ok(None);
+        }
+
+        let ast = self.compile_ast(sexpr, ast_compiler)?;
+        let Ast::MacroCall(macro_call) = &ast else {
+            unreachable!()
         };
 
-        let mut buff = String::new();
+        let args = self.eval_macro_args(&ast, macro_call, vm, ast_compiler)?;
+        let expansion = macros::call_macro(macro_call.r#macro.as_str(), &args, vm)?;
 
-        object.print(&mut buff).map_err(|_| Error::Il {
-            ast: source.clone(),
-            message: "failed to print macro result".to_string(),
-        })?;
+        read_expansion(&ast, &expansion).map(Some)
+    }
 
-        let context: &'static _ = Box::leak(Box::new(reader::Context::new(
-            buff.as_str(),
-            "macro-expansion",
-        )));
-        let mut reader = Reader::new(context);
-        let sexpr: &'static _ = Box::leak(Box::new(reader.next().unwrap()?));
-        let ast = ast_compiler.compile(sexpr)?;
+    fn compile_macroexpand(
+        &mut self,
+        source: &Ast,
+        macroexpand: &ast::MacroExpand,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        let expansion = if macroexpand.once {
+            self.macroexpand_1(&macroexpand.form, vm, ast_compiler)?
+        } else {
+            self.macroexpand(&macroexpand.form, vm, ast_compiler)?
+        };
 
-        self.compile(&ast, vm, ast_compiler)
+        self.compile_quoted(
+            source,
+            &ast::quote(&macroexpand.source, &expansion),
+            vm,
+            ast_compiler,
+        )
     }
 
+    // Unused parameters are reported as the given kind, since a lambda that's
+    // called on the spot is how let binds variables.
     fn compile_lambda(
         &mut self,
         source: &Ast,
         lambda: &ast::Lambda,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        unused: WarningKind,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
-        let arity = match &lambda.parameters {
-            ast::Parameters::Normal(_) if lambda.parameters.len() == 0 => Arity::Nullary,
-            ast::Parameters::Normal(_) => Arity::Nary(lambda.parameters.len()),
-            ast::Parameters::Rest(..) => Arity::Variadic(lambda.parameters.len() - 1),
-        };
+        let arity = arity(&lambda.parameters);
 
         let parameters =
             Parameters::from_ast(source, &lambda.parameters).map_err(|_| Error::Il {
-                ast: source.clone(),
+                span: source.source_span().clone(),
                 message: "failed to compile parameters".to_string(),
             })?;
 
-        self.environment
-            .push_scope(parameters.iter().map(|param| (param.name, param.r#type)));
-
-        let body = lambda
-            .body
-            .iter()
-            .map(|ast| self.compile(ast, vm, ast_compiler))
-            .collect::<Result<Vec<Il>, Error>>()?;
-
-        let upvalues = self.environment.upvalues().collect::<Vec<UpValue>>();
+        for parameter in &parameters {
+            if !diagnostics::is_exempt(&parameter.name)
+                && self.environment.is_local(&parameter.name)
+            {
+                self.warn(
+                    WarningKind::Shadowing,
+                    &parameter.source,
+                    format!("{} shadows a variable of the same name", parameter.name),
+                )?;
+            }
+        }
 
         let r#type = match lambda.r#type.as_ref().map(Type::from_ast) {
             Some(Ok(t)) => Some(t),
             Some(Err(_)) => {
                 return Err(Error::Il {
-                    ast: source.clone(),
+                    span: source.source_span().clone(),
                     message: "failed to compile type".to_string(),
                 })
             }
             None => None,
         };
 
+        let errors = self.errors.len();
+        // only the lambda a match clause binds with, not those inside it
+        let mut unused_bindings = self.unused_bindings.take();
+
+        self.environment
+            .push_scope(parameters.iter().map(|param| (param.name, param.r#type)));
+
+        let optional = self.compile_optional(&lambda.parameters, vm, ast_compiler);
+
+        let body = lambda
+            .body
+            .iter()
+            .map(|ast| self.compile_expr(ast, vm, ast_compiler))
+            .collect::<Vec<Il>>();
+
+        let upvalues = self.environment.upvalues().collect::<Vec<UpValue>>();
+
         self.environment.pop_scope();
 
-        Ok(Il::Lambda(Lambda {
+        let lambda = Lambda {
             source: source.clone(),
             parameters,
             r#type,
             arity,
             upvalues,
+            optional,
+            doc: lambda.doc.clone(),
             body,
-        }))
+        };
+
+        // a use may have been replaced by nil if it failed to compile
+        let used = lambda.used_locals();
+        let complete = self.errors.len() == errors;
+
+        for (i, parameter) in (&lambda.parameters).into_iter().enumerate() {
+            if complete && !used.contains(&i) && !diagnostics::is_exempt(&parameter.name) {
+                if let Some(unused_bindings) = &mut unused_bindings {
+                    unused_bindings.push((parameter.name.to_string(), parameter.source.clone()));
+                    continue;
+                }
+
+                let what = match unused {
+                    WarningKind::UnusedVariable => "variable",
+                    _ => "parameter",
+                };
+
+                self.warn(
+                    unused,
+                    &parameter.source,
+                    format!("unused {what}: {}", parameter.name),
+                )?;
+            }
+        }
+
+        self.unused_bindings = unused_bindings;
+
+        Ok(Il::Lambda(lambda))
+    }
+
+    // Defaults are compiled in the lambda's own scope so that they can refer to
+    // the parameters before them.
+    fn compile_optional(
+        &mut self,
+        parameters: &ast::Parameters,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Vec<(usize, Il)> {
+        let ast::Parameters::Optional(required, optional) = parameters else {
+            return Vec::new();
+        };
+
+        optional
+            .iter()
+            .enumerate()
+            .map(|(i, (_, default))| {
+                (
+                    required.len() + i,
+                    self.compile_expr(default, vm, ast_compiler),
+                )
+            })
+            .collect()
     }
 
     fn compile_if(
         &mut self,
         source: &Ast,
         r#if: &ast::If,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::If(If {
             source: source.clone(),
-            predicate: Box::new(self.compile(&r#if.predicate, vm, ast_compiler)?),
-            then: Box::new(self.compile(&r#if.then, vm, ast_compiler)?),
-            r#else: Box::new(self.compile(&r#if.r#else, vm, ast_compiler)?),
+            predicate: Box::new(self.compile_expr(&r#if.predicate, vm, ast_compiler)),
+            then: Box::new(self.compile_expr(&r#if.then, vm, ast_compiler)),
+            r#else: Box::new(self.compile_expr(&r#if.r#else, vm, ast_compiler)),
         }))
     }
 
-    fn compile_def(
+    fn compile_def(
+        &mut self,
+        source: &Ast,
+        def: &ast::Def,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        let parameter = Parameter::from_ast(source, &def.parameter).map_err(|_| Error::Il {
+            span: source.source_span().clone(),
+            message: "failed to parse parameter".to_string(),
+        })?;
+
+        let r#type = match def.parameter.r#type.as_ref().map(Type::from_ast) {
+            Some(Ok(t)) => Some(t),
+            Some(Err(_)) => {
+                return Err(Error::Il {
+                    span: source.source_span().clone(),
+                    message: "failed to parse type".to_string(),
+                })
+            }
+            None => None,
+        };
+
+        if self.environment.current_module().is_none()
+            && self.constants.contains_key(def.parameter.name.as_str())
+        {
+            return Err(Error::Il {
+                span: source.source_span().clone(),
+                message: format!("can't redefine {}, it's a constant", def.parameter.name),
+            });
+        }
+
+        let lambda = def.body.as_lambda();
+
+        if self.pragmas.require_decl
+            && lambda.is_some()
+            && !self.decls.contains(def.parameter.name.as_str())
+        {
+            return Err(Error::Il {
+                span: source.source_span().clone(),
+                message: format!(
+                    "{} is defined without a decl (require-decl)",
+                    def.parameter.name
+                ),
+            });
+        }
+
+        let key = (
+            self.environment.current_module().map(|s| s.to_string()),
+            def.parameter.name.clone(),
+        );
+
+        let declared = self.decls.contains(def.parameter.name.as_str());
+
+        match lambda {
+            Some(lambda) => {
+                let mut signature =
+                    Signature::from_lambda(arity(&lambda.parameters), lambda, false).map_err(
+                        |_| Error::Il {
+                            span: source.source_span().clone(),
+                            message: "failed to parse type".to_string(),
+                        },
+                    )?;
+
+                // a decl fills in whatever the def leaves out
+                if declared
+                    && let Some(decl) = self.signatures.get(&(None, def.parameter.name.clone()))
+                {
+                    signature.merge(decl);
+                }
+
+                self.signatures.insert(key.clone(), signature);
+                self.definition_sites.insert(key.clone(), source.source_span().clone());
+            }
+            None => {
+                self.signatures.remove(&key);
+                self.definition_sites.remove(&key);
+            }
+        }
+
+        self.definitions.push(Definition {
+            source: source.clone(),
+            kind: if lambda.is_some() {
+                DefinitionKind::Function
+            } else {
+                DefinitionKind::Variable
+            },
+            name: def.parameter.name.clone(),
+            module: self.environment.current_module().map(|s| s.to_string()),
+            r#type: def.parameter.r#type.clone(),
+            parameters: lambda.map(|lambda| lambda.parameters.clone()),
+            return_type: lambda.and_then(|lambda| lambda.r#type.clone()),
+            doc: def
+                .doc
+                .clone()
+                .or_else(|| lambda.and_then(|lambda| lambda.doc.clone())),
+        });
+
+        let module = self.environment.current_module().map(|s| s.to_string());
+
+        match &module {
+            Some(module) => self.environment.insert_module_var(
+                module.as_str(),
+                def.parameter.name.as_str(),
+                r#type,
+            ),
+            None => self.insert_global(def.parameter.name.as_str(), r#type),
+        }
+
+        let body = Box::new(self.compile_expr(&def.body, vm, ast_compiler));
+
+        if let Il::Lambda(lambda) = &*body
+            && self.signatures.get(&key).is_some_and(|signature| signature.r#return.is_none())
+        {
+            match typecheck::infer_result(lambda, &self.signatures) {
+                Some(result) => {
+                    self.signatures.get_mut(&key).unwrap().r#return = Some(result);
+                }
+                None if !declared => self.warn(
+                    WarningKind::Inference,
+                    source,
+                    format!(
+                        "couldn't infer what {} returns, add a decl or a return type",
+                        def.parameter.name
+                    ),
+                )?,
+                None => (),
+            }
+        }
+
+        Ok(match module {
+            Some(module) => Il::Def(Def::Module {
+                source: source.clone(),
+                parameter,
+                module,
+                body,
+            }),
+            None => Il::Def(Def::Global {
+                source: source.clone(),
+                parameter,
+                body,
+            }),
+        })
+    }
+
+    // The value is worked out now, running the body if it isn't already a
+    // constant, so (defconst size (* 4 1024)) costs nothing where it's used.
+    fn compile_defconst(
         &mut self,
         source: &Ast,
-        def: &ast::Def,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        defconst: &ast::DefConst,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
-        let parameter = Parameter::from_ast(source, &def.parameter).map_err(|_| Error::Il {
-            ast: source.clone(),
-            message: "failed to parse parameter".to_string(),
-        })?;
+        if self.environment.current_module().is_some() {
+            return Err(Error::Il {
+                span: source.source_span().clone(),
+                message: "defconst is only allowed outside of a module".to_string(),
+            });
+        }
 
-        let r#type = match def.parameter.r#type.as_ref().map(Type::from_ast) {
-            Some(Ok(t)) => Some(t),
-            Some(Err(_)) => {
-                return Err(Error::Il {
-                    ast: source.clone(),
-                    message: "failed to parse type".to_string(),
-                })
+        let errors = self.errors.len();
+        let body = self.compile_expr(&defconst.body, vm, ast_compiler);
+        self.failed_since(errors)?;
+
+        let value = match body {
+            Il::Constant(constant) => ConstantValue::from(&constant),
+            il => {
+                let mut opcode_table = OpCodeTable::new();
+                bytecode::compile(&il, &mut opcode_table)?;
+
+                vm.eval(&opcode_table)
+                    .map_err(|(error, span)| Error::VmWithDebug { error, span })?;
+
+                ConstantValue::try_from(&vm.pop().unwrap().into_object()).map_err(|_| {
+                    Error::Il {
+                        span: source.source_span().clone(),
+                        message: format!(
+                            "{} must be a symbol, string, char, int, bool or nil to be a constant",
+                            defconst.name
+                        ),
+                    }
+                })?
             }
-            None => None,
         };
 
-        Ok(
-            if let Some(module) = self.environment.current_module().map(|s| s.to_string()) {
-                self.environment.insert_module_var(
-                    module.as_str(),
-                    def.parameter.name.as_str(),
-                    r#type,
-                );
+        self.definitions.push(Definition {
+            source: source.clone(),
+            kind: DefinitionKind::Constant,
+            name: defconst.name.clone(),
+            module: None,
+            r#type: None,
+            parameters: None,
+            return_type: None,
+            doc: defconst.doc.clone(),
+        });
 
-                Il::Def(Def::Module {
-                    source: source.clone(),
-                    parameter,
-                    module: self
-                        .environment
-                        .current_module()
-                        .map(|s| s.to_string())
-                        .unwrap(),
-                    body: Box::new(self.compile(&def.body, vm, ast_compiler)?),
-                })
-            } else {
-                self.environment
-                    .insert_global(def.parameter.name.as_str(), r#type);
+        self.insert_global(defconst.name.as_str(), Some(value.r#type()));
+        self.constants.insert(defconst.name.clone(), value);
 
-                Il::Def(Def::Global {
-                    source: source.clone(),
-                    parameter,
-                    body: Box::new(self.compile(&def.body, vm, ast_compiler)?),
-                })
-            },
-        )
+        Ok(Il::Constant(Constant::Nil {
+            source: source.clone(),
+        }))
     }
 
     fn compile_decl(&mut self, source: &Ast, decl: &ast::Decl) -> Result<Il, Error> {
-        self.environment.insert_global(
+        let lambda = decl.body.as_lambda();
+
+        self.definitions.push(Definition {
+            source: source.clone(),
+            kind: DefinitionKind::Decl,
+            name: decl.parameter.name.clone(),
+            module: None,
+            r#type: decl.parameter.r#type.clone(),
+            parameters: lambda.map(|lambda| lambda.parameters.clone()),
+            return_type: lambda.and_then(|lambda| lambda.r#type.clone()),
+            doc: None,
+        });
+
+        self.decls.insert(decl.parameter.name.clone());
+
+        if let Some(lambda) = lambda {
+            let signature = Signature::from_lambda(arity(&lambda.parameters), lambda, true)
+                .map_err(|_| Error::Il {
+                    span: source.source_span().clone(),
+                    message: "failed to parse type".to_string(),
+                })?;
+            self.signatures
+                .insert((None, decl.parameter.name.clone()), signature);
+        }
+
+        self.insert_global(
             decl.parameter.name.as_str(),
             match decl.parameter.r#type.as_ref().map(Type::from_ast) {
                 Some(Ok(t)) => Some(t),
                 Some(Err(_)) => {
                     return Err(Error::Il {
-                        ast: source.clone(),
+                        span: source.source_span().clone(),
                         message: "failed to parse type".to_string(),
                     })
                 }
@@ -824,79 +1992,133 @@ impl Compiler {
         }))
     }
 
+    fn compile_declaim(&mut self, source: &Ast, declaim: &ast::Declaim) -> Result<Il, Error> {
+        for (pragma, value) in &declaim.pragmas {
+            match pragma {
+                ast::Pragma::StrictArity => self.pragmas.strict_arity = *value,
+                ast::Pragma::WarningsAsErrors => self.pragmas.warnings_as_errors = *value,
+                ast::Pragma::RequireDecl => self.pragmas.require_decl = *value,
+            }
+        }
+
+        Ok(Il::Constant(Constant::Nil {
+            source: source.clone(),
+        }))
+    }
+
     fn compile_set(
         &mut self,
         source: &Ast,
         set: &ast::Set,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
-        Ok(Il::Set(Set {
-            source: source.clone(),
-            target: match &set.variable {
-                ast::Variable::WithoutModule { name, .. } => {
-                    match self.environment.resolve(name.as_str()) {
-                        Some(Variable::Local(index, r#type)) => VarRef::Local {
-                            source: source.clone(),
-                            name: name.clone(),
-                            r#type,
-                            index,
-                        },
-                        Some(Variable::Upvalue(index, r#type)) => VarRef::UpValue {
-                            source: source.clone(),
-                            name: name.clone(),
-                            r#type,
-                            index,
-                        },
-                        Some(Variable::Global(r#type)) => VarRef::Global {
-                            source: source.clone(),
-                            name: name.clone(),
-                            r#type,
-                        },
-                        Some(Variable::Module(r#type)) => VarRef::Module {
-                            source: source.clone(),
+        let target = match &set.variable {
+            ast::Variable::WithoutModule { name, .. } => {
+                match self.environment.resolve(name.as_str()) {
+                    Some(Variable::Local(index, r#type)) => VarRef::Local {
+                        source: source.clone(),
+                        name: name.clone(),
+                        r#type,
+                        index,
+                    },
+                    Some(Variable::Upvalue(index, r#type)) => VarRef::UpValue {
+                        source: source.clone(),
+                        name: name.clone(),
+                        r#type,
+                        index,
+                    },
+                    Some(Variable::Global(_)) if self.constants.contains_key(name) => {
+                        return Err(Error::Il {
+                            span: source.source_span().clone(),
+                            message: format!("can't set! {name}, it's a constant"),
+                        })
+                    }
+                    Some(Variable::Global(r#type)) => VarRef::Global {
+                        source: source.clone(),
+                        name: name.clone(),
+                        r#type,
+                    },
+                    Some(Variable::Module(module, r#type)) => VarRef::Module {
+                        source: source.clone(),
+                        name: name.clone(),
+                        module,
+                        r#type,
+                    },
+                    None => {
+                        return Err(Error::UndeclaredSet {
+                            span: source.source_span().clone(),
                             name: name.clone(),
-                            module: self.environment.current_module().unwrap().to_string(),
-                            r#type,
-                        },
-                        None => {
-                            return Err(Error::Il {
-                                ast: source.clone(),
-                                message: "unknown variable".to_string(),
-                            })
-                        }
+                        })
                     }
                 }
-                ast::Variable::WithModule { name, module, .. } => {
-                    match self.environment.resolve_module_var(module, name.as_str()) {
-                        Some(ModuleVar { r#type, visible }) if visible => VarRef::Module {
+            }
+            ast::Variable::WithModule { name, module, .. } => {
+                let module = self.environment.resolve_module_name(module).to_string();
+                let own_module = self.environment.current_module() == Some(module.as_str());
+                let imported = self.environment.is_imported(module.as_str(), name.as_str());
+
+                match self.environment.resolve_module_var(&module, name.as_str()) {
+                    Some(ModuleVar { r#type, visible }) if own_module || visible && imported => {
+                        VarRef::Module {
                             source: source.clone(),
                             name: name.clone(),
-                            module: module.to_string(),
+                            module,
                             r#type,
-                        },
-                        Some(_) => {
-                            return Err(Error::Il {
-                                ast: source.clone(),
-                                message: "referenced private symbol".to_string(),
-                            })
-                        }
-                        None => {
-                            return Err(Error::Il {
-                                ast: source.clone(),
-                                message: "referenced unknown variable".to_string(),
-                            })
                         }
                     }
+                    Some(_) => {
+                        return Err(Error::Il {
+                            span: source.source_span().clone(),
+                            message: "referenced private symbol".to_string(),
+                        })
+                    }
+                    None => {
+                        return Err(Error::Il {
+                            span: source.source_span().clone(),
+                            message: "referenced unknown variable".to_string(),
+                        })
+                    }
                 }
-            },
-            body: Box::new(self.compile(&set.body, vm, ast_compiler)?),
+            }
+        };
+
+        // whatever the variable holds now, it's not the function that was defined
+        let key = match &target {
+            VarRef::Global { name, .. } => Some((None, name.clone())),
+            VarRef::Module { name, module, .. } => Some((Some(module.clone()), name.clone())),
+            _ => None,
+        };
+
+        if let Some(key) = key {
+            self.definition_sites.remove(&key);
+        }
+
+        Ok(Il::Set(Set {
+            source: source.clone(),
+            target,
+            body: Box::new(self.compile_expr(&set.body, vm, ast_compiler)),
         }))
     }
 
-    fn compile_quoted(&mut self, source: &Ast, quoted: &ast::Quoted) -> Result<Il, Error> {
+    fn compile_quoted(
+        &mut self,
+        source: &Ast,
+        quoted: &ast::Quoted,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
         Ok(match &quoted {
-            Quoted::List { list, .. } => self.compile_quoted_list(source, list.as_slice())?,
+            Quoted::List { list, .. } => Il::List(List {
+                source: source.clone(),
+                exprs: list
+                    .iter()
+                    .map(|quoted| self.compile_quoted(source, quoted, vm, ast_compiler))
+                    .collect::<Result<Vec<_>, Error>>()?,
+            }),
+            Quoted::DottedList { list, tail, .. } => {
+                self.compile_quoted_dotted_list(source, list.as_slice(), tail, vm, ast_compiler)?
+            }
             Quoted::Symbol { symbol, .. } => Il::Constant(Constant::Symbol {
                 source: source.clone(),
                 symbol: symbol.clone(),
@@ -920,64 +2142,119 @@ impl Compiler {
             Quoted::Nil { .. } => Il::Constant(Constant::Nil {
                 source: source.clone(),
             }),
+            // filled in with the quoted keys and values the same way a map
+            // literal is with evaluated ones
+            Quoted::Map { source: span, entries } => {
+                let quote = |quoted: &Quoted| {
+                    Ast::Quote(ast::Quote {
+                        source: span.clone(),
+                        body: quoted.clone(),
+                    })
+                };
+
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| (span.clone(), quote(key), quote(value)))
+                    .collect();
+
+                let map = ast::build_map(span, vm.fresh_symbol(), entries);
+
+                self.compile_expr(&map, vm, ast_compiler)
+            }
         })
     }
 
-    #[allow(clippy::only_used_in_recursion)]
-    fn compile_quoted_list(&mut self, source: &Ast, list: &[Quoted]) -> Result<Il, Error> {
-        Ok(Il::List(List {
-            source: source.clone(),
-            exprs: list
-                .iter()
-                .map(|quoted| {
-                    Ok(match quoted {
-                        Quoted::List { list, .. } => {
-                            self.compile_quoted_list(source, list.as_slice())?
-                        }
-                        Quoted::Symbol { symbol, .. } => Il::Constant(Constant::Symbol {
-                            source: source.clone(),
-                            symbol: symbol.clone(),
-                        }),
-                        Quoted::String { string, .. } => Il::Constant(Constant::String {
-                            source: source.clone(),
-                            string: string.clone(),
-                        }),
-                        Quoted::Char { char, .. } => Il::Constant(Constant::Char {
-                            source: source.clone(),
-                            char: *char,
-                        }),
-                        Quoted::Int { int, .. } => Il::Constant(Constant::Int {
-                            source: source.clone(),
-                            int: *int,
-                        }),
-                        Quoted::Bool { bool, .. } => Il::Constant(Constant::Bool {
-                            source: source.clone(),
-                            bool: *bool,
-                        }),
-                        Quoted::Nil { .. } => Il::Constant(Constant::Nil {
-                            source: source.clone(),
-                        }),
-                    })
-                })
-                .collect::<Result<Vec<_>, Error>>()?,
-        }))
+    // There's no opcode for building an improper list, so (a b . c) is consed
+    // onto its tail one element at a time.
+    fn compile_quoted_dotted_list(
+        &mut self,
+        source: &Ast,
+        list: &[Quoted],
+        tail: &Quoted,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        let tail = self.compile_quoted(source, tail, vm, ast_compiler)?;
+
+        list.iter().rev().try_fold(tail, |rhs, quoted| {
+            Ok(Il::Cons(Cons {
+                source: source.clone(),
+                lhs: Box::new(self.compile_quoted(source, quoted, vm, ast_compiler)?),
+                rhs: Box::new(rhs),
+            }))
+        })
     }
 
     fn compile_fncall(
         &mut self,
         source: &Ast,
         fncall: &ast::FnCall,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
+        let function = match &*fncall.function {
+            Ast::Lambda(lambda) => self.compile_lambda(
+                &fncall.function,
+                lambda,
+                WarningKind::UnusedVariable,
+                vm,
+                ast_compiler,
+            )?,
+            function => self.compile_expr(function, vm, ast_compiler),
+        };
+
+        let key = match &function {
+            Il::VarRef(VarRef::Global { name, .. }) => Some((None, name.clone())),
+            Il::VarRef(VarRef::Module { name, module, .. }) => {
+                Some((Some(module.clone()), name.clone()))
+            }
+            _ => None,
+        };
+
+        let definition = match &function {
+            Il::Lambda(lambda) => Some((lambda.arity, fncall.function.source_span().clone())),
+            _ => key.as_ref().and_then(|key| {
+                let site = self.definition_sites.get(key)?;
+                Some((self.signatures.get(key)?.arity, site.clone()))
+            }),
+        };
+
+        if let Some((arity, definition)) = definition {
+            if !accepts(arity, fncall.exprs.len()) {
+                return Err(Error::Arity {
+                    span: source.source_span().clone(),
+                    definition,
+                    message: format!(
+                        "wrong number of arguments: expected {}, received {}",
+                        describe_arity(arity),
+                        fncall.exprs.len()
+                    ),
+                });
+            }
+        } else if self.pragmas.strict_arity
+            && let Some(arity) = key
+                .and_then(|key| self.signatures.get(&key))
+                .map(|signature| signature.arity)
+            && !accepts(arity, fncall.exprs.len())
+        {
+            return Err(Error::Il {
+                span: source.source_span().clone(),
+                message: format!(
+                    "wrong number of arguments: expected {}, received {} (strict-arity)",
+                    describe_arity(arity),
+                    fncall.exprs.len()
+                ),
+            });
+        }
+
         Ok(Il::FnCall(FnCall {
             source: source.clone(),
-            function: Box::new(self.compile(&fncall.function, vm, ast_compiler)?),
+            function: Box::new(function),
             args: fncall
                 .exprs
                 .iter()
-                .map(|arg| self.compile(arg, vm, ast_compiler))
-                .collect::<Result<Vec<_>, _>>()?,
+                .map(|arg| self.compile_expr(arg, vm, ast_compiler))
+                .collect::<Vec<_>>(),
         }))
     }
 
@@ -985,13 +2262,13 @@ impl Compiler {
         &mut self,
         source: &Ast,
         apply: &ast::Apply,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::Apply(Apply {
             source: source.clone(),
-            function: Box::new(self.compile(&apply.function, vm, ast_compiler)?),
-            list: Box::new(self.compile(&apply.list, vm, ast_compiler)?),
+            function: Box::new(self.compile_expr(&apply.function, vm, ast_compiler)),
+            list: Box::new(self.compile_expr(&apply.list, vm, ast_compiler)),
         }))
     }
 
@@ -999,7 +2276,7 @@ impl Compiler {
         &mut self,
         source: &Ast,
         op: &ast::BinaryArithmeticOperation,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::ArithmeticOperation(ArithmeticOperation {
@@ -1010,8 +2287,9 @@ impl Compiler {
                 ast::BinaryArithmeticOperator::Mul => ArithmeticOperator::Mul,
                 ast::BinaryArithmeticOperator::Div => ArithmeticOperator::Div,
             },
-            lhs: Box::new(self.compile(&op.lhs, vm, ast_compiler)?),
-            rhs: Box::new(self.compile(&op.rhs, vm, ast_compiler)?),
+            lhs: Box::new(self.compile_expr(&op.lhs, vm, ast_compiler)),
+            rhs: Box::new(self.compile_expr(&op.rhs, vm, ast_compiler)),
+            ints: Cell::new(false),
         }))
     }
 
@@ -1019,7 +2297,7 @@ impl Compiler {
         &mut self,
         source: &Ast,
         op: &ast::ComparisonOperation,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::ComparisonOperation(ComparisonOperation {
@@ -1029,8 +2307,8 @@ impl Compiler {
                 ast::ComparisonOperator::Lt => ComparisonOperator::Lt,
                 ast::ComparisonOperator::Gt => ComparisonOperator::Gt,
             },
-            lhs: Box::new(self.compile(&op.lhs, vm, ast_compiler)?),
-            rhs: Box::new(self.compile(&op.rhs, vm, ast_compiler)?),
+            lhs: Box::new(self.compile_expr(&op.lhs, vm, ast_compiler)),
+            rhs: Box::new(self.compile_expr(&op.rhs, vm, ast_compiler)),
         }))
     }
 
@@ -1038,7 +2316,7 @@ impl Compiler {
         &mut self,
         source: &Ast,
         list: &ast::List,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::List(List {
@@ -1046,8 +2324,8 @@ impl Compiler {
             exprs: list
                 .exprs
                 .iter()
-                .map(|expr| self.compile(expr, vm, ast_compiler))
-                .collect::<Result<Vec<_>, _>>()?,
+                .map(|expr| self.compile_expr(expr, vm, ast_compiler))
+                .collect::<Vec<_>>(),
         }))
     }
 
@@ -1055,13 +2333,41 @@ impl Compiler {
         &mut self,
         source: &Ast,
         cons: &ast::Cons,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::Cons(Cons {
             source: source.clone(),
-            lhs: Box::new(self.compile(&cons.lhs, vm, ast_compiler)?),
-            rhs: Box::new(self.compile(&cons.rhs, vm, ast_compiler)?),
+            lhs: Box::new(self.compile_expr(&cons.lhs, vm, ast_compiler)),
+            rhs: Box::new(self.compile_expr(&cons.rhs, vm, ast_compiler)),
+        }))
+    }
+
+    fn compile_set_car(
+        &mut self,
+        source: &Ast,
+        set_car: &ast::SetCar,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        Ok(Il::SetCar(SetCar {
+            source: source.clone(),
+            cons: Box::new(self.compile_expr(&set_car.cons, vm, ast_compiler)),
+            body: Box::new(self.compile_expr(&set_car.body, vm, ast_compiler)),
+        }))
+    }
+
+    fn compile_set_cdr(
+        &mut self,
+        source: &Ast,
+        set_cdr: &ast::SetCdr,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        Ok(Il::SetCdr(SetCdr {
+            source: source.clone(),
+            cons: Box::new(self.compile_expr(&set_cdr.cons, vm, ast_compiler)),
+            body: Box::new(self.compile_expr(&set_cdr.body, vm, ast_compiler)),
         }))
     }
 
@@ -1069,12 +2375,12 @@ impl Compiler {
         &mut self,
         source: &Ast,
         car: &ast::Car,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::Car(Car {
             source: source.clone(),
-            body: Box::new(self.compile(&car.body, vm, ast_compiler)?),
+            body: Box::new(self.compile_expr(&car.body, vm, ast_compiler)),
         }))
     }
 
@@ -1082,12 +2388,12 @@ impl Compiler {
         &mut self,
         source: &Ast,
         cdr: &ast::Cdr,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::Cdr(Cdr {
             source: source.clone(),
-            body: Box::new(self.compile(&cdr.body, vm, ast_compiler)?),
+            body: Box::new(self.compile_expr(&cdr.body, vm, ast_compiler)),
         }))
     }
 
@@ -1095,7 +2401,7 @@ impl Compiler {
         &mut self,
         source: &Ast,
         is_type: &ast::IsType,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::IsType(IsType {
@@ -1110,7 +2416,7 @@ impl Compiler {
                 ast::IsTypeParameter::Bool => IsTypeParameter::Bool,
                 ast::IsTypeParameter::Nil => IsTypeParameter::Nil,
             },
-            body: Box::new(self.compile(&is_type.body, vm, ast_compiler)?),
+            body: Box::new(self.compile_expr(&is_type.body, vm, ast_compiler)),
         }))
     }
 
@@ -1118,12 +2424,12 @@ impl Compiler {
         &mut self,
         source: &Ast,
         assert: &ast::Assert,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::Assert(Assert {
             source: source.clone(),
-            body: Box::new(self.compile(&assert.body, vm, ast_compiler)?),
+            body: Box::new(self.compile_expr(&assert.body, vm, ast_compiler)),
         }))
     }
 
@@ -1133,18 +2439,187 @@ impl Compiler {
         }))
     }
 
+    fn compile_gensym(&mut self, source: &Ast) -> Result<Il, Error> {
+        Ok(Il::Gensym(Gensym {
+            source: source.clone(),
+        }))
+    }
+
+    fn compile_match(
+        &mut self,
+        source: &Ast,
+        r#match: &ast::Match,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        let clauses = r#match
+            .clauses
+            .iter()
+            .map(|clause| {
+                let pattern = self.compile_pattern(&clause.pattern, vm, ast_compiler)?;
+
+                let Some(guard) = &clause.guard else {
+                    return Ok(MatchClause {
+                        pattern,
+                        guard: None,
+                        body: self.compile_expr(&clause.body, vm, ast_compiler),
+                    });
+                };
+
+                self.unused_bindings = Some(Vec::new());
+                let guard = self.compile_expr(guard, vm, ast_compiler);
+                let unused_by_guard = self.unused_bindings.replace(Vec::new());
+                let body = self.compile_expr(&clause.body, vm, ast_compiler);
+                let unused_by_body = self.unused_bindings.take();
+
+                for (name, source) in unused_by_body.unwrap_or_default() {
+                    if unused_by_guard
+                        .iter()
+                        .flatten()
+                        .any(|(other, _)| *other == name)
+                    {
+                        self.warn(
+                            WarningKind::UnusedVariable,
+                            &source,
+                            format!("unused variable: {name}"),
+                        )?;
+                    }
+                }
+
+                Ok(MatchClause {
+                    pattern,
+                    guard: Some(guard),
+                    body,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Il::Match(Match {
+            source: source.clone(),
+            scrutinee: Box::new(self.compile_expr(&r#match.scrutinee, vm, ast_compiler)),
+            clauses,
+        }))
+    }
+
+    fn compile_pattern(
+        &mut self,
+        pattern: &ast::Pattern,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Pattern, Error> {
+        Ok(match pattern {
+            ast::Pattern::Wildcard => Pattern::Wildcard,
+            ast::Pattern::Literal(literal) => {
+                Pattern::Literal(Box::new(self.compile_expr(literal, vm, ast_compiler)))
+            }
+            ast::Pattern::Cons(car, cdr) => Pattern::Cons(
+                Box::new(self.compile_pattern(car, vm, ast_compiler)?),
+                Box::new(self.compile_pattern(cdr, vm, ast_compiler)?),
+            ),
+        })
+    }
+
+    fn compile_case(
+        &mut self,
+        source: &Ast,
+        case: &ast::Case,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        let clauses = case
+            .clauses
+            .iter()
+            .map(|clause| {
+                Ok(CaseClause {
+                    keys: clause
+                        .keys
+                        .iter()
+                        .map(|key| self.compile_quoted(source, key, vm, ast_compiler))
+                        .collect::<Result<Vec<_>, Error>>()?,
+                    body: self.compile_expr(&clause.body, vm, ast_compiler),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Il::Case(Case {
+            source: source.clone(),
+            scrutinee: Box::new(self.compile_expr(&case.scrutinee, vm, ast_compiler)),
+            clauses,
+            default: case
+                .default
+                .as_ref()
+                .map(|default| Box::new(self.compile_expr(default, vm, ast_compiler))),
+        }))
+    }
+
+    fn compile_loop(
+        &mut self,
+        source: &Ast,
+        r#loop: &ast::Loop,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        Ok(Il::Loop(Loop {
+            source: source.clone(),
+            test: Box::new(self.compile_expr(&r#loop.test, vm, ast_compiler)),
+            body: r#loop
+                .body
+                .iter()
+                .map(|expr| self.compile_expr(expr, vm, ast_compiler))
+                .collect::<Vec<_>>(),
+        }))
+    }
+
+    fn compile_documentation(
+        &mut self,
+        source: &Ast,
+        documentation: &ast::Documentation,
+        vm: &mut Vm<Span>,
+        ast_compiler: &mut ast::Compiler,
+    ) -> Result<Il, Error> {
+        // macros only exist while compiling, so their docstrings are looked up now
+        if let Ast::Variable(ast::Variable::WithoutModule { name, .. }) =
+            documentation.function.as_ref()
+            && let Some(global) = ast_compiler.resolve_macro(name)
+        {
+            vm.get_global(&global)?;
+
+            let doc = match vm.pop().map(|local| local.into_object()) {
+                Some(vm::Object::Function(function)) => {
+                    function.borrow().doc().map(str::to_string)
+                }
+                _ => None,
+            };
+
+            return Ok(Il::Constant(match doc {
+                Some(string) => Constant::String {
+                    source: source.clone(),
+                    string,
+                },
+                None => Constant::Nil {
+                    source: source.clone(),
+                },
+            }));
+        }
+
+        Ok(Il::Documentation(Documentation {
+            source: source.clone(),
+            function: Box::new(self.compile_expr(&documentation.function, vm, ast_compiler)),
+        }))
+    }
+
     fn compile_map_insert(
         &mut self,
         source: &Ast,
         map_insert: &ast::MapInsert,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::MapInsert(MapInsert {
             source: source.clone(),
-            map: Box::new(self.compile(&map_insert.map, vm, ast_compiler)?),
-            key: Box::new(self.compile(&map_insert.key, vm, ast_compiler)?),
-            value: Box::new(self.compile(&map_insert.value, vm, ast_compiler)?),
+            map: Box::new(self.compile_expr(&map_insert.map, vm, ast_compiler)),
+            key: Box::new(self.compile_expr(&map_insert.key, vm, ast_compiler)),
+            value: Box::new(self.compile_expr(&map_insert.value, vm, ast_compiler)),
         }))
     }
 
@@ -1152,13 +2627,13 @@ impl Compiler {
         &mut self,
         source: &Ast,
         map_retrieve: &ast::MapRetrieve,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::MapRetrieve(MapRetrieve {
             source: source.clone(),
-            map: Box::new(self.compile(&map_retrieve.map, vm, ast_compiler)?),
-            key: Box::new(self.compile(&map_retrieve.key, vm, ast_compiler)?),
+            map: Box::new(self.compile_expr(&map_retrieve.map, vm, ast_compiler)),
+            key: Box::new(self.compile_expr(&map_retrieve.key, vm, ast_compiler)),
         }))
     }
 
@@ -1166,27 +2641,44 @@ impl Compiler {
         &mut self,
         source: &Ast,
         map_items: &ast::MapItems,
-        vm: &mut Vm<&'static Sexpr<'static>>,
+        vm: &mut Vm<Span>,
         ast_compiler: &mut ast::Compiler,
     ) -> Result<Il, Error> {
         Ok(Il::MapItems(MapItems {
             source: source.clone(),
-            map: Box::new(self.compile(&map_items.map, vm, ast_compiler)?),
+            map: Box::new(self.compile_expr(&map_items.map, vm, ast_compiler)),
         }))
     }
 
     fn compile_export(&mut self, source: &Ast, export: &ast::Export) -> Result<Il, Error> {
+        // the ast compiler has already exported it
+        if export.r#macro {
+            return Ok(Il::Constant(Constant::Nil {
+                source: source.clone(),
+            }));
+        }
+
         let current_module = self
             .environment
             .current_module()
             .ok_or(Error::Il {
-                ast: source.clone(),
+                span: source.source_span().clone(),
                 message: "can't export symbol at global scope".to_string(),
             })?
             .to_string();
 
-        self.environment
-            .export_module_var(current_module.as_str(), export.symbol.as_str());
+        if !self
+            .environment
+            .export_module_var(current_module.as_str(), export.symbol.as_str())
+        {
+            return Err(Error::Il {
+                span: source.source_span().clone(),
+                message: format!(
+                    "can't export {}, {current_module} doesn't define it",
+                    export.symbol
+                ),
+            });
+        }
 
         Ok(Il::Constant(Constant::Nil {
             source: source.clone(),
@@ -1204,3 +2696,46 @@ impl<'parameters> IntoIterator for &'parameters Parameters {
         }
     }
 }
+
+fn read_expansion(source: &Ast, object: &vm::Object<Span>) -> Result<Sexpr, Error> {
+    let mut buff = String::new();
+
+    object.print(&mut buff).map_err(|_| Error::Il {
+        span: source.source_span().clone(),
+        message: "failed to print macro result".to_string(),
+    })?;
+
+    let context = Rc::new(reader::Context::new(buff.as_str(), "macro-expansion"));
+    let mut reader = Reader::new(&context);
+
+    Ok(reader.next().unwrap()?)
+}
+
+fn arity(parameters: &ast::Parameters) -> Arity {
+    match parameters {
+        ast::Parameters::Normal(_) if parameters.len() == 0 => Arity::Nullary,
+        ast::Parameters::Normal(_) => Arity::Nary(parameters.len()),
+        ast::Parameters::Rest(..) => Arity::Variadic(parameters.len() - 1),
+        ast::Parameters::Optional(required, optional) => {
+            Arity::Optional(required.len(), optional.len())
+        }
+    }
+}
+
+pub fn accepts(arity: Arity, args: usize) -> bool {
+    match arity {
+        Arity::Nullary => args == 0,
+        Arity::Nary(n) => args == n,
+        Arity::Variadic(required) => args >= required,
+        Arity::Optional(required, optional) => (required..=required + optional).contains(&args),
+    }
+}
+
+pub fn describe_arity(arity: Arity) -> String {
+    match arity {
+        Arity::Nullary => "0".to_string(),
+        Arity::Nary(n) => n.to_string(),
+        Arity::Variadic(required) => format!("at least {required}"),
+        Arity::Optional(required, optional) => format!("{required} to {}", required + optional),
+    }
+}
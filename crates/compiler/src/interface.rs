@@ -0,0 +1,235 @@
+use crate::environment::ModuleVar;
+use crate::il::ConstantValue;
+use crate::types::{Signature, Type};
+use vm::encode::{Decoder, Encoder, Error};
+
+// What other files need to know about a module to compile against it without
+// compiling it again: its variables and their types, the signatures of its
+// functions, the globals and constants it declared and its macros.
+#[derive(Clone, Debug)]
+pub struct Interface {
+    pub module: String,
+    pub(crate) vars: Vec<(String, ModuleVar)>,
+    pub(crate) globals: Vec<(String, Option<Type>)>,
+    pub(crate) constants: Vec<(String, ConstantValue)>,
+    pub(crate) signatures: Vec<((Option<String>, String), Signature)>,
+    pub(crate) macros: Vec<String>,
+    pub(crate) exported_macros: Vec<String>,
+}
+
+impl Interface {
+    pub fn encode(&self, encoder: &mut Encoder) {
+        encoder.str(&self.module);
+
+        encoder.usize(self.vars.len());
+        for (name, module_var) in &self.vars {
+            encoder.str(name);
+            encode_optional_type(encoder, module_var.r#type.as_ref());
+            encoder.bool(module_var.visible);
+        }
+
+        encoder.usize(self.globals.len());
+        for (name, r#type) in &self.globals {
+            encoder.str(name);
+            encode_optional_type(encoder, r#type.as_ref());
+        }
+
+        encoder.usize(self.constants.len());
+        for (name, value) in &self.constants {
+            encoder.str(name);
+            encode_constant(encoder, value);
+        }
+
+        encoder.usize(self.signatures.len());
+        for ((module, name), signature) in &self.signatures {
+            encoder.bool(module.is_some());
+            encoder.str(module.as_deref().unwrap_or_default());
+            encoder.str(name);
+            encoder.arity(signature.arity);
+            encoder.usize(signature.parameters.len());
+            for parameter in &signature.parameters {
+                encode_optional_type(encoder, parameter.as_ref());
+            }
+            encode_optional_type(encoder, signature.rest.as_ref());
+            encode_optional_type(encoder, signature.r#return.as_ref());
+        }
+
+        for macros in [&self.macros, &self.exported_macros] {
+            encoder.usize(macros.len());
+            for name in macros {
+                encoder.str(name);
+            }
+        }
+    }
+
+    pub fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        let module = decoder.str()?.to_string();
+
+        let vars = (0..decoder.usize()?)
+            .map(|_| {
+                let name = decoder.str()?.to_string();
+                let r#type = decode_optional_type(decoder)?;
+                let visible = decoder.bool()?;
+                Ok((name, ModuleVar { r#type, visible }))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let globals = (0..decoder.usize()?)
+            .map(|_| Ok((decoder.str()?.to_string(), decode_optional_type(decoder)?)))
+            .collect::<Result<_, Error>>()?;
+
+        let constants = (0..decoder.usize()?)
+            .map(|_| Ok((decoder.str()?.to_string(), decode_constant(decoder)?)))
+            .collect::<Result<_, Error>>()?;
+
+        let signatures = (0..decoder.usize()?)
+            .map(|_| {
+                let has_module = decoder.bool()?;
+                let module = decoder.str()?.to_string();
+                let name = decoder.str()?.to_string();
+                let arity = decoder.arity()?;
+                let parameters = (0..decoder.usize()?)
+                    .map(|_| decode_optional_type(decoder))
+                    .collect::<Result<_, _>>()?;
+                let rest = decode_optional_type(decoder)?;
+                let r#return = decode_optional_type(decoder)?;
+
+                Ok((
+                    (has_module.then_some(module), name),
+                    Signature {
+                        arity,
+                        parameters,
+                        rest,
+                        r#return,
+                    },
+                ))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let mut decode_names = || {
+            (0..decoder.usize()?)
+                .map(|_| Ok(decoder.str()?.to_string()))
+                .collect::<Result<Vec<_>, Error>>()
+        };
+
+        let macros = decode_names()?;
+        let exported_macros = decode_names()?;
+
+        Ok(Self {
+            module,
+            vars,
+            globals,
+            constants,
+            signatures,
+            macros,
+            exported_macros,
+        })
+    }
+}
+
+fn encode_optional_type(encoder: &mut Encoder, r#type: Option<&Type>) {
+    match r#type {
+        Some(r#type) => {
+            encoder.bool(true);
+            encode_type(encoder, r#type);
+        }
+        None => encoder.bool(false),
+    }
+}
+
+fn encode_type(encoder: &mut Encoder, r#type: &Type) {
+    match r#type {
+        Type::List(inner) => {
+            encoder.u8(0);
+            encode_type(encoder, inner);
+        }
+        Type::Cons => encoder.u8(1),
+        Type::Function => encoder.u8(2),
+        Type::Symbol => encoder.u8(3),
+        Type::String => encoder.u8(4),
+        Type::Char => encoder.u8(5),
+        Type::Int => encoder.u8(6),
+        Type::Bool => encoder.u8(7),
+        Type::Nil => encoder.u8(8),
+        Type::Union(types) => {
+            encoder.u8(9);
+            encoder.usize(types.len());
+            for r#type in types {
+                encode_type(encoder, r#type);
+            }
+        }
+    }
+}
+
+fn decode_optional_type(decoder: &mut Decoder) -> Result<Option<Type>, Error> {
+    match decoder.bool()? {
+        true => Ok(Some(decode_type(decoder)?)),
+        false => Ok(None),
+    }
+}
+
+fn decode_type(decoder: &mut Decoder) -> Result<Type, Error> {
+    Ok(match decoder.u8()? {
+        0 => Type::List(Box::new(decode_type(decoder)?)),
+        1 => Type::Cons,
+        2 => Type::Function,
+        3 => Type::Symbol,
+        4 => Type::String,
+        5 => Type::Char,
+        6 => Type::Int,
+        7 => Type::Bool,
+        8 => Type::Nil,
+        9 => Type::Union(
+            (0..decoder.usize()?)
+                .map(|_| decode_type(decoder))
+                .collect::<Result<_, _>>()?,
+        ),
+        tag => return Err(Error::Tag { what: "type", tag }),
+    })
+}
+
+fn encode_constant(encoder: &mut Encoder, value: &ConstantValue) {
+    match value {
+        ConstantValue::Symbol(symbol) => {
+            encoder.u8(0);
+            encoder.str(symbol);
+        }
+        ConstantValue::String(string) => {
+            encoder.u8(1);
+            encoder.str(string);
+        }
+        ConstantValue::Char(char) => {
+            encoder.u8(2);
+            encoder.u64(*char as u64);
+        }
+        ConstantValue::Int(int) => {
+            encoder.u8(3);
+            encoder.i64(*int);
+        }
+        ConstantValue::Bool(bool) => {
+            encoder.u8(4);
+            encoder.bool(*bool);
+        }
+        ConstantValue::Nil => encoder.u8(5),
+    }
+}
+
+fn decode_constant(decoder: &mut Decoder) -> Result<ConstantValue, Error> {
+    Ok(match decoder.u8()? {
+        0 => ConstantValue::Symbol(decoder.str()?.to_string()),
+        1 => ConstantValue::String(decoder.str()?.to_string()),
+        2 => ConstantValue::Char(
+            char::from_u32(decoder.u64()? as u32)
+                .ok_or_else(|| Error::Other("invalid char".to_string()))?,
+        ),
+        3 => ConstantValue::Int(decoder.i64()?),
+        4 => ConstantValue::Bool(decoder.bool()?),
+        5 => ConstantValue::Nil,
+        tag => {
+            return Err(Error::Tag {
+                what: "constant",
+                tag,
+            })
+        }
+    })
+}
@@ -3,6 +3,11 @@
 
 pub mod ast;
 pub mod bytecode;
+pub mod diagnostics;
 mod environment;
 pub mod il;
+pub mod interface;
+mod macros;
+mod pretty;
+mod typecheck;
 mod types;
@@ -0,0 +1,262 @@
+use crate::{ast, il::Error, types::Type};
+use gc::Gc;
+use reader::Span;
+use std::collections::{HashMap, HashSet};
+use vm::{OpCodeTable, Vm};
+
+type Object = vm::Object<Span>;
+
+pub(crate) fn call_macro(name: &str, args: &[Object], vm: &mut Vm<Span>) -> Result<Object, Error> {
+    vm.get_global(name)?;
+
+    for arg in args {
+        vm.push(arg.clone());
+    }
+
+    vm.call(args.len())?;
+
+    vm.eval(&OpCodeTable::new())
+        .map_err(|(error, span)| Error::VmWithDebug { error, span })?;
+
+    Ok(vm
+        .pop()
+        .map(|local| local.into_object())
+        .unwrap_or(Object::Nil))
+}
+
+// Expands every macro call in an expansion so that the bindings it introduces
+// are visible to the hygiene pass. Symbols are passed through untouched, which
+// keeps track of which ones came from the macro call's arguments.
+pub(crate) fn expand_all(
+    object: Object,
+    vm: &mut Vm<Span>,
+    ast_compiler: &ast::Compiler,
+) -> Result<Object, Error> {
+    let Object::Cons(cons) = &object else {
+        return Ok(object);
+    };
+
+    let list = cons.borrow().iter_cars().collect::<Vec<_>>();
+
+    match list.as_slice() {
+        [Object::Symbol(symbol), ..]
+            if matches!(symbol.as_str(), "quote" | "defmacro" | "decl") =>
+        {
+            Ok(object)
+        }
+        [Object::Symbol(symbol), args @ ..] if ast_compiler.is_macro(symbol) => {
+            let global = ast_compiler.resolve_macro(symbol).unwrap();
+            let expansion = call_macro(global.as_str(), args, vm)?;
+            expand_all(expansion, vm, ast_compiler)
+        }
+        [lambda @ Object::Symbol(symbol), parameters, body @ ..] if symbol.as_str() == "lambda" => {
+            let body = body
+                .iter()
+                .map(|object| expand_all(object.clone(), vm, ast_compiler))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok([lambda.clone(), parameters.clone()]
+                .into_iter()
+                .chain(body)
+                .collect())
+        }
+        _ => list
+            .into_iter()
+            .map(|object| expand_all(object, vm, ast_compiler))
+            .collect(),
+    }
+}
+
+// Renames the parameters of lambdas that were introduced by the macro itself,
+// along with the references to them that also came from the macro, so that they
+// cannot capture variables passed in by the caller.
+pub(crate) fn rename_introduced_bindings(
+    expansion: Object,
+    args: &[Object],
+    vm: &mut Vm<Span>,
+) -> Object {
+    let mut from_args = HashSet::new();
+
+    for arg in args {
+        collect_symbols(arg, &mut from_args);
+    }
+
+    rename(expansion, &from_args, &HashMap::new(), vm)
+}
+
+fn collect_symbols(object: &Object, symbols: &mut HashSet<*const String>) {
+    match object {
+        Object::Symbol(symbol) => {
+            symbols.insert(Gc::as_ptr(symbol));
+        }
+        Object::Cons(cons) => {
+            let cons = cons.borrow();
+            collect_symbols(&cons.0, symbols);
+            collect_symbols(&cons.1, symbols);
+        }
+        _ => (),
+    }
+}
+
+fn rename(
+    object: Object,
+    from_args: &HashSet<*const String>,
+    renames: &HashMap<String, String>,
+    vm: &mut Vm<Span>,
+) -> Object {
+    match &object {
+        Object::Symbol(symbol) if !from_args.contains(&Gc::as_ptr(symbol)) => {
+            match renames.get(symbol.as_str()) {
+                Some(renamed) => Object::Symbol(Gc::new(renamed.clone())),
+                None => object,
+            }
+        }
+        Object::Cons(cons) => {
+            let list = cons.borrow().iter_cars().collect::<Vec<_>>();
+
+            match list.as_slice() {
+                [Object::Symbol(symbol), ..] if symbol.as_str() == "quote" => object,
+                [lambda @ Object::Symbol(symbol), parameters, body @ ..]
+                    if symbol.as_str() == "lambda" =>
+                {
+                    let mut renames = renames.clone();
+                    let parameters = rename_parameters(parameters, from_args, &mut renames, vm);
+                    let body = body
+                        .iter()
+                        .map(|object| rename(object.clone(), from_args, &renames, vm))
+                        .collect::<Vec<_>>();
+
+                    [lambda.clone(), parameters]
+                        .into_iter()
+                        .chain(body)
+                        .collect()
+                }
+                [form @ Object::Symbol(symbol), Object::Cons(spec), body @ ..]
+                    if matches!(symbol.as_str(), "dolist" | "dotimes") =>
+                {
+                    let spec = spec.borrow().iter_cars().collect::<Vec<_>>();
+                    let [variable, init, result @ ..] = spec.as_slice() else {
+                        return object;
+                    };
+
+                    // the loop variable is in scope for the body and the result
+                    // but not for the expression being iterated over
+                    let init = rename(init.clone(), from_args, renames, vm);
+                    let mut renames = renames.clone();
+                    let variable = rename_binding(variable, from_args, &mut renames, vm);
+                    let spec = [variable, init]
+                        .into_iter()
+                        .chain(
+                            result
+                                .iter()
+                                .map(|object| rename(object.clone(), from_args, &renames, vm)),
+                        )
+                        .collect();
+                    let body = body
+                        .iter()
+                        .map(|object| rename(object.clone(), from_args, &renames, vm))
+                        .collect::<Vec<_>>();
+
+                    [form.clone(), spec].into_iter().chain(body).collect()
+                }
+                _ => list
+                    .into_iter()
+                    .map(|object| rename(object, from_args, renames, vm))
+                    .collect(),
+            }
+        }
+        _ => object,
+    }
+}
+
+fn rename_parameters(
+    parameters: &Object,
+    from_args: &HashSet<*const String>,
+    renames: &mut HashMap<String, String>,
+    vm: &mut Vm<Span>,
+) -> Object {
+    let Object::Cons(cons) = parameters else {
+        return parameters.clone();
+    };
+
+    let mut renamed = Vec::new();
+    let mut is_optional = false;
+
+    for parameter in cons.borrow().iter_cars() {
+        renamed.push(match &parameter {
+            Object::Symbol(symbol) => {
+                is_optional |= symbol.as_str() == "&optional";
+                rename_parameter(symbol, from_args, renames, vm)
+            }
+            Object::Cons(cons) if is_optional => {
+                let list = cons.borrow().iter_cars().collect::<Vec<_>>();
+                match list.as_slice() {
+                    // optional parameters are written as (parameter default), the
+                    // default may refer to the parameters before it
+                    [parameter, default] => {
+                        let default = rename(default.clone(), from_args, renames, vm);
+                        [rename_binding(parameter, from_args, renames, vm), default]
+                            .into_iter()
+                            .collect()
+                    }
+                    _ => parameter.clone(),
+                }
+            }
+            _ => rename_binding(&parameter, from_args, renames, vm),
+        });
+    }
+
+    renamed.into_iter().collect()
+}
+
+fn rename_binding(
+    parameter: &Object,
+    from_args: &HashSet<*const String>,
+    renames: &mut HashMap<String, String>,
+    vm: &mut Vm<Span>,
+) -> Object {
+    match parameter {
+        Object::Symbol(symbol) => rename_parameter(symbol, from_args, renames, vm),
+        Object::Cons(cons) => {
+            let list = cons.borrow().iter_cars().collect::<Vec<_>>();
+            match list.as_slice() {
+                // typed parameters are written as (name type)
+                [Object::Symbol(symbol), r#type] if is_type(r#type) => [
+                    rename_parameter(symbol, from_args, renames, vm),
+                    r#type.clone(),
+                ]
+                .into_iter()
+                .collect(),
+                // anything else is a destructuring pattern
+                _ => rename_parameters(parameter, from_args, renames, vm),
+            }
+        }
+        _ => parameter.clone(),
+    }
+}
+
+fn is_type(object: &Object) -> bool {
+    match object {
+        Object::Symbol(symbol) => Type::from_scalar(symbol).is_some(),
+        Object::Cons(cons) => {
+            matches!(&cons.borrow().0, Object::Symbol(symbol) if symbol.as_str() == "union")
+        }
+        _ => false,
+    }
+}
+
+fn rename_parameter(
+    symbol: &Gc<String>,
+    from_args: &HashSet<*const String>,
+    renames: &mut HashMap<String, String>,
+    vm: &mut Vm<Span>,
+) -> Object {
+    if symbol.starts_with('&') || from_args.contains(&Gc::as_ptr(symbol)) {
+        return Object::Symbol(symbol.clone());
+    }
+
+    let renamed = vm.fresh_symbol();
+    renames.insert(symbol.to_string(), renamed.clone());
+
+    Object::Symbol(Gc::new(renamed))
+}
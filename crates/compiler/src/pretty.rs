@@ -0,0 +1,541 @@
+// The ast and il as indented trees, one node per line with its children under
+// it, for looking at what a form turned into before it became bytecode. Where
+// a node came from is left out, the source is what it's being compared with.
+
+use crate::ast::{self, Ast};
+use crate::il::{self, Il};
+use std::fmt::Write;
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize,
+}
+
+impl Printer {
+    fn line(&mut self, line: impl AsRef<str>) {
+        writeln!(
+            self.out,
+            "{}{}",
+            "  ".repeat(self.depth),
+            line.as_ref().trim_end()
+        )
+        .unwrap();
+    }
+
+    fn node(&mut self, line: impl AsRef<str>, children: impl FnOnce(&mut Self)) {
+        self.line(line);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+}
+
+impl Ast {
+    pub fn to_pretty_string(&self) -> String {
+        let mut printer = Printer::default();
+        print_ast(&mut printer, self);
+        printer.out
+    }
+}
+
+impl Il {
+    pub fn to_pretty_string(&self) -> String {
+        let mut printer = Printer::default();
+        print_il(&mut printer, self);
+        printer.out
+    }
+}
+
+fn print_ast(p: &mut Printer, ast: &Ast) {
+    match ast {
+        Ast::Require(require) => p.line(format!("require {}", require.module)),
+        Ast::Module(module) => p.line(format!("module {}", module.name)),
+        Ast::Export(export) => p.line(format!("export {}", export.symbol)),
+        Ast::EvalWhenCompile(eval_when_compile) => p.node("eval-when-compile", |p| {
+            asts(p, &eval_when_compile.exprs);
+        }),
+        Ast::DefMacro(defmacro) => p.node(
+            format!(
+                "defmacro {} {}",
+                defmacro.name,
+                ast_parameters(&defmacro.parameters)
+            ),
+            |p| asts(p, &defmacro.body),
+        ),
+        Ast::Lambda(lambda) => p.node(
+            format!(
+                "lambda {}{}",
+                ast_parameters(&lambda.parameters),
+                lambda
+                    .r#type
+                    .as_ref()
+                    .map(|t| format!(" -> {t}"))
+                    .unwrap_or_default()
+            ),
+            |p| {
+                if let ast::Parameters::Optional(_, optional) = &lambda.parameters {
+                    for (parameter, default) in optional {
+                        p.node(format!("default {}", parameter.name), |p| {
+                            print_ast(p, default)
+                        });
+                    }
+                }
+                asts(p, &lambda.body);
+            },
+        ),
+        Ast::Def(def) => p.node(format!("def {}", ast_parameter(&def.parameter)), |p| {
+            print_ast(p, &def.body)
+        }),
+        Ast::DefConst(defconst) => p.node(format!("defconst {}", defconst.name), |p| {
+            print_ast(p, &defconst.body)
+        }),
+        Ast::Decl(decl) => p.node(format!("decl {}", ast_parameter(&decl.parameter)), |p| {
+            print_ast(p, &decl.body)
+        }),
+        Ast::Declaim(declaim) => p.line(format!(
+            "declaim {}",
+            declaim
+                .pragmas
+                .iter()
+                .map(|(pragma, on)| format!("{pragma:?}={on}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        )),
+        Ast::Set(set) => p.node(format!("set! {}", variable(&set.variable)), |p| {
+            print_ast(p, &set.body)
+        }),
+        Ast::If(r#if) => p.node("if", |p| {
+            print_ast(p, &r#if.predicate);
+            print_ast(p, &r#if.then);
+            print_ast(p, &r#if.r#else);
+        }),
+        Ast::Apply(apply) => p.node("apply", |p| {
+            print_ast(p, &apply.function);
+            print_ast(p, &apply.list);
+        }),
+        Ast::BinaryArithemticOperation(op) => p.node(
+            match op.operator {
+                ast::BinaryArithmeticOperator::Add => "+",
+                ast::BinaryArithmeticOperator::Sub => "-",
+                ast::BinaryArithmeticOperator::Mul => "*",
+                ast::BinaryArithmeticOperator::Div => "/",
+            },
+            |p| {
+                print_ast(p, &op.lhs);
+                print_ast(p, &op.rhs);
+            },
+        ),
+        Ast::ComparisonOperation(op) => p.node(
+            match op.operator {
+                ast::ComparisonOperator::Lt => "<",
+                ast::ComparisonOperator::Gt => ">",
+                ast::ComparisonOperator::Eq => "=",
+            },
+            |p| {
+                print_ast(p, &op.lhs);
+                print_ast(p, &op.rhs);
+            },
+        ),
+        Ast::List(list) => p.node("list", |p| asts(p, &list.exprs)),
+        Ast::Cons(cons) => p.node("cons", |p| {
+            print_ast(p, &cons.lhs);
+            print_ast(p, &cons.rhs);
+        }),
+        Ast::Car(car) => p.node("car", |p| print_ast(p, &car.body)),
+        Ast::Cdr(cdr) => p.node("cdr", |p| print_ast(p, &cdr.body)),
+        Ast::SetCar(set_car) => p.node("set-car!", |p| {
+            print_ast(p, &set_car.cons);
+            print_ast(p, &set_car.body);
+        }),
+        Ast::SetCdr(set_cdr) => p.node("set-cdr!", |p| {
+            print_ast(p, &set_cdr.cons);
+            print_ast(p, &set_cdr.body);
+        }),
+        Ast::FnCall(fn_call) => p.node("call", |p| {
+            print_ast(p, &fn_call.function);
+            asts(p, &fn_call.exprs);
+        }),
+        Ast::MacroCall(macro_call) => p.node(format!("macro-call {}", macro_call.r#macro), |p| {
+            for arg in &macro_call.args {
+                p.line(format!("quote {}", quoted(arg)));
+            }
+        }),
+        Ast::Quote(quote) => p.line(format!("quote {}", quoted(&quote.body))),
+        Ast::IsType(is_type) => p.node(format!("is-type {:?}", is_type.parameter), |p| {
+            print_ast(p, &is_type.body)
+        }),
+        Ast::Assert(assert) => p.node("assert", |p| print_ast(p, &assert.body)),
+        Ast::MapCreate(_) => p.line("map-create"),
+        Ast::MapInsert(map_insert) => p.node("map-insert!", |p| {
+            print_ast(p, &map_insert.map);
+            print_ast(p, &map_insert.key);
+            print_ast(p, &map_insert.value);
+        }),
+        Ast::MapRetrieve(map_retrieve) => p.node("map-retrieve", |p| {
+            print_ast(p, &map_retrieve.map);
+            print_ast(p, &map_retrieve.key);
+        }),
+        Ast::MapItems(map_items) => p.node("map-items", |p| print_ast(p, &map_items.map)),
+        Ast::Gensym(_) => p.line("gensym"),
+        Ast::Documentation(documentation) => {
+            p.node("documentation", |p| print_ast(p, &documentation.function))
+        }
+        Ast::Match(r#match) => p.node("match", |p| {
+            print_ast(p, &r#match.scrutinee);
+            for clause in &r#match.clauses {
+                p.node("clause", |p| {
+                    ast_pattern(p, &clause.pattern);
+                    if let Some(guard) = &clause.guard {
+                        p.node("guard", |p| print_ast(p, guard));
+                    }
+                    print_ast(p, &clause.body);
+                });
+            }
+        }),
+        Ast::Case(case) => p.node("case", |p| {
+            print_ast(p, &case.scrutinee);
+            for clause in &case.clauses {
+                let keys = clause.keys.iter().map(quoted).collect::<Vec<_>>();
+                p.node(format!("keys {}", keys.join(" ")), |p| {
+                    print_ast(p, &clause.body)
+                });
+            }
+            if let Some(default) = &case.default {
+                p.node("default", |p| print_ast(p, default));
+            }
+        }),
+        Ast::Loop(r#loop) => p.node("loop", |p| {
+            print_ast(p, &r#loop.test);
+            asts(p, &r#loop.body);
+        }),
+        Ast::MacroExpand(macro_expand) => p.line(format!(
+            "{} {}",
+            if macro_expand.once {
+                "macroexpand-1"
+            } else {
+                "macroexpand"
+            },
+            macro_expand.form
+        )),
+        Ast::Variable(var) => p.line(format!("variable {}", variable(var))),
+        Ast::Constant(constant) => p.line(match constant {
+            ast::Constant::String { string, .. } => format!("string {string:?}"),
+            ast::Constant::Char { char, .. } => format!("char {char:?}"),
+            ast::Constant::Int { int, .. } => format!("int {int}"),
+            ast::Constant::Bool { bool, .. } => format!("bool {bool}"),
+            ast::Constant::Nil { .. } => "nil".to_string(),
+        }),
+    }
+}
+
+fn asts(p: &mut Printer, asts: &[Ast]) {
+    for ast in asts {
+        print_ast(p, ast);
+    }
+}
+
+fn ast_pattern(p: &mut Printer, pattern: &ast::Pattern) {
+    match pattern {
+        ast::Pattern::Wildcard => p.line("_"),
+        ast::Pattern::Literal(literal) => print_ast(p, literal),
+        ast::Pattern::Cons(car, cdr) => p.node("cons", |p| {
+            ast_pattern(p, car);
+            ast_pattern(p, cdr);
+        }),
+    }
+}
+
+fn ast_parameter(parameter: &ast::Parameter) -> String {
+    match &parameter.r#type {
+        Some(r#type) => format!("{}: {type}", parameter.name),
+        None => parameter.name.clone(),
+    }
+}
+
+fn ast_parameters(parameters: &ast::Parameters) -> String {
+    let names = match parameters {
+        ast::Parameters::Normal(required) => required.iter().map(ast_parameter).collect(),
+        ast::Parameters::Rest(required, rest) => required
+            .iter()
+            .map(ast_parameter)
+            .chain([format!("&rest {}", ast_parameter(rest))])
+            .collect(),
+        ast::Parameters::Optional(required, optional) => required
+            .iter()
+            .map(ast_parameter)
+            .chain(
+                optional
+                    .iter()
+                    .map(|(parameter, _)| format!("&optional {}", ast_parameter(parameter))),
+            )
+            .collect::<Vec<_>>(),
+    };
+
+    format!("({})", names.join(" "))
+}
+
+fn variable(variable: &ast::Variable) -> String {
+    match variable {
+        ast::Variable::WithoutModule { name, .. } => name.clone(),
+        ast::Variable::WithModule { name, module, .. } => format!("{module}::{name}"),
+    }
+}
+
+// Quoted data doesn't keep a source of its own, so it's written back out.
+fn quoted(quoted: &ast::Quoted) -> String {
+    match quoted {
+        ast::Quoted::List { list, .. } => {
+            format!(
+                "({})",
+                list.iter().map(self::quoted).collect::<Vec<_>>().join(" ")
+            )
+        }
+        ast::Quoted::DottedList { list, tail, .. } => {
+            format!(
+                "({} . {})",
+                list.iter().map(self::quoted).collect::<Vec<_>>().join(" "),
+                self::quoted(tail)
+            )
+        }
+        ast::Quoted::Symbol { symbol, .. } => symbol.clone(),
+        ast::Quoted::String { string, .. } => format!("{string:?}"),
+        ast::Quoted::Char { char, .. } => format!("{char:?}"),
+        ast::Quoted::Int { int, .. } => int.to_string(),
+        ast::Quoted::Bool { bool, .. } => bool.to_string(),
+        ast::Quoted::Nil { .. } => "nil".to_string(),
+        ast::Quoted::Map { entries, .. } => format!(
+            "{{{}}}",
+            entries
+                .iter()
+                .map(|(key, value)| format!("{} {}", self::quoted(key), self::quoted(value)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+    }
+}
+
+fn print_il(p: &mut Printer, il: &Il) {
+    match il {
+        Il::Module(module) => p.line(format!("module {}", module.name)),
+        Il::Lambda(lambda) => p.node(
+            format!(
+                "lambda {} {:?}{}",
+                il_parameters(&lambda.parameters),
+                lambda.arity,
+                lambda
+                    .r#type
+                    .as_ref()
+                    .map(|t| format!(" -> {t}"))
+                    .unwrap_or_default()
+            ),
+            |p| {
+                if !lambda.upvalues.is_empty() {
+                    let upvalues = lambda
+                        .upvalues
+                        .iter()
+                        .map(|upvalue| format!("{upvalue:?}"))
+                        .collect::<Vec<_>>();
+                    p.line(format!("upvalues {}", upvalues.join(" ")));
+                }
+                for (index, default) in &lambda.optional {
+                    p.node(format!("default {index}"), |p| print_il(p, default));
+                }
+                ils(p, &lambda.body);
+            },
+        ),
+        Il::If(r#if) => p.node("if", |p| {
+            print_il(p, &r#if.predicate);
+            print_il(p, &r#if.then);
+            print_il(p, &r#if.r#else);
+        }),
+        Il::Apply(apply) => p.node("apply", |p| {
+            print_il(p, &apply.function);
+            print_il(p, &apply.list);
+        }),
+        Il::Def(il::Def::Global {
+            parameter, body, ..
+        }) => p.node(format!("def global {}", il_parameter(parameter)), |p| {
+            print_il(p, body)
+        }),
+        Il::Def(il::Def::Module {
+            parameter,
+            module,
+            body,
+            ..
+        }) => p.node(
+            format!("def module {module}::{}", il_parameter(parameter)),
+            |p| print_il(p, body),
+        ),
+        Il::Set(set) => p.node(format!("set! {}", var_ref(&set.target)), |p| {
+            print_il(p, &set.body)
+        }),
+        Il::FnCall(fn_call) => p.node("call", |p| {
+            print_il(p, &fn_call.function);
+            ils(p, &fn_call.args);
+        }),
+        Il::ArithmeticOperation(op) => p.node(
+            match op.operator {
+                il::ArithmeticOperator::Add => "+",
+                il::ArithmeticOperator::Sub => "-",
+                il::ArithmeticOperator::Mul => "*",
+                il::ArithmeticOperator::Div => "/",
+            },
+            |p| {
+                print_il(p, &op.lhs);
+                print_il(p, &op.rhs);
+            },
+        ),
+        Il::ComparisonOperation(op) => p.node(
+            match op.operator {
+                il::ComparisonOperator::Eq => "=",
+                il::ComparisonOperator::Lt => "<",
+                il::ComparisonOperator::Gt => ">",
+            },
+            |p| {
+                print_il(p, &op.lhs);
+                print_il(p, &op.rhs);
+            },
+        ),
+        Il::List(list) => p.node("list", |p| ils(p, &list.exprs)),
+        Il::Cons(cons) => p.node("cons", |p| {
+            print_il(p, &cons.lhs);
+            print_il(p, &cons.rhs);
+        }),
+        Il::Car(car) => p.node("car", |p| print_il(p, &car.body)),
+        Il::Cdr(cdr) => p.node("cdr", |p| print_il(p, &cdr.body)),
+        Il::SetCar(set_car) => p.node("set-car!", |p| {
+            print_il(p, &set_car.cons);
+            print_il(p, &set_car.body);
+        }),
+        Il::SetCdr(set_cdr) => p.node("set-cdr!", |p| {
+            print_il(p, &set_cdr.cons);
+            print_il(p, &set_cdr.body);
+        }),
+        Il::MapCreate(_) => p.line("map-create"),
+        Il::MapInsert(map_insert) => p.node("map-insert!", |p| {
+            print_il(p, &map_insert.map);
+            print_il(p, &map_insert.key);
+            print_il(p, &map_insert.value);
+        }),
+        Il::MapRetrieve(map_retrieve) => p.node("map-retrieve", |p| {
+            print_il(p, &map_retrieve.map);
+            print_il(p, &map_retrieve.key);
+        }),
+        Il::MapItems(map_items) => p.node("map-items", |p| print_il(p, &map_items.map)),
+        Il::Gensym(_) => p.line("gensym"),
+        Il::Documentation(documentation) => {
+            p.node("documentation", |p| print_il(p, &documentation.function))
+        }
+        Il::Match(r#match) => p.node("match", |p| {
+            print_il(p, &r#match.scrutinee);
+            for clause in &r#match.clauses {
+                p.node("clause", |p| {
+                    il_pattern(p, &clause.pattern);
+                    if let Some(guard) = &clause.guard {
+                        p.node("guard", |p| print_il(p, guard));
+                    }
+                    print_il(p, &clause.body);
+                });
+            }
+        }),
+        Il::Case(case) => p.node("case", |p| {
+            print_il(p, &case.scrutinee);
+            for clause in &case.clauses {
+                p.node("clause", |p| {
+                    p.node("keys", |p| ils(p, &clause.keys));
+                    print_il(p, &clause.body);
+                });
+            }
+            if let Some(default) = &case.default {
+                p.node("default", |p| print_il(p, default));
+            }
+        }),
+        Il::Loop(r#loop) => p.node("loop", |p| {
+            print_il(p, &r#loop.test);
+            ils(p, &r#loop.body);
+        }),
+        Il::IsType(is_type) => p.node(format!("is-type {:?}", is_type.r#type), |p| {
+            print_il(p, &is_type.body)
+        }),
+        Il::Assert(assert) => p.node("assert", |p| print_il(p, &assert.body)),
+        Il::VarRef(var) => p.line(var_ref(var)),
+        Il::Constant(constant) => p.line(match constant {
+            il::Constant::Symbol { symbol, .. } => format!("symbol {symbol}"),
+            il::Constant::String { string, .. } => format!("string {string:?}"),
+            il::Constant::Char { char, .. } => format!("char {char:?}"),
+            il::Constant::Int { int, .. } => format!("int {int}"),
+            il::Constant::Bool { bool, .. } => format!("bool {bool}"),
+            il::Constant::Nil { .. } => "nil".to_string(),
+        }),
+    }
+}
+
+fn ils(p: &mut Printer, ils: &[Il]) {
+    for il in ils {
+        print_il(p, il);
+    }
+}
+
+fn il_pattern(p: &mut Printer, pattern: &il::Pattern) {
+    match pattern {
+        il::Pattern::Wildcard => p.line("_"),
+        il::Pattern::Literal(literal) => print_il(p, literal),
+        il::Pattern::Cons(car, cdr) => p.node("cons", |p| {
+            il_pattern(p, car);
+            il_pattern(p, cdr);
+        }),
+    }
+}
+
+fn il_parameter(parameter: &il::Parameter) -> String {
+    match &parameter.r#type {
+        Some(r#type) => format!("{}: {type}", parameter.name),
+        None => parameter.name.clone(),
+    }
+}
+
+fn il_parameters(parameters: &il::Parameters) -> String {
+    let names = match parameters {
+        il::Parameters::Nary(parameters) => parameters.iter().map(il_parameter).collect(),
+        il::Parameters::Variadic(parameters) => {
+            let (rest, required) = parameters.split_last().unwrap();
+            required
+                .iter()
+                .map(il_parameter)
+                .chain([format!("&rest {}", il_parameter(rest))])
+                .collect::<Vec<_>>()
+        }
+    };
+
+    format!("({})", names.join(" "))
+}
+
+fn var_ref(var: &il::VarRef) -> String {
+    let (kind, name, r#type) = match var {
+        il::VarRef::Local {
+            name,
+            index,
+            r#type,
+            ..
+        } => (format!("local {index}"), name.clone(), r#type),
+        il::VarRef::UpValue {
+            name,
+            index,
+            r#type,
+            ..
+        } => (format!("upvalue {index}"), name.clone(), r#type),
+        il::VarRef::Global { name, r#type, .. } => ("global".to_string(), name.clone(), r#type),
+        il::VarRef::Module {
+            name,
+            module,
+            r#type,
+            ..
+        } => ("module".to_string(), format!("{module}::{name}"), r#type),
+    };
+
+    match r#type {
+        Some(r#type) => format!("{kind} {name}: {type}"),
+        None => format!("{kind} {name}"),
+    }
+}
@@ -0,0 +1,438 @@
+use crate::ast::Ast;
+use crate::il::{self, Error, Il, VarRef};
+use crate::types::{Signature, Type};
+use std::collections::{HashMap, HashSet};
+use vm::UpValue;
+
+type Signatures = HashMap<(Option<String>, String), Signature>;
+
+// Returns the type of il when it's known. Anything unannotated is unknown, and
+// only known types that can't be used where they end up are errors.
+pub(crate) fn check(il: &Il, signatures: &Signatures) -> Result<Option<Type>, Error> {
+    Checker {
+        signatures,
+        frames: Vec::new(),
+    }
+    .check(il)
+}
+
+// The type of what lambda returns, if the types its body depends on are known.
+// A union is as good as unknown here since a caller can't rely on it.
+pub(crate) fn infer_result(lambda: &il::Lambda, signatures: &Signatures) -> Option<Type> {
+    let mut checker = Checker {
+        signatures,
+        frames: Vec::new(),
+    };
+
+    match checker.check_lambda(lambda, &[]) {
+        Ok(Some(Type::Union(_))) | Err(_) => None,
+        Ok(result) => result,
+    }
+}
+
+// The types of a lambda's locals and where its upvalues come from, so that
+// locals whose types were inferred can be looked up through closures.
+struct Frame {
+    locals: Vec<Option<Type>>,
+    upvalues: Vec<UpValue>,
+}
+
+struct Checker<'a> {
+    signatures: &'a Signatures,
+    frames: Vec<Frame>,
+}
+
+impl Checker<'_> {
+    fn check(&mut self, il: &Il) -> Result<Option<Type>, Error> {
+        Ok(match il {
+            Il::Module(_) | Il::MapCreate(_) => None,
+            Il::Gensym(_) => Some(Type::Symbol),
+            Il::Constant(constant) => Some(match constant {
+                il::Constant::Symbol { .. } => Type::Symbol,
+                il::Constant::String { .. } => Type::String,
+                il::Constant::Char { .. } => Type::Char,
+                il::Constant::Int { .. } => Type::Int,
+                il::Constant::Bool { .. } => Type::Bool,
+                il::Constant::Nil { .. } => Type::Nil,
+            }),
+            Il::VarRef(VarRef::Local { index, r#type, .. }) => r#type
+                .clone()
+                .or_else(|| self.local_type(self.frames.len(), UpValue::Local(*index))),
+            Il::VarRef(VarRef::UpValue { index, r#type, .. }) => r#type
+                .clone()
+                .or_else(|| self.local_type(self.frames.len(), UpValue::UpValue(*index))),
+            Il::VarRef(VarRef::Global { r#type, .. } | VarRef::Module { r#type, .. }) => {
+                r#type.clone()
+            }
+            Il::Lambda(lambda) => {
+                self.check_lambda(lambda, &[])?;
+
+                Some(Type::Function)
+            }
+            Il::If(r#if) => {
+                self.expect(&r#if.predicate, &Type::Bool, "if predicate")?;
+
+                let then = self.check(&r#if.then)?;
+                let r#else = self.check(&r#if.r#else)?;
+
+                then.zip(r#else).map(|(then, r#else)| then.join(r#else))
+            }
+            Il::Def(il::Def::Global {
+                parameter, body, ..
+            })
+            | Il::Def(il::Def::Module {
+                parameter, body, ..
+            }) => {
+                match &parameter.r#type {
+                    Some(expected) => self.expect(body, expected, &parameter.name)?,
+                    None => self.check(body)?,
+                };
+
+                None
+            }
+            Il::Set(set) => {
+                let (VarRef::Local { name, r#type, .. }
+                | VarRef::UpValue { name, r#type, .. }
+                | VarRef::Global { name, r#type, .. }
+                | VarRef::Module { name, r#type, .. }) = &set.target;
+
+                match r#type {
+                    Some(expected) => self.expect(&set.body, expected, name)?,
+                    None => self.check(&set.body)?,
+                }
+            }
+            Il::FnCall(fncall) => self.check_fncall(fncall)?,
+            Il::Apply(apply) => {
+                self.expect(&apply.function, &Type::Function, "applied value")?;
+                self.check(&apply.list)?;
+
+                None
+            }
+            Il::ArithmeticOperation(op) => {
+                let lhs = self.expect(&op.lhs, &Type::Int, "arithmetic operand")?;
+                let rhs = self.expect(&op.rhs, &Type::Int, "arithmetic operand")?;
+
+                op.ints
+                    .set(lhs == Some(Type::Int) && rhs == Some(Type::Int));
+
+                Some(Type::Int)
+            }
+            Il::ComparisonOperation(op) => {
+                self.check(&op.lhs)?;
+                self.check(&op.rhs)?;
+
+                Some(Type::Bool)
+            }
+            Il::List(list) => {
+                for expr in &list.exprs {
+                    self.check(expr)?;
+                }
+
+                Some(if list.exprs.is_empty() {
+                    Type::Nil
+                } else {
+                    Type::Cons
+                })
+            }
+            Il::Cons(cons) => {
+                self.check(&cons.lhs)?;
+                self.check(&cons.rhs)?;
+
+                Some(Type::Cons)
+            }
+            Il::Car(il::Car { body, .. }) | Il::Cdr(il::Cdr { body, .. }) => {
+                self.check(body)?;
+
+                None
+            }
+            Il::SetCar(il::SetCar { cons, body, .. })
+            | Il::SetCdr(il::SetCdr { cons, body, .. }) => {
+                self.check(cons)?;
+                self.check(body)?
+            }
+            Il::MapInsert(map_insert) => {
+                self.check(&map_insert.map)?;
+                self.check(&map_insert.key)?;
+                self.check(&map_insert.value)?
+            }
+            Il::MapRetrieve(map_retrieve) => {
+                self.check(&map_retrieve.map)?;
+                self.check(&map_retrieve.key)?;
+
+                None
+            }
+            Il::MapItems(map_items) => {
+                self.check(&map_items.map)?;
+
+                None
+            }
+            Il::Documentation(documentation) => {
+                self.check(&documentation.function)?;
+
+                None
+            }
+            Il::Match(r#match) => {
+                self.check(&r#match.scrutinee)?;
+
+                for clause in &r#match.clauses {
+                    if let Some(guard) = &clause.guard {
+                        self.check(guard)?;
+                    }
+                    self.check(&clause.body)?;
+                }
+
+                None
+            }
+            Il::Case(case) => {
+                self.check(&case.scrutinee)?;
+
+                for clause in &case.clauses {
+                    self.check(&clause.body)?;
+                }
+
+                if let Some(default) = &case.default {
+                    self.check(default)?;
+                }
+
+                None
+            }
+            Il::Loop(r#loop) => {
+                self.expect(&r#loop.test, &Type::Bool, "loop test")?;
+
+                for expr in &r#loop.body {
+                    self.check(expr)?;
+                }
+
+                Some(Type::Nil)
+            }
+            Il::IsType(is_type) => {
+                self.check(&is_type.body)?;
+
+                Some(Type::Bool)
+            }
+            Il::Assert(assert) => {
+                self.check(&assert.body)?;
+
+                None
+            }
+        })
+    }
+
+    // Untyped parameters take the types in inferred, which are the arguments
+    // when the lambda is applied immediately like let does, unless the body
+    // assigns to them. Returns the type of the body's result.
+    fn check_lambda(
+        &mut self,
+        lambda: &il::Lambda,
+        inferred: &[Option<Type>],
+    ) -> Result<Option<Type>, Error> {
+        let assigned = if inferred.iter().any(Option::is_some) {
+            assigned_locals(lambda)
+        } else {
+            HashSet::new()
+        };
+
+        // arguments to a rest parameter are collected into a list, so only
+        // fixed parameters are inferred
+        let locals = match &lambda.parameters {
+            il::Parameters::Nary(parameters) => parameters
+                .iter()
+                .enumerate()
+                .map(|(i, parameter)| {
+                    parameter.r#type.clone().or_else(|| {
+                        inferred
+                            .get(i)
+                            .cloned()
+                            .flatten()
+                            .filter(|_| !assigned.contains(&i))
+                    })
+                })
+                .collect(),
+            il::Parameters::Variadic(parameters) => parameters
+                .iter()
+                .map(|parameter| parameter.r#type.clone())
+                .collect(),
+        };
+
+        self.frames.push(Frame {
+            locals,
+            upvalues: lambda.upvalues.clone(),
+        });
+
+        let result = self.check_lambda_body(lambda);
+
+        self.frames.pop();
+
+        result
+    }
+
+    fn check_lambda_body(&mut self, lambda: &il::Lambda) -> Result<Option<Type>, Error> {
+        for (_, default) in &lambda.optional {
+            self.check(default)?;
+        }
+
+        let mut result = Some(Type::Nil);
+        for expr in &lambda.body {
+            result = self.check(expr)?;
+        }
+
+        if let (Some(expected), Some(actual)) = (&lambda.r#type, &result)
+            && !expected.accepts(actual)
+        {
+            return Err(mismatch(
+                lambda.body.last().map_or(&lambda.source, Il::source_ast),
+                "return value",
+                expected,
+                actual,
+            ));
+        }
+
+        Ok(lambda.r#type.clone().or(result))
+    }
+
+    // Follows upvalues out through the enclosing frames to the local they
+    // capture. depth is the number of frames that are visible.
+    fn local_type(&self, depth: usize, slot: UpValue) -> Option<Type> {
+        let frame = self.frames.get(depth.checked_sub(1)?)?;
+
+        match slot {
+            UpValue::Local(i) => frame.locals.get(i).cloned().flatten(),
+            UpValue::UpValue(i) => self.local_type(depth - 1, *frame.upvalues.get(i)?),
+        }
+    }
+
+    fn check_fncall(&mut self, fncall: &il::FnCall) -> Result<Option<Type>, Error> {
+        if let Il::Lambda(lambda) = &*fncall.function {
+            let signature = lambda_signature(lambda);
+            let args = self.check_args(&fncall.args, Some(&signature))?;
+            let inferred = args
+                .into_iter()
+                .map(|arg| arg.filter(|t| !matches!(t, Type::Nil | Type::Union(_))))
+                .collect::<Vec<_>>();
+
+            return self.check_lambda(lambda, inferred.as_slice());
+        }
+
+        let signature = match &*fncall.function {
+            Il::VarRef(VarRef::Global { name, .. }) => {
+                self.signatures.get(&(None, name.clone())).cloned()
+            }
+            Il::VarRef(VarRef::Module { name, module, .. }) => self
+                .signatures
+                .get(&(Some(module.clone()), name.clone()))
+                .cloned(),
+            _ => None,
+        };
+
+        self.expect(&fncall.function, &Type::Function, "called value")?;
+        self.check_args(&fncall.args, signature.as_ref())?;
+
+        Ok(signature.and_then(|signature| signature.r#return))
+    }
+
+    fn check_args(
+        &mut self,
+        args: &[Il],
+        signature: Option<&Signature>,
+    ) -> Result<Vec<Option<Type>>, Error> {
+        args.iter()
+            .enumerate()
+            .map(
+                |(i, arg)| match signature.and_then(|signature| signature.parameter(i)) {
+                    Some(expected) => self.expect(arg, expected, &format!("argument {}", i + 1)),
+                    None => self.check(arg),
+                },
+            )
+            .collect()
+    }
+
+    fn expect(&mut self, il: &Il, expected: &Type, what: &str) -> Result<Option<Type>, Error> {
+        let actual = self.check(il)?;
+
+        match &actual {
+            Some(actual) if !expected.accepts(actual) => {
+                Err(mismatch(il.source_ast(), what, expected, actual))
+            }
+            _ => Ok(actual),
+        }
+    }
+}
+
+// Indices of the lambda's locals that are set! anywhere in its body, including
+// through the upvalues of lambdas nested in it.
+fn assigned_locals(lambda: &il::Lambda) -> HashSet<usize> {
+    fn walk(il: &Il, resolve: &dyn Fn(UpValue) -> Option<usize>, assigned: &mut HashSet<usize>) {
+        match il {
+            Il::Set(il::Set {
+                target: VarRef::Local { index, .. },
+                ..
+            }) => assigned.extend(resolve(UpValue::Local(*index))),
+            Il::Set(il::Set {
+                target: VarRef::UpValue { index, .. },
+                ..
+            }) => assigned.extend(resolve(UpValue::UpValue(*index))),
+            _ => (),
+        }
+
+        match il {
+            Il::Lambda(nested) => {
+                let resolve = |slot| match slot {
+                    UpValue::Local(_) => None,
+                    UpValue::UpValue(i) => resolve(*nested.upvalues.get(i)?),
+                };
+
+                for child in il.children() {
+                    walk(child, &resolve, assigned);
+                }
+            }
+            _ => {
+                for child in il.children() {
+                    walk(child, resolve, assigned);
+                }
+            }
+        }
+    }
+
+    let mut assigned = HashSet::new();
+    let resolve = |slot| match slot {
+        UpValue::Local(i) => Some(i),
+        UpValue::UpValue(_) => None,
+    };
+
+    for (_, default) in &lambda.optional {
+        walk(default, &resolve, &mut assigned);
+    }
+
+    for expr in &lambda.body {
+        walk(expr, &resolve, &mut assigned);
+    }
+
+    assigned
+}
+
+fn lambda_signature(lambda: &il::Lambda) -> Signature {
+    let (parameters, rest) = match &lambda.parameters {
+        il::Parameters::Nary(parameters) => (parameters.as_slice(), None),
+        il::Parameters::Variadic(parameters) => match parameters.split_last() {
+            Some((rest, parameters)) => (parameters, rest.r#type.clone()),
+            None => (parameters.as_slice(), None),
+        },
+    };
+
+    Signature {
+        arity: lambda.arity,
+        parameters: parameters
+            .iter()
+            .map(|parameter| parameter.r#type.clone())
+            .collect(),
+        rest,
+        r#return: lambda.r#type.clone(),
+    }
+}
+
+fn mismatch(ast: &Ast, what: &str, expected: &Type, actual: &Type) -> Error {
+    Error::Type {
+        span: ast.source_span().clone(),
+        message: format!("{what} expected {expected}, found {actual}"),
+    }
+}
@@ -1,5 +1,16 @@
 use crate::ast;
 use std::collections::BTreeSet;
+use std::fmt;
+use vm::Arity;
+
+// What's known about a top-level function from its def or decl.
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub arity: Arity,
+    pub parameters: Vec<Option<Type>>,
+    pub rest: Option<Type>,
+    pub r#return: Option<Type>,
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Type {
@@ -27,17 +38,132 @@ impl Type {
                 ),
                 _ => return Err(()),
             },
-            ast::Type::Scalar(scalar) => match scalar.as_str() {
-                "cons" => Type::Cons,
-                "function" => Type::Function,
-                "symbol" => Type::Symbol,
-                "string" => Type::String,
-                "char" => Type::Char,
-                "int" => Type::Int,
-                "bool" => Type::Bool,
-                "nil" => Type::Nil,
-                _ => return Err(()),
-            },
+            ast::Type::Scalar(scalar) => Type::from_scalar(scalar).ok_or(())?,
         })
     }
+
+    pub fn from_scalar(scalar: &str) -> Option<Self> {
+        Some(match scalar {
+            "cons" => Type::Cons,
+            "function" => Type::Function,
+            "symbol" => Type::Symbol,
+            "string" => Type::String,
+            "char" => Type::Char,
+            "int" => Type::Int,
+            "bool" => Type::Bool,
+            "nil" => Type::Nil,
+            _ => return None,
+        })
+    }
+
+    // Whether a value of type actual can be used where self is expected. A list
+    // may be a cons or nil, so either is accepted for one and vice versa.
+    pub fn accepts(&self, actual: &Type) -> bool {
+        match (self, actual) {
+            _ if self == actual => true,
+            (_, Type::Union(actuals)) => actuals.iter().all(|actual| self.accepts(actual)),
+            (Type::Union(expected), _) => expected.iter().any(|t| t.accepts(actual)),
+            (Type::List(_), Type::Cons | Type::Nil) | (Type::Cons, Type::List(_)) => true,
+            (Type::List(expected), Type::List(actual)) => expected.accepts(actual),
+            _ => false,
+        }
+    }
+
+    pub fn join(self, other: Type) -> Type {
+        if self == other {
+            return self;
+        }
+
+        let members = |t: Type| match t {
+            Type::Union(types) => types,
+            t => BTreeSet::from([t]),
+        };
+
+        Type::Union(members(self).into_iter().chain(members(other)).collect())
+    }
+}
+
+impl Signature {
+    // Decls name their parameters by type, so (decl f (lambda (string))) takes a
+    // string, while untyped parameters of a def are unknown.
+    pub fn from_lambda(arity: Arity, lambda: &ast::Lambda, decl: bool) -> Result<Self, ()> {
+        let r#type = |parameter: &ast::Parameter| match &parameter.r#type {
+            Some(t) => Type::from_ast(t).map(Some),
+            None if decl => Ok(Type::from_scalar(parameter.name.as_str())),
+            None => Ok(None),
+        };
+
+        let (parameters, rest): (Result<Vec<_>, ()>, _) = match &lambda.parameters {
+            ast::Parameters::Normal(required) => (required.iter().map(r#type).collect(), None),
+            ast::Parameters::Rest(required, rest) => {
+                (required.iter().map(r#type).collect(), r#type(rest)?)
+            }
+            ast::Parameters::Optional(required, optional) => (
+                required
+                    .iter()
+                    .chain(optional.iter().map(|(parameter, _)| parameter))
+                    .map(r#type)
+                    .collect(),
+                None,
+            ),
+        };
+
+        Ok(Self {
+            arity,
+            parameters: parameters?,
+            rest,
+            r#return: lambda.r#type.as_ref().map(Type::from_ast).transpose()?,
+        })
+    }
+
+    // Fills in anything unknown from other, if it takes the same arguments.
+    pub fn merge(&mut self, other: &Signature) {
+        if self.arity != other.arity {
+            return;
+        }
+
+        for (t, other) in self.parameters.iter_mut().zip(&other.parameters) {
+            if t.is_none() {
+                t.clone_from(other);
+            }
+        }
+
+        if self.rest.is_none() {
+            self.rest.clone_from(&other.rest);
+        }
+
+        if self.r#return.is_none() {
+            self.r#return.clone_from(&other.r#return);
+        }
+    }
+
+    pub fn parameter(&self, index: usize) -> Option<&Type> {
+        match self.parameters.get(index) {
+            Some(t) => t.as_ref(),
+            None => self.rest.as_ref(),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::List(t) => write!(f, "(list {t})"),
+            Type::Cons => write!(f, "cons"),
+            Type::Function => write!(f, "function"),
+            Type::Symbol => write!(f, "symbol"),
+            Type::String => write!(f, "string"),
+            Type::Char => write!(f, "char"),
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::Union(types) => {
+                write!(f, "(union")?;
+                for t in types {
+                    write!(f, " {t}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
 }
@@ -70,6 +70,13 @@ impl<T: Trace + 'static> Gc<T> {
     }
 }
 
+impl<T: Trace> Gc<T> {
+    #[must_use]
+    pub fn as_ptr(this: &Self) -> *const T {
+        unsafe { std::ptr::addr_of!((*this.inner.as_ptr()).data) }
+    }
+}
+
 impl<T: Trace + ?Sized> Clone for Gc<T> {
     fn clone(&self) -> Self {
         unsafe {
@@ -8,7 +8,7 @@ pub use crate::gc::Gc;
 pub use crate::gc::Inner;
 
 use std::cell::Cell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::BuildHasher;
 use std::mem;
 use std::ptr::NonNull;
@@ -107,6 +107,12 @@ unsafe impl Trace for String {
     unsafe fn trace(&self, _: &mut dyn FnMut(NonNull<Inner<dyn Trace>>) -> bool) {}
 }
 
+unsafe impl Trace for u8 {
+    unsafe fn root(&self) {}
+    unsafe fn unroot(&self) {}
+    unsafe fn trace(&self, _: &mut dyn FnMut(NonNull<Inner<dyn Trace>>) -> bool) {}
+}
+
 unsafe impl<T: Trace> Trace for &[T] {
     unsafe fn root(&self) {
         for element in *self {
@@ -141,6 +147,46 @@ unsafe impl<T: Trace> Trace for Vec<T> {
     }
 }
 
+unsafe impl<T: Trace> Trace for VecDeque<T> {
+    unsafe fn root(&self) {
+        for element in self {
+            element.root();
+        }
+    }
+
+    unsafe fn unroot(&self) {
+        for element in self {
+            element.unroot();
+        }
+    }
+
+    unsafe fn trace(&self, tracer: &mut dyn FnMut(NonNull<Inner<dyn Trace>>) -> bool) {
+        for element in self {
+            element.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace, S: BuildHasher> Trace for HashSet<T, S> {
+    unsafe fn root(&self) {
+        for element in self {
+            element.root();
+        }
+    }
+
+    unsafe fn unroot(&self) {
+        for element in self {
+            element.unroot();
+        }
+    }
+
+    unsafe fn trace(&self, tracer: &mut dyn FnMut(NonNull<Inner<dyn Trace>>) -> bool) {
+        for element in self {
+            element.trace(tracer);
+        }
+    }
+}
+
 unsafe impl<K: Trace, V: Trace, S: BuildHasher> Trace for HashMap<K, V, S> {
     unsafe fn root(&self) {
         for (k, v) in self {
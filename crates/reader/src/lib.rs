@@ -1,30 +1,78 @@
 use core::fmt;
-use logos::{Lexer, Logos};
-use std::{cmp::Ordering, ops::Range};
+use logos::{Lexer, Logos, Skip};
+use std::{cmp::Ordering, collections::HashMap, io::BufRead, ops::Range, rc::Rc};
 use thiserror::Error;
 use unwrap_enum::EnumIs;
 
+// Displayed the way compile errors are, with the line they're on underlined.
 #[derive(Clone, Error)]
-pub enum Error<'a> {
-    #[error("lexer error: remaining input: {0}")]
-    Lexer(&'a str),
+pub enum Error {
+    #[error("{}", .0.render_diagnostic("error: unexpected input"))]
+    Lexer(Span),
 
-    #[error("unbalanced parens")]
-    UnbalancedParens,
+    #[error("{}", .0.render_diagnostic("error: unbalanced parens"))]
+    UnbalancedParens(Span),
 
-    #[error("unexpected end of file")]
-    UnExpectedEof,
+    // The span is the start of whatever the input ended in the middle of.
+    #[error("{}", .0.render_diagnostic("error: unexpected end of file before this was closed"))]
+    UnexpectedEof(Span),
+
+    #[error("{}", .0.render_diagnostic("error: map literal has a key without a value"))]
+    UnpairedKey(Span),
+
+    #[error("failed to read input: {0}")]
+    Io(String),
+
+    #[error("{}", .span.render_diagnostic(&format!("error: #{tag}: {message}")))]
+    Dispatch {
+        span: Span,
+        tag: String,
+        message: String,
+    },
+}
+
+// What #tag followed by a form reads as is up to the handler registered for
+// the tag. It gets the form, #u8(1 2 3) passes (1 2 3), and returns the sexpr
+// to read in its place or a message saying what's wrong with it.
+pub type DispatchHandler = Rc<dyn Fn(Sexpr) -> Result<Sexpr, String>>;
+
+#[derive(Clone, Default)]
+struct Dispatch(Rc<HashMap<String, DispatchHandler>>);
+
+#[derive(Clone, Debug, Default)]
+struct Extras {
+    dispatch: Dispatch,
+    // Where the comments lexed so far are, for tools such as lispfmt that
+    // print the source back out.
+    comments: Vec<Range<usize>>,
 }
 
-#[derive(Clone, Debug, Logos)]
-#[logos(skip r#"[\s\t\n]|;.*\n"#)]
+#[derive(Clone, Debug, PartialEq, Logos)]
+#[logos(skip r#"[\s\t\n]"#)]
+#[logos(extras = Extras)]
 enum Token {
+    // Never returned, comments are skipped once their span is recorded.
+    #[regex(r#";[^\n]*"#, comment)]
+    Comment,
+
     #[token("(")]
     LeftParen,
 
     #[token(")")]
     RightParen,
 
+    #[token("{")]
+    LeftBrace,
+
+    #[token("}")]
+    RightBrace,
+
+    #[token("[")]
+    LeftBracket,
+
+    #[token("]")]
+    RightBracket,
+
     #[token("'")]
     Quote,
 
@@ -37,6 +85,12 @@ enum Token {
     #[token(",@")]
     Splice,
 
+    #[token(".")]
+    Dot,
+
+    #[token("#(")]
+    AnonymousFunction,
+
     #[token("true")]
     True,
 
@@ -46,17 +100,34 @@ enum Token {
     #[token("nil")]
     Nil,
 
-    #[regex(r#"[a-zA-Z+_*/?@^=!<>&-][a-zA-Z0-9+_*/?@^=!<>&:-]*"#)]
+    #[regex(r#"[a-zA-Z+_*/?@^=!<>&:-][a-zA-Z0-9+_*/?@^=!<>&:-]*"#)]
+    #[regex(r#"#:[a-zA-Z0-9+_*/?@^=!<>&-]+"#)]
+    #[regex(r#"%([0-9]+|&)?"#)]
     Symbol,
 
     #[regex(r#""[^"]*""#)]
+    #[token("#\"", raw_string)]
     String,
 
     #[regex(r#"'[^']{1}'"#)]
+    #[token("#\\", named_char)]
     Char,
 
+    // #x1f is an int rather than the dispatch tag x1f
     #[regex("[0-9]+")]
+    #[regex("(#|0)[xX][0-9a-fA-F]+", priority = 10)]
+    #[regex("(#|0)[oO][0-7]+", priority = 10)]
+    #[regex("(#|0)[bB][01]+", priority = 10)]
     Int,
+
+    #[regex("#[a-zA-Z][a-zA-Z0-9_-]*")]
+    Dispatch,
+}
+
+fn comment(lexer: &mut Lexer<Token>) -> Skip {
+    let span = lexer.span();
+    lexer.extras.comments.push(span);
+    Skip
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -68,39 +139,61 @@ enum Macro {
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, EnumIs)]
-pub enum Sexpr<'context> {
+pub enum Sexpr {
     List {
-        list: Vec<Sexpr<'context>>,
-        context: &'context Context,
+        list: Vec<Sexpr>,
+        context: Rc<Context>,
+        span: Range<usize>,
+    },
+    // (a b . c), whose last cdr is c rather than nil. The tail is never a list
+    // or nil, (a . (b c)) and (a . ()) read as (a b c) and (a).
+    DottedList {
+        list: Vec<Sexpr>,
+        tail: Box<Sexpr>,
+        context: Rc<Context>,
         span: Range<usize>,
     },
     Symbol {
         symbol: String,
-        context: &'context Context,
+        context: Rc<Context>,
         span: Range<usize>,
     },
     String {
         string: String,
-        context: &'context Context,
+        context: Rc<Context>,
         span: Range<usize>,
     },
     Char {
         char: char,
-        context: &'context Context,
+        context: Rc<Context>,
         span: Range<usize>,
     },
     Int {
         int: i64,
-        context: &'context Context,
+        context: Rc<Context>,
         span: Range<usize>,
     },
     Bool {
         bool: bool,
-        context: &'context Context,
+        context: Rc<Context>,
         span: Range<usize>,
     },
     Nil {
-        context: &'context Context,
+        context: Rc<Context>,
+        span: Range<usize>,
+    },
+    // [a b ...], which evaluates its elements into a list and quotes as a list
+    // of them.
+    Vector {
+        list: Vec<Sexpr>,
+        context: Rc<Context>,
+        span: Range<usize>,
+    },
+    // {k v ...}, which evaluates its entries into a map and quotes as a map of
+    // them.
+    Map {
+        list: Vec<Sexpr>,
+        context: Rc<Context>,
         span: Range<usize>,
     },
 }
@@ -109,12 +202,37 @@ pub enum Sexpr<'context> {
 pub struct Context {
     display: String,
     source: String,
+    // The line the source starts on, which is only past the first for the
+    // pieces of a larger input a StreamReader reads.
+    first_line: usize,
+}
+
+// Where a sexpr was read from. It keeps the source alive on its own, so the
+// compiler holds on to these for diagnostics and debug info rather than to the
+// sexprs, which can be dropped once their form is compiled.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Span {
+    context: Rc<Context>,
+    range: Range<usize>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Reader<'context> {
     lexer: Lexer<'context, Token>,
-    context: &'context Context,
+    context: Rc<Context>,
+}
+
+// Reads forms from input that doesn't have to be in memory all at once, like
+// a pipe or a large file. Lines are read until they hold a whole form, and each
+// form gets a context of its own with just the lines it was read from.
+#[derive(Debug)]
+pub struct StreamReader<R> {
+    input: R,
+    dispatch: Dispatch,
+    display: String,
+    buffer: String,
+    line: usize,
+    done: bool,
 }
 
 impl Context {
@@ -122,9 +240,14 @@ impl Context {
         Self {
             display: display.to_string(),
             source: source.to_string(),
+            first_line: 1,
         }
     }
 
+    pub fn with_first_line(self, first_line: usize) -> Self {
+        Self { first_line, ..self }
+    }
+
     pub fn source(&self) -> &str {
         self.source.as_str()
     }
@@ -136,18 +259,141 @@ impl Context {
     pub fn display(&self) -> &str {
         &self.display
     }
+
+    // 1-based, columns count bytes.
+    pub fn line_and_column(&self, offset: usize) -> (usize, usize) {
+        let before = &self.source[..offset];
+        let line = before.matches('\n').count() + self.first_line;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+
+        (line, column)
+    }
+
+    // The message followed by where the span is and the line it starts on,
+    // underlined, in the style of rustc:
+    //
+    // error: unknown variable referenced: x
+    //  --> main.lisp:3:8
+    //   |
+    // 3 | (print x)
+    //   |        ^
+    //
+    // A span that runs over several lines is underlined to the end of its first.
+    pub fn render_diagnostic(&self, span: Range<usize>, message: &str) -> String {
+        let start = span.start.min(self.source.len());
+        let (line, column) = self.line_and_column(start);
+
+        let line_start = start - (column - 1);
+        let line_end = self.source[start..]
+            .find('\n')
+            .map_or(self.source.len(), |i| start + i);
+        let text = &self.source[line_start..line_end];
+
+        let underline = span.end.clamp(start, line_end) - start;
+        let gutter = " ".repeat(line.to_string().len());
+
+        format!(
+            "{message}\n{gutter}--> {}:{line}:{column}\n{gutter} |\n{line} | {text}\n{gutter} | {}{}",
+            self.display,
+            " ".repeat(column - 1),
+            "^".repeat(underline.max(1))
+        )
+    }
+}
+
+impl Span {
+    pub fn new(context: Rc<Context>, range: Range<usize>) -> Self {
+        Self { context, range }
+    }
+
+    pub fn context(&self) -> &Rc<Context> {
+        &self.context
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    pub fn text(&self) -> &str {
+        self.context.span(self.range())
+    }
+
+    pub fn render_diagnostic(&self, message: &str) -> String {
+        self.context.render_diagnostic(self.range(), message)
+    }
+}
+
+impl Dispatch {
+    fn insert<F>(&mut self, tag: &str, handler: F)
+    where
+        F: Fn(Sexpr) -> Result<Sexpr, String> + 'static,
+    {
+        Rc::make_mut(&mut self.0).insert(tag.to_string(), Rc::new(handler));
+    }
 }
 
 impl<'context> Reader<'context> {
-    pub fn new(context: &'context Context) -> Self {
+    pub fn new(context: &'context Rc<Context>) -> Self {
+        let mut lexer = Lexer::new(context.source());
+
+        skip_shebang(&mut lexer);
+
         Self {
-            lexer: Lexer::new(context.source()),
-            context,
+            lexer,
+            context: context.clone(),
+        }
+    }
+
+    pub fn with_dispatch<F>(mut self, tag: &str, handler: F) -> Self
+    where
+        F: Fn(Sexpr) -> Result<Sexpr, String> + 'static,
+    {
+        self.lexer.extras.dispatch.insert(tag, handler);
+        self
+    }
+
+    // The spans of the comments in the source up to where reading has got to,
+    // all of them once every form has been read.
+    pub fn comments(&self) -> &[Range<usize>] {
+        &self.lexer.extras.comments
+    }
+}
+
+impl<R: BufRead> StreamReader<R> {
+    pub fn new(input: R, display: &str) -> Self {
+        Self {
+            input,
+            dispatch: Dispatch::default(),
+            display: display.to_string(),
+            buffer: String::new(),
+            line: 1,
+            done: false,
+        }
+    }
+
+    pub fn with_dispatch<F>(mut self, tag: &str, handler: F) -> Self
+    where
+        F: Fn(Sexpr) -> Result<Sexpr, String> + 'static,
+    {
+        self.dispatch.insert(tag, handler);
+        self
+    }
+
+    fn fill(&mut self) -> Result<(), Error> {
+        match self.input.read_line(&mut self.buffer) {
+            Ok(0) => self.done = true,
+            Ok(_) => (),
+            Err(error) => {
+                self.done = true;
+                return Err(Error::Io(error.to_string()));
+            }
         }
+
+        Ok(())
     }
 }
 
-impl<'context> Sexpr<'context> {
+impl Sexpr {
     pub fn as_list(&self) -> Option<&[Sexpr]> {
         match self {
             Self::List { list, .. } => Some(list.as_slice()),
@@ -190,165 +436,294 @@ impl<'context> Sexpr<'context> {
         }
     }
 
-    pub fn context(&self) -> &Context {
+    pub fn context(&self) -> &Rc<Context> {
         match self {
             Self::List { context, .. }
+            | Self::DottedList { context, .. }
             | Self::Symbol { context, .. }
             | Self::String { context, .. }
             | Self::Char { context, .. }
             | Self::Int { context, .. }
             | Self::Bool { context, .. }
-            | Self::Nil { context, .. } => context,
+            | Self::Nil { context, .. }
+            | Self::Vector { context, .. }
+            | Self::Map { context, .. } => context,
         }
     }
 
     pub fn span(&self) -> Range<usize> {
         match self {
             Self::List { span, .. }
+            | Self::DottedList { span, .. }
             | Self::Symbol { span, .. }
             | Self::String { span, .. }
             | Self::Char { span, .. }
             | Self::Int { span, .. }
             | Self::Bool { span, .. }
-            | Self::Nil { span, .. } => span.clone(),
+            | Self::Nil { span, .. }
+            | Self::Vector { span, .. }
+            | Self::Map { span, .. } => span.clone(),
         }
     }
 }
 
-impl<'context> Sexpr<'context> {
-    pub fn quote(&'context self) -> Sexpr<'context> {
+impl Sexpr {
+    pub fn render_diagnostic(&self, message: &str) -> String {
+        self.context().render_diagnostic(self.span(), message)
+    }
+
+    pub fn source_span(&self) -> Span {
+        Span {
+            context: self.context().clone(),
+            range: self.span(),
+        }
+    }
+
+    pub fn quote(&self) -> Sexpr {
         let quote = Sexpr::Symbol {
             symbol: "quote".to_string(),
-            context: self.context(),
+            context: self.context().clone(),
             span: self.span(),
         };
 
         Sexpr::List {
             list: vec![quote, self.clone()],
-            context: self.context(),
+            context: self.context().clone(),
             span: self.span(),
         }
     }
 }
 
 impl<'context> Iterator for Reader<'context> {
-    type Item = Result<Sexpr<'context>, Error<'context>>;
+    type Item = Result<Sexpr, Error>;
     fn next(&mut self) -> Option<Self::Item> {
-        read(&mut self.lexer, self.context)
+        read(&mut self.lexer, &self.context)
+    }
+}
+
+impl<R: BufRead> Iterator for StreamReader<R> {
+    type Item = Result<Sexpr, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let context =
+                Rc::new(Context::new(&self.buffer, &self.display).with_first_line(self.line));
+            let mut lexer = Lexer::with_extras(
+                context.source(),
+                Extras {
+                    dispatch: self.dispatch.clone(),
+                    comments: Vec::new(),
+                },
+            );
+
+            if self.line == 1 {
+                skip_shebang(&mut lexer);
+            }
+
+            match read(&mut lexer, &context) {
+                None if !self.done => {
+                    // nothing but whitespace and comments so far
+                    self.line += self.buffer.matches('\n').count();
+                    self.buffer.clear();
+
+                    if let Err(error) = self.fill() {
+                        return Some(Err(error));
+                    }
+                }
+                Some(Err(Error::UnexpectedEof(_))) if !self.done => {
+                    if let Err(error) = self.fill() {
+                        return Some(Err(error));
+                    }
+                }
+                None => return None,
+                Some(result) => {
+                    // an error throws away the rest of what was buffered
+                    let end = match result {
+                        Ok(_) => lexer.span().end,
+                        Err(_) => self.buffer.len(),
+                    };
+
+                    self.line += self.buffer[..end].matches('\n').count();
+                    self.buffer.drain(..end);
+
+                    return Some(result);
+                }
+            }
+        }
     }
 }
 
-impl<'context> fmt::Display for Sexpr<'context> {
+impl fmt::Display for Sexpr {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Sexpr::List { list, .. } => {
+            Sexpr::List { list, .. } => write_elements(f, "(", list, ")"),
+            Sexpr::DottedList { list, tail, .. } => {
                 write!(f, "(")?;
-                for (i, sexpr) in list.iter().enumerate() {
-                    write!(f, "{sexpr}")?;
-                    if i < list.len() - 1 {
-                        write!(f, " ")?;
-                    }
+                for sexpr in list {
+                    write!(f, "{sexpr} ")?;
                 }
-                write!(f, ")")?;
-                Ok(())
+                write!(f, ". {tail})")
             }
             Sexpr::Symbol { symbol, .. } => write!(f, "{symbol}"),
+            Sexpr::String { string, .. } if string.contains('"') => write!(f, r##"#"{string}"#"##),
             Sexpr::String { string, .. } => write!(f, r#""{string}""#),
             Sexpr::Char { char, .. } => write!(f, "'{char}'"),
             Sexpr::Int { int, .. } => write!(f, "{int}"),
             Sexpr::Bool { bool, .. } if !bool => write!(f, "false"),
             Sexpr::Bool { .. } => write!(f, "true"),
             Self::Nil { .. } => write!(f, "()"),
+            Self::Vector { list, .. } => write_elements(f, "[", list, "]"),
+            Self::Map { list, .. } => write_elements(f, "{", list, "}"),
+        }
+    }
+}
+
+fn write_elements(
+    f: &mut fmt::Formatter<'_>,
+    open: &str,
+    list: &[Sexpr],
+    close: &str,
+) -> fmt::Result {
+    write!(f, "{open}")?;
+    for (i, sexpr) in list.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
         }
+        write!(f, "{sexpr}")?;
     }
+    write!(f, "{close}")
 }
 
 fn read<'context>(
     lexer: &mut Lexer<'context, Token>,
-    context: &'context Context,
-) -> Option<Result<Sexpr<'context>, Error<'context>>> {
+    context: &Rc<Context>,
+) -> Option<Result<Sexpr, Error>> {
     Some(Ok(match lexer.next()? {
-        Ok(Token::LeftParen) => match read_list(lexer, context) {
+        Ok(Token::LeftParen) => match read_list(lexer, context, Token::RightParen) {
+            Ok(sexpr) => sexpr,
+            Err(error) => return Some(Err(error)),
+        },
+        Ok(Token::LeftBrace) => match read_literal(lexer, context, Token::RightBrace) {
             Ok(sexpr) => sexpr,
-            Err(_) => return Some(Err(Error::Lexer(lexer.remainder()))),
+            Err(error) => return Some(Err(error)),
+        },
+        Ok(Token::LeftBracket) => match read_literal(lexer, context, Token::RightBracket) {
+            Ok(sexpr) => sexpr,
+            Err(error) => return Some(Err(error)),
+        },
+        Ok(Token::RightParen | Token::RightBrace | Token::RightBracket) => {
+            return Some(Err(Error::UnbalancedParens(token_span(lexer, context))))
+        }
+        Ok(Token::AnonymousFunction) => match read_anonymous_function(lexer, context) {
+            Ok(sexpr) => sexpr,
+            Err(error) => return Some(Err(error)),
+        },
+        Ok(Token::Dispatch) => match read_dispatch(lexer, context) {
+            Ok(sexpr) => sexpr,
+            Err(error) => return Some(Err(error)),
         },
-        Ok(Token::RightParen) => return Some(Err(Error::UnbalancedParens)),
         Ok(Token::Quote) => match expand_macro(lexer, context, Macro::Quote) {
             Ok(sexpr) => sexpr,
-            Err(_) => return Some(Err(Error::Lexer(lexer.remainder()))),
+            Err(error) => return Some(Err(error)),
         },
         Ok(Token::QuasiQuote) => match expand_macro(lexer, context, Macro::QuasiQuote) {
             Ok(sexpr) => sexpr,
-            Err(_) => return Some(Err(Error::Lexer(lexer.remainder()))),
+            Err(error) => return Some(Err(error)),
         },
         Ok(Token::UnQuote) => match expand_macro(lexer, context, Macro::UnQuote) {
             Ok(sexpr) => sexpr,
-            Err(_) => return Some(Err(Error::Lexer(lexer.remainder()))),
+            Err(error) => return Some(Err(error)),
         },
         Ok(Token::Splice) => match expand_macro(lexer, context, Macro::Splice) {
             Ok(sexpr) => sexpr,
-            Err(_) => return Some(Err(Error::Lexer(lexer.remainder()))),
+            Err(error) => return Some(Err(error)),
         },
         Ok(Token::Symbol) => Sexpr::Symbol {
             symbol: lexer.slice().to_string(),
-            context,
+            context: context.clone(),
             span: lexer.span(),
         },
         Ok(Token::String) => Sexpr::String {
-            string: lexer.slice()[1..lexer.slice().len() - 1].to_string(),
-            context,
+            string: read_string(lexer.slice()),
+            context: context.clone(),
             span: lexer.span(),
         },
-        Ok(Token::Char) => Sexpr::Char {
-            char: lexer.slice().parse().unwrap(),
-            context,
-            span: lexer.span(),
+        Ok(Token::Char) => match read_char(lexer.slice()) {
+            Some(char) => Sexpr::Char {
+                char,
+                context: context.clone(),
+                span: lexer.span(),
+            },
+            None => return Some(Err(Error::Lexer(token_span(lexer, context)))),
         },
-        Ok(Token::Int) => Sexpr::Int {
-            int: lexer.slice().parse().unwrap(),
-            context,
-            span: lexer.span(),
+        Ok(Token::Int) => match read_int(lexer.slice()) {
+            Some(int) => Sexpr::Int {
+                int,
+                context: context.clone(),
+                span: lexer.span(),
+            },
+            None => return Some(Err(Error::Lexer(token_span(lexer, context)))),
         },
         Ok(Token::True) => Sexpr::Bool {
             bool: true,
-            context,
+            context: context.clone(),
             span: lexer.span(),
         },
         Ok(Token::False) => Sexpr::Bool {
             bool: false,
-            context,
+            context: context.clone(),
             span: lexer.span(),
         },
         Ok(Token::Nil) => Sexpr::Nil {
-            context,
+            context: context.clone(),
             span: lexer.span(),
         },
-        Err(_) => return Some(Err(Error::Lexer(lexer.remainder()))),
+        Ok(Token::Dot | Token::Comment) | Err(_) => return Some(Err(lexer_error(lexer, context))),
     }))
 }
 
+// The elements up to close, which is ) for a list and } or ] for the literals
+// read_literal builds on top of one.
 fn read_list<'context>(
     lexer: &mut Lexer<'context, Token>,
-    context: &'context Context,
-) -> Result<Sexpr<'context>, Error<'context>> {
+    context: &Rc<Context>,
+    close: Token,
+) -> Result<Sexpr, Error> {
+    let open = lexer.span();
+    let start = open.start;
     let mut list = Vec::new();
 
     loop {
         match lexer.next() {
-            Some(Ok(Token::LeftParen)) => list.push(read_list(lexer, context)?),
-            Some(Ok(Token::RightParen)) if list.is_empty() => {
+            Some(Ok(Token::LeftParen)) => list.push(read_list(lexer, context, Token::RightParen)?),
+            Some(Ok(Token::LeftBrace)) => {
+                list.push(read_literal(lexer, context, Token::RightBrace)?)
+            }
+            Some(Ok(Token::LeftBracket)) => {
+                list.push(read_literal(lexer, context, Token::RightBracket)?)
+            }
+            Some(Ok(Token::AnonymousFunction)) => {
+                list.push(read_anonymous_function(lexer, context)?)
+            }
+            Some(Ok(Token::Dispatch)) => list.push(read_dispatch(lexer, context)?),
+            Some(Ok(token)) if token == close && list.is_empty() => {
                 return Ok(Sexpr::Nil {
-                    context,
-                    span: lexer.span(),
+                    context: context.clone(),
+                    span: start..lexer.span().end,
                 })
             }
-            Some(Ok(Token::RightParen)) => {
+            Some(Ok(token)) if token == close => {
                 return Ok(Sexpr::List {
                     list,
-                    context,
-                    span: lexer.span(),
+                    context: context.clone(),
+                    span: start..lexer.span().end,
                 })
             }
+            Some(Ok(Token::RightParen | Token::RightBrace | Token::RightBracket)) => {
+                return Err(Error::UnbalancedParens(token_span(lexer, context)))
+            }
+            Some(Ok(Token::Dot)) if close == Token::RightParen && !list.is_empty() => {
+                return read_dotted_tail(lexer, context, open, list)
+            }
             Some(Ok(Token::Quote)) => list.push(expand_macro(lexer, context, Macro::Quote)?),
             Some(Ok(Token::QuasiQuote)) => {
                 list.push(expand_macro(lexer, context, Macro::QuasiQuote)?)
@@ -357,49 +732,344 @@ fn read_list<'context>(
             Some(Ok(Token::Splice)) => list.push(expand_macro(lexer, context, Macro::Splice)?),
             Some(Ok(Token::Symbol)) => list.push(Sexpr::Symbol {
                 symbol: lexer.slice().to_string(),
-                context,
+                context: context.clone(),
                 span: lexer.span(),
             }),
             Some(Ok(Token::String)) => list.push(Sexpr::String {
-                string: lexer.slice()[1..lexer.slice().len() - 1].to_string(),
-                context,
+                string: read_string(lexer.slice()),
+                context: context.clone(),
                 span: lexer.span(),
             }),
             Some(Ok(Token::Char)) => list.push(Sexpr::Char {
-                char: lexer.slice().parse().unwrap(),
-                context,
+                char: read_char(lexer.slice())
+                    .ok_or_else(|| Error::Lexer(token_span(lexer, context)))?,
+                context: context.clone(),
                 span: lexer.span(),
             }),
             Some(Ok(Token::Int)) => list.push(Sexpr::Int {
-                int: lexer.slice().parse().unwrap(),
-                context,
+                int: read_int(lexer.slice())
+                    .ok_or_else(|| Error::Lexer(token_span(lexer, context)))?,
+                context: context.clone(),
                 span: lexer.span(),
             }),
             Some(Ok(Token::True)) => list.push(Sexpr::Bool {
                 bool: true,
-                context,
+                context: context.clone(),
                 span: lexer.span(),
             }),
             Some(Ok(Token::False)) => list.push(Sexpr::Bool {
                 bool: false,
-                context,
+                context: context.clone(),
                 span: lexer.span(),
             }),
             Some(Ok(Token::Nil)) => list.push(Sexpr::Nil {
-                context,
+                context: context.clone(),
                 span: lexer.span(),
             }),
-            Some(Err(_)) => return Err(Error::Lexer(lexer.remainder())),
-            None => return Err(Error::UnExpectedEof),
+            Some(Ok(Token::Dot | Token::Comment) | Err(_)) => {
+                return Err(lexer_error(lexer, context))
+            }
+            None => return Err(Error::UnexpectedEof(Span::new(context.clone(), open))),
         }
     }
 }
 
+// A string that's still open when the input runs out is incomplete rather than
+// wrong, like a list that is, so more input could finish it.
+fn lexer_error(lexer: &Lexer<Token>, context: &Rc<Context>) -> Error {
+    let slice = lexer.slice();
+    let remainder = lexer.remainder();
+
+    if (slice.starts_with('"') && !remainder.contains('"'))
+        || (slice.starts_with("#\"") && !remainder.contains("\"#"))
+    {
+        Error::UnexpectedEof(token_span(lexer, context))
+    } else {
+        Error::Lexer(token_span(lexer, context))
+    }
+}
+
+// The span of the token the lexer is on.
+fn token_span(lexer: &Lexer<Token>, context: &Rc<Context>) -> Span {
+    Span::new(context.clone(), lexer.span())
+}
+
+// A #!/usr/bin/env lisp line at the start of a script is for the shell.
+fn skip_shebang(lexer: &mut Lexer<Token>) {
+    let source = lexer.remainder();
+
+    if source.starts_with("#!") {
+        lexer.bump(source.find('\n').unwrap_or(source.len()));
+    }
+}
+
+fn read_dispatch<'context>(
+    lexer: &mut Lexer<'context, Token>,
+    context: &Rc<Context>,
+) -> Result<Sexpr, Error> {
+    let tag = lexer.slice()[1..].to_string();
+    let span = token_span(lexer, context);
+
+    let Some(handler) = lexer.extras.dispatch.0.get(&tag).cloned() else {
+        return Err(Error::Dispatch {
+            span,
+            tag,
+            message: "nothing is registered to read this".to_string(),
+        });
+    };
+
+    let form = match read(lexer, context) {
+        Some(Ok(sexpr)) => sexpr,
+        Some(Err(error)) => return Err(error),
+        None => return Err(Error::UnexpectedEof(span)),
+    };
+
+    let span = Span::new(context.clone(), span.range().start..form.span().end);
+
+    handler(form).map_err(|message| Error::Dispatch { span, tag, message })
+}
+
+// {k v ...} reads as a Map and [a b ...] as a Vector, close says which.
+fn read_literal<'context>(
+    lexer: &mut Lexer<'context, Token>,
+    context: &Rc<Context>,
+    close: Token,
+) -> Result<Sexpr, Error> {
+    let open = lexer.span();
+    let elements = read_list(lexer, context, close.clone())?;
+    let span = open.start..elements.span().end;
+    let context = context.clone();
+
+    let list = match elements {
+        Sexpr::List { list, .. } => list,
+        _ => Vec::new(),
+    };
+
+    Ok(match close {
+        Token::RightBrace if !list.len().is_multiple_of(2) => {
+            return Err(Error::UnpairedKey(Span::new(context, span)))
+        }
+        Token::RightBrace => Sexpr::Map {
+            list,
+            context,
+            span,
+        },
+        _ => Sexpr::Vector {
+            list,
+            context,
+            span,
+        },
+    })
+}
+
+// What follows the dot in (a b . c): exactly one sexpr and then the closing
+// paren.
+fn read_dotted_tail<'context>(
+    lexer: &mut Lexer<'context, Token>,
+    context: &Rc<Context>,
+    open: Range<usize>,
+    mut list: Vec<Sexpr>,
+) -> Result<Sexpr, Error> {
+    let eof = || Error::UnexpectedEof(Span::new(context.clone(), open.clone()));
+
+    let tail = match read(lexer, context) {
+        Some(Ok(sexpr)) => sexpr,
+        Some(Err(error)) => return Err(error),
+        None => return Err(eof()),
+    };
+
+    match lexer.next() {
+        Some(Ok(Token::RightParen)) => (),
+        Some(_) => return Err(Error::Lexer(token_span(lexer, context))),
+        None => return Err(eof()),
+    }
+
+    let span = open.start..lexer.span().end;
+
+    let tail = match tail {
+        Sexpr::List { list: rest, .. } => {
+            list.extend(rest);
+            None
+        }
+        Sexpr::DottedList {
+            list: rest, tail, ..
+        } => {
+            list.extend(rest);
+            Some(tail)
+        }
+        Sexpr::Nil { .. } => None,
+        tail => Some(Box::new(tail)),
+    };
+
+    Ok(match tail {
+        Some(tail) => Sexpr::DottedList {
+            list,
+            tail,
+            context: context.clone(),
+            span,
+        },
+        None => Sexpr::List {
+            list,
+            context: context.clone(),
+            span,
+        },
+    })
+}
+
+// #(+ %1 %2) reads as (lambda (%1 %2) (+ %1 %2)). % is shorthand for %1 and
+// %& binds any remaining arguments.
+fn read_anonymous_function<'context>(
+    lexer: &mut Lexer<'context, Token>,
+    context: &Rc<Context>,
+) -> Result<Sexpr, Error> {
+    let mut body = read_list(lexer, context, Token::RightParen)?;
+    let span = body.span();
+    let mut arity = 0;
+    let mut rest = false;
+
+    if let Sexpr::List { list, .. } = &mut body {
+        for sexpr in list {
+            positional_parameters(sexpr, &mut arity, &mut rest);
+        }
+    }
+
+    let symbol = |symbol: String| Sexpr::Symbol {
+        symbol,
+        context: context.clone(),
+        span: span.clone(),
+    };
+
+    let mut parameters = (1..=arity)
+        .map(|i| symbol(format!("%{i}")))
+        .collect::<Vec<_>>();
+
+    if rest {
+        parameters.push(symbol("&rest".to_string()));
+        parameters.push(symbol("%&".to_string()));
+    }
+
+    let parameters = if parameters.is_empty() {
+        Sexpr::Nil {
+            context: context.clone(),
+            span: span.clone(),
+        }
+    } else {
+        Sexpr::List {
+            list: parameters,
+            context: context.clone(),
+            span: span.clone(),
+        }
+    };
+
+    Ok(Sexpr::List {
+        list: vec![symbol("lambda".to_string()), parameters, body],
+        context: context.clone(),
+        span,
+    })
+}
+
+fn positional_parameters(sexpr: &mut Sexpr, arity: &mut usize, rest: &mut bool) {
+    match sexpr {
+        Sexpr::Symbol { symbol, .. } if symbol == "%" => {
+            *symbol = "%1".to_string();
+            *arity = (*arity).max(1);
+        }
+        Sexpr::Symbol { symbol, .. } if symbol == "%&" => *rest = true,
+        Sexpr::Symbol { symbol, .. } => {
+            if let Some(Ok(i)) = symbol.strip_prefix('%').map(str::parse::<usize>) {
+                *arity = (*arity).max(i);
+            }
+        }
+        // nested shorthand functions bind their own parameters
+        Sexpr::List { context, span, .. } if context.source[span.start..].starts_with("#(") => {}
+        Sexpr::List { list, .. } | Sexpr::Vector { list, .. } | Sexpr::Map { list, .. } => {
+            for sexpr in list {
+                positional_parameters(sexpr, arity, rest);
+            }
+        }
+        _ => (),
+    }
+}
+
+// #\ is followed by any one char, or by a name such as newline or x41.
+fn named_char(lexer: &mut Lexer<Token>) -> bool {
+    let remainder = lexer.remainder();
+
+    let len = match remainder.chars().next() {
+        None | Some('\n') => return false,
+        Some(char) if char.is_ascii_alphabetic() => remainder
+            .find(|char: char| !char.is_ascii_alphanumeric())
+            .unwrap_or(remainder.len()),
+        Some(char) => char.len_utf8(),
+    };
+
+    lexer.bump(len);
+    true
+}
+
+// A raw string runs from #" to the first "# after it.
+fn raw_string(lexer: &mut Lexer<Token>) -> bool {
+    match lexer.remainder().find("\"#") {
+        Some(end) => {
+            lexer.bump(end + 2);
+            true
+        }
+        None => false,
+    }
+}
+
+// "text", or #"text"# which can have quotes in it as long as they aren't
+// followed by a #.
+fn read_string(slice: &str) -> String {
+    match slice.strip_prefix('#') {
+        Some(raw) => raw[1..raw.len() - 2].to_string(),
+        None => slice[1..slice.len() - 1].to_string(),
+    }
+}
+
+// Decimal, or #x1f, #o17 and #b1010 (also written 0x1f, 0o17 and 0b1010). None
+// if it doesn't fit in an i64.
+fn read_int(slice: &str) -> Option<i64> {
+    let radix = match slice.as_bytes().get(1) {
+        Some(b'x' | b'X') => 16,
+        Some(b'o' | b'O') => 8,
+        Some(b'b' | b'B') => 2,
+        _ => return slice.parse().ok(),
+    };
+
+    i64::from_str_radix(&slice[2..], radix).ok()
+}
+
+// 'a', or #\a, #\newline, #\space, #\tab and #\x41 for characters that
+// can't be written between quotes. None for a name that isn't one of those.
+fn read_char(slice: &str) -> Option<char> {
+    if let Some(name) = slice.strip_prefix("#\\") {
+        let mut chars = name.chars();
+
+        return match (chars.next(), chars.next()) {
+            (Some(char), None) => Some(char),
+            _ => match name {
+                "newline" => Some('\n'),
+                "space" => Some(' '),
+                "tab" => Some('\t'),
+                _ => name
+                    .strip_prefix('x')
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .and_then(char::from_u32),
+            },
+        };
+    }
+
+    slice[1..slice.len() - 1].parse().ok()
+}
+
+// 'x, `x, ,x and ,@x read as (quote x), (quasiquote x), (unquote x) and
+// (unquote-splice x).
 fn expand_macro<'context>(
     lexer: &mut Lexer<'context, Token>,
-    context: &'context Context,
+    context: &Rc<Context>,
     r#macro: Macro,
-) -> Result<Sexpr<'context>, Error<'context>> {
+) -> Result<Sexpr, Error> {
     let span = lexer.span();
 
     let symbol = Sexpr::Symbol {
@@ -410,73 +1080,119 @@ fn expand_macro<'context>(
             Macro::Splice => "unquote-splice",
         }
         .to_string(),
-        context,
+        context: context.clone(),
         span: span.clone(),
     };
 
     let body = match read(lexer, context) {
         Some(Ok(sexpr)) => sexpr,
-        Some(Err(_)) => return Err(Error::Lexer(lexer.remainder())),
-        None => return Err(Error::UnExpectedEof),
+        Some(Err(error)) => return Err(error),
+        None => return Err(Error::UnexpectedEof(Span::new(context.clone(), span))),
     };
 
+    // the expansion covers the shorthand and what it quotes, 'x rather than '
+    let end = body.span().end;
+
     Ok(Sexpr::List {
         list: vec![symbol, body],
-        context,
-        span,
+        context: context.clone(),
+        span: span.start..end,
     })
 }
 
-impl<'a> PartialOrd for Sexpr<'a> {
+impl PartialOrd for Sexpr {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         match (self, other) {
             (Self::List { list: a, .. }, Self::List { list: b, .. }) => a.partial_cmp(b),
+            (
+                Self::DottedList {
+                    list: a, tail: c, ..
+                },
+                Self::DottedList {
+                    list: b, tail: d, ..
+                },
+            ) => (a, c).partial_cmp(&(b, d)),
             (Self::Symbol { symbol: a, .. }, Self::Symbol { symbol: b, .. }) => a.partial_cmp(b),
             (Self::String { string: a, .. }, Self::String { string: b, .. }) => a.partial_cmp(b),
             (Self::Char { char: a, .. }, Self::Char { char: b, .. }) => a.partial_cmp(b),
             (Self::Int { int: a, .. }, Self::Int { int: b, .. }) => a.partial_cmp(b),
             (Self::Bool { bool: a, .. }, Self::Bool { bool: b, .. }) => a.partial_cmp(b),
             (Self::Nil { .. }, Self::Nil { .. }) => Some(Ordering::Equal),
+            (Self::Vector { list: a, .. }, Self::Vector { list: b, .. })
+            | (Self::Map { list: a, .. }, Self::Map { list: b, .. }) => a.partial_cmp(b),
             _ => None,
         }
     }
 }
 
-impl<'context> fmt::Debug for Error<'context> {
+impl Error {
+    // What the error is about, None if it isn't about the input.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Self::Lexer(span)
+            | Self::UnbalancedParens(span)
+            | Self::UnexpectedEof(span)
+            | Self::UnpairedKey(span) => Some(span),
+            Self::Dispatch { span, .. } => Some(span),
+            Self::Io(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Lexer(remainder) => {
-                let mut buff = remainder.to_string();
-                buff.truncate(10);
-                write!(f, "lexer error: {buff}...")
-            }
-            Self::UnExpectedEof => write!(f, "unexpected eof"),
-            Self::UnbalancedParens => write!(f, "unbalanced parens"),
+            Self::Lexer(span) => write!(f, "lexer error at {span:?}"),
+            Self::UnexpectedEof(span) => write!(f, "unexpected eof in {span:?}"),
+            Self::UnbalancedParens(span) => write!(f, "unbalanced parens at {span:?}"),
+            Self::UnpairedKey(span) => write!(f, "key without a value in {span:?}"),
+            Self::Io(error) => write!(f, "io error: {error}"),
+            Self::Dispatch { span, tag, message } => write!(f, "#{tag}: {message} at {span:?}"),
         }
     }
 }
 
-impl<'context> fmt::Debug for Sexpr<'context> {
+impl fmt::Debug for Dispatch {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let span = match self {
-            Self::List { list, .. } => list.first().unwrap().span().start - 1..self.span().end,
-            _ => self.span(),
-        };
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
 
+impl fmt::Debug for Sexpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.source_span(), f)
+    }
+}
+
+impl PartialOrd for Span {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(
+            self.context
+                .cmp(&other.context)
+                .then(self.range.start.cmp(&other.range.start))
+                .then(self.range.end.cmp(&other.range.end)),
+        )
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let line = self
-            .context()
+            .context
             .source
             .bytes()
-            .take(span.start)
+            .take(self.range.start)
             .filter(|b| *b == b'\n')
-            .count();
-
-        write!(
-            f,
-            "{}:{}: {}",
-            self.context().display,
-            line,
-            &self.context().source[span]
-        )
+            .count()
+            + self.context.first_line
+            - 1;
+
+        write!(f, "{}:{}: {}", self.context.display, line, self.text())
     }
 }
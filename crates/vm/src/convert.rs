@@ -0,0 +1,187 @@
+// Conversions between rust values and objects, for natives and for code
+// embedding the vm. Going into an object can't fail: ints, bools, chars and
+// strings become the object of the same name, a Vec becomes a list, a HashMap a
+// map and None becomes nil. Coming back out is a TryFrom that fails with a type
+// error when the object isn't what was asked for. The one that's missing is
+// Option, since std already converts anything into an Option; use
+// Object::into_option to get None for nil and then convert what's left.
+
+use crate::object::{HashMapKey, Object, Type};
+use crate::Error;
+use gc::{Gc, GcCell};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::hash::Hash;
+
+fn type_error<D>(expected: Type, object: &Object<D>) -> Error {
+    Error::Type {
+        expected,
+        recieved: Type::from(object),
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(infallible: Infallible) -> Self {
+        match infallible {}
+    }
+}
+
+impl<D> Object<D> {
+    pub fn into_option(self) -> Option<Self> {
+        match self {
+            Object::Nil => None,
+            object => Some(object),
+        }
+    }
+}
+
+macro_rules! scalar {
+    ($rust:ty, $variant:ident) => {
+        impl<D> From<$rust> for Object<D> {
+            fn from(value: $rust) -> Self {
+                Object::$variant(value)
+            }
+        }
+
+        impl From<$rust> for HashMapKey {
+            fn from(value: $rust) -> Self {
+                HashMapKey::$variant(value)
+            }
+        }
+
+        impl<D> TryFrom<Object<D>> for $rust {
+            type Error = Error;
+
+            fn try_from(object: Object<D>) -> Result<Self, Error> {
+                match object {
+                    Object::$variant(value) => Ok(value),
+                    object => Err(type_error(Type::$variant, &object)),
+                }
+            }
+        }
+    };
+}
+
+scalar!(i64, Int);
+scalar!(bool, Bool);
+scalar!(char, Char);
+
+impl<D> From<String> for Object<D> {
+    fn from(string: String) -> Self {
+        Object::String(Gc::new(string))
+    }
+}
+
+impl<D> From<&str> for Object<D> {
+    fn from(string: &str) -> Self {
+        Object::String(Gc::new(string.to_string()))
+    }
+}
+
+impl From<String> for HashMapKey {
+    fn from(string: String) -> Self {
+        HashMapKey::String(Gc::new(string))
+    }
+}
+
+impl From<&str> for HashMapKey {
+    fn from(string: &str) -> Self {
+        HashMapKey::String(Gc::new(string.to_string()))
+    }
+}
+
+// Only the objects that can be a map's key, which are the scalars and strings.
+impl<D> TryFrom<Object<D>> for HashMapKey {
+    type Error = Error;
+
+    fn try_from(object: Object<D>) -> Result<Self, Error> {
+        HashMapKey::try_from(&object).map_err(|()| Error::HashKey(Type::from(&object)))
+    }
+}
+
+impl<D> TryFrom<Object<D>> for String {
+    type Error = Error;
+
+    fn try_from(object: Object<D>) -> Result<Self, Error> {
+        match object {
+            Object::String(string) => Ok(string.to_string()),
+            object => Err(type_error(Type::String, &object)),
+        }
+    }
+}
+
+impl<D, T: Into<Object<D>>> From<Option<T>> for Object<D> {
+    fn from(option: Option<T>) -> Self {
+        option.map_or(Object::Nil, Into::into)
+    }
+}
+
+impl<D: Clone, T: Into<Object<D>>> From<Vec<T>> for Object<D> {
+    fn from(vec: Vec<T>) -> Self {
+        vec.into_iter().map(Into::into).collect()
+    }
+}
+
+// A proper list, nil being the empty one.
+impl<D: Clone, T> TryFrom<Object<D>> for Vec<T>
+where
+    T: TryFrom<Object<D>>,
+    T::Error: Into<Error>,
+{
+    type Error = Error;
+
+    fn try_from(object: Object<D>) -> Result<Self, Error> {
+        let cons = match object {
+            Object::Nil => return Ok(Vec::new()),
+            Object::Cons(cons) => cons.borrow().clone(),
+            object => return Err(type_error(Type::Cons, &object)),
+        };
+
+        let tail = cons.tail();
+
+        if !tail.is_nil() {
+            return Err(type_error(Type::Nil, &tail));
+        }
+
+        cons.iter_cars()
+            .map(|object| T::try_from(object).map_err(Into::into))
+            .collect()
+    }
+}
+
+impl<D, K: Into<HashMapKey>, V: Into<Object<D>>> From<HashMap<K, V>> for Object<D> {
+    fn from(map: HashMap<K, V>) -> Self {
+        Object::HashMap(Gc::new(GcCell::new(
+            map.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )))
+    }
+}
+
+impl<D: Clone, K, V> TryFrom<Object<D>> for HashMap<K, V>
+where
+    K: TryFrom<Object<D>> + Eq + Hash,
+    K::Error: Into<Error>,
+    V: TryFrom<Object<D>>,
+    V::Error: Into<Error>,
+{
+    type Error = Error;
+
+    fn try_from(object: Object<D>) -> Result<Self, Error> {
+        let Object::HashMap(map) = object else {
+            return Err(type_error(Type::Map, &object));
+        };
+
+        let map = map.borrow();
+
+        map.iter()
+            .map(|(key, value)| {
+                Ok((
+                    K::try_from(Object::from(key)).map_err(Into::into)?,
+                    V::try_from(value.clone()).map_err(Into::into)?,
+                ))
+            })
+            .collect()
+    }
+}
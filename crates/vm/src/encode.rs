@@ -0,0 +1,405 @@
+// A binary format for opcode tables so compiled code can be kept on disk.
+// Numbers are little endian and fixed width, strings are a length followed by
+// their bytes. Debug info is left to the caller since the vm doesn't know what
+// it is.
+
+use crate::object::Type;
+use crate::{Arity, JumpTable, OpCode, OpCodeTable, UpValue};
+use gc::Gc;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("unexpected end of input")]
+    Eof,
+    #[error("invalid {what} tag: {tag}")]
+    Tag { what: &'static str, tag: u8 },
+    #[error("invalid utf-8 in string")]
+    Utf8,
+    #[error("{0}")]
+    Other(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Encoder {
+    bytes: Vec<u8>,
+}
+
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn append(&mut self, other: Encoder) {
+        self.bytes.extend(other.bytes);
+    }
+
+    pub fn u8(&mut self, n: u8) {
+        self.bytes.push(n);
+    }
+
+    pub fn u64(&mut self, n: u64) {
+        self.bytes.extend(n.to_le_bytes());
+    }
+
+    pub fn i64(&mut self, n: i64) {
+        self.bytes.extend(n.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, n: usize) {
+        self.u64(n as u64);
+    }
+
+    pub fn bool(&mut self, b: bool) {
+        self.u8(b as u8);
+    }
+
+    pub fn str(&mut self, s: &str) {
+        self.usize(s.len());
+        self.bytes.extend(s.as_bytes());
+    }
+
+    pub fn arity(&mut self, arity: Arity) {
+        match arity {
+            Arity::Nullary => self.u8(0),
+            Arity::Nary(n) => {
+                self.u8(1);
+                self.usize(n);
+            }
+            Arity::Variadic(n) => {
+                self.u8(2);
+                self.usize(n);
+            }
+            Arity::Optional(n, m) => {
+                self.u8(3);
+                self.usize(n);
+                self.usize(m);
+            }
+        }
+    }
+
+    pub fn table<D>(&mut self, table: &OpCodeTable<D>, debug: &mut impl FnMut(&mut Self, &D)) {
+        self.usize(table.len());
+
+        for (opcode, d) in table.opcodes().iter().zip(table.debug()) {
+            self.opcode(opcode, debug);
+            debug(self, d);
+        }
+    }
+
+    fn opcode<D>(&mut self, opcode: &OpCode<D>, debug: &mut impl FnMut(&mut Self, &D)) {
+        match opcode {
+            OpCode::DefGlobal(s) => self.tagged_str(0, s),
+            OpCode::SetGlobal(s) => self.tagged_str(1, s),
+            OpCode::GetGlobal(s) => self.tagged_str(2, s),
+            OpCode::SetLocal(n) => self.tagged_usize(3, *n),
+            OpCode::GetLocal(n) => self.tagged_usize(4, *n),
+            OpCode::SetUpValue(n) => self.tagged_usize(5, *n),
+            OpCode::GetUpValue(n) => self.tagged_usize(6, *n),
+            OpCode::DefModuleVar(s) => self.tagged_str(7, s),
+            OpCode::SetModuleVar(s) => self.tagged_str(8, s),
+            OpCode::GetModuleVar(s) => self.tagged_str(9, s),
+            OpCode::Call(n) => self.tagged_usize(10, *n),
+            OpCode::Tail(n) => self.tagged_usize(11, *n),
+            OpCode::Apply => self.u8(12),
+            OpCode::Return => self.u8(13),
+            OpCode::Lambda { arity, body, doc } => {
+                self.u8(14);
+                self.arity(*arity);
+                self.table(body, debug);
+                match doc {
+                    Some(doc) => {
+                        self.bool(true);
+                        self.str(doc);
+                    }
+                    None => self.bool(false),
+                }
+            }
+            OpCode::CreateUpValue(UpValue::Local(n)) => self.tagged_usize(15, *n),
+            OpCode::CreateUpValue(UpValue::UpValue(n)) => self.tagged_usize(16, *n),
+            OpCode::CreateModule(s) => self.tagged_str(17, s),
+            OpCode::PushSymbol(s) => self.tagged_str(18, s),
+            OpCode::PushInt(n) => {
+                self.u8(19);
+                self.i64(*n);
+            }
+            OpCode::PushChar(c) => {
+                self.u8(20);
+                self.u64(*c as u64);
+            }
+            OpCode::PushString(s) => self.tagged_str(21, s),
+            OpCode::PushBool(b) => {
+                self.u8(22);
+                self.bool(*b);
+            }
+            OpCode::PushNil => self.u8(23),
+            OpCode::Pop => self.u8(24),
+            OpCode::Add => self.u8(25),
+            OpCode::Sub => self.u8(26),
+            OpCode::Mul => self.u8(27),
+            OpCode::Div => self.u8(28),
+            OpCode::AddInt => self.u8(29),
+            OpCode::SubInt => self.u8(30),
+            OpCode::MulInt => self.u8(31),
+            OpCode::DivInt => self.u8(32),
+            OpCode::Car => self.u8(33),
+            OpCode::Cdr => self.u8(34),
+            OpCode::Cons => self.u8(35),
+            OpCode::SetCar => self.u8(36),
+            OpCode::SetCdr => self.u8(37),
+            OpCode::List(n) => self.tagged_usize(38, *n),
+            OpCode::Jmp(n) => {
+                self.u8(39);
+                self.i64(*n as i64);
+            }
+            OpCode::Branch(n) => self.tagged_usize(40, *n),
+            OpCode::IsType(t) => {
+                self.u8(41);
+                self.u8(type_tag(*t));
+            }
+            OpCode::Assert => self.u8(42),
+            OpCode::Lt => self.u8(43),
+            OpCode::Gt => self.u8(44),
+            OpCode::Eq => self.u8(45),
+            OpCode::MapCreate => self.u8(46),
+            OpCode::MapInsert => self.u8(47),
+            OpCode::MapRetrieve => self.u8(48),
+            OpCode::MapItems => self.u8(49),
+            OpCode::Gensym => self.u8(50),
+            OpCode::ArgCount => self.u8(51),
+            OpCode::Documentation => self.u8(52),
+            OpCode::JumpTable(table) => {
+                self.u8(53);
+                self.i64(table.min);
+                self.usize(table.offsets.len());
+                for offset in &table.offsets {
+                    self.usize(*offset);
+                }
+                self.usize(table.default);
+            }
+        }
+    }
+
+    fn tagged_str(&mut self, tag: u8, s: &str) {
+        self.u8(tag);
+        self.str(s);
+    }
+
+    fn tagged_usize(&mut self, tag: u8, n: usize) {
+        self.u8(tag);
+        self.usize(n);
+    }
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < n {
+            return Err(Error::Eof);
+        }
+
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, Error> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, Error> {
+        Ok(self.u64()? as usize)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn str(&mut self) -> Result<&'a str, Error> {
+        let len = self.usize()?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| Error::Utf8)
+    }
+
+    pub fn arity(&mut self) -> Result<Arity, Error> {
+        Ok(match self.u8()? {
+            0 => Arity::Nullary,
+            1 => Arity::Nary(self.usize()?),
+            2 => Arity::Variadic(self.usize()?),
+            3 => Arity::Optional(self.usize()?, self.usize()?),
+            tag => return Err(Error::Tag { what: "arity", tag }),
+        })
+    }
+
+    pub fn table<D: 'static>(
+        &mut self,
+        debug: &mut impl FnMut(&mut Self) -> Result<D, Error>,
+    ) -> Result<OpCodeTable<D>, Error> {
+        let len = self.usize()?;
+        let mut table = OpCodeTable::new();
+
+        for _ in 0..len {
+            let opcode = self.opcode(debug)?;
+            table.push(opcode, debug(self)?);
+        }
+
+        Ok(table)
+    }
+
+    fn opcode<D: 'static>(
+        &mut self,
+        debug: &mut impl FnMut(&mut Self) -> Result<D, Error>,
+    ) -> Result<OpCode<D>, Error> {
+        Ok(match self.u8()? {
+            0 => OpCode::DefGlobal(self.gc_str()?),
+            1 => OpCode::SetGlobal(self.gc_str()?),
+            2 => OpCode::GetGlobal(self.gc_str()?),
+            3 => OpCode::SetLocal(self.usize()?),
+            4 => OpCode::GetLocal(self.usize()?),
+            5 => OpCode::SetUpValue(self.usize()?),
+            6 => OpCode::GetUpValue(self.usize()?),
+            7 => OpCode::DefModuleVar(self.gc_str()?),
+            8 => OpCode::SetModuleVar(self.gc_str()?),
+            9 => OpCode::GetModuleVar(self.gc_str()?),
+            10 => OpCode::Call(self.usize()?),
+            11 => OpCode::Tail(self.usize()?),
+            12 => OpCode::Apply,
+            13 => OpCode::Return,
+            14 => OpCode::Lambda {
+                arity: self.arity()?,
+                body: Gc::new(self.table(debug)?),
+                doc: match self.bool()? {
+                    true => Some(self.gc_str()?),
+                    false => None,
+                },
+            },
+            15 => OpCode::CreateUpValue(UpValue::Local(self.usize()?)),
+            16 => OpCode::CreateUpValue(UpValue::UpValue(self.usize()?)),
+            17 => OpCode::CreateModule(self.gc_str()?),
+            18 => OpCode::PushSymbol(self.gc_str()?),
+            19 => OpCode::PushInt(self.i64()?),
+            20 => OpCode::PushChar(
+                char::from_u32(self.u64()? as u32)
+                    .ok_or_else(|| Error::Other("invalid char".to_string()))?,
+            ),
+            21 => OpCode::PushString(self.gc_str()?),
+            22 => OpCode::PushBool(self.bool()?),
+            23 => OpCode::PushNil,
+            24 => OpCode::Pop,
+            25 => OpCode::Add,
+            26 => OpCode::Sub,
+            27 => OpCode::Mul,
+            28 => OpCode::Div,
+            29 => OpCode::AddInt,
+            30 => OpCode::SubInt,
+            31 => OpCode::MulInt,
+            32 => OpCode::DivInt,
+            33 => OpCode::Car,
+            34 => OpCode::Cdr,
+            35 => OpCode::Cons,
+            36 => OpCode::SetCar,
+            37 => OpCode::SetCdr,
+            38 => OpCode::List(self.usize()?),
+            39 => OpCode::Jmp(self.i64()? as isize),
+            40 => OpCode::Branch(self.usize()?),
+            41 => OpCode::IsType(type_from_tag(self.u8()?)?),
+            42 => OpCode::Assert,
+            43 => OpCode::Lt,
+            44 => OpCode::Gt,
+            45 => OpCode::Eq,
+            46 => OpCode::MapCreate,
+            47 => OpCode::MapInsert,
+            48 => OpCode::MapRetrieve,
+            49 => OpCode::MapItems,
+            50 => OpCode::Gensym,
+            51 => OpCode::ArgCount,
+            52 => OpCode::Documentation,
+            53 => {
+                let min = self.i64()?;
+                let offsets = (0..self.usize()?)
+                    .map(|_| self.usize())
+                    .collect::<Result<_, _>>()?;
+                let default = self.usize()?;
+
+                OpCode::JumpTable(Gc::new(JumpTable {
+                    min,
+                    offsets,
+                    default,
+                }))
+            }
+            tag => {
+                return Err(Error::Tag {
+                    what: "opcode",
+                    tag,
+                })
+            }
+        })
+    }
+
+    fn gc_str(&mut self) -> Result<Gc<String>, Error> {
+        Ok(Gc::new(self.str()?.to_string()))
+    }
+}
+
+fn type_tag(t: Type) -> u8 {
+    match t {
+        Type::Module => 0,
+        Type::Function => 1,
+        Type::Cons => 2,
+        Type::Map => 3,
+        Type::String => 4,
+        Type::Symbol => 5,
+        Type::Int => 6,
+        Type::Char => 7,
+        Type::Bool => 8,
+        Type::Nil => 9,
+        Type::Port => 10,
+        Type::Bytes => 11,
+        Type::Deque => 12,
+        Type::Set => 13,
+        Type::Heap => 14,
+    }
+}
+
+fn type_from_tag(tag: u8) -> Result<Type, Error> {
+    Ok(match tag {
+        0 => Type::Module,
+        1 => Type::Function,
+        2 => Type::Cons,
+        3 => Type::Map,
+        4 => Type::String,
+        5 => Type::Symbol,
+        6 => Type::Int,
+        7 => Type::Char,
+        8 => Type::Bool,
+        9 => Type::Nil,
+        10 => Type::Port,
+        11 => Type::Bytes,
+        12 => Type::Deque,
+        13 => Type::Set,
+        14 => Type::Heap,
+        tag => return Err(Error::Tag { what: "type", tag }),
+    })
+}
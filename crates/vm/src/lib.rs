@@ -1,8 +1,10 @@
 #![allow(dead_code)]
 
+pub mod convert;
+pub mod encode;
 pub mod object;
 
-use crate::object::{Cons, Lambda, NativeFunction, Type};
+use crate::object::{Cons, Lambda, Native, NativeFunction, Type};
 use core::fmt;
 use gc::{Gc, GcCell, Trace};
 use object::{HashMapKey, Module};
@@ -10,6 +12,7 @@ use std::cmp::{Ordering, PartialOrd};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::mem;
 use std::ops::{Deref, DerefMut};
 use thiserror::Error;
 use unwrap_enum::{EnumAs, EnumIs};
@@ -21,6 +24,7 @@ pub enum Arity {
     Nullary,
     Nary(usize),
     Variadic(usize),
+    Optional(usize, usize),
 }
 
 #[derive(Debug, Error)]
@@ -41,6 +45,13 @@ pub enum Error {
     Apply,
     #[error("other error: {0}")]
     Other(#[from] Box<dyn std::error::Error>),
+    // Signalled from lisp with (error message data). The data is an Object<D>,
+    // boxed since errors aren't generic over D.
+    #[error("error: {message}")]
+    Raised {
+        message: String,
+        data: Box<dyn std::any::Any>,
+    },
 }
 
 #[derive(Clone, Debug, EnumAs, EnumIs, PartialEq, Eq, Hash)]
@@ -62,6 +73,7 @@ pub enum OpCode<D> {
     Lambda {
         arity: Arity,
         body: Gc<OpCodeTable<D>>,
+        doc: Option<Gc<String>>,
     },
     CreateUpValue(UpValue),
     CreateModule(Gc<String>),
@@ -76,6 +88,11 @@ pub enum OpCode<D> {
     Sub,
     Mul,
     Div,
+    // Only emitted where the compiler has proven both operands are ints.
+    AddInt,
+    SubInt,
+    MulInt,
+    DivInt,
     Car,
     Cdr,
     Cons,
@@ -93,6 +110,10 @@ pub enum OpCode<D> {
     MapInsert,
     MapRetrieve,
     MapItems,
+    Gensym,
+    ArgCount,
+    Documentation,
+    JumpTable(Gc<JumpTable>),
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -107,11 +128,34 @@ pub enum UpValue {
     UpValue(usize),
 }
 
+// Pops an int and jumps by the offset stored for it, anything that isn't an int
+// between min and min + offsets.len() takes the default offset. Offsets are
+// relative to the next opcode like Jmp.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct JumpTable {
+    pub min: i64,
+    pub offsets: Vec<usize>,
+    pub default: usize,
+}
+
 #[derive(Clone, Debug)]
 struct Frame<D: 'static> {
     function: Option<Gc<GcCell<Lambda<D>>>>,
     pc: usize,
     bp: usize,
+    argc: usize,
+}
+
+// Where the vm was when an operation failed, put aside by suspend so that
+// something else can run before resume carries on from there.
+pub struct Suspended<D: 'static> {
+    frames: Vec<Frame<D>>,
+    current_function: Option<Gc<GcCell<Lambda<D>>>>,
+    pc: usize,
+    bp: usize,
+    argc: usize,
+    // The stack depth the failed operation's value goes at.
+    depth: usize,
 }
 
 #[derive(Clone, Debug, EnumAs, EnumIs)]
@@ -127,6 +171,125 @@ pub struct Vm<D: 'static> {
     current_function: Option<Gc<GcCell<Lambda<D>>>>,
     pc: usize,
     bp: usize,
+    argc: usize,
+    // Set when eval fails on an operation that pushes one value in place of
+    // what it takes off the stack, to the depth that value would have gone at.
+    resume_depth: Option<usize>,
+    deterministic: bool,
+    gensyms: usize,
+    tracer: Option<Box<Tracer<D>>>,
+    profiler: Option<Profiler<D>>,
+}
+
+// Called before each opcode runs with where it is in its function and the
+// debug info it was compiled with.
+pub type Tracer<D> = dyn FnMut(usize, &OpCode<D>, &D);
+
+// Counts the opcodes run under each call stack while profiling is on. The
+// stacks are a tree, each one the call site that entered it under the stack it
+// was entered from, so counting an opcode only has to bump the count of the
+// stack the vm is in.
+struct Profiler<D> {
+    stacks: Vec<ProfiledStack<D>>,
+    // The stack entered from a call site under another, keyed by that stack
+    // and the address of the opcode table the call site is in and its pc.
+    children: HashMap<(usize, usize, usize), usize>,
+    // The stack of each frame the vm is in, the innermost last.
+    current: Vec<usize>,
+    // The opcode run before this one, which is what entered any frame the vm
+    // is in now that it wasn't before.
+    last: Option<ProfiledOpCode<D>>,
+}
+
+struct ProfiledStack<D> {
+    parent: usize,
+    call_site: Option<D>,
+    count: u64,
+}
+
+struct ProfiledOpCode<D> {
+    site: (usize, usize),
+    debug: D,
+    tail: bool,
+}
+
+impl<D: Clone + PartialEq> Profiler<D> {
+    fn new() -> Self {
+        Self {
+            stacks: vec![ProfiledStack {
+                parent: 0,
+                call_site: None,
+                count: 0,
+            }],
+            children: HashMap::new(),
+            current: vec![0],
+            last: None,
+        }
+    }
+
+    fn step(&mut self, depth: usize, site: (usize, usize), opcode: &OpCode<D>, debug: &D) {
+        self.current.truncate(depth + 1);
+
+        // a tail call runs the new function in the frame of the old one
+        if let Some(ProfiledOpCode { tail: true, .. }) = self.last {
+            if site.1 == 0 && self.current.len() == depth + 1 && depth > 0 {
+                self.current.pop();
+            }
+        }
+
+        while self.current.len() < depth + 1 {
+            let parent = *self.current.last().unwrap();
+            let (key, call_site) = match &self.last {
+                Some(last) => ((parent, last.site.0, last.site.1), Some(last.debug.clone())),
+                None => ((parent, 0, 0), None),
+            };
+
+            // the table a call site is in can be dropped and another put where
+            // it was, so a stack is only the same one if its call site is too
+            let stack = match self.children.get(&key) {
+                Some(&stack) if self.stacks[stack].call_site == call_site => stack,
+                _ => {
+                    self.stacks.push(ProfiledStack {
+                        parent,
+                        call_site,
+                        count: 0,
+                    });
+                    self.children.insert(key, self.stacks.len() - 1);
+                    self.stacks.len() - 1
+                }
+            };
+
+            self.current.push(stack);
+        }
+
+        self.stacks[*self.current.last().unwrap()].count += 1;
+
+        self.last = Some(ProfiledOpCode {
+            site,
+            debug: debug.clone(),
+            tail: matches!(opcode, OpCode::Tail(_)),
+        });
+    }
+
+    fn profile(&self) -> Vec<(Vec<D>, u64)> {
+        self.stacks
+            .iter()
+            .filter(|stack| stack.count > 0)
+            .map(|stack| {
+                let mut call_sites = Vec::new();
+                let mut caller = stack;
+
+                while let Some(call_site) = &caller.call_site {
+                    call_sites.push(call_site.clone());
+                    caller = &self.stacks[caller.parent];
+                }
+
+                call_sites.reverse();
+
+                (call_sites, stack.count)
+            })
+            .collect()
+    }
 }
 
 #[allow(clippy::new_without_default)]
@@ -139,9 +302,44 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             current_function: None,
             pc: 0,
             bp: 0,
+            argc: 0,
+            resume_depth: None,
+            deterministic: false,
+            gensyms: 0,
+            tracer: None,
+            profiler: None,
         }
     }
 
+    // In deterministic mode operations whose results would otherwise depend on
+    // hashing, such as the order of map-items, produce a stable ordering.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn set_tracer<F>(&mut self, tracer: Option<F>)
+    where
+        F: FnMut(usize, &OpCode<D>, &D) + 'static,
+    {
+        self.tracer = tracer.map(|tracer| Box::new(tracer) as Box<Tracer<D>>);
+    }
+
+    // Turning profiling on starts counting from nothing, turning it off throws
+    // the counts away.
+    pub fn set_profiling(&mut self, profiling: bool) {
+        self.profiler = profiling.then(Profiler::new);
+    }
+
+    // How many opcodes have run under each call stack since profiling was
+    // turned on. A stack is given as the debug info of the calls that entered
+    // it, outermost first, so code outside of any function has none.
+    pub fn profile(&self) -> Vec<(Vec<D>, u64)> {
+        self.profiler
+            .as_ref()
+            .map(Profiler::profile)
+            .unwrap_or_default()
+    }
+
     pub fn load_native_function<F>(&mut self, name: &str, f: F)
     where
         F: Fn(&mut [Local<D>]) -> Result<Object<D>, Error> + 'static,
@@ -151,6 +349,15 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             .insert(name.to_string(), Object::NativeFunction(native_function));
     }
 
+    pub fn load_native_function_with_vm<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&mut Vm<D>, &mut [Local<D>]) -> Result<Object<D>, Error> + 'static,
+    {
+        let native_function = NativeFunction::with_vm(f);
+        self.globals
+            .insert(name.to_string(), Object::NativeFunction(native_function));
+    }
+
     pub fn eval(&mut self, opcode_table: &OpCodeTable<D>) -> Result<(), (Error, D)> {
         loop {
             let opcode = if let Some(function) = &self.current_function {
@@ -162,16 +369,51 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
                 return Ok(());
             };
 
+            if let Some(tracer) = &mut self.tracer {
+                match &self.current_function {
+                    Some(function) => {
+                        tracer(self.pc, &opcode, &function.borrow().opcodes.debug[self.pc])
+                    }
+                    None => tracer(self.pc, &opcode, &opcode_table.debug[self.pc]),
+                }
+            }
+
+            if let Some(profiler) = &mut self.profiler {
+                match &self.current_function {
+                    Some(function) => {
+                        let function = function.borrow();
+                        let site = (&*function.opcodes as *const _ as usize, self.pc);
+                        let debug = &function.opcodes.debug[self.pc];
+                        profiler.step(self.frames.len(), site, &opcode, debug)
+                    }
+                    None => {
+                        let site = (opcode_table as *const _ as usize, self.pc);
+                        let debug = &opcode_table.debug[self.pc];
+                        profiler.step(self.frames.len(), site, &opcode, debug)
+                    }
+                }
+            }
+
+            let depth = self.stack.len();
+
             self.pc += 1;
 
             match self.dispatch(opcode) {
                 Ok(_) => continue,
                 Err(e) => {
-                    let debug = if let Some(function) = &self.current_function {
-                        function.borrow().opcodes.debug[self.pc - 1].clone()
+                    let (inputs, debug) = if let Some(function) = &self.current_function {
+                        let function = function.borrow();
+                        (
+                            resumable(&function.opcodes.opcodes[self.pc - 1]),
+                            function.opcodes.debug[self.pc - 1].clone(),
+                        )
                     } else {
-                        opcode_table.debug[self.pc - 1].clone()
+                        (
+                            resumable(&opcode_table.opcodes[self.pc - 1]),
+                            opcode_table.debug[self.pc - 1].clone(),
+                        )
                     };
+                    self.resume_depth = inputs.map(|inputs| depth - inputs);
                     return Err((e, debug));
                 }
             }
@@ -194,7 +436,7 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             OpCode::Tail(args) => self.tail(args)?,
             OpCode::Return => self.ret()?,
             OpCode::Apply => self.apply()?,
-            OpCode::Lambda { arity, body } => self.lambda(arity, body)?,
+            OpCode::Lambda { arity, body, doc } => self.lambda(arity, body, doc)?,
             OpCode::CreateUpValue(upvalue) => self.create_upvalue(upvalue)?,
             OpCode::CreateModule(module_name) => {
                 self.stack
@@ -222,6 +464,10 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             OpCode::Sub => self.sub()?,
             OpCode::Mul => self.mul()?,
             OpCode::Div => self.div()?,
+            OpCode::AddInt => self.unchecked_integer_op(|a, b| a + b)?,
+            OpCode::SubInt => self.unchecked_integer_op(|a, b| a - b)?,
+            OpCode::MulInt => self.unchecked_integer_op(|a, b| a * b)?,
+            OpCode::DivInt => self.unchecked_integer_op(|a, b| a / b)?,
             OpCode::Cons => self.cons()?,
             OpCode::Car => self.car()?,
             OpCode::Cdr => self.cdr()?,
@@ -230,7 +476,7 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             OpCode::List(args) => self.list(args)?,
             OpCode::Branch(offset) => self.branch(offset)?,
             OpCode::Jmp(offset) => {
-                self.pc += offset as usize;
+                self.pc = self.pc.wrapping_add_signed(offset);
             }
             OpCode::IsType(ty) => self.is_type(ty)?,
             OpCode::Assert => self.assert()?,
@@ -241,11 +487,69 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             OpCode::MapInsert => self.map_insert()?,
             OpCode::MapRetrieve => self.map_retrieve()?,
             OpCode::MapItems => self.map_items()?,
+            OpCode::Gensym => self.gensym()?,
+            OpCode::ArgCount => self.stack.push(Local::Value(Object::Int(self.argc as i64))),
+            OpCode::Documentation => self.documentation()?,
+            OpCode::JumpTable(table) => self.jump_table(&table),
         }
 
         Ok(())
     }
 
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    // Throws away whatever was in flight when eval returned an error, so the vm
+    // can be used again. The stack is cut back to depth.
+    pub fn unwind(&mut self, depth: usize) {
+        self.stack.truncate(depth);
+        self.frames.clear();
+        self.current_function = None;
+        self.pc = 0;
+        self.bp = 0;
+        self.argc = 0;
+        self.resume_depth = None;
+    }
+
+    // Puts aside where the vm was when eval last failed, leaving it free to
+    // eval something else on top of the stack, or None if the operation that
+    // failed couldn't be given a value instead.
+    pub fn suspend(&mut self) -> Option<Suspended<D>> {
+        let depth = self.resume_depth.take()?;
+        let suspended = Suspended {
+            frames: mem::take(&mut self.frames),
+            current_function: self.current_function.take(),
+            pc: self.pc,
+            bp: self.bp,
+            argc: self.argc,
+            depth,
+        };
+
+        self.pc = 0;
+        self.bp = 0;
+        self.argc = 0;
+
+        Some(suspended)
+    }
+
+    // Goes back to where the vm was suspended as though the operation that
+    // failed had pushed value, so the next eval carries on from there.
+    pub fn resume(&mut self, suspended: Suspended<D>, value: Object<D>) {
+        self.stack.truncate(suspended.depth);
+        self.stack.push(Local::Value(value));
+        self.frames = suspended.frames;
+        self.current_function = suspended.current_function;
+        self.pc = suspended.pc;
+        self.bp = suspended.bp;
+        self.argc = suspended.argc;
+    }
+
+    // The names of every global defined so far, natives included.
+    pub fn globals(&self) -> impl Iterator<Item = &str> + '_ {
+        self.globals.keys().map(String::as_str)
+    }
+
     pub fn peek(&self, i: usize) -> Option<&Local<D>> {
         self.stack.get(self.stack.len() - i - 1)
     }
@@ -315,7 +619,9 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             .unwrap()
             .borrow_mut()
             .upvalues[upvalue]
-            .borrow_mut() = val.into_object();
+            .borrow_mut() = val.clone().into_object();
+
+        self.stack.push(val);
 
         Ok(())
     }
@@ -336,12 +642,16 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
 
     pub fn create_upvalue(&mut self, upvalue: UpValue) -> Result<(), Error> {
         let val = match upvalue {
-            UpValue::Local(i) => {
-                let val = self.stack[self.bp + i].clone().into_object();
-                let gc = Gc::new(GcCell::new(val));
-                self.stack[self.bp + i] = Local::UpValue(gc.clone());
-                gc
-            }
+            // a local that's already been captured is shared with the closure
+            // that captured it, rather than copied into a second upvalue
+            UpValue::Local(i) => match &self.stack[self.bp + i] {
+                Local::UpValue(gc) => gc.clone(),
+                Local::Value(val) => {
+                    let gc = Gc::new(GcCell::new(val.clone()));
+                    self.stack[self.bp + i] = Local::UpValue(gc.clone());
+                    gc
+                }
+            },
             UpValue::UpValue(i) => {
                 self.current_function.as_ref().unwrap().borrow().upvalues[i].clone()
             }
@@ -387,11 +697,13 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
         match module {
             Object::Module(m) => {
                 if let Some(var) = m.borrow_mut().globals.get_mut(var) {
-                    *var = val;
+                    *var = val.clone();
                 } else {
                     return Err(Error::NotFound(var.to_string()));
                 }
 
+                self.stack.push(Local::Value(val));
+
                 Ok(())
             }
             object => Err(Error::Type {
@@ -424,25 +736,41 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
     }
 
     pub fn call(&mut self, args: usize) -> Result<(), Error> {
-        match self.stack[self.stack.len() - args - 1]
-            .clone()
-            .into_object()
-        {
+        let callee = self.stack.len() - args - 1;
+
+        // a native is moved out of its slot rather than cloned, the slot goes
+        // along with the arguments once it returns
+        match mem::replace(&mut self.stack[callee], Local::Value(Object::Nil)) {
+            Local::Value(Object::NativeFunction(function)) => {
+                return self.native_call(args, function)
+            }
+            local => self.stack[callee] = local,
+        }
+
+        match self.stack[callee].clone().into_object() {
             Object::Function(function) => {
+                self.check_arity(args, &function)?;
+
                 self.frames.push(Frame {
                     function: self.current_function.clone(),
                     bp: self.bp,
                     pc: self.pc,
+                    argc: self.argc,
                 });
 
                 self.current_function = Some(function.clone());
                 self.pc = 0;
-                self.bp = if let Arity::Variadic(n) = function.borrow().arity() {
-                    let rest = if args > n { args - n } else { 0 };
-                    self.list(rest)?;
-                    self.stack.len() - (n + 1)
-                } else {
-                    self.stack.len() - args
+                self.argc = args;
+                self.bp = match function.borrow().arity() {
+                    Arity::Variadic(n) => {
+                        self.list(args.saturating_sub(n))?;
+                        self.stack.len() - (n + 1)
+                    }
+                    Arity::Optional(required, optional) => {
+                        self.fill_optional(args, required + optional);
+                        self.stack.len() - (required + optional)
+                    }
+                    _ => self.stack.len() - args,
                 };
 
                 Ok(())
@@ -461,14 +789,21 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             .into_object()
         {
             Object::Function(function) => {
+                self.check_arity(args, &function)?;
+
                 self.stack.drain(self.bp..self.stack.len() - args);
 
                 self.current_function = Some(function.clone());
 
                 self.pc = 0;
-
-                if let Arity::Variadic(n) = function.borrow().arity {
-                    self.list(args - n)?;
+                self.argc = args;
+
+                match function.borrow().arity {
+                    Arity::Variadic(n) => self.list(args - n)?,
+                    Arity::Optional(required, optional) => {
+                        self.fill_optional(args, required + optional)
+                    }
+                    _ => (),
                 }
 
                 Ok(())
@@ -481,7 +816,7 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
         }
     }
 
-    fn check_arity(&mut self, args: usize, function: Gc<GcCell<Lambda<D>>>) -> Result<(), Error> {
+    fn check_arity(&self, args: usize, function: &Gc<GcCell<Lambda<D>>>) -> Result<(), Error> {
         match function.borrow().arity {
             Arity::Nullary if args != 0 => Err(Error::Parameters(format!(
                 "expected 0 parameters, received {args}"
@@ -492,16 +827,41 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             Arity::Variadic(n) if args < n => Err(Error::Parameters(format!(
                 "expected at least {n} parameters, received {args}"
             ))),
+            Arity::Optional(required, optional)
+                if args < required || args > required + optional =>
+            {
+                Err(Error::Parameters(format!(
+                    "expected {required} to {} parameters, received {args}",
+                    required + optional
+                )))
+            }
             _ => Ok(()),
         }
     }
 
+    // Missing optional arguments are passed as nil, the function's prologue
+    // replaces them with their defaults by checking the argument count.
+    fn fill_optional(&mut self, args: usize, parameters: usize) {
+        for _ in args..parameters {
+            self.stack.push(Local::Value(Object::Nil));
+        }
+    }
+
+    // Natives never get a frame, they run directly on their arguments where they
+    // sit on the stack and the result replaces them and the callee.
     fn native_call(&mut self, args: usize, function: NativeFunction<D>) -> Result<(), Error> {
         let len = self.stack.len();
-        let parameters = &mut self.stack[len - args..];
-        let ret = function.0(parameters);
+        let ret = match function.0 {
+            Native::Args(function) => function(&mut self.stack[len - args..]),
+            // the vm can't be lent out while its stack is, so the arguments
+            // are taken off it first
+            Native::Vm(function) => {
+                let mut parameters = self.stack.split_off(len - args);
+                function(self, &mut parameters)
+            }
+        };
 
-        self.stack.truncate(self.stack.len() - args - 1);
+        self.stack.truncate(len - args - 1);
 
         match ret {
             Ok(val) => {
@@ -512,6 +872,61 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
         }
     }
 
+    // Calls a function from a native and runs it to completion, on top of
+    // whatever the vm was doing when the native was called. If the function
+    // fails the vm is put back the way it was before the call, so the native
+    // can carry on.
+    pub fn call_function(
+        &mut self,
+        function: Object<D>,
+        args: impl IntoIterator<Item = Object<D>>,
+    ) -> Result<Object<D>, Error> {
+        let depth = self.frames.len();
+        let len = self.stack.len();
+
+        self.stack.push(Local::Value(function));
+        self.stack.extend(args.into_iter().map(Local::Value));
+
+        match self.run_call(depth, self.stack.len() - len - 1) {
+            Ok(()) => Ok(self.stack.pop().unwrap().into_object()),
+            Err(error) => {
+                if let Some(frame) = self.frames.drain(depth..).next() {
+                    self.current_function = frame.function;
+                    self.pc = frame.pc;
+                    self.bp = frame.bp;
+                    self.argc = frame.argc;
+                }
+                self.stack.truncate(len);
+                Err(error)
+            }
+        }
+    }
+
+    fn run_call(&mut self, depth: usize, args: usize) -> Result<(), Error> {
+        self.call(args)?;
+
+        while self.frames.len() > depth {
+            let function = self.current_function.clone().unwrap();
+            let opcode = function.borrow().opcodes.opcodes[self.pc].clone();
+
+            if let Some(tracer) = &mut self.tracer {
+                tracer(self.pc, &opcode, &function.borrow().opcodes.debug[self.pc]);
+            }
+
+            if let Some(profiler) = &mut self.profiler {
+                let function = function.borrow();
+                let site = (&*function.opcodes as *const _ as usize, self.pc);
+                let debug = &function.opcodes.debug[self.pc];
+                profiler.step(self.frames.len(), site, &opcode, debug);
+            }
+
+            self.pc += 1;
+            self.dispatch(opcode)?;
+        }
+
+        Ok(())
+    }
+
     pub fn ret(&mut self) -> Result<(), Error> {
         let ret = self.stack.pop().unwrap();
         self.stack.truncate(self.bp - 1);
@@ -519,6 +934,7 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
         let frame = self.frames.pop().unwrap();
         self.pc = frame.pc;
         self.bp = frame.bp;
+        self.argc = frame.argc;
         self.current_function = frame.function;
         Ok(())
     }
@@ -543,11 +959,17 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
         Ok(())
     }
 
-    pub fn lambda(&mut self, arity: Arity, opcodes: Gc<OpCodeTable<D>>) -> Result<(), Error> {
+    pub fn lambda(
+        &mut self,
+        arity: Arity,
+        opcodes: Gc<OpCodeTable<D>>,
+        doc: Option<Gc<String>>,
+    ) -> Result<(), Error> {
         let function = Lambda {
             arity,
             opcodes: opcodes.clone(),
             upvalues: Vec::new(),
+            doc,
         };
 
         let object = Object::Function(Gc::new(GcCell::new(function)));
@@ -604,6 +1026,23 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
         self.binary_integer_op(|a, b| a / b)
     }
 
+    // Skips the dispatch on each operand in binary_integer_op. A decl is taken on
+    // faith though, so a caller the checker couldn't see can still pass
+    // something else, which is reported the same way.
+    fn unchecked_integer_op(&mut self, f: impl Fn(i64, i64) -> i64) -> Result<(), Error> {
+        let len = self.stack.len();
+
+        if let [Local::Value(Object::Int(a)), Local::Value(Object::Int(b))] = &self.stack[len - 2..]
+        {
+            let result = f(*a, *b);
+            self.stack.truncate(len - 2);
+            self.stack.push(Local::Value(Object::Int(result)));
+            Ok(())
+        } else {
+            self.binary_integer_op(f)
+        }
+    }
+
     pub fn car(&mut self) -> Result<(), Error> {
         let car = match self.stack.pop().unwrap().into_object() {
             Object::Cons(cons) => cons.borrow().0.clone(),
@@ -652,40 +1091,40 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
 
     pub fn set_car(&mut self) -> Result<(), Error> {
         let val = self.stack.pop().unwrap();
-        let mut cons = self.stack.pop().unwrap();
+        let cons = self.stack.pop().unwrap();
 
-        cons.with_mut(|object| match object {
+        cons.with(|object| match object {
             Object::Cons(cons) => {
-                cons.borrow_mut().0 = val.into_object();
+                cons.borrow_mut().0 = val.clone().into_object();
                 Ok(())
             }
             object => Err(Error::Type {
                 expected: Type::Cons,
-                recieved: Type::from(&*object),
+                recieved: Type::from(object),
             }),
         })?;
 
-        self.stack.push(cons);
+        self.stack.push(val);
 
         Ok(())
     }
 
     pub fn set_cdr(&mut self) -> Result<(), Error> {
         let val = self.stack.pop().unwrap();
-        let mut cons = self.stack.pop().unwrap();
+        let cons = self.stack.pop().unwrap();
 
-        cons.with_mut(|object| match object {
+        cons.with(|object| match object {
             Object::Cons(cons) => {
-                cons.borrow_mut().1 = val.into_object();
+                cons.borrow_mut().1 = val.clone().into_object();
                 Ok(())
             }
             object => Err(Error::Type {
                 expected: Type::Cons,
-                recieved: Type::from(&*object),
+                recieved: Type::from(object),
             }),
         })?;
 
-        self.stack.push(cons);
+        self.stack.push(val);
 
         Ok(())
     }
@@ -701,6 +1140,19 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
         Ok(())
     }
 
+    pub fn jump_table(&mut self, table: &JumpTable) {
+        let offset = match self.stack.pop().unwrap().into_object() {
+            Object::Int(i) => i
+                .checked_sub(table.min)
+                .and_then(|i| usize::try_from(i).ok())
+                .and_then(|i| table.offsets.get(i).copied())
+                .unwrap_or(table.default),
+            _ => table.default,
+        };
+
+        self.pc += offset;
+    }
+
     pub fn branch(&mut self, i: usize) -> Result<(), Error> {
         let p = self.stack.pop().unwrap();
 
@@ -721,13 +1173,13 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
     }
 
     pub fn is_type(&mut self, ty: Type) -> Result<(), Error> {
-        self.stack.push(
-            if Type::from(&self.stack.last().unwrap().clone().into_object()) == ty {
-                Local::Value(Object::Bool(true))
-            } else {
-                Local::Value(Object::Bool(false))
-            },
-        );
+        let object = self.stack.pop().unwrap().into_object();
+
+        self.stack.push(if Type::from(&object) == ty {
+            Local::Value(Object::Bool(true))
+        } else {
+            Local::Value(Object::Bool(false))
+        });
         Ok(())
     }
 
@@ -817,7 +1269,7 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
 
         map.with_mut(|object| match object {
             Object::HashMap(hm) => {
-                hm.borrow_mut().insert(key, rhs.into_object());
+                hm.borrow_mut().insert(key, rhs.clone().into_object());
                 Ok(())
             }
             object => Err(Error::Type {
@@ -826,6 +1278,8 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             }),
         })?;
 
+        self.stack.push(rhs);
+
         Ok(())
     }
 
@@ -865,7 +1319,15 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
             }
         };
 
-        let list = Object::from_iter(map.borrow().iter().map(|(key, value)| {
+        let map = map.borrow();
+
+        let mut items = map.iter().collect::<Vec<_>>();
+
+        if self.deterministic {
+            items.sort_by_key(|&(key, _)| key);
+        }
+
+        let list = Object::from_iter(items.into_iter().map(|(key, value)| {
             Object::Cons(Gc::new(GcCell::new(Cons(
                 Object::from(key),
                 Object::Cons(Gc::new(GcCell::new(Cons(value.clone(), Object::Nil)))),
@@ -876,6 +1338,54 @@ impl<D: Clone + PartialEq + PartialOrd + Hash + Debug> Vm<D> {
 
         Ok(())
     }
+
+    pub fn documentation(&mut self) -> Result<(), Error> {
+        let doc = match self.stack.pop().unwrap().into_object() {
+            Object::Function(function) => function.borrow().doc.clone(),
+            _ => None,
+        };
+
+        self.stack.push(Local::Value(match doc {
+            Some(doc) => Object::String(doc),
+            None => Object::Nil,
+        }));
+
+        Ok(())
+    }
+
+    pub fn gensym(&mut self) -> Result<(), Error> {
+        let symbol = self.fresh_symbol();
+        self.stack
+            .push(Local::Value(Object::Symbol(Gc::new(symbol))));
+        Ok(())
+    }
+
+    // Gensyms use a prefix the reader accepts but that user code is unlikely
+    // to spell by accident, so they survive being printed and read back
+    // during macro expansion.
+    pub fn fresh_symbol(&mut self) -> String {
+        self.gensyms += 1;
+        format!("#:g{}", self.gensyms)
+    }
+}
+
+// How many values an opcode takes off the stack, for the opcodes that only
+// push their result in place of them. Anything else can leave the stack
+// halfway through a change when it fails, so it can't be resumed.
+fn resumable<D>(opcode: &OpCode<D>) -> Option<usize> {
+    match opcode {
+        OpCode::GetGlobal(_) => Some(0),
+        OpCode::GetModuleVar(_) | OpCode::Car | OpCode::Cdr => Some(1),
+        OpCode::Apply
+        | OpCode::Add
+        | OpCode::Sub
+        | OpCode::Mul
+        | OpCode::Div
+        | OpCode::Lt
+        | OpCode::Gt => Some(2),
+        OpCode::Call(args) | OpCode::Tail(args) => Some(args + 1),
+        _ => None,
+    }
 }
 
 impl<D: Clone> Local<D> {
@@ -950,6 +1460,14 @@ impl<T> OpCodeTable<T> {
     }
 }
 
+unsafe impl Trace for JumpTable {
+    unsafe fn root(&self) {}
+
+    unsafe fn unroot(&self) {}
+
+    unsafe fn trace(&self, _: &mut dyn FnMut(std::ptr::NonNull<gc::Inner<dyn Trace>>) -> bool) {}
+}
+
 unsafe impl<D> Trace for OpCodeTable<D> {
     unsafe fn root(&self) {}
 
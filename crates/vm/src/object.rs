@@ -1,9 +1,11 @@
 use crate::{Arity, Error, OpCodeTable};
 use gc::{Gc, GcCell, Trace};
+use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Write;
 use std::fmt::{self, Debug, Display};
+use std::io;
 use std::ops::Deref;
 use std::ptr::NonNull;
 use std::rc::Rc;
@@ -15,6 +17,11 @@ pub enum Type {
     Function,
     Cons,
     Map,
+    Deque,
+    Set,
+    Heap,
+    Port,
+    Bytes,
     String,
     Symbol,
     Int,
@@ -40,6 +47,11 @@ pub enum Object<D: 'static> {
     Function(Gc<GcCell<Lambda<D>>>),
     Cons(Gc<GcCell<Cons<D>>>),
     HashMap(Gc<GcCell<HashMap<HashMapKey, Object<D>>>>),
+    Deque(Gc<GcCell<VecDeque<Object<D>>>>),
+    Set(Gc<GcCell<HashSet<HashMapKey>>>),
+    Heap(Gc<GcCell<Heap<D>>>),
+    Port(Rc<RefCell<Port>>),
+    Bytes(Gc<Vec<u8>>),
     String(Gc<String>),
     Symbol(Gc<String>),
     Int(i64),
@@ -53,13 +65,28 @@ pub struct Lambda<D: 'static> {
     pub(crate) arity: Arity,
     pub(crate) opcodes: Gc<OpCodeTable<D>>,
     pub(crate) upvalues: Vec<Gc<GcCell<Object<D>>>>,
+    pub(crate) doc: Option<Gc<String>>,
 }
 
+#[derive(Clone)]
+pub struct NativeFunction<D: 'static>(pub(crate) Native<D>);
+
 #[allow(clippy::type_complexity)]
 #[derive(Clone)]
-pub struct NativeFunction<D: 'static>(
-    pub(crate) Rc<dyn Fn(&mut [crate::Local<D>]) -> Result<Object<D>, Error>>,
-);
+pub(crate) enum Native<D: 'static> {
+    Args(Rc<dyn Fn(&mut [crate::Local<D>]) -> Result<Object<D>, Error>>),
+    // Natives that call back into lisp, such as sort with its comparator, are
+    // given the vm to do it with.
+    Vm(Rc<dyn Fn(&mut crate::Vm<D>, &mut [crate::Local<D>]) -> Result<Object<D>, Error>>),
+}
+
+// A file or stream read or written a piece at a time. Closing a port drops
+// its handle, and anything done with it after that is an error.
+pub enum Port {
+    Input(Box<dyn io::BufRead>),
+    Output(Box<dyn io::Write>),
+    Closed,
+}
 
 #[derive(Debug)]
 pub struct Module<D: 'static> {
@@ -70,6 +97,15 @@ pub struct Module<D: 'static> {
 #[derive(Clone, Debug, PartialEq)]
 pub struct Cons<D: 'static>(pub Object<D>, pub Object<D>);
 
+// A binary heap of (priority . item) entries. Without a less function the
+// priorities are ints and the smallest comes out first; with one the priority
+// is nil and items are compared with it instead.
+#[derive(Clone, Debug)]
+pub struct Heap<D: 'static> {
+    pub less: Option<Object<D>>,
+    pub entries: Vec<Cons<D>>,
+}
+
 #[derive(Clone, Debug)]
 pub struct IterCons<D: 'static>(Option<Cons<D>>);
 
@@ -106,12 +142,21 @@ impl<D: Clone + 'static> Cons<D> {
     pub fn iter_cars(&self) -> IterCars<D> {
         IterCars(self.iter())
     }
+
+    // The cdr of the last cons, nil unless this is an improper list.
+    pub fn tail(&self) -> Object<D> {
+        self.iter().last().map_or(Object::Nil, |cons| cons.1)
+    }
 }
 
 impl<D> Lambda<D> {
     pub fn arity(&self) -> Arity {
         self.arity
     }
+
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref().map(String::as_str)
+    }
 }
 
 impl<D> NativeFunction<D> {
@@ -119,7 +164,14 @@ impl<D> NativeFunction<D> {
     where
         F: Fn(&mut [crate::Local<D>]) -> Result<Object<D>, Error> + 'static,
     {
-        Self(Rc::new(f))
+        Self(Native::Args(Rc::new(f)))
+    }
+
+    pub fn with_vm<F>(f: F) -> Self
+    where
+        F: Fn(&mut crate::Vm<D>, &mut [crate::Local<D>]) -> Result<Object<D>, Error> + 'static,
+    {
+        Self(Native::Vm(Rc::new(f)))
     }
 }
 
@@ -141,6 +193,16 @@ impl<D> Debug for NativeFunction<D> {
     }
 }
 
+impl Debug for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Input(_) => write!(f, "Port::Input"),
+            Self::Output(_) => write!(f, "Port::Output"),
+            Self::Closed => write!(f, "Port::Closed"),
+        }
+    }
+}
+
 impl<D> From<&Object<D>> for Type {
     fn from(value: &Object<D>) -> Self {
         match value {
@@ -148,6 +210,11 @@ impl<D> From<&Object<D>> for Type {
             Object::Function(_) | Object::NativeFunction(_) => Type::Function,
             Object::Cons(_) => Type::Cons,
             Object::HashMap(_) => Type::Map,
+            Object::Deque(_) => Type::Deque,
+            Object::Set(_) => Type::Set,
+            Object::Heap(_) => Type::Heap,
+            Object::Port(_) => Type::Port,
+            Object::Bytes(_) => Type::Bytes,
             Object::String(_) => Type::String,
             Object::Symbol(_) => Type::Symbol,
             Object::Int(_) => Type::Int,
@@ -165,6 +232,11 @@ impl fmt::Display for Type {
             Self::Function => write!(f, "function"),
             Self::Cons => write!(f, "cons"),
             Self::Map => write!(f, "map"),
+            Self::Deque => write!(f, "deque"),
+            Self::Set => write!(f, "set"),
+            Self::Heap => write!(f, "heap"),
+            Self::Port => write!(f, "port"),
+            Self::Bytes => write!(f, "bytes"),
             Self::Symbol => write!(f, "symbol"),
             Self::String => write!(f, "string"),
             Self::Int => write!(f, "int"),
@@ -179,9 +251,15 @@ impl<D: PartialEq> PartialEq for Object<D> {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Object::Cons(a), Object::Cons(b)) => *a.borrow() == *b.borrow(),
+            (Object::Deque(a), Object::Deque(b)) => *a.borrow() == *b.borrow(),
+            (Object::Set(a), Object::Set(b)) => *a.borrow() == *b.borrow(),
+            (Object::Heap(a), Object::Heap(b)) => Gc::as_ptr(a) == Gc::as_ptr(b),
+            (Object::Port(a), Object::Port(b)) => Rc::ptr_eq(a, b),
+            (Object::Bytes(a), Object::Bytes(b)) => a == b,
             (Object::String(a), Object::String(b)) => a == b,
             (Object::Symbol(a), Object::Symbol(b)) => a == b,
             (Object::Int(a), Object::Int(b)) => a == b,
+            (Object::Char(a), Object::Char(b)) => a == b,
             (Object::Bool(a), Object::Bool(b)) => a == b,
             (Object::Nil, Object::Nil) => true,
             _ => false,
@@ -194,7 +272,9 @@ impl<D: PartialOrd> PartialOrd for Object<D> {
         Some(match (self, other) {
             (Object::Symbol(a), Object::Symbol(b)) => a.cmp(b),
             (Object::String(a), Object::String(b)) => a.cmp(b),
+            (Object::Bytes(a), Object::Bytes(b)) => a.cmp(b),
             (Object::Int(a), Object::Int(b)) => a.cmp(b),
+            (Object::Char(a), Object::Char(b)) => a.cmp(b),
             (Object::Bool(a), Object::Bool(b)) => a.cmp(b),
             (Object::Nil, Object::Nil) => Ordering::Equal,
             _ => return None,
@@ -208,6 +288,9 @@ impl<D> Display for Lambda<D> {
             Arity::Nullary => write!(f, "nullary lambda"),
             Arity::Nary(n) => write!(f, "{n}-ary lambda"),
             Arity::Variadic(n) => write!(f, "{n}-ary variadic lambda"),
+            Arity::Optional(required, optional) => {
+                write!(f, "{required}-ary lambda with {optional} optional")
+            }
         }
     }
 }
@@ -237,6 +320,46 @@ impl<D: Clone> Display for Object<D> {
                 }
                 Ok(())
             }
+            Self::Deque(deque) => {
+                write!(f, "#deque(")?;
+                for (i, object) in deque.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{object}")?;
+                }
+                write!(f, ")")
+            }
+            // sorted, so a set always prints the same way
+            Self::Set(set) => {
+                let set = set.borrow();
+                let mut keys = set.iter().collect::<Vec<_>>();
+                keys.sort();
+                write!(f, "#set(")?;
+                for (i, key) in keys.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", Object::<D>::from(key))?;
+                }
+                write!(f, ")")
+            }
+            Self::Heap(heap) => write!(f, "heap of {}", heap.borrow().entries.len()),
+            Self::Port(port) => match *port.borrow() {
+                Port::Input(_) => write!(f, "input port"),
+                Port::Output(_) => write!(f, "output port"),
+                Port::Closed => write!(f, "closed port"),
+            },
+            Self::Bytes(bytes) => {
+                write!(f, "#u8(")?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{byte}")?;
+                }
+                write!(f, ")")
+            }
             Self::Symbol(symbol) => write!(f, "'{symbol}"),
             Self::String(string) => write!(f, r#""{string}""#),
             Self::Int(i) => write!(f, "{i}"),
@@ -380,8 +503,12 @@ unsafe impl<D: 'static> Trace for Object<D> {
             Self::Function(function) => function.root(),
             Self::Cons(cons) => cons.root(),
             Self::HashMap(hm) => hm.root(),
+            Self::Deque(deque) => deque.root(),
+            Self::Set(set) => set.root(),
+            Self::Heap(heap) => heap.root(),
             Self::Symbol(symbol) => symbol.root(),
             Self::String(string) => string.root(),
+            Self::Bytes(bytes) => bytes.root(),
             _ => (),
         }
     }
@@ -391,8 +518,12 @@ unsafe impl<D: 'static> Trace for Object<D> {
             Self::Function(function) => function.unroot(),
             Self::Cons(cons) => cons.unroot(),
             Self::HashMap(hm) => hm.unroot(),
+            Self::Deque(deque) => deque.unroot(),
+            Self::Set(set) => set.unroot(),
+            Self::Heap(heap) => heap.unroot(),
             Self::Symbol(symbol) => symbol.unroot(),
             Self::String(string) => string.unroot(),
+            Self::Bytes(bytes) => bytes.unroot(),
             _ => (),
         }
     }
@@ -402,8 +533,12 @@ unsafe impl<D: 'static> Trace for Object<D> {
             Self::Function(function) => function.trace(tracer),
             Self::Cons(cons) => cons.trace(tracer),
             Self::HashMap(hm) => hm.trace(tracer),
+            Self::Deque(deque) => deque.trace(tracer),
+            Self::Set(set) => set.trace(tracer),
+            Self::Heap(heap) => heap.trace(tracer),
             Self::Symbol(symbol) => symbol.trace(tracer),
             Self::String(string) => string.trace(tracer),
+            Self::Bytes(bytes) => bytes.trace(tracer),
             _ => (),
         }
     }
@@ -426,17 +561,51 @@ unsafe impl<D> Trace for Cons<D> {
     }
 }
 
+unsafe impl<D> Trace for Heap<D> {
+    unsafe fn root(&self) {
+        self.less.iter().for_each(|less| less.root());
+        self.entries.root();
+    }
+
+    unsafe fn unroot(&self) {
+        self.less.iter().for_each(|less| less.unroot());
+        self.entries.unroot();
+    }
+
+    unsafe fn trace(&self, tracer: &mut dyn FnMut(NonNull<gc::Inner<dyn Trace>>) -> bool) {
+        self.less.iter().for_each(|less| less.trace(tracer));
+        self.entries.trace(tracer);
+    }
+}
+
 impl<D: Clone> Object<D> {
     pub fn print(&self, buffer: &mut String) -> Result<(), ()> {
         match self {
             Self::Cons(cons) => cons.borrow().print(buffer)?,
             Self::Symbol(symbol) => write!(buffer, " {symbol} ").map_err(|_| ())?,
+            // read back as a raw string so the quotes in it don't end it
+            Self::String(string) if string.contains('"') => {
+                write!(buffer, r##" #"{string}"# "##).map_err(|_| ())?
+            }
             Self::String(string) => write!(buffer, r#" "{string}" "#).map_err(|_| ())?,
             Self::Char(char) => write!(buffer, r#" '{char}' "#).map_err(|_| ())?,
             Self::Int(int) => write!(buffer, " {int} ").map_err(|_| ())?,
             Self::Bool(true) => write!(buffer, " true ").map_err(|_| ())?,
             Self::Bool(false) => write!(buffer, " false ").map_err(|_| ())?,
             Self::Nil => write!(buffer, " nil ").map_err(|_| ())?,
+            // read back as a map literal, in key order so it always prints the
+            // same way
+            Self::HashMap(map) => {
+                let map = map.borrow();
+                let mut entries = map.iter().collect::<Vec<_>>();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                write!(buffer, " {{ ").map_err(|_| ())?;
+                for (key, value) in entries {
+                    Object::<D>::from(key).print(buffer)?;
+                    value.print(buffer)?;
+                }
+                write!(buffer, " }} ").map_err(|_| ())?
+            }
             _ => return Err(()),
         }
 
@@ -452,6 +621,13 @@ impl<D: Clone> Cons<D> {
             e.print(buffer)?;
         }
 
+        let tail = self.tail();
+
+        if !tail.is_nil() {
+            write!(buffer, " . ").map_err(|_| ())?;
+            tail.print(buffer)?;
+        }
+
         write!(buffer, " ) ").map_err(|_| ())?;
 
         Ok(())
@@ -467,6 +643,10 @@ fn pretty_print<D: Clone>(cons: &Cons<D>, depth: usize, f: &mut fmt::Formatter)
             write!(f, "{indent} ")?;
         }
     }
+    let tail = cons.tail();
+    if !tail.is_nil() {
+        write!(f, "{indent} . {tail}")?;
+    }
     write!(f, ")")?;
     Ok(())
 }
@@ -107,11 +107,20 @@
                   (cons 'let* (cons (cdr bindings) body)))
             (cdar bindings))))
 
+;; A clause may carry a guard, (test :when guard expr), which must also hold
+;; for the clause to be taken.
 (defmacro cond (&rest clauses)
   (if (nil? clauses)
       nil
-      (list 'if (caar clauses) (cadar clauses)
-            (cons 'cond (cdr clauses)))))
+      (let ((clause (car clauses)))
+        (if (if (cons? (cdr clause))
+                (= (cadr clause) ':when)
+                false)
+            (list 'if (list 'if (car clause) (car (cddr clause)) false)
+                  (cadr (cddr clause))
+                  (cons 'cond (cdr clauses)))
+            (list 'if (car clause) (cadr clause)
+                  (cons 'cond (cdr clauses)))))))
 
 (defmacro and (&rest exprs)
   (cond ((nil? exprs) true)
@@ -127,17 +136,23 @@
                     true
                     (cons 'or (cdr exprs))))))
 
+;; Works through the list a cons at a time so a dotted tail, `(a . ,b) or
+;; `(a . b), ends up as the cdr of the last cons.
 (defmacro quasiquote (exprs)
-  (cons 'append (map (lambda (expr)
-                       (cond ((and (cons? expr) (= (car expr) 'unquote))
-                              (list 'list (cadr expr)))
-                             ((and (cons? expr) (= (car expr) 'unquote-splice))
-                              (cadr expr))
-                             ((cons? expr)
-                              (list 'list (list 'quasiquote expr)))
-                             (true
-                              (list 'list (list 'quote expr)))))
-                     exprs)))
+  (if (cons? exprs)
+      (if (= (car exprs) 'unquote)
+          (cadr exprs)
+          (let ((expr (car exprs))
+                (rest (list 'quasiquote (cdr exprs))))
+            (cond ((and (cons? expr) (= (car expr) 'unquote))
+                   (list 'cons (cadr expr) rest))
+                  ((and (cons? expr) (= (car expr) 'unquote-splice))
+                   (list 'append (cadr expr) rest))
+                  ((cons? expr)
+                   (list 'cons (list 'quasiquote expr) rest))
+                  (true
+                   (list 'cons (list 'quote expr) rest)))))
+      (list 'quote exprs)))
 
 (defmacro named-let (name bindings &rest body)
   `(let ((,name (Z (lambda (,name)
@@ -145,6 +160,16 @@
                        ,@body)))))
      (,name ,@(map cadr bindings))))
 
+;; The functions are bound to nil first and then set, so every one of them
+;; closes over the others and may call them.
+(defmacro labels (definitions &rest body)
+  `((lambda ,(map car definitions)
+      ,@(map (lambda (definition)
+               `(set! ,(car definition) (lambda ,@(cdr definition))))
+             definitions)
+      ,@body)
+    ,@(map (lambda (definition) nil) definitions)))
+
 (def fold (lambda (fn list)
             (let ((acc (car list)))
               (do (lambda (e)
@@ -160,3 +185,49 @@
                                   counter
                                   (loop (cdr list) pred (+ counter 1) loop))))))
               (loop list pred 0 loop))))
+
+;; with-retry evaluates body again after sleeping when it raises an error or
+;; returns (err ...), doubling the wait each time. Gives up after the given
+;; number of attempts, letting the last error through or returning whatever the
+;; body returned last. Errors are caught with with-error-handler, so the
+;; natives have to be loaded.
+(defmacro with-retry (options &rest body)
+  (let ((option (lambda (key default)
+                  ((lambda (loop) (loop options loop))
+                   (lambda (options loop)
+                     (cond ((nil? options) default)
+                           ((= (car options) key) (cadr options))
+                           (true (loop (cddr options) loop))))))))
+    `(named-let retry ((attempts ,(option ':attempts 3))
+                       (backoff-ms ,(option ':backoff-ms 100)))
+       (let ((attempt (lambda () ,@body))
+             (failed false))
+         (let ((result (if (> attempts 1)
+                           (with-error-handler (lambda (condition)
+                                                 (set! failed true)
+                                                 condition)
+                             attempt)
+                           (attempt))))
+           (if (and (> attempts 1)
+                    (or failed (and (cons? result) (= (car result) 'err))))
+               (progn
+                 (sleep-ms backoff-ms)
+                 (retry (- attempts 1) (* backoff-ms 2)))
+               result))))))
+
+;; (deftest name body...) defines name as a function of no arguments and lists
+;; it in *tests*, most recent first, for lisp test to find and run one at a
+;; time.
+(def *tests* nil)
+
+(def register-test (lambda (name test)
+                     (set! *tests* (cons name *tests*))
+                     test))
+
+;; (defbench name body...) is the same for benchmarks, which lisp bench finds in
+;; *benches* and times.
+(def *benches* nil)
+
+(def register-bench (lambda (name bench)
+                      (set! *benches* (cons name *benches*))
+                      bench))
@@ -2,8 +2,242 @@
 
 (decl argv (lambda ()))
 
+(decl command-line-args (lambda ()))
+
+(decl getenv (lambda (string)))
+
+(decl setenv (lambda (string string)))
+
+(decl is-tty? (lambda ()))
+
+(decl terminal-width (lambda ()))
+
+(decl ansi-code (lambda (&rest styles)))
+
+(decl ansi-style (lambda (string &rest styles)))
+
+(decl strip-ansi (lambda (string)))
+
 (decl read-file (lambda (string)))
 
+(decl write-file (lambda (string string &rest options)))
+
+(decl read-file-bytes (lambda (string)))
+
+(decl write-file-bytes (lambda (string bytes)))
+
+(decl list-dir (lambda (string)))
+
+(decl create-dir (lambda (string)))
+
+(decl remove-dir (lambda (string &rest options)))
+
+(decl copy-file (lambda (string string)))
+
+(decl rename-file (lambda (string string)))
+
+(decl file-metadata (lambda (string)))
+
+(decl open (lambda (string &rest options)))
+
+(decl read-line (lambda (port)))
+
+(decl read-char (lambda (port)))
+
+(decl write-string (lambda (port string)))
+
+(decl flush (lambda (port)))
+
+(decl close (lambda (port)))
+
+(decl format (lambda (string &rest objects)))
+
+(decl log-debug (lambda (message)))
+
+(decl log-info (lambda (message)))
+
+(decl log-warn (lambda (message)))
+
+(decl log-error (lambda (message)))
+
+(decl log-level (lambda (symbol)))
+
+(decl csv-parse (lambda (string &rest options)))
+
+(decl csv-write (lambda (cons &rest options)))
+
+(decl read-string (lambda (string)))
+
+(decl bytes (lambda (&rest ints)))
+
+(decl bytes? (lambda (object)))
+
+(decl bytes-length (lambda (bytes)))
+
+(decl bytes-ref (lambda (bytes int)))
+
+(decl bytes-slice (lambda (bytes int &optional int)))
+
+(decl utf8->string (lambda (bytes)))
+
+(decl string->utf8 (lambda (string)))
+
+(decl sha256 (lambda (data)))
+
+(decl md5 (lambda (data)))
+
+(decl base64-encode (lambda (data)))
+
+(decl base64-decode (lambda (string)))
+
+(decl base64-decode-bytes (lambda (string)))
+
+(decl error (lambda (string &optional data)))
+
+(decl with-error-handler (lambda (function function)))
+
+(decl is-digit? (lambda (char)))
+
+(decl string->list (lambda (string)))
+
+(decl list->string (lambda (list)))
+
+(decl symbol->string (lambda (symbol)))
+
+(decl string->symbol (lambda (string)))
+
+(decl char->int (lambda (char)))
+
+(decl int->char (lambda (int)))
+
+(decl char-upcase (lambda (char)))
+
+(decl char-downcase (lambda (char)))
+
+(decl char-alpha? (lambda (char)))
+
+(decl char-whitespace? (lambda (char)))
+
 (decl string-split-whitespace (lambda (string)))
 
-(decl string->int (lambda (string)))
+(decl string-trim (lambda (string)))
+
+(decl string-replace (lambda (string string string)))
+
+(decl string-contains? (lambda (string string)))
+
+(decl string-starts-with? (lambda (string string)))
+
+(decl string-ends-with? (lambda (string string)))
+
+(decl string-upcase (lambda (string)))
+
+(decl string-downcase (lambda (string)))
+
+(decl string-length (lambda (string)))
+
+(decl string-ref (lambda (string int)))
+
+(decl substring (lambda (string int &optional int)))
+
+(decl string-append (lambda (&rest strings)))
+
+(decl string->int (lambda (string &optional int)))
+
+(decl int->string (lambda (int &optional int)))
+
+(decl string->int? (lambda (string &optional int int int)))
+
+(decl sort (lambda (list function)))
+
+(decl reverse (lambda (list)))
+
+(decl native-length (lambda (list)))
+
+(decl native-append (lambda (&rest lists)))
+
+(decl native-nth (lambda (list int)))
+
+(decl native-last (lambda (list)))
+
+(decl native-map (lambda (function list)))
+
+(decl native-filter (lambda (function list)))
+
+;; The bootstrap's versions of these are written in lisp so macros can use them
+;; when there are no natives. Where there are, the natives take their place.
+(set! length native-length)
+
+(set! append native-append)
+
+(set! nth native-nth)
+
+(set! last native-last)
+
+(set! map native-map)
+
+(set! filter native-filter)
+
+(decl fold-left (lambda (function acc list)))
+
+(decl fold-right (lambda (function acc list)))
+
+(decl for-each (lambda (function list)))
+
+(decl queue-create (lambda (&rest items)))
+
+(decl push-front! (lambda (deque item)))
+
+(decl push-back! (lambda (deque item)))
+
+(decl pop-front! (lambda (deque)))
+
+(decl pop-back! (lambda (deque)))
+
+(decl queue-length (lambda (deque)))
+
+(decl queue->list (lambda (deque)))
+
+(decl set-create (lambda (&rest items)))
+
+(decl set-insert! (lambda (set item)))
+
+(decl set-contains? (lambda (set item)))
+
+(decl set-remove! (lambda (set item)))
+
+(decl set-length (lambda (set)))
+
+(decl set->list (lambda (set)))
+
+(decl heap-create (lambda (&optional function)))
+
+(decl heap-push! (lambda (heap item &optional int)))
+
+(decl heap-pop! (lambda (heap)))
+
+(decl heap-length (lambda (heap)))
+
+(decl assoc (lambda (key list)))
+
+(decl assq (lambda (key list)))
+
+(decl alist->map (lambda (list)))
+
+(decl map->alist (lambda (map)))
+
+(decl random (lambda (int)))
+
+(decl time-monotonic (lambda ()))
+
+(decl sleep-ms (lambda (int)))
+
+(decl time-now (lambda ()))
+
+(decl time-format (lambda (time string)))
+
+(decl time-parse (lambda (string string)))
+
+(decl ulid (lambda ()))
+
+(decl next-id (lambda (symbol)))
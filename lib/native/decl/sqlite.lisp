@@ -0,0 +1,9 @@
+;; Only loaded when built with the sqlite feature, which is what adds these.
+
+(decl sqlite-open (lambda (string)))
+
+(decl sqlite-close (lambda (int)))
+
+(decl sqlite-exec (lambda (int string &rest params)))
+
+(decl sqlite-query (lambda (int string &rest params)))
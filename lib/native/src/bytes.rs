@@ -0,0 +1,124 @@
+use crate::{check_arity, check_type};
+use gc::Gc;
+use std::fs;
+use vm::{object::Type, Error, Local, Object};
+
+fn io_error(error: std::io::Error) -> Error {
+    Error::Other(Box::new(error))
+}
+
+// (bytes 1 2 3) makes a byte vector from ints between 0 and 255.
+pub fn bytes<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    let bytes = objects
+        .iter()
+        .map(|object| {
+            let int = check_type!(object, Int);
+            u8::try_from(int).map_err(|_| {
+                Error::Parameters(format!("bytes expects ints from 0 to 255, received {int}"))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Object::Bytes(Gc::new(bytes)))
+}
+
+pub fn is_bytes<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("bytes?", 1, objects);
+    Ok(Object::Bool(objects[0].with(|object| object.is_bytes())))
+}
+
+pub fn length<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("bytes-length", 1, objects);
+
+    let bytes = check_type!(objects[0], Bytes);
+
+    Ok(Object::Int(bytes.len() as i64))
+}
+
+pub fn bytes_ref<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("bytes-ref", 2, objects);
+
+    let bytes = check_type!(objects[0], Bytes);
+    let i = check_type!(objects[1], Int);
+
+    usize::try_from(i)
+        .ok()
+        .and_then(|i| bytes.get(i))
+        .map(|byte| Object::Int(i64::from(*byte)))
+        .ok_or_else(|| {
+            Error::Parameters(format!(
+                "bytes-ref index {i} is out of range for {} bytes",
+                bytes.len()
+            ))
+        })
+}
+
+// (bytes-slice bytes start) or (bytes-slice bytes start end), end exclusive.
+pub fn slice<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if !matches!(objects.len(), 2 | 3) {
+        return Err(Error::Parameters(
+            "bytes-slice expects 2 or 3 parameters".to_string(),
+        ));
+    }
+
+    let bytes = check_type!(objects[0], Bytes);
+    let start = check_type!(objects[1], Int);
+    let end = match objects.get(2) {
+        Some(end) => check_type!(end, Int),
+        None => bytes.len() as i64,
+    };
+
+    if start < 0 || end < start || end > bytes.len() as i64 {
+        return Err(Error::Parameters(format!(
+            "bytes-slice range {start}..{end} is out of range for {} bytes",
+            bytes.len()
+        )));
+    }
+
+    Ok(Object::Bytes(Gc::new(
+        bytes[start as usize..end as usize].to_vec(),
+    )))
+}
+
+pub fn read_file<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("read-file-bytes", 1, objects);
+
+    let path = check_type!(objects[0], String);
+
+    Ok(Object::Bytes(Gc::new(fs::read(path).map_err(io_error)?)))
+}
+
+// Replaces whatever was in the file, creating it if it isn't there.
+pub fn write_file<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("write-file-bytes", 2, objects);
+
+    let path = check_type!(objects[0], String);
+    let bytes = check_type!(objects[1], Bytes);
+
+    fs::write(path, bytes).map_err(io_error)?;
+
+    Ok(Object::Nil)
+}
+
+pub fn to_string<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("utf8->string", 1, objects);
+
+    let bytes = check_type!(objects[0], Bytes);
+
+    String::from_utf8(bytes)
+        .map(|string| Object::String(Gc::new(string)))
+        .map_err(|error| {
+            Error::Parameters(format!(
+                "utf8->string found invalid utf-8 at byte {}",
+                error.utf8_error().valid_up_to()
+            ))
+        })
+}
+
+pub fn from_string<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string->utf8", 1, objects);
+
+    let string = check_type!(objects[0], String);
+
+    Ok(Object::Bytes(Gc::new(string.into_bytes())))
+}
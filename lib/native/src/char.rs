@@ -0,0 +1,64 @@
+use crate::{check_arity, check_type};
+use vm::{object::Type, Error, Local, Object};
+
+// The char's unicode code point.
+pub fn to_int<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("char->int", 1, objects);
+
+    let c = check_type!(objects[0], Char);
+
+    Ok(Object::Int(i64::from(u32::from(c))))
+}
+
+pub fn from_int<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("int->char", 1, objects);
+
+    let i = check_type!(objects[0], Int);
+
+    u32::try_from(i)
+        .ok()
+        .and_then(char::from_u32)
+        .map(Object::Char)
+        .ok_or_else(|| Error::Parameters(format!("int->char expects a code point, received {i}")))
+}
+
+// Chars whose case changes into more than one char, like ß into SS, are left
+// as they are.
+pub fn upcase<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("char-upcase", 1, objects);
+
+    let c = check_type!(objects[0], Char);
+
+    Ok(Object::Char(single(c.to_uppercase()).unwrap_or(c)))
+}
+
+pub fn downcase<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("char-downcase", 1, objects);
+
+    let c = check_type!(objects[0], Char);
+
+    Ok(Object::Char(single(c.to_lowercase()).unwrap_or(c)))
+}
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+pub fn is_alpha<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("char-alpha?", 1, objects);
+
+    let c = check_type!(objects[0], Char);
+
+    Ok(Object::Bool(c.is_alphabetic()))
+}
+
+pub fn is_whitespace<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("char-whitespace?", 1, objects);
+
+    let c = check_type!(objects[0], Char);
+
+    Ok(Object::Bool(c.is_whitespace()))
+}
@@ -0,0 +1,165 @@
+use crate::options::Options;
+use crate::{check_type, keyword_map};
+use gc::Gc;
+use vm::{object::Type, Error, Local, Object};
+
+// (csv-parse string :delimiter #\; :header true). Rows are lists of strings,
+// or with :header maps from the names in the first row, as keywords, to the
+// fields under them. Fields can be quoted to hold the delimiter, newlines or
+// quotes, which are written twice.
+pub fn parse<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.is_empty() {
+        return Err(Error::Parameters("csv-parse expects a string".to_string()));
+    }
+
+    let string = check_type!(objects[0], String);
+
+    let mut options = Options::parse("csv-parse", &objects[1..])?;
+    let delimiter = options.char("delimiter")?.unwrap_or(',');
+    let header = options.bool("header")?.unwrap_or(false);
+    options.finish()?;
+
+    let mut rows = parse_rows(string.as_str(), delimiter)?.into_iter();
+
+    if !header {
+        return Ok(Object::from_iter(rows.map(|row| {
+            Object::from_iter(row.into_iter().map(|field| Object::String(Gc::new(field))))
+        })));
+    }
+
+    let names = rows.next().unwrap_or_default();
+
+    let rows = rows
+        .enumerate()
+        .map(|(i, row)| {
+            if row.len() != names.len() {
+                return Err(Error::Parameters(format!(
+                    "csv-parse expects {} fields in row {} to match the header, found {}",
+                    names.len(),
+                    i + 2,
+                    row.len()
+                )));
+            }
+
+            Ok(keyword_map(names.iter().map(String::as_str).zip(
+                row.into_iter().map(|field| Object::String(Gc::new(field))),
+            )))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Object::from_iter(rows))
+}
+
+fn parse_rows(string: &str, delimiter: char) -> Result<Vec<Vec<String>>, Error> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = string.chars().peekable();
+    let mut line = 1;
+
+    while let Some(char) = chars.next() {
+        match char {
+            '"' if field.is_empty() => {
+                let start = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(char) => {
+                            line += usize::from(char == '\n');
+                            field.push(char);
+                        }
+                        None => {
+                            return Err(Error::Parameters(format!(
+                                "csv-parse found a quoted field on line {start} that isn't closed"
+                            )))
+                        }
+                    }
+                }
+
+                if !matches!(chars.peek(), None | Some('\n' | '\r'))
+                    && chars.peek() != Some(&delimiter)
+                {
+                    return Err(Error::Parameters(format!(
+                        "csv-parse expects a delimiter after the quoted field on line {line}"
+                    )));
+                }
+            }
+            char if char == delimiter => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+                line += 1;
+            }
+            char => field.push(char),
+        }
+    }
+
+    // a last line without a line ending
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+// (csv-write rows :delimiter #\;) where rows is a list of lists of strings,
+// ints, chars or bools. Fields are quoted only when they need to be.
+pub fn write<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.is_empty() {
+        return Err(Error::Parameters("csv-write expects rows".to_string()));
+    }
+
+    let rows = objects[0].clone().into_object();
+
+    let mut options = Options::parse("csv-write", &objects[1..])?;
+    let delimiter = options.char("delimiter")?.unwrap_or(',');
+    options.finish()?;
+
+    let mut buffer = String::new();
+
+    for row in list("csv-write", &rows)? {
+        let fields = list("csv-write", &row)?
+            .iter()
+            .map(|field| {
+                let field = match field {
+                    Object::String(string) => string.to_string(),
+                    Object::Int(int) => int.to_string(),
+                    Object::Char(char) => char.to_string(),
+                    Object::Bool(bool) => bool.to_string(),
+                    object => {
+                        return Err(Error::Parameters(format!(
+                            "csv-write can't write {object} as a field"
+                        )))
+                    }
+                };
+
+                Ok(if field.contains([delimiter, '"', '\n', '\r']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        buffer.push_str(&fields.join(delimiter.to_string().as_str()));
+        buffer.push('\n');
+    }
+
+    Ok(Object::String(Gc::new(buffer)))
+}
+
+fn list<D: Clone>(name: &str, object: &Object<D>) -> Result<Vec<Object<D>>, Error> {
+    match object {
+        Object::Cons(cons) => Ok(cons.borrow().iter_cars().collect()),
+        Object::Nil => Ok(Vec::new()),
+        object => Err(Error::Parameters(format!(
+            "{name} expects a list, received {object}"
+        ))),
+    }
+}
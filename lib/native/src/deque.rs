@@ -0,0 +1,75 @@
+use crate::{check_arity, check_type};
+use gc::{Gc, GcCell};
+use std::collections::VecDeque;
+use vm::{object::Type, Error, Local, Object};
+
+// (queue-create 1 2 3) is a deque holding 1, 2 and 3 from front to back.
+// Either end can be pushed to or popped from in constant time.
+pub fn create<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    let items = objects
+        .iter()
+        .map(|object| object.clone().into_object())
+        .collect::<VecDeque<_>>();
+
+    Ok(Object::Deque(Gc::new(GcCell::new(items))))
+}
+
+pub fn push_front<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("push-front!", 2, objects);
+
+    let deque = check_type!(objects[0], Deque);
+    deque
+        .borrow_mut()
+        .push_front(objects[1].clone().into_object());
+
+    Ok(Object::Nil)
+}
+
+pub fn push_back<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("push-back!", 2, objects);
+
+    let deque = check_type!(objects[0], Deque);
+    deque
+        .borrow_mut()
+        .push_back(objects[1].clone().into_object());
+
+    Ok(Object::Nil)
+}
+
+// Popping from an empty deque returns nil.
+pub fn pop_front<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("pop-front!", 1, objects);
+
+    let deque = check_type!(objects[0], Deque);
+    let item = deque.borrow_mut().pop_front();
+
+    Ok(item.unwrap_or(Object::Nil))
+}
+
+pub fn pop_back<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("pop-back!", 1, objects);
+
+    let deque = check_type!(objects[0], Deque);
+    let item = deque.borrow_mut().pop_back();
+
+    Ok(item.unwrap_or(Object::Nil))
+}
+
+pub fn length<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("queue-length", 1, objects);
+
+    let deque = check_type!(objects[0], Deque);
+    let length = deque.borrow().len();
+
+    Ok(Object::Int(length as i64))
+}
+
+// From front to back, leaving the deque as it was.
+pub fn to_list<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("queue->list", 1, objects);
+
+    let deque = check_type!(objects[0], Deque);
+    let items = deque.borrow().iter().cloned().collect::<Vec<_>>();
+
+    Ok(Object::from_iter(items))
+}
@@ -0,0 +1,60 @@
+use crate::{check_arity, check_type, keyword_map};
+use gc::Gc;
+use std::{fmt::Debug, hash::Hash};
+use vm::{object::Type, Error, Local, Object, Vm};
+
+// (error "message" data) fails with the message, and data can be anything
+// the handler might want to look at, or left out.
+pub fn error<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.is_empty() || objects.len() > 2 {
+        return Err(Error::Parameters(
+            "error expects a message and optional data".to_string(),
+        ));
+    }
+
+    let message = check_type!(objects[0], String);
+    let data = objects
+        .get(1)
+        .map_or(Object::Nil, |data| data.clone().into_object());
+
+    Err(Error::Raised {
+        message,
+        data: Box::new(data),
+    })
+}
+
+// (with-error-handler handler thunk) calls thunk and returns what it does,
+// unless it fails, in which case handler is called with {:message :data} and
+// what it returns is returned instead. Any error can be handled, not only
+// those from error, but only those have data.
+pub fn with_error_handler<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("with-error-handler", 2, objects);
+
+    let handler = objects[0].clone().into_object();
+    let thunk = objects[1].clone().into_object();
+
+    let error = match vm.call_function(thunk, []) {
+        Ok(object) => return Ok(object),
+        Err(error) => error,
+    };
+
+    let (message, data) = match error {
+        Error::Raised { message, data } => (
+            message,
+            data.downcast::<Object<D>>()
+                .map_or(Object::Nil, |data| *data),
+        ),
+        error => (error.to_string(), Object::Nil),
+    };
+
+    vm.call_function(
+        handler,
+        [keyword_map([
+            ("message", Object::String(Gc::new(message))),
+            ("data", data),
+        ])],
+    )
+}
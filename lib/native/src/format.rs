@@ -0,0 +1,68 @@
+use crate::check_type;
+use gc::Gc;
+use vm::{object::Type, Error, Local, Object};
+
+// (format "x=~a y=~s~%" x y). ~a writes a value for people to read, so strings
+// and chars are written without their quotes, ~s writes it the way it would be
+// written in code, ~d takes an int, ~% is a newline and ~~ is a tilde.
+pub fn format<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.is_empty() {
+        return Err(Error::Parameters(
+            "format expects a format string".to_string(),
+        ));
+    }
+
+    let template = check_type!(objects[0], String);
+    let mut args = objects[1..].iter();
+    let mut buffer = String::new();
+    let mut chars = template.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '~' {
+            buffer.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('%') => buffer.push('\n'),
+            Some('~') => buffer.push('~'),
+            Some(directive @ ('a' | 's' | 'd')) => {
+                let arg = args.next().ok_or_else(|| {
+                    Error::Parameters(format!("format ran out of arguments for ~{directive}"))
+                })?;
+
+                let text = match directive {
+                    'd' => check_type!(arg, Int).to_string(),
+                    'a' => arg.with(|object| match object {
+                        Object::String(string) => string.to_string(),
+                        Object::Symbol(symbol) => symbol.to_string(),
+                        Object::Char(char) => char.to_string(),
+                        object => object.to_string(),
+                    }),
+                    _ => arg.with(|object| object.to_string()),
+                };
+
+                buffer.push_str(&text);
+            }
+            Some(other) => {
+                return Err(Error::Parameters(format!(
+                    "format does not know the directive ~{other}"
+                )))
+            }
+            None => {
+                return Err(Error::Parameters(
+                    "format string ends in a lone ~".to_string(),
+                ))
+            }
+        }
+    }
+
+    if args.len() > 0 {
+        return Err(Error::Parameters(format!(
+            "format was given {} more arguments than its directives use",
+            args.len()
+        )));
+    }
+
+    Ok(Object::String(Gc::new(buffer)))
+}
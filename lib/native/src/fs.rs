@@ -0,0 +1,106 @@
+use crate::options::Options;
+use crate::{check_arity, check_type, keyword_map};
+use gc::Gc;
+use std::fs;
+use std::time::UNIX_EPOCH;
+use vm::{object::Type, Error, Local, Object};
+
+fn io_error(error: std::io::Error) -> Error {
+    Error::Other(Box::new(error))
+}
+
+// The names of the entries in a directory, sorted so the order doesn't depend
+// on the filesystem.
+pub fn list_dir<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("list-dir", 1, objects);
+
+    let path = check_type!(objects[0], String);
+
+    let mut names = fs::read_dir(path)
+        .map_err(io_error)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>, std::io::Error>>()
+        .map_err(io_error)?;
+
+    names.sort();
+
+    Ok(Object::from_iter(
+        names.into_iter().map(|name| Object::String(Gc::new(name))),
+    ))
+}
+
+// Creates any missing parent directories too, and does nothing if the
+// directory is already there.
+pub fn create_dir<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("create-dir", 1, objects);
+
+    let path = check_type!(objects[0], String);
+
+    fs::create_dir_all(path).map_err(io_error)?;
+
+    Ok(Object::Nil)
+}
+
+// (remove-dir path :recursive true) removes everything in it as well, without
+// it the directory has to be empty.
+pub fn remove_dir<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.is_empty() {
+        return Err(Error::Parameters("remove-dir expects a path".to_string()));
+    }
+
+    let path = check_type!(objects[0], String);
+
+    let mut options = Options::parse("remove-dir", &objects[1..])?;
+    let recursive = options.bool("recursive")?.unwrap_or(false);
+    options.finish()?;
+
+    if recursive {
+        fs::remove_dir_all(path).map_err(io_error)?;
+    } else {
+        fs::remove_dir(path).map_err(io_error)?;
+    }
+
+    Ok(Object::Nil)
+}
+
+pub fn copy_file<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("copy-file", 2, objects);
+
+    let from = check_type!(objects[0], String);
+    let to = check_type!(objects[1], String);
+
+    fs::copy(from, to).map_err(io_error)?;
+
+    Ok(Object::Nil)
+}
+
+pub fn rename_file<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("rename-file", 2, objects);
+
+    let from = check_type!(objects[0], String);
+    let to = check_type!(objects[1], String);
+
+    fs::rename(from, to).map_err(io_error)?;
+
+    Ok(Object::Nil)
+}
+
+// {:size bytes :mtime unix-ms :is-dir bool}
+pub fn metadata<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("file-metadata", 1, objects);
+
+    let path = check_type!(objects[0], String);
+
+    let metadata = fs::metadata(path).map_err(io_error)?;
+    let mtime = metadata
+        .modified()
+        .map_err(io_error)?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as i64);
+
+    Ok(keyword_map([
+        ("size", Object::Int(metadata.len() as i64)),
+        ("mtime", Object::Int(mtime)),
+        ("is-dir", Object::Bool(metadata.is_dir())),
+    ]))
+}
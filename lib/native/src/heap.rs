@@ -0,0 +1,168 @@
+use crate::{check_type, list::call_predicate};
+use gc::{Gc, GcCell};
+use std::{fmt::Debug, hash::Hash, mem};
+use vm::{
+    object::{Cons, Heap, Type},
+    Error, Local, Object, Vm,
+};
+
+// (heap-create) is a min-heap of items pushed with int priorities, and
+// (heap-create less) is one of items ordered by less instead.
+pub fn create<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.len() > 1 {
+        return Err(Error::Parameters(
+            "heap-create expects 0 or 1 parameters".to_string(),
+        ));
+    }
+
+    let less = objects.first().map(|less| less.clone().into_object());
+
+    Ok(Object::Heap(Gc::new(GcCell::new(Heap {
+        less,
+        entries: Vec::new(),
+    }))))
+}
+
+// (heap-push! heap item priority), or (heap-push! heap item) when the heap
+// was created with a less function.
+pub fn push<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    let heap = check_type!(objects.first().ok_or_else(push_arity)?, Heap);
+    let ordered = heap.borrow().less.is_some();
+
+    let entry = match (ordered, objects.len()) {
+        (true, 2) => Cons(Object::Nil, objects[1].clone().into_object()),
+        (false, 3) => Cons(
+            Object::Int(check_type!(objects[2], Int)),
+            objects[1].clone().into_object(),
+        ),
+        _ => return Err(push_arity()),
+    };
+
+    with_entries(vm, &heap, |vm, less, entries| {
+        entries.push(entry);
+        let last = entries.len() - 1;
+        sift_up(vm, less, entries, last)
+    })?;
+
+    Ok(Object::Nil)
+}
+
+fn push_arity() -> Error {
+    Error::Parameters(
+        "heap-push! expects a heap, an item and a priority, or no priority if the heap has a less function"
+            .to_string(),
+    )
+}
+
+// The first item out of the heap, or nil if it's empty.
+pub fn pop<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    if objects.len() != 1 {
+        return Err(Error::Parameters(
+            "heap-pop! expects 1 parameters".to_string(),
+        ));
+    }
+
+    let heap = check_type!(objects[0], Heap);
+
+    with_entries(vm, &heap, |vm, less, entries| {
+        if entries.is_empty() {
+            return Ok(Object::Nil);
+        }
+
+        let Cons(_, item) = entries.swap_remove(0);
+        if !entries.is_empty() {
+            sift_down(vm, less, entries, 0)?;
+        }
+
+        Ok(item)
+    })
+}
+
+pub fn length<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.len() != 1 {
+        return Err(Error::Parameters(
+            "heap-length expects 1 parameters".to_string(),
+        ));
+    }
+
+    let heap = check_type!(objects[0], Heap);
+    let length = heap.borrow().entries.len();
+
+    Ok(Object::Int(length as i64))
+}
+
+// The entries are taken out of the heap while less runs, so that less is free
+// to look at the heap itself without tripping over a borrow.
+fn with_entries<D: Clone + PartialEq + PartialOrd + Hash + Debug, T>(
+    vm: &mut Vm<D>,
+    heap: &Gc<GcCell<Heap<D>>>,
+    f: impl FnOnce(&mut Vm<D>, Option<&Object<D>>, &mut Vec<Cons<D>>) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let less = heap.borrow().less.clone();
+    let mut entries = mem::take(&mut heap.borrow_mut().entries);
+    let result = f(vm, less.as_ref(), &mut entries);
+
+    heap.borrow_mut().entries.append(&mut entries);
+
+    result
+}
+
+fn less<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    less: Option<&Object<D>>,
+    a: &Cons<D>,
+    b: &Cons<D>,
+) -> Result<bool, Error> {
+    match (less, &a.0, &b.0) {
+        (Some(less), _, _) => call_predicate(vm, less, [a.1.clone(), b.1.clone()]),
+        (None, Object::Int(a), Object::Int(b)) => Ok(a < b),
+        _ => unreachable!(),
+    }
+}
+
+fn sift_up<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    f: Option<&Object<D>>,
+    entries: &mut [Cons<D>],
+    mut i: usize,
+) -> Result<(), Error> {
+    while i > 0 {
+        let parent = (i - 1) / 2;
+        if !less(vm, f, &entries[i], &entries[parent])? {
+            break;
+        }
+        entries.swap(i, parent);
+        i = parent;
+    }
+
+    Ok(())
+}
+
+fn sift_down<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    f: Option<&Object<D>>,
+    entries: &mut [Cons<D>],
+    mut i: usize,
+) -> Result<(), Error> {
+    loop {
+        let mut first = i;
+        for child in [2 * i + 1, 2 * i + 2] {
+            if child < entries.len() && less(vm, f, &entries[child], &entries[first])? {
+                first = child;
+            }
+        }
+
+        if first == i {
+            return Ok(());
+        }
+
+        entries.swap(i, first);
+        i = first;
+    }
+}
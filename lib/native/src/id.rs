@@ -0,0 +1,83 @@
+use crate::random::Rng;
+use crate::time::Clock;
+use crate::{check_arity, check_type};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use vm::{object::Type, Error, Local, Object};
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+const RANDOM_BITS: u32 = 80;
+
+// ULIDs generated within the same millisecond increment the random part of the
+// previous one so that they still sort in the order they were made.
+#[derive(Debug)]
+pub struct Ulid {
+    clock: Clock,
+    last: Cell<(u64, u128)>,
+}
+
+impl Ulid {
+    pub fn new(clock: Clock) -> Self {
+        Self {
+            clock,
+            last: Cell::new((0, 0)),
+        }
+    }
+
+    fn next(&self, rng: &RefCell<Rng>) -> Result<u128, Error> {
+        let now = self.clock.unix_millis() as u64 & ((1 << 48) - 1);
+        let (last_time, last_random) = self.last.get();
+
+        let (time, random) = if now <= last_time {
+            let random = last_random + 1;
+
+            if random >> RANDOM_BITS != 0 {
+                return Err(Error::Other(
+                    "ulid overflowed within a single millisecond".into(),
+                ));
+            }
+
+            (last_time, random)
+        } else {
+            let mut rng = rng.borrow_mut();
+            let random = (rng.next_u64() as u128) << 16 | (rng.next_u64() >> 48) as u128;
+            (now, random)
+        };
+
+        self.last.set((time, random));
+
+        Ok((time as u128) << RANDOM_BITS | random)
+    }
+}
+
+pub fn ulid<D: Clone>(
+    ulid: &Ulid,
+    rng: &RefCell<Rng>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("ulid", 0, objects);
+
+    let id = ulid.next(rng)?;
+
+    let string = (0..26)
+        .map(|i| CROCKFORD[(id >> (125 - i * 5)) as usize & 31] as char)
+        .collect::<String>();
+
+    Ok(Object::String(gc::Gc::new(string)))
+}
+
+pub fn next_id<D: Clone>(
+    counters: &RefCell<HashMap<String, i64>>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("next-id", 1, objects);
+
+    let counter = check_type!(objects[0], Symbol);
+    let mut counters = counters.borrow_mut();
+    let id = counters.entry(counter).or_insert(0);
+
+    *id += 1;
+
+    Ok(Object::Int(*id))
+}
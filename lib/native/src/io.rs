@@ -1,6 +1,10 @@
+use crate::options::{Keyword, Options};
 use crate::{check_arity, check_type};
 use gc::Gc;
-use std::{fs::File, io::Read};
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+};
 use vm::{
     object::{self, Type},
     Error, Local, Object,
@@ -18,6 +22,46 @@ pub fn argv<D: Clone>(_: &mut [Local<D>]) -> Result<Object<D>, Error> {
     ))
 }
 
+pub fn command_line_args<D: Clone>(
+    args: &[String],
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("command-line-args", 0, objects);
+    Ok(Object::from_iter(
+        args.iter().map(|arg| Object::String(Gc::new(arg.clone()))),
+    ))
+}
+
+// nil if the variable isn't set or isn't valid unicode.
+pub fn getenv<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("getenv", 1, objects);
+
+    let name = check_type!(objects[0], String);
+
+    Ok(match std::env::var(name) {
+        Ok(value) => Object::String(Gc::new(value)),
+        Err(_) => Object::Nil,
+    })
+}
+
+pub fn setenv<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("setenv", 2, objects);
+
+    let name = check_type!(objects[0], String);
+    let value = check_type!(objects[1], String);
+
+    // set_var panics on these rather than returning an error
+    if name.is_empty() || name.contains(['=', '\0']) || value.contains('\0') {
+        return Err(Error::Parameters(format!(
+            "setenv can't set {name:?} to {value:?}"
+        )));
+    }
+
+    std::env::set_var(name, value);
+
+    Ok(Object::Nil)
+}
+
 pub fn read_file<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
     check_arity!("read-file", 1, objects);
 
@@ -30,3 +74,44 @@ pub fn read_file<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error>
 
     Ok(Object::String(Gc::new(buff)))
 }
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Truncate,
+    Append,
+}
+
+impl Keyword for Mode {
+    const KEYWORDS: &'static [(&'static str, Self)] =
+        &[("truncate", Mode::Truncate), ("append", Mode::Append)];
+}
+
+// (write-file path string :mode :truncate :create true)
+pub fn write_file<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.len() < 2 {
+        return Err(Error::Parameters(
+            "write-file expects a path and a string".to_string(),
+        ));
+    }
+
+    let path = check_type!(objects[0], String);
+    let string = check_type!(objects[1], String);
+
+    let mut options = Options::parse("write-file", &objects[2..])?;
+    let mode = options.keyword("mode")?.unwrap_or(Mode::Truncate);
+    let create = options.bool("create")?.unwrap_or(true);
+    options.finish()?;
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(create)
+        .truncate(matches!(mode, Mode::Truncate))
+        .append(matches!(mode, Mode::Append))
+        .open(path)
+        .map_err(|e| Error::Other(Box::new(e)))?;
+
+    file.write_all(string.as_bytes())
+        .map_err(|e| Error::Other(Box::new(e)))?;
+
+    Ok(Object::Nil)
+}
@@ -1,9 +1,71 @@
+mod bytes;
+mod char;
+mod csv;
+mod deque;
+mod error;
+mod format;
+mod fs;
+mod hash;
+mod heap;
+mod id;
 mod io;
+mod list;
+mod log;
+mod options;
+mod port;
+mod random;
+mod read;
+mod set;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 mod string;
+mod terminal;
+mod time;
 
-use std::{fmt::Debug, hash::Hash};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    rc::Rc,
+};
 
-use vm::Vm;
+use gc::{Gc, GcCell};
+use vm::{object::HashMapKey, Object, Vm};
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    // Seed the rng from `seed`, read time from a virtual clock, and leave out
+    // natives whose results depend on the host environment.
+    pub deterministic: bool,
+    pub seed: u64,
+    // What command-line-args returns, the arguments given to the script.
+    pub args: Vec<String>,
+}
+
+// The natives that are only there when built with the feature of the same
+// name, by feature. Compiled programs record which of these they call so a
+// build without them can refuse to run them.
+pub const GROUPS: &[(&str, &[&str])] = &[(
+    "sqlite",
+    &["sqlite-open", "sqlite-close", "sqlite-exec", "sqlite-query"],
+)];
+
+// The group a native belongs to, None if every build has it.
+pub fn group(native: &str) -> Option<&'static str> {
+    GROUPS
+        .iter()
+        .find(|(_, natives)| natives.contains(&native))
+        .map(|(group, _)| *group)
+}
+
+// Whether this build has the natives of group.
+pub fn has_group(group: &str) -> bool {
+    match group {
+        "sqlite" => cfg!(feature = "sqlite"),
+        _ => false,
+    }
+}
 
 #[macro_export]
 macro_rules! check_arity {
@@ -27,6 +89,51 @@ macro_rules! check_type {
             }),
         })?
     }};
+    ($object:expr, HashMap) => {{
+        $object.with(|object| match object {
+            Object::HashMap(map) => Ok(map.clone()),
+            object => Err(::vm::Error::Type {
+                expected: Type::Map,
+                recieved: Type::from(object),
+            }),
+        })?
+    }};
+    ($object:expr, Deque) => {{
+        $object.with(|object| match object {
+            Object::Deque(deque) => Ok(deque.clone()),
+            object => Err(::vm::Error::Type {
+                expected: Type::Deque,
+                recieved: Type::from(object),
+            }),
+        })?
+    }};
+    ($object:expr, Set) => {{
+        $object.with(|object| match object {
+            Object::Set(set) => Ok(set.clone()),
+            object => Err(::vm::Error::Type {
+                expected: Type::Set,
+                recieved: Type::from(object),
+            }),
+        })?
+    }};
+    ($object:expr, Heap) => {{
+        $object.with(|object| match object {
+            Object::Heap(heap) => Ok(heap.clone()),
+            object => Err(::vm::Error::Type {
+                expected: Type::Heap,
+                recieved: Type::from(object),
+            }),
+        })?
+    }};
+    ($object:expr, Port) => {{
+        $object.with(|object| match object {
+            Object::Port(port) => Ok(port.clone()),
+            object => Err(::vm::Error::Type {
+                expected: Type::Port,
+                recieved: Type::from(object),
+            }),
+        })?
+    }};
     ($object:expr, Symbol) => {{
         use std::ops::Deref;
         $object.with(|object| match object {
@@ -37,6 +144,16 @@ macro_rules! check_type {
             }),
         })?
     }};
+    ($object:expr, Bytes) => {{
+        use std::ops::Deref;
+        $object.with(|object| match object {
+            Object::Bytes(bytes) => Ok(bytes.deref().clone()),
+            object => Err(::vm::Error::Type {
+                expected: Type::Bytes,
+                recieved: Type::from(object),
+            }),
+        })?
+    }};
     ($object:expr, String) => {{
         use std::ops::Deref;
         $object.with(|object| match object {
@@ -67,15 +184,221 @@ macro_rules! check_type {
     };
 }
 
+// Natives that can fail in expected ways return (ok value) or (err message)
+// rather than raising an error.
+pub(crate) fn ok<D: Clone>(object: Object<D>) -> Object<D> {
+    Object::from_iter([Object::Symbol(Gc::new("ok".to_string())), object])
+}
+
+pub(crate) fn err<D: Clone>(message: String) -> Object<D> {
+    Object::from_iter([
+        Object::Symbol(Gc::new("err".to_string())),
+        Object::String(Gc::new(message)),
+    ])
+}
+
+// A map keyed by keywords, {:name value ...}.
+pub(crate) fn keyword_map<'a, D: Clone>(
+    entries: impl IntoIterator<Item = (&'a str, Object<D>)>,
+) -> Object<D> {
+    let entries = entries
+        .into_iter()
+        .map(|(name, value)| (HashMapKey::Symbol(Gc::new(format!(":{name}"))), value));
+
+    Object::HashMap(Gc::new(GcCell::new(HashMap::from_iter(entries))))
+}
+
 pub fn load_module<D: Clone + PartialEq + PartialOrd + Hash + Debug>(vm: &mut Vm<D>) {
+    load_module_with_config(vm, &Config::default());
+}
+
+pub fn load_module_with_config<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    config: &Config,
+) {
+    let rng = Rc::new(RefCell::new(if config.deterministic {
+        random::Rng::new(config.seed)
+    } else {
+        random::Rng::from_entropy()
+    }));
+
+    let clock = || {
+        if config.deterministic {
+            time::Clock::virtual_clock()
+        } else {
+            time::Clock::real()
+        }
+    };
+
+    let monotonic_clock = Rc::new(clock());
+    let wall_clock = clock();
+    let sleep_clock = monotonic_clock.clone();
+    let ulid = id::Ulid::new(clock());
+    let ulid_rng = rng.clone();
+    let counters = RefCell::new(HashMap::new());
+    let log_filter = Rc::new(Cell::new(::log::LevelFilter::Trace));
+
+    let args = config.args.clone();
+
+    if !config.deterministic {
+        vm.load_native_function("argv", io::argv);
+        vm.load_native_function("getenv", io::getenv);
+        vm.load_native_function("setenv", io::setenv);
+    }
+
+    vm.load_native_function("command-line-args", move |objects| {
+        io::command_line_args(&args, objects)
+    });
+
+    let terminal = terminal::Terminal::new(config.deterministic);
+    vm.load_native_function("is-tty?", move |objects| {
+        terminal::is_tty(&terminal, objects)
+    });
+    vm.load_native_function("terminal-width", move |objects| {
+        terminal::width(&terminal, objects)
+    });
+    vm.load_native_function("ansi-code", terminal::ansi_code);
+    vm.load_native_function("ansi-style", move |objects| {
+        terminal::ansi_style(&terminal, objects)
+    });
+    vm.load_native_function("strip-ansi", terminal::strip_ansi);
+
     vm.load_native_function("print", io::print);
     vm.load_native_function("read-file", io::read_file);
-    vm.load_native_function("argv", io::argv);
+    vm.load_native_function("write-file", io::write_file);
+    vm.load_native_function("read-file-bytes", bytes::read_file);
+    vm.load_native_function("write-file-bytes", bytes::write_file);
+    vm.load_native_function("list-dir", fs::list_dir);
+    vm.load_native_function("create-dir", fs::create_dir);
+    vm.load_native_function("remove-dir", fs::remove_dir);
+    vm.load_native_function("copy-file", fs::copy_file);
+    vm.load_native_function("rename-file", fs::rename_file);
+    vm.load_native_function("file-metadata", fs::metadata);
+    vm.load_native_function("open", port::open);
+    vm.load_native_function("read-line", port::read_line);
+    vm.load_native_function("read-char", port::read_char);
+    vm.load_native_function("write-string", port::write_string);
+    vm.load_native_function("flush", port::flush);
+    vm.load_native_function("close", port::close);
+    vm.load_native_function("random", move |objects| random::random(&rng, objects));
+    vm.load_native_function("time-monotonic", move |objects| {
+        time::monotonic(&monotonic_clock, objects)
+    });
+    vm.load_native_function("sleep-ms", move |objects| {
+        time::sleep_ms(&sleep_clock, objects)
+    });
+    vm.load_native_function("time-now", move |objects| time::now(&wall_clock, objects));
+    vm.load_native_function("time-format", time::format);
+    vm.load_native_function("time-parse", time::parse);
+    vm.load_native_function("ulid", move |objects| id::ulid(&ulid, &ulid_rng, objects));
+    vm.load_native_function("next-id", move |objects| id::next_id(&counters, objects));
+    vm.load_native_function("format", format::format);
+    for (name, level) in [
+        ("log-debug", ::log::Level::Debug),
+        ("log-info", ::log::Level::Info),
+        ("log-warn", ::log::Level::Warn),
+        ("log-error", ::log::Level::Error),
+    ] {
+        let filter = log_filter.clone();
+        vm.load_native_function(name, move |objects| log::log(&filter, level, name, objects));
+    }
+    vm.load_native_function("log-level", move |objects| {
+        log::set_level(&log_filter, objects)
+    });
+
+    #[cfg(feature = "sqlite")]
+    {
+        let connections = Rc::new(sqlite::Connections::default());
+        let (open, close, exec) = (
+            connections.clone(),
+            connections.clone(),
+            connections.clone(),
+        );
+        vm.load_native_function("sqlite-open", move |objects| sqlite::open(&open, objects));
+        vm.load_native_function("sqlite-close", move |objects| {
+            sqlite::close(&close, objects)
+        });
+        vm.load_native_function("sqlite-exec", move |objects| sqlite::exec(&exec, objects));
+        vm.load_native_function("sqlite-query", move |objects| {
+            sqlite::query(&connections, objects)
+        });
+    }
+
+    vm.load_native_function("csv-parse", csv::parse);
+    vm.load_native_function("csv-write", csv::write);
+    vm.load_native_function("read-string", read::read_string);
+    vm.load_native_function("bytes", bytes::bytes);
+    vm.load_native_function("bytes?", bytes::is_bytes);
+    vm.load_native_function("bytes-length", bytes::length);
+    vm.load_native_function("bytes-ref", bytes::bytes_ref);
+    vm.load_native_function("bytes-slice", bytes::slice);
+    vm.load_native_function("utf8->string", bytes::to_string);
+    vm.load_native_function("string->utf8", bytes::from_string);
+    vm.load_native_function("sha256", hash::sha256);
+    vm.load_native_function("md5", hash::md5);
+    vm.load_native_function("base64-encode", hash::base64_encode);
+    vm.load_native_function("base64-decode", hash::base64_decode);
+    vm.load_native_function("base64-decode-bytes", hash::base64_decode_bytes);
+    vm.load_native_function("error", error::error);
+    vm.load_native_function_with_vm("with-error-handler", error::with_error_handler);
+    vm.load_native_function_with_vm("sort", list::sort);
+    vm.load_native_function("native-length", list::length);
+    vm.load_native_function("reverse", list::reverse);
+    vm.load_native_function("native-append", list::append);
+    vm.load_native_function("native-nth", list::nth);
+    vm.load_native_function("native-last", list::last);
+    vm.load_native_function_with_vm("native-map", list::map);
+    vm.load_native_function_with_vm("native-filter", list::filter);
+    vm.load_native_function_with_vm("fold-left", list::fold_left);
+    vm.load_native_function_with_vm("fold-right", list::fold_right);
+    vm.load_native_function_with_vm("for-each", list::for_each);
+    vm.load_native_function("queue-create", deque::create);
+    vm.load_native_function("push-front!", deque::push_front);
+    vm.load_native_function("push-back!", deque::push_back);
+    vm.load_native_function("pop-front!", deque::pop_front);
+    vm.load_native_function("pop-back!", deque::pop_back);
+    vm.load_native_function("queue-length", deque::length);
+    vm.load_native_function("queue->list", deque::to_list);
+    vm.load_native_function("set-create", set::create);
+    vm.load_native_function("set-insert!", set::insert);
+    vm.load_native_function("set-contains?", set::contains);
+    vm.load_native_function("set-remove!", set::remove);
+    vm.load_native_function("set-length", set::length);
+    vm.load_native_function("set->list", set::to_list);
+    vm.load_native_function("heap-create", heap::create);
+    vm.load_native_function_with_vm("heap-push!", heap::push);
+    vm.load_native_function_with_vm("heap-pop!", heap::pop);
+    vm.load_native_function("heap-length", heap::length);
+    vm.load_native_function("assoc", list::assoc);
+    vm.load_native_function("assq", list::assq);
+    vm.load_native_function("alist->map", list::alist_to_map);
+    vm.load_native_function("map->alist", list::map_to_alist);
     vm.load_native_function("string-split", string::split);
     vm.load_native_function("string->list", string::to_list);
     vm.load_native_function("string-lines", string::lines);
     vm.load_native_function("is-digit?", string::is_digit);
+    vm.load_native_function("char->int", char::to_int);
+    vm.load_native_function("int->char", char::from_int);
+    vm.load_native_function("char-upcase", char::upcase);
+    vm.load_native_function("char-downcase", char::downcase);
+    vm.load_native_function("char-alpha?", char::is_alpha);
+    vm.load_native_function("char-whitespace?", char::is_whitespace);
     vm.load_native_function("list->string", string::from_list);
+    vm.load_native_function("symbol->string", string::from_symbol);
+    vm.load_native_function("string->symbol", string::to_symbol);
     vm.load_native_function("string->int", string::parse);
+    vm.load_native_function("string->int?", string::parse_checked);
+    vm.load_native_function("int->string", string::from_int);
     vm.load_native_function("string-split-whitespace", string::split_ascii_whitespace);
+    vm.load_native_function("string-trim", string::trim);
+    vm.load_native_function("string-replace", string::replace);
+    vm.load_native_function("string-contains?", string::contains);
+    vm.load_native_function("string-starts-with?", string::starts_with);
+    vm.load_native_function("string-ends-with?", string::ends_with);
+    vm.load_native_function("string-upcase", string::upcase);
+    vm.load_native_function("string-downcase", string::downcase);
+    vm.load_native_function("string-length", string::length);
+    vm.load_native_function("string-ref", string::get);
+    vm.load_native_function("substring", string::substring);
+    vm.load_native_function("string-append", string::append);
 }
@@ -0,0 +1,310 @@
+use crate::{check_arity, check_type};
+use gc::{Gc, GcCell};
+use std::{collections::HashMap, fmt::Debug, hash::Hash};
+use vm::{
+    object::{Cons, HashMapKey, Type},
+    Error, Local, Object, Vm,
+};
+
+// The cars of a list, read off the chain as they're asked for so that a
+// caller after one of them doesn't walk or copy the rest.
+fn cars<D: Clone>(name: &str, object: &Local<D>) -> Result<impl Iterator<Item = Object<D>>, Error> {
+    object
+        .with(|object| match object {
+            Object::Cons(cons) => Ok(Some(cons.borrow().iter_cars())),
+            Object::Nil => Ok(None),
+            object => Err(Error::Parameters(format!(
+                "{name} expects a list, received {object}"
+            ))),
+        })
+        .map(|cars| cars.into_iter().flatten())
+}
+
+fn list<D: Clone>(name: &str, object: &Local<D>) -> Result<Vec<Object<D>>, Error> {
+    cars(name, object).map(Iterator::collect)
+}
+
+// These replace the bootstrap's versions of the same functions, which recurse
+// once per element.
+
+pub fn length<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("length", 1, objects);
+    Ok(Object::Int(cars("length", &objects[0])?.count() as i64))
+}
+
+pub fn reverse<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("reverse", 1, objects);
+    Ok(Object::from_iter(
+        list("reverse", &objects[0])?.into_iter().rev(),
+    ))
+}
+
+// Every list but the last is copied, the last becomes the tail of the result.
+pub fn append<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    let Some((last, lists)) = objects.split_last() else {
+        return Ok(Object::Nil);
+    };
+
+    let mut items = Vec::new();
+    for object in lists {
+        items.extend(list("append", object)?);
+    }
+
+    Ok(items
+        .into_iter()
+        .rev()
+        .fold(last.clone().into_object(), |tail, item| {
+            Object::Cons(Gc::new(GcCell::new(Cons(item, tail))))
+        }))
+}
+
+// nil when n is past the end of the list.
+pub fn nth<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("nth", 2, objects);
+
+    let mut items = cars("nth", &objects[0])?;
+    let n = check_type!(objects[1], Int);
+
+    Ok(usize::try_from(n)
+        .ok()
+        .and_then(|n| items.nth(n))
+        .unwrap_or(Object::Nil))
+}
+
+pub fn last<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("last", 1, objects);
+    Ok(cars("last", &objects[0])?.last().unwrap_or(Object::Nil))
+}
+
+// (sort list less) returns a new list, sorted with a stable merge sort so that
+// less is only ever asked whether one thing comes before another.
+pub fn sort<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("sort", 2, objects);
+
+    let items = list("sort", &objects[0])?;
+    let less = objects[1].clone().into_object();
+
+    Ok(Object::from_iter(merge_sort(vm, &less, items)?))
+}
+
+fn merge_sort<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    less: &Object<D>,
+    mut items: Vec<Object<D>>,
+) -> Result<Vec<Object<D>>, Error> {
+    if items.len() < 2 {
+        return Ok(items);
+    }
+
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(vm, less, items)?;
+    let right = merge_sort(vm, less, right)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // only taking from the right when it's strictly less keeps it stable
+        let object = if call_predicate(vm, less, [r.clone(), l.clone()])? {
+            right.next()
+        } else {
+            left.next()
+        };
+        merged.extend(object);
+    }
+
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
+
+pub(crate) fn call_predicate<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    predicate: &Object<D>,
+    args: impl IntoIterator<Item = Object<D>>,
+) -> Result<bool, Error> {
+    match vm.call_function(predicate.clone(), args)? {
+        Object::Bool(bool) => Ok(bool),
+        object => Err(Error::Type {
+            expected: Type::Bool,
+            recieved: Type::from(&object),
+        }),
+    }
+}
+
+// Like the bootstrap's map and filter, which these replace, but without a host
+// stack frame per element.
+pub fn map<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("map", 2, objects);
+
+    let function = objects[0].clone().into_object();
+    let items = list("map", &objects[1])?
+        .into_iter()
+        .map(|item| vm.call_function(function.clone(), [item]))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Object::from_iter(items))
+}
+
+pub fn filter<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("filter", 2, objects);
+
+    let predicate = objects[0].clone().into_object();
+    let mut items = Vec::new();
+
+    for item in list("filter", &objects[1])? {
+        if call_predicate(vm, &predicate, [item.clone()])? {
+            items.push(item);
+        }
+    }
+
+    Ok(Object::from_iter(items))
+}
+
+// (fold-left (lambda (acc e) ...) init list) works from the front of the list,
+// (fold-right (lambda (e acc) ...) init list) from the back.
+pub fn fold_left<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("fold-left", 3, objects);
+
+    let function = objects[0].clone().into_object();
+
+    list("fold-left", &objects[2])?
+        .into_iter()
+        .try_fold(objects[1].clone().into_object(), |acc, item| {
+            vm.call_function(function.clone(), [acc, item])
+        })
+}
+
+pub fn fold_right<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("fold-right", 3, objects);
+
+    let function = objects[0].clone().into_object();
+
+    list("fold-right", &objects[2])?
+        .into_iter()
+        .rev()
+        .try_fold(objects[1].clone().into_object(), |acc, item| {
+            vm.call_function(function.clone(), [item, acc])
+        })
+}
+
+// Calls the function on each element for its side effects and returns nil.
+pub fn for_each<D: Clone + PartialEq + PartialOrd + Hash + Debug>(
+    vm: &mut Vm<D>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    check_arity!("for-each", 2, objects);
+
+    let function = objects[0].clone().into_object();
+
+    for item in list("for-each", &objects[1])? {
+        vm.call_function(function.clone(), [item])?;
+    }
+
+    Ok(Object::Nil)
+}
+
+// The first pair in an alist whose car is key, or nil. assoc compares keys the
+// way = does, assq only matches the same object, so two strings that are
+// spelled the same but made separately are different keys to it.
+pub fn assoc<D: Clone + PartialEq>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("assoc", 2, objects);
+    find_pair("assoc", objects, |a, b| a == b)
+}
+
+pub fn assq<D: Clone + PartialEq>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("assq", 2, objects);
+    find_pair("assq", objects, identical)
+}
+
+fn find_pair<D: Clone>(
+    name: &str,
+    objects: &[Local<D>],
+    matches: impl Fn(&Object<D>, &Object<D>) -> bool,
+) -> Result<Object<D>, Error> {
+    let key = objects[0].clone().into_object();
+
+    for pair in list(name, &objects[1])? {
+        let Object::Cons(cons) = &pair else {
+            return Err(Error::Parameters(format!(
+                "{name} expects a list of pairs, received {pair}"
+            )));
+        };
+
+        if matches(&key, &cons.borrow().0) {
+            return Ok(pair);
+        }
+    }
+
+    Ok(Object::Nil)
+}
+
+fn identical<D: PartialEq>(a: &Object<D>, b: &Object<D>) -> bool {
+    match (a, b) {
+        (Object::String(a), Object::String(b)) => std::ptr::eq(Gc::as_ptr(a), Gc::as_ptr(b)),
+        (Object::Cons(a), Object::Cons(b)) => std::ptr::eq(Gc::as_ptr(a), Gc::as_ptr(b)),
+        (Object::HashMap(a), Object::HashMap(b)) => std::ptr::eq(Gc::as_ptr(a), Gc::as_ptr(b)),
+        (Object::Function(a), Object::Function(b)) => std::ptr::eq(Gc::as_ptr(a), Gc::as_ptr(b)),
+        // symbols with the same name are the same symbol
+        (a, b) => a == b,
+    }
+}
+
+// When a key is in the alist more than once the first pair wins, the same as
+// it would for assoc.
+pub fn alist_to_map<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("alist->map", 1, objects);
+
+    let mut entries = Vec::new();
+
+    for pair in list("alist->map", &objects[0])? {
+        let Object::Cons(cons) = &pair else {
+            return Err(Error::Parameters(format!(
+                "alist->map expects a list of pairs, received {pair}"
+            )));
+        };
+
+        let Cons(key, value) = &*cons.borrow();
+        let key = HashMapKey::try_from(key)
+            .map_err(|_| Error::Parameters(format!("alist->map can't use {key} as a map key")))?;
+
+        entries.push((key, value.clone()));
+    }
+
+    // inserted back to front so the first pair for a key is the one left
+    Ok(Object::HashMap(Gc::new(GcCell::new(HashMap::from_iter(
+        entries.into_iter().rev(),
+    )))))
+}
+
+// The pairs are sorted by key so the order doesn't change from run to run.
+pub fn map_to_alist<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("map->alist", 1, objects);
+
+    let map = check_type!(objects[0], HashMap);
+    let map = map.borrow();
+    let mut entries = map.iter().collect::<Vec<_>>();
+
+    entries.sort_by_key(|&(key, _)| key);
+
+    Ok(Object::from_iter(entries.into_iter().map(
+        |(key, value)| Object::Cons(Gc::new(GcCell::new(Cons(Object::from(key), value.clone())))),
+    )))
+}
@@ -0,0 +1,85 @@
+use crate::options::Keyword;
+use std::cell::Cell;
+use vm::{Error, Local, Object};
+
+// The lowest level that log-* passes on to the host's logger, which can still
+// filter out more on its own.
+#[derive(Clone, Copy)]
+enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Off,
+}
+
+impl Keyword for Level {
+    const KEYWORDS: &'static [(&'static str, Self)] = &[
+        ("debug", Level::Debug),
+        ("info", Level::Info),
+        ("warn", Level::Warn),
+        ("error", Level::Error),
+        ("off", Level::Off),
+    ];
+}
+
+impl From<Level> for log::LevelFilter {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Debug => log::LevelFilter::Debug,
+            Level::Info => log::LevelFilter::Info,
+            Level::Warn => log::LevelFilter::Warn,
+            Level::Error => log::LevelFilter::Error,
+            Level::Off => log::LevelFilter::Off,
+        }
+    }
+}
+
+// (log-info "message") logs to the "lisp" target. Anything other than a
+// string is logged the way it would be printed.
+pub fn log<D: Clone>(
+    filter: &Cell<log::LevelFilter>,
+    level: log::Level,
+    name: &str,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    if objects.len() != 1 {
+        return Err(Error::Parameters(format!("{name} expects a message")));
+    }
+
+    if level <= filter.get() {
+        let message = objects[0].with(|object| match object {
+            Object::String(string) => string.to_string(),
+            object => object.to_string(),
+        });
+
+        log::log!(target: "lisp", level, "{message}");
+    }
+
+    Ok(Object::Nil)
+}
+
+// (log-level :warn) drops anything logged below warn from then on.
+pub fn set_level<D: Clone>(
+    filter: &Cell<log::LevelFilter>,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    let level = match objects {
+        [level] => level.with(|object| match object {
+            Object::Symbol(symbol) => Level::KEYWORDS
+                .iter()
+                .find(|(keyword, _)| symbol.strip_prefix(':') == Some(*keyword))
+                .map(|(_, level)| *level),
+            _ => None,
+        }),
+        _ => None,
+    };
+
+    let level = level.ok_or_else(|| {
+        Error::Parameters("log-level expects :debug, :info, :warn, :error or :off".to_string())
+    })?;
+
+    filter.set(level.into());
+
+    Ok(Object::Nil)
+}
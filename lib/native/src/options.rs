@@ -0,0 +1,110 @@
+use vm::{Error, Local, Object};
+
+// Natives that take options accept them as trailing keyword and value pairs,
+// (write-file "f" "text" :mode :append :create false). Each getter takes the
+// option it names, and finish rejects anything that wasn't taken.
+pub(crate) struct Options<D: 'static> {
+    name: &'static str,
+    options: Vec<(String, Object<D>)>,
+}
+
+// An enum chosen with a keyword, such as :append for a file's mode.
+pub(crate) trait Keyword: Copy + 'static {
+    const KEYWORDS: &'static [(&'static str, Self)];
+}
+
+impl<D: Clone> Options<D> {
+    pub fn parse(name: &'static str, objects: &[Local<D>]) -> Result<Self, Error> {
+        if !objects.len().is_multiple_of(2) {
+            return Err(Error::Parameters(format!(
+                "{name} expects options as :keyword value pairs"
+            )));
+        }
+
+        let mut options: Vec<(String, Object<D>)> = Vec::new();
+
+        for pair in objects.chunks(2) {
+            let key = pair[0].with(|object| match object {
+                Object::Symbol(symbol) if symbol.len() > 1 && symbol.starts_with(':') => {
+                    Ok(symbol[1..].to_string())
+                }
+                object => Err(Error::Parameters(format!(
+                    "{name} expects an option keyword, received {object}"
+                ))),
+            })?;
+
+            if options.iter().any(|(k, _)| *k == key) {
+                return Err(Error::Parameters(format!(
+                    "{name} received :{key} more than once"
+                )));
+            }
+
+            options.push((key, pair[1].clone().into_object()));
+        }
+
+        Ok(Self { name, options })
+    }
+
+    fn take(&mut self, key: &str) -> Option<Object<D>> {
+        let i = self.options.iter().position(|(k, _)| k == key)?;
+        Some(self.options.remove(i).1)
+    }
+
+    fn expected(&self, key: &str, expected: &str, object: &Object<D>) -> Error {
+        Error::Parameters(format!(
+            "{} expects {expected} for :{key}, received {object}",
+            self.name
+        ))
+    }
+
+    // nil is accepted as false.
+    pub fn bool(&mut self, key: &str) -> Result<Option<bool>, Error> {
+        Ok(match self.take(key) {
+            None => None,
+            Some(Object::Bool(bool)) => Some(bool),
+            Some(Object::Nil) => Some(false),
+            Some(object) => return Err(self.expected(key, "a bool", &object)),
+        })
+    }
+
+    pub fn char(&mut self, key: &str) -> Result<Option<char>, Error> {
+        Ok(match self.take(key) {
+            None => None,
+            Some(Object::Char(char)) => Some(char),
+            Some(object) => return Err(self.expected(key, "a char", &object)),
+        })
+    }
+
+    pub fn keyword<K: Keyword>(&mut self, key: &str) -> Result<Option<K>, Error> {
+        let Some(object) = self.take(key) else {
+            return Ok(None);
+        };
+
+        let found = match &object {
+            Object::Symbol(symbol) => K::KEYWORDS
+                .iter()
+                .find(|(keyword, _)| symbol.strip_prefix(':') == Some(*keyword))
+                .map(|(_, k)| *k),
+            _ => None,
+        };
+
+        found.map(Some).ok_or_else(|| {
+            let choices = K::KEYWORDS
+                .iter()
+                .map(|(keyword, _)| format!(":{keyword}"))
+                .collect::<Vec<_>>()
+                .join(" or ");
+            self.expected(key, choices.as_str(), &object)
+        })
+    }
+
+    pub fn finish(self) -> Result<(), Error> {
+        match self.options.first() {
+            Some((key, _)) => Err(Error::Parameters(format!(
+                "{} does not take the option :{key}",
+                self.name
+            ))),
+            None => Ok(()),
+        }
+    }
+}
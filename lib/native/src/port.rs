@@ -0,0 +1,185 @@
+use crate::options::{Keyword, Options};
+use crate::{check_arity, check_type};
+use gc::Gc;
+use std::cell::RefCell;
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind};
+use std::rc::Rc;
+use vm::{
+    object::{Port, Type},
+    Error, Local, Object,
+};
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Read,
+    Write,
+    Append,
+}
+
+impl Keyword for Mode {
+    const KEYWORDS: &'static [(&'static str, Self)] = &[
+        ("read", Mode::Read),
+        ("write", Mode::Write),
+        ("append", Mode::Append),
+    ];
+}
+
+fn expected(name: &str, expected: &str, port: &Port) -> Error {
+    let received = match port {
+        Port::Input(_) => "an input port",
+        Port::Output(_) => "an output port",
+        Port::Closed => "a closed port",
+    };
+
+    Error::Parameters(format!("{name} expects {expected}, received {received}"))
+}
+
+fn io_error(error: io::Error) -> Error {
+    Error::Other(Box::new(error))
+}
+
+// (open path :mode :read), where :write truncates the file and :append adds to
+// it. Both create it if it doesn't exist unless given :create false.
+pub fn open<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if objects.is_empty() {
+        return Err(Error::Parameters("open expects a path".to_string()));
+    }
+
+    let path = check_type!(objects[0], String);
+
+    let mut options = Options::parse("open", &objects[1..])?;
+    let mode = options.keyword("mode")?.unwrap_or(Mode::Read);
+    let create = options.bool("create")?.unwrap_or(true);
+    options.finish()?;
+
+    let port = match mode {
+        Mode::Read => {
+            let file = OpenOptions::new().read(true).open(path).map_err(io_error)?;
+            Port::Input(Box::new(BufReader::new(file)))
+        }
+        Mode::Write | Mode::Append => {
+            let file = OpenOptions::new()
+                .write(true)
+                .create(create)
+                .truncate(matches!(mode, Mode::Write))
+                .append(matches!(mode, Mode::Append))
+                .open(path)
+                .map_err(io_error)?;
+            Port::Output(Box::new(BufWriter::new(file)))
+        }
+    };
+
+    Ok(Object::Port(Rc::new(RefCell::new(port))))
+}
+
+// The next line without its line ending, or nil at the end of the input.
+pub fn read_line<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("read-line", 1, objects);
+
+    let port = check_type!(objects[0], Port);
+    let mut port = port.borrow_mut();
+    let Port::Input(reader) = &mut *port else {
+        return Err(expected("read-line", "an input port", &port));
+    };
+
+    let mut line = String::new();
+
+    if reader.read_line(&mut line).map_err(io_error)? == 0 {
+        return Ok(Object::Nil);
+    }
+
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+
+    Ok(Object::String(Gc::new(line)))
+}
+
+// The next char, or nil at the end of the input.
+pub fn read_char<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("read-char", 1, objects);
+
+    let port = check_type!(objects[0], Port);
+    let mut port = port.borrow_mut();
+    let Port::Input(reader) = &mut *port else {
+        return Err(expected("read-char", "an input port", &port));
+    };
+
+    Ok(match next_char(reader).map_err(io_error)? {
+        Some(char) => Object::Char(char),
+        None => Object::Nil,
+    })
+}
+
+// Reads only as many bytes as the char's utf-8 encoding takes, so the rest are
+// left for the next read.
+fn next_char(reader: &mut dyn BufRead) -> io::Result<Option<char>> {
+    let mut bytes = [0; 4];
+
+    match reader.read_exact(&mut bytes[..1]) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+
+    let len = match bytes[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+
+    reader.read_exact(&mut bytes[1..len])?;
+
+    std::str::from_utf8(&bytes[..len])
+        .map(|s| s.chars().next())
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+pub fn write_string<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("write-string", 2, objects);
+
+    let port = check_type!(objects[0], Port);
+    let string = check_type!(objects[1], String);
+    let mut port = port.borrow_mut();
+    let Port::Output(writer) = &mut *port else {
+        return Err(expected("write-string", "an output port", &port));
+    };
+
+    writer.write_all(string.as_bytes()).map_err(io_error)?;
+
+    Ok(Object::Nil)
+}
+
+pub fn flush<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("flush", 1, objects);
+
+    let port = check_type!(objects[0], Port);
+    let mut port = port.borrow_mut();
+    let Port::Output(writer) = &mut *port else {
+        return Err(expected("flush", "an output port", &port));
+    };
+
+    writer.flush().map_err(io_error)?;
+
+    Ok(Object::Nil)
+}
+
+// Output is flushed before the port is closed. Closing a port twice is fine.
+pub fn close<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("close", 1, objects);
+
+    let port = check_type!(objects[0], Port);
+    let mut port = port.borrow_mut();
+
+    if let Port::Output(writer) = &mut *port {
+        writer.flush().map_err(io_error)?;
+    }
+
+    *port = Port::Closed;
+
+    Ok(Object::Nil)
+}
@@ -0,0 +1,46 @@
+use crate::{check_arity, check_type};
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use vm::{object::Type, Error, Local, Object};
+
+// xorshift64*, seeded through splitmix64 so that small seeds still produce
+// well distributed output.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        Self(if z == 0 { 0x9e37_79b9_7f4a_7c15 } else { z })
+    }
+
+    pub fn from_entropy() -> Self {
+        Self::new(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+pub fn random<D: Clone>(rng: &RefCell<Rng>, objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("random", 1, objects);
+
+    let bound = check_type!(objects[0], Int);
+
+    if bound <= 0 {
+        return Err(Error::Parameters(
+            "random expects a positive bound".to_string(),
+        ));
+    }
+
+    Ok(Object::Int((rng.borrow_mut().next_u64() % bound as u64) as i64))
+}
@@ -0,0 +1,77 @@
+use crate::{check_arity, check_type};
+use gc::{Gc, GcCell};
+use reader::{Context, Reader, Sexpr};
+use std::collections::HashMap;
+use std::rc::Rc;
+use vm::{
+    object::{Cons, HashMapKey, Type},
+    Error, Local, Object,
+};
+
+// (read-string "(1 2 3)") reads one form and returns it as data, the way quote
+// would. Reader shorthand is expanded as it is in code, so "'a" reads as
+// (quote a), while "[1 2]" is the list (1 2) and "{:a 1}" a map.
+pub fn read_string<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("read-string", 1, objects);
+
+    let string = check_type!(objects[0], String);
+    let context = Rc::new(Context::new(string.as_str(), "read-string"));
+    let mut reader = Reader::new(&context);
+
+    let sexpr = match reader.next() {
+        Some(Ok(sexpr)) => sexpr,
+        Some(Err(error)) => return Err(Error::Other(Box::new(error))),
+        None => {
+            return Err(Error::Parameters(
+                "read-string expects a form to read".to_string(),
+            ))
+        }
+    };
+
+    if reader.next().is_some() {
+        return Err(Error::Parameters(
+            "read-string expects only one form".to_string(),
+        ));
+    }
+
+    sexpr_to_object(&sexpr)
+}
+
+fn sexpr_to_object<D: Clone>(sexpr: &Sexpr) -> Result<Object<D>, Error> {
+    Ok(match sexpr {
+        Sexpr::List { list, .. } | Sexpr::Vector { list, .. } => Object::from_iter(
+            list.iter()
+                .map(sexpr_to_object)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Sexpr::DottedList { list, tail, .. } => {
+            list.iter()
+                .rev()
+                .try_fold(sexpr_to_object(tail)?, |tail, sexpr| {
+                    Ok::<_, Error>(Object::Cons(Gc::new(GcCell::new(Cons(
+                        sexpr_to_object(sexpr)?,
+                        tail,
+                    )))))
+                })?
+        }
+        Sexpr::Map { list, .. } => {
+            let mut entries = Vec::new();
+
+            for entry in list.chunks_exact(2) {
+                let key = sexpr_to_object::<D>(&entry[0])?;
+                let key =
+                    HashMapKey::try_from(&key).map_err(|_| Error::HashKey(Type::from(&key)))?;
+
+                entries.push((key, sexpr_to_object(&entry[1])?));
+            }
+
+            Object::HashMap(Gc::new(GcCell::new(HashMap::from_iter(entries))))
+        }
+        Sexpr::Symbol { symbol, .. } => Object::Symbol(Gc::new(symbol.clone())),
+        Sexpr::String { string, .. } => Object::String(Gc::new(string.clone())),
+        Sexpr::Char { char, .. } => Object::Char(*char),
+        Sexpr::Int { int, .. } => Object::Int(*int),
+        Sexpr::Bool { bool, .. } => Object::Bool(*bool),
+        Sexpr::Nil { .. } => Object::Nil,
+    })
+}
@@ -0,0 +1,82 @@
+use crate::{check_arity, check_type};
+use gc::{Gc, GcCell};
+use std::collections::HashSet;
+use vm::{
+    object::{HashMapKey, Type},
+    Error, Local, Object,
+};
+
+// Sets hold anything that can be a map key: strings, symbols, ints, chars,
+// bools and nil.
+fn key<D: Clone>(name: &str, object: &Local<D>) -> Result<HashMapKey, Error> {
+    object.with(|object| {
+        HashMapKey::try_from(object).map_err(|_| {
+            Error::Parameters(format!("{name} can't hold {object}, it can't be hashed"))
+        })
+    })
+}
+
+// (set-create 1 2 2 3) is a set of 1, 2 and 3.
+pub fn create<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    let keys = objects
+        .iter()
+        .map(|object| key("set-create", object))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Object::Set(Gc::new(GcCell::new(HashSet::from_iter(keys)))))
+}
+
+// True if the item wasn't already in the set.
+pub fn insert<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("set-insert!", 2, objects);
+
+    let set = check_type!(objects[0], Set);
+    let key = key("set-insert!", &objects[1])?;
+    let inserted = set.borrow_mut().insert(key);
+
+    Ok(Object::Bool(inserted))
+}
+
+// Anything that can't be hashed can't be in a set, so isn't.
+pub fn contains<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("set-contains?", 2, objects);
+
+    let set = check_type!(objects[0], Set);
+    let contains = objects[1]
+        .with(|object| HashMapKey::try_from(object).is_ok_and(|key| set.borrow().contains(&key)));
+
+    Ok(Object::Bool(contains))
+}
+
+// True if the item was in the set.
+pub fn remove<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("set-remove!", 2, objects);
+
+    let set = check_type!(objects[0], Set);
+    let removed = objects[1]
+        .with(|object| HashMapKey::try_from(object).is_ok_and(|key| set.borrow_mut().remove(&key)));
+
+    Ok(Object::Bool(removed))
+}
+
+pub fn length<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("set-length", 1, objects);
+
+    let set = check_type!(objects[0], Set);
+    let length = set.borrow().len();
+
+    Ok(Object::Int(length as i64))
+}
+
+// Sorted, so the order doesn't change from run to run.
+pub fn to_list<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("set->list", 1, objects);
+
+    let set = check_type!(objects[0], Set);
+    let set = set.borrow();
+    let mut keys = set.iter().collect::<Vec<_>>();
+
+    keys.sort();
+
+    Ok(Object::from_iter(keys.into_iter().map(Object::from)))
+}
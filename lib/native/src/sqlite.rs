@@ -0,0 +1,169 @@
+use crate::check_type;
+use gc::Gc;
+use rusqlite::{types::Value, Connection};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use vm::{object::Type, Error, Local, Object};
+
+// Open databases, looked up by the int handle sqlite-open returned for them.
+#[derive(Default)]
+pub struct Connections {
+    next: Cell<i64>,
+    open: RefCell<HashMap<i64, Connection>>,
+}
+
+fn sqlite_error(error: rusqlite::Error) -> Error {
+    Error::Other(Box::new(error))
+}
+
+impl Connections {
+    fn with<D: Clone, T>(
+        &self,
+        name: &str,
+        handle: &Local<D>,
+        f: impl FnOnce(&Connection) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let handle = check_type!(handle, Int);
+        let open = self.open.borrow();
+        let connection = open.get(&handle).ok_or_else(|| {
+            Error::Parameters(format!(
+                "{name} expects an open database, received {handle}"
+            ))
+        })?;
+
+        f(connection)
+    }
+}
+
+// (sqlite-open path), or ":memory:" for a database that goes away when it's
+// closed. Returns a handle for the other sqlite natives.
+pub fn open<D: Clone>(
+    connections: &Connections,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    if objects.len() != 1 {
+        return Err(Error::Parameters("sqlite-open expects a path".to_string()));
+    }
+
+    let path = check_type!(objects[0], String);
+    let connection = Connection::open(path).map_err(sqlite_error)?;
+    let handle = connections.next.get();
+
+    connections.next.set(handle + 1);
+    connections.open.borrow_mut().insert(handle, connection);
+
+    Ok(Object::Int(handle))
+}
+
+pub fn close<D: Clone>(
+    connections: &Connections,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    if objects.len() != 1 {
+        return Err(Error::Parameters(
+            "sqlite-close expects a database".to_string(),
+        ));
+    }
+
+    let handle = check_type!(objects[0], Int);
+    let connection = connections.open.borrow_mut().remove(&handle);
+
+    match connection {
+        Some(connection) => connection
+            .close()
+            .map(|()| Object::Nil)
+            .map_err(|(_, error)| sqlite_error(error)),
+        None => Err(Error::Parameters(format!(
+            "sqlite-close expects an open database, received {handle}"
+        ))),
+    }
+}
+
+// (sqlite-exec db "insert into t values (?, ?)" 1 "a") runs one statement and
+// returns how many rows it changed.
+pub fn exec<D: Clone>(
+    connections: &Connections,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    if objects.len() < 2 {
+        return Err(Error::Parameters(
+            "sqlite-exec expects a database and a statement".to_string(),
+        ));
+    }
+
+    let sql = check_type!(objects[1], String);
+    let params = params("sqlite-exec", &objects[2..])?;
+
+    connections.with("sqlite-exec", &objects[0], |connection| {
+        let changed = connection
+            .execute(&sql, rusqlite::params_from_iter(params))
+            .map_err(sqlite_error)?;
+
+        Ok(Object::Int(changed as i64))
+    })
+}
+
+// (sqlite-query db "select a, b from t where a > ?" 1) returns the rows as
+// lists of their columns. Reals are returned as strings, since there are no
+// floats to return them as.
+pub fn query<D: Clone>(
+    connections: &Connections,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    if objects.len() < 2 {
+        return Err(Error::Parameters(
+            "sqlite-query expects a database and a query".to_string(),
+        ));
+    }
+
+    let sql = check_type!(objects[1], String);
+    let params = params("sqlite-query", &objects[2..])?;
+
+    connections.with("sqlite-query", &objects[0], |connection| {
+        let mut statement = connection.prepare(&sql).map_err(sqlite_error)?;
+        let columns = statement.column_count();
+        let mut rows = statement
+            .query(rusqlite::params_from_iter(params))
+            .map_err(sqlite_error)?;
+        let mut result = Vec::new();
+
+        while let Some(row) = rows.next().map_err(sqlite_error)? {
+            let row = (0..columns)
+                .map(|i| row.get::<_, Value>(i).map(object))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(sqlite_error)?;
+
+            result.push(Object::from_iter(row));
+        }
+
+        Ok(Object::from_iter(result))
+    })
+}
+
+fn params<D: Clone>(name: &str, objects: &[Local<D>]) -> Result<Vec<Value>, Error> {
+    objects
+        .iter()
+        .map(|object| {
+            object.with(|object| match object {
+                Object::Int(int) => Ok(Value::Integer(*int)),
+                Object::String(string) => Ok(Value::Text(string.to_string())),
+                Object::Bytes(bytes) => Ok(Value::Blob(bytes.to_vec())),
+                Object::Bool(bool) => Ok(Value::Integer(i64::from(*bool))),
+                Object::Nil => Ok(Value::Null),
+                object => Err(Error::Parameters(format!(
+                    "{name} can't bind {object} as a parameter"
+                ))),
+            })
+        })
+        .collect()
+}
+
+fn object<D: Clone>(value: Value) -> Object<D> {
+    match value {
+        Value::Null => Object::Nil,
+        Value::Integer(int) => Object::Int(int),
+        Value::Real(real) => Object::String(Gc::new(real.to_string())),
+        Value::Text(text) => Object::String(Gc::new(text)),
+        Value::Blob(blob) => Object::Bytes(Gc::new(blob)),
+    }
+}
@@ -1,5 +1,6 @@
-use crate::{check_arity, check_type};
+use crate::{check_arity, check_type, err, ok};
 use gc::Gc;
+use std::num::IntErrorKind;
 use vm::{object::Type, Error, Local, Object};
 
 pub fn split<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
@@ -57,16 +58,144 @@ pub fn from_list<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error>
     Ok(Object::String(Gc::new(string)))
 }
 
+pub fn from_symbol<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("symbol->string", 1, objects);
+
+    let symbol = check_type!(objects[0], Symbol);
+
+    Ok(Object::String(Gc::new(symbol)))
+}
+
+// Any string but the empty one names a symbol, even ones the reader would
+// never read as a symbol, like "a b".
+pub fn to_symbol<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string->symbol", 1, objects);
+
+    let string = check_type!(objects[0], String);
+
+    if string.is_empty() {
+        return Err(Error::Parameters(
+            "string->symbol expects a non-empty string".to_string(),
+        ));
+    }
+
+    Ok(Object::Symbol(Gc::new(string)))
+}
+
+// (string->int string) or (string->int string radix).
 pub fn parse<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
-    check_arity!("string->int", 1, objects);
+    if !matches!(objects.len(), 1 | 2) {
+        return Err(Error::Parameters(
+            "string->int expects 1 or 2 parameters".to_string(),
+        ));
+    }
 
     let string = check_type!(objects[0], String);
+    let radix = match objects.get(1) {
+        Some(radix) => check_radix("string->int", check_type!(radix, Int))?,
+        None => 10,
+    };
 
-    let i: i64 = string.parse().map_err(|e| Error::Other(Box::new(e)))?;
+    let i = parse_int(string.as_str(), radix).map_err(|e| Error::Other(e.into()))?;
 
     Ok(Object::Int(i))
 }
 
+// Unlike string->int this returns (ok i) or (err message) instead of raising,
+// optionally parsing in another radix and requiring the result to be within an
+// inclusive range.
+pub fn parse_checked<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if !matches!(objects.len(), 1 | 2 | 4) {
+        return Err(Error::Parameters(
+            "string->int? expects 1, 2 or 4 parameters".to_string(),
+        ));
+    }
+
+    let string = check_type!(objects[0], String);
+
+    let radix = if objects.len() > 1 {
+        check_radix("string->int?", check_type!(objects[1], Int))?
+    } else {
+        10
+    };
+
+    let range = if objects.len() == 4 {
+        Some((check_type!(objects[2], Int), check_type!(objects[3], Int)))
+    } else {
+        None
+    };
+
+    Ok(match parse_int(string.as_str(), radix) {
+        Ok(i) => match range {
+            Some((min, max)) if !(min..=max).contains(&i) => {
+                err(format!("{i} is outside of {min} to {max}"))
+            }
+            _ => ok(Object::Int(i)),
+        },
+        Err(e) => err(e),
+    })
+}
+
+// (int->string int) or (int->string int radix), with lowercase digits past 9.
+pub fn from_int<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if !matches!(objects.len(), 1 | 2) {
+        return Err(Error::Parameters(
+            "int->string expects 1 or 2 parameters".to_string(),
+        ));
+    }
+
+    let int = check_type!(objects[0], Int);
+    let radix = match objects.get(1) {
+        Some(radix) => check_radix("int->string", check_type!(radix, Int))?,
+        None => 10,
+    };
+
+    let mut magnitude = int.unsigned_abs();
+    let mut digits = Vec::new();
+
+    loop {
+        digits.push(char::from_digit((magnitude % radix as u64) as u32, radix).unwrap());
+        magnitude /= radix as u64;
+        if magnitude == 0 {
+            break;
+        }
+    }
+
+    if int < 0 {
+        digits.push('-');
+    }
+
+    Ok(Object::String(Gc::new(digits.iter().rev().collect())))
+}
+
+fn check_radix(name: &str, radix: i64) -> Result<u32, Error> {
+    if (2..=36).contains(&radix) {
+        Ok(radix as u32)
+    } else {
+        Err(Error::Parameters(format!(
+            "{name} expects a radix between 2 and 36"
+        )))
+    }
+}
+
+// Errors point at the first char that isn't a digit in the radix.
+fn parse_int(string: &str, radix: u32) -> Result<i64, String> {
+    i64::from_str_radix(string, radix).map_err(|e| {
+        let invalid = string
+            .chars()
+            .enumerate()
+            .skip(usize::from(string.starts_with(['+', '-'])))
+            .find(|(_, char)| !char.is_digit(radix));
+
+        match (e.kind(), invalid) {
+            (IntErrorKind::InvalidDigit, Some((position, char))) => format!(
+                "failed to parse {string:?}: {char:?} at position {position} is not a base {radix} digit"
+            ),
+            _ => format!("failed to parse {string:?}: {e}"),
+        }
+    })
+}
+
 pub fn lines<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
     check_arity!("string-lines", 1, objects);
 
@@ -90,3 +219,149 @@ pub fn is_digit<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error>
         Ok(Object::Bool(false))
     }
 }
+
+pub fn trim<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-trim", 1, objects);
+
+    let string = check_type!(objects[0], String);
+
+    Ok(Object::String(Gc::new(string.trim().to_string())))
+}
+
+// Replaces every occurrence of from.
+pub fn replace<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-replace", 3, objects);
+
+    let string = check_type!(objects[0], String);
+    let from = check_type!(objects[1], String);
+    let to = check_type!(objects[2], String);
+
+    if from.is_empty() {
+        return Err(Error::Parameters(
+            "string-replace expects a non-empty string to replace".to_string(),
+        ));
+    }
+
+    Ok(Object::String(Gc::new(
+        string.replace(from.as_str(), to.as_str()),
+    )))
+}
+
+pub fn contains<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-contains?", 2, objects);
+
+    let string = check_type!(objects[0], String);
+    let pattern = check_type!(objects[1], String);
+
+    Ok(Object::Bool(string.contains(pattern.as_str())))
+}
+
+pub fn starts_with<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-starts-with?", 2, objects);
+
+    let string = check_type!(objects[0], String);
+    let prefix = check_type!(objects[1], String);
+
+    Ok(Object::Bool(string.starts_with(prefix.as_str())))
+}
+
+pub fn ends_with<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-ends-with?", 2, objects);
+
+    let string = check_type!(objects[0], String);
+    let suffix = check_type!(objects[1], String);
+
+    Ok(Object::Bool(string.ends_with(suffix.as_str())))
+}
+
+pub fn upcase<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-upcase", 1, objects);
+
+    let string = check_type!(objects[0], String);
+
+    Ok(Object::String(Gc::new(string.to_uppercase())))
+}
+
+pub fn downcase<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-downcase", 1, objects);
+
+    let string = check_type!(objects[0], String);
+
+    Ok(Object::String(Gc::new(string.to_lowercase())))
+}
+
+// Strings are indexed by char rather than by byte.
+fn char_index(name: &str, index: i64, len: usize) -> Result<usize, Error> {
+    usize::try_from(index)
+        .ok()
+        .filter(|index| *index <= len)
+        .ok_or_else(|| {
+            Error::Parameters(format!(
+                "{name} index {index} is out of bounds of a string of length {len}"
+            ))
+        })
+}
+
+pub fn length<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-length", 1, objects);
+
+    let string = check_type!(objects[0], String);
+
+    Ok(Object::Int(string.chars().count() as i64))
+}
+
+pub fn get<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("string-ref", 2, objects);
+
+    let string = check_type!(objects[0], String);
+    let index = check_type!(objects[1], Int);
+
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| string.chars().nth(index))
+        .map(Object::Char)
+        .ok_or_else(|| {
+            Error::Parameters(format!(
+                "string-ref index {index} is out of bounds of a string of length {}",
+                string.chars().count()
+            ))
+        })
+}
+
+// (substring string start) or (substring string start end), where end is
+// exclusive and defaults to the end of the string.
+pub fn substring<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    if !matches!(objects.len(), 2 | 3) {
+        return Err(Error::Parameters(
+            "substring expects 2 or 3 parameters".to_string(),
+        ));
+    }
+
+    let string = check_type!(objects[0], String);
+    let len = string.chars().count();
+    let start = char_index("substring", check_type!(objects[1], Int), len)?;
+    let end = match objects.get(2) {
+        Some(end) => char_index("substring", check_type!(end, Int), len)?,
+        None => len,
+    };
+
+    if start > end {
+        return Err(Error::Parameters(format!(
+            "substring expects start {start} to be before end {end}"
+        )));
+    }
+
+    Ok(Object::String(Gc::new(
+        string.chars().skip(start).take(end - start).collect(),
+    )))
+}
+
+pub fn append<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    let mut buffer = String::new();
+
+    for object in objects.iter() {
+        buffer.push_str(check_type!(object, String).as_str());
+    }
+
+    Ok(Object::String(Gc::new(buffer)))
+}
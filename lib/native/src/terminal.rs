@@ -0,0 +1,193 @@
+use crate::{check_arity, check_type};
+use gc::Gc;
+use std::env;
+use vm::{object::Type, Error, Local, Object};
+
+// What the natives report about the terminal. In deterministic mode stdout is
+// never a terminal and is always 80 columns wide, so scripts print the same
+// thing wherever they run.
+#[derive(Clone, Copy, Debug)]
+pub struct Terminal {
+    pub tty: bool,
+    pub width: Option<i64>,
+}
+
+impl Terminal {
+    pub fn new(deterministic: bool) -> Self {
+        use std::io::IsTerminal;
+
+        if deterministic {
+            return Self {
+                tty: false,
+                width: None,
+            };
+        }
+
+        Self {
+            tty: std::io::stdout().is_terminal(),
+            width: env::var("COLUMNS")
+                .ok()
+                .and_then(|columns| columns.parse().ok())
+                .or_else(window_width),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn window_width() -> Option<i64> {
+    #[repr(C)]
+    #[derive(Default)]
+    struct Winsize {
+        rows: u16,
+        columns: u16,
+        x_pixels: u16,
+        y_pixels: u16,
+    }
+
+    extern "C" {
+        fn ioctl(fd: i32, request: u64, ...) -> i32;
+    }
+
+    const TIOCGWINSZ: u64 = 0x5413;
+
+    let mut size = Winsize::default();
+    // SAFETY: TIOCGWINSZ only writes a winsize through the pointer.
+    let result = unsafe { ioctl(1, TIOCGWINSZ, &mut size as *mut Winsize) };
+
+    (result == 0 && size.columns > 0).then_some(i64::from(size.columns))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn window_width() -> Option<i64> {
+    None
+}
+
+pub fn is_tty<D: Clone>(terminal: &Terminal, objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("is-tty?", 0, objects);
+    Ok(Object::Bool(terminal.tty))
+}
+
+// 80 when the width can't be found, such as when stdout isn't a terminal.
+pub fn width<D: Clone>(terminal: &Terminal, objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("terminal-width", 0, objects);
+    Ok(Object::Int(terminal.width.unwrap_or(80)))
+}
+
+const STYLES: &[(&str, u8)] = &[
+    ("bold", 1),
+    ("dim", 2),
+    ("italic", 3),
+    ("underline", 4),
+    ("black", 30),
+    ("red", 31),
+    ("green", 32),
+    ("yellow", 33),
+    ("blue", 34),
+    ("magenta", 35),
+    ("cyan", 36),
+    ("white", 37),
+];
+
+fn code<D: Clone>(name: &str, object: &Local<D>) -> Result<u8, Error> {
+    object.with(|object| {
+        let style = match object {
+            Object::Symbol(symbol) => symbol.strip_prefix(':').and_then(|symbol| {
+                STYLES
+                    .iter()
+                    .find(|(style, _)| *style == symbol)
+                    .map(|(_, code)| *code)
+            }),
+            _ => None,
+        };
+
+        style.ok_or_else(|| {
+            Error::Parameters(format!(
+                "{name} does not know the style {object}, expected one of {}",
+                STYLES
+                    .iter()
+                    .map(|(style, _)| format!(":{style}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ))
+        })
+    })
+}
+
+fn sequence(codes: &[u8]) -> String {
+    let codes = codes
+        .iter()
+        .map(u8::to_string)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    format!("\x1b[{codes}m")
+}
+
+// (ansi-code :bold :red) is the escape sequence that turns those styles on,
+// and (ansi-code) the one that turns everything off again.
+pub fn ansi_code<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    let codes = objects
+        .iter()
+        .map(|object| code("ansi-code", object))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Object::String(Gc::new(if codes.is_empty() {
+        sequence(&[0])
+    } else {
+        sequence(&codes)
+    })))
+}
+
+// (ansi-style "text" :bold :red) wraps text in the sequences for the styles
+// when stdout is a terminal, and returns it as it is when it isn't.
+pub fn ansi_style<D: Clone>(
+    terminal: &Terminal,
+    objects: &mut [Local<D>],
+) -> Result<Object<D>, Error> {
+    if objects.is_empty() {
+        return Err(Error::Parameters(
+            "ansi-style expects a string and styles".to_string(),
+        ));
+    }
+
+    let text = check_type!(objects[0], String);
+    let codes = objects[1..]
+        .iter()
+        .map(|object| code("ansi-style", object))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Object::String(Gc::new(
+        if terminal.tty && !codes.is_empty() {
+            format!("{}{text}{}", sequence(&codes), sequence(&[0]))
+        } else {
+            text
+        },
+    )))
+}
+
+// Removes escape sequences such as colors and cursor movement, for measuring
+// or logging text that was styled for a terminal.
+pub fn strip_ansi<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("strip-ansi", 1, objects);
+
+    let text = check_type!(objects[0], String);
+    let mut stripped = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        if char != '\x1b' || chars.peek() != Some(&'[') {
+            stripped.push(char);
+            continue;
+        }
+
+        chars.next();
+        // parameters and intermediates, up to the final byte from @ to ~
+        for char in chars.by_ref() {
+            if ('@'..='~').contains(&char) {
+                break;
+            }
+        }
+    }
+
+    Ok(Object::String(Gc::new(stripped)))
+}
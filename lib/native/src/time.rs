@@ -0,0 +1,339 @@
+use crate::{check_arity, check_type, err, keyword_map, ok};
+use gc::Gc;
+use std::cell::Cell;
+use std::fmt::Write;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use vm::{
+    object::{HashMapKey, Type},
+    Error, Local, Object,
+};
+
+// How far the virtual clock advances each time it is read, in nanoseconds.
+const VIRTUAL_TICK: i64 = 1_000_000;
+
+#[derive(Clone, Debug)]
+pub enum Clock {
+    Real(Instant),
+    Virtual(Cell<i64>),
+}
+
+impl Clock {
+    pub fn real() -> Self {
+        Self::Real(Instant::now())
+    }
+
+    pub fn virtual_clock() -> Self {
+        Self::Virtual(Cell::new(0))
+    }
+
+    pub fn now(&self) -> i64 {
+        match self {
+            Self::Real(start) => start.elapsed().as_nanos() as i64,
+            Self::Virtual(now) => {
+                now.set(now.get() + VIRTUAL_TICK);
+                now.get()
+            }
+        }
+    }
+
+    // The virtual clock skips ahead instead of waiting.
+    pub fn sleep(&self, duration: Duration) {
+        match self {
+            Self::Real(_) => thread::sleep(duration),
+            Self::Virtual(now) => now.set(now.get() + duration.as_nanos() as i64),
+        }
+    }
+
+    // Milliseconds since the unix epoch, the virtual clock starts at the epoch.
+    pub fn unix_millis(&self) -> i64 {
+        match self {
+            Self::Real(_) => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_millis() as i64),
+            Self::Virtual(_) => self.now() / 1_000_000,
+        }
+    }
+}
+
+pub fn monotonic<D: Clone>(clock: &Clock, objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("time-monotonic", 0, objects);
+    Ok(Object::Int(clock.now()))
+}
+
+pub fn sleep_ms<D: Clone>(clock: &Clock, objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("sleep-ms", 1, objects);
+
+    let ms = check_type!(objects[0], Int);
+
+    if ms < 0 {
+        return Err(Error::Parameters(
+            "sleep-ms expects a non-negative duration".to_string(),
+        ));
+    }
+
+    clock.sleep(Duration::from_millis(ms as u64));
+
+    Ok(Object::Nil)
+}
+
+// A moment in UTC broken into its calendar fields.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DateTime {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    millisecond: i64,
+}
+
+const FIELDS: [&str; 7] = [
+    "year",
+    "month",
+    "day",
+    "hour",
+    "minute",
+    "second",
+    "millisecond",
+];
+
+impl DateTime {
+    // Days are converted to and from the proleptic gregorian calendar with
+    // Howard Hinnant's days_from_civil and civil_from_days.
+    fn from_unix_millis(millis: i64) -> Self {
+        let days = millis.div_euclid(86_400_000);
+        let millis_of_day = millis.rem_euclid(86_400_000);
+
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        Self {
+            year,
+            month,
+            day,
+            hour: millis_of_day / 3_600_000,
+            minute: millis_of_day / 60_000 % 60,
+            second: millis_of_day / 1000 % 60,
+            millisecond: millis_of_day % 1000,
+        }
+    }
+
+    fn unix_millis(&self) -> i64 {
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = (self.month + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + self.day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+
+        days * 86_400_000
+            + self.hour * 3_600_000
+            + self.minute * 60_000
+            + self.second * 1000
+            + self.millisecond
+    }
+
+    fn days_in_month(&self) -> i64 {
+        match self.month {
+            2 if self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    fn fields(&self) -> [i64; 7] {
+        [
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.millisecond,
+        ]
+    }
+
+    // {:year 2024 :month 1 ... :millisecond 0 :unix-ms 1704067200000}
+    fn into_object<D: Clone>(self) -> Object<D> {
+        keyword_map(
+            FIELDS
+                .into_iter()
+                .zip(self.fields().map(Object::Int))
+                .chain([("unix-ms", Object::Int(self.unix_millis()))]),
+        )
+    }
+}
+
+pub fn now<D: Clone>(clock: &Clock, objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("time-now", 0, objects);
+    Ok(DateTime::from_unix_millis(clock.unix_millis()).into_object())
+}
+
+// (time-format time format), where time is a map from time-now or time-parse
+// or milliseconds since the unix epoch. %Y, %m, %d, %H, %M, %S and %L are the
+// year, month, day, hour, minute, second and millisecond, %s is seconds since
+// the epoch and %% is a percent sign.
+pub fn format<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("time-format", 2, objects);
+
+    let millis = objects[0].with(|object| match object {
+        Object::Int(millis) => Ok(*millis),
+        Object::HashMap(map) => {
+            let key = HashMapKey::Symbol(Gc::new(":unix-ms".to_string()));
+            match map.borrow().get(&key) {
+                Some(Object::Int(millis)) => Ok(*millis),
+                _ => Err(Error::Parameters(
+                    "time-format expects a time with an int :unix-ms".to_string(),
+                )),
+            }
+        }
+        object => Err(Error::Type {
+            expected: Type::Int,
+            recieved: Type::from(object),
+        }),
+    })?;
+    let format = check_type!(objects[1], String);
+    let time = DateTime::from_unix_millis(millis);
+
+    let mut buffer = String::new();
+    let mut chars = format.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            buffer.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('Y') => write!(buffer, "{:04}", time.year),
+            Some('m') => write!(buffer, "{:02}", time.month),
+            Some('d') => write!(buffer, "{:02}", time.day),
+            Some('H') => write!(buffer, "{:02}", time.hour),
+            Some('M') => write!(buffer, "{:02}", time.minute),
+            Some('S') => write!(buffer, "{:02}", time.second),
+            Some('L') => write!(buffer, "{:03}", time.millisecond),
+            Some('s') => write!(buffer, "{}", millis.div_euclid(1000)),
+            Some('%') => write!(buffer, "%"),
+            Some(other) => {
+                return Err(Error::Parameters(format!(
+                    "time-format does not know the directive %{other}"
+                )))
+            }
+            None => {
+                return Err(Error::Parameters(
+                    "time-format string ends in a lone %".to_string(),
+                ))
+            }
+        }
+        .unwrap();
+    }
+
+    Ok(Object::String(Gc::new(buffer)))
+}
+
+// (time-parse string format) returns (ok time) or (err message). It takes the
+// same directives as time-format except %s, fields that aren't in the format
+// are the start of the epoch, and the input has to match the format exactly.
+pub fn parse<D: Clone>(objects: &mut [Local<D>]) -> Result<Object<D>, Error> {
+    check_arity!("time-parse", 2, objects);
+
+    let string = check_type!(objects[0], String);
+    let format = check_type!(objects[1], String);
+
+    Ok(match parse_time(string.as_str(), format.as_str())? {
+        Ok(time) => ok(time.into_object()),
+        Err(message) => err(message),
+    })
+}
+
+// The outer error is a bad format, the inner one input that doesn't match it.
+fn parse_time(string: &str, format: &str) -> Result<Result<DateTime, String>, Error> {
+    let mut time = DateTime::from_unix_millis(0);
+    let mut input = string;
+    let mut chars = format.chars();
+
+    while let Some(char) = chars.next() {
+        let (field, digits) = match char {
+            '%' => match chars.next() {
+                Some('Y') => (&mut time.year, 4),
+                Some('m') => (&mut time.month, 2),
+                Some('d') => (&mut time.day, 2),
+                Some('H') => (&mut time.hour, 2),
+                Some('M') => (&mut time.minute, 2),
+                Some('S') => (&mut time.second, 2),
+                Some('L') => (&mut time.millisecond, 3),
+                Some('%') => {
+                    let Some(rest) = input.strip_prefix('%') else {
+                        return Ok(Err(mismatch(string, input, "%")));
+                    };
+                    input = rest;
+                    continue;
+                }
+                Some(other) => {
+                    return Err(Error::Parameters(format!(
+                        "time-parse does not know the directive %{other}"
+                    )))
+                }
+                None => {
+                    return Err(Error::Parameters(
+                        "time-parse format ends in a lone %".to_string(),
+                    ))
+                }
+            },
+            char => {
+                let Some(rest) = input.strip_prefix(char) else {
+                    return Ok(Err(mismatch(string, input, &char.to_string())));
+                };
+                input = rest;
+                continue;
+            }
+        };
+
+        let number = input
+            .get(..digits)
+            .filter(|number| number.bytes().all(|byte| byte.is_ascii_digit()));
+
+        let Some(number) = number else {
+            return Ok(Err(mismatch(string, input, &format!("{digits} digits"))));
+        };
+
+        *field = number.parse().unwrap();
+        input = &input[digits..];
+    }
+
+    if !input.is_empty() {
+        return Ok(Err(format!("{string:?} has {input:?} left over")));
+    }
+
+    let out_of_range = FIELDS.iter().zip(time.fields()).find(|(name, value)| {
+        let range = match **name {
+            "month" => 1..=12,
+            "day" => 1..=time.days_in_month(),
+            "hour" => 0..=23,
+            "minute" | "second" => 0..=59,
+            _ => return false,
+        };
+        !range.contains(value)
+    });
+
+    Ok(match out_of_range {
+        Some((name, value)) => Err(format!("{value} is not a valid {name} in {string:?}")),
+        None => Ok(time),
+    })
+}
+
+fn mismatch(string: &str, input: &str, expected: &str) -> String {
+    let position = string[..string.len() - input.len()].chars().count();
+    format!("{string:?} doesn't match the format at position {position}, expected {expected}")
+}
@@ -0,0 +1,132 @@
+// What lisp bench runs. Files are found and loaded the same way as for lisp
+// test, then each defbench in a file is called a few times to warm up and
+// timed over a number of iterations with time-monotonic, so that the timing
+// happens in the vm rather than around the compiler.
+
+use crate::test_runner::registered;
+use crate::{compile_str, Interpreter, Manifest};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use vm::{Object, OpCodeTable};
+
+pub struct BenchResult {
+    pub file: PathBuf,
+    pub name: String,
+    // How long each iteration took, in nanoseconds.
+    pub samples: Vec<i64>,
+}
+
+impl BenchResult {
+    pub fn mean(&self) -> Duration {
+        Duration::from_nanos(mean(&self.samples) as u64)
+    }
+
+    // The sample standard deviation, zero with fewer than two samples.
+    pub fn stddev(&self) -> Duration {
+        if self.samples.len() < 2 {
+            return Duration::ZERO;
+        }
+
+        let mean = mean(&self.samples);
+        let variance = self
+            .samples
+            .iter()
+            .map(|sample| (*sample as f64 - mean).powi(2))
+            .sum::<f64>()
+            / (self.samples.len() - 1) as f64;
+
+        Duration::from_nanos(variance.sqrt() as u64)
+    }
+}
+
+fn mean(samples: &[i64]) -> f64 {
+    match samples.len() {
+        0 => 0.0,
+        len => samples.iter().map(|sample| *sample as f64).sum::<f64>() / len as f64,
+    }
+}
+
+// The benchmarks in a file in the order they're defined. Unlike a failing
+// test, a benchmark that errors stops the file, since its timings would be
+// meaningless.
+pub fn run_file(
+    manifest: &Manifest,
+    file: &Path,
+    warmup: usize,
+    iterations: usize,
+) -> Result<Vec<BenchResult>, Box<dyn Error>> {
+    let mut interpreter = Interpreter::with_manifest(manifest)?;
+
+    interpreter.compile_file(file)?;
+    interpreter.eval()?;
+
+    let mut results = Vec::new();
+
+    for name in registered(&mut interpreter, "*benches*")? {
+        time(&mut interpreter, &name, warmup)?;
+
+        let samples = time(&mut interpreter, &name, iterations)?;
+
+        results.push(BenchResult {
+            file: file.to_path_buf(),
+            name,
+            samples,
+        });
+    }
+
+    Ok(results)
+}
+
+fn time(
+    interpreter: &mut Interpreter,
+    name: &str,
+    iterations: usize,
+) -> Result<Vec<i64>, Box<dyn Error>> {
+    let depth = interpreter.vm.depth();
+
+    interpreter.opcode_table = OpCodeTable::new();
+
+    // the benchmark is looked up before the loop binds anything, so one named
+    // loop or start still means the global
+    compile_str(
+        &format!(
+            "(let ((bench {name}))
+               (named-let loop ((i 0) (samples nil))
+                 (if (= i {iterations})
+                     samples
+                     (let ((start (time-monotonic)))
+                       (bench)
+                       (loop (+ i 1) (cons (- (time-monotonic) start) samples))))))"
+        ),
+        name,
+        &mut interpreter.il_compiler,
+        &mut interpreter.ast_compiler,
+        &mut interpreter.vm,
+        &mut interpreter.opcode_table,
+    )?;
+
+    let result = interpreter.eval().and_then(|_| {
+        let samples = interpreter.vm.pop().unwrap().into_object();
+
+        match samples {
+            Object::Cons(cons) => cons
+                .borrow()
+                .iter_cars()
+                .map(|sample| match sample {
+                    Object::Int(sample) => Ok(sample),
+                    object => Err(format!("expected a time, found {object}").into()),
+                })
+                .collect(),
+            Object::Nil => Ok(Vec::new()),
+            object => Err(format!("expected a list of times, found {object}").into()),
+        }
+    });
+
+    interpreter.vm.unwind(depth);
+
+    result.map(|mut samples: Vec<i64>| {
+        samples.reverse();
+        samples
+    })
+}
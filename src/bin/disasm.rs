@@ -1,63 +1,94 @@
-use std::{
-    env,
-    fs::File,
-    io::Read,
-    path::{Path, PathBuf},
-};
-
-use compiler::{ast, bytecode, il};
-use reader::{Reader, Sexpr};
-use vm::{OpCode, OpCodeTable, Vm};
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut il_compiler = il::Compiler::new();
-    let mut ast_compiler = ast::Compiler::new();
-    let mut vm: Vm<&Sexpr<'_>> = Vm::new();
-    let mut opcode_table = OpCodeTable::new();
-
-    lisp::compile_file(
-        PathBuf::from("lib/bootstrap/bootstrap.lisp").as_path(),
-        &mut il_compiler,
-        &mut ast_compiler,
-        &mut vm,
-        &mut opcode_table,
-    )?;
-
-    lisp::compile_file(
-        PathBuf::from("lib/native/decl/native.lisp").as_path(),
-        &mut il_compiler,
-        &mut ast_compiler,
-        &mut vm,
-        &mut opcode_table,
-    )?;
-
-    for arg in env::args().skip(1) {
-        let path = PathBuf::from(arg);
+use std::{env, path::PathBuf, process::ExitCode};
 
-        let mut opcode_table = OpCodeTable::new();
+use compiler::il::{CompilerOptions, Dump};
+use lisp::Interpreter;
+use vm::OpCodeTable;
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-        lisp::compile_file(
-            path.as_path(),
-            &mut il_compiler,
-            &mut ast_compiler,
-            &mut vm,
-            &mut opcode_table,
-        )?;
+// disasm [--ast] [--il] [--format text|json] <file>...
+//
+// Prints the bytecode of each file, after the ast and il of each of its forms
+// when asked for them. A .lbc from lispc is printed as the bytecode it holds. As json it's an array with an object for each file,
+// which has no room for the ast and il.
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut interpreter = Interpreter::new()?;
+    let mut options = CompilerOptions::default();
+    let mut json = false;
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
 
-        disasm(&opcode_table, 0);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ast" => options.dump_ast = true,
+            "--il" => options.dump_il = true,
+            "--format" => match args.next().as_deref() {
+                Some("text") => json = false,
+                Some("json") => json = true,
+                _ => return Err("--format expects text or json".into()),
+            },
+            _ => paths.push(PathBuf::from(arg)),
+        }
     }
 
-    Ok(())
-}
+    if json && (options.dump_ast || options.dump_il) {
+        return Err("--ast and --il can't be printed as json".into());
+    }
+
+    interpreter.il_compiler.set_options(options);
+
+    let mut files = Vec::new();
 
-fn disasm(opcode_table: &OpCodeTable<&Sexpr>, depth: usize) {
-    let indent = "  ".repeat(depth);
+    for path in paths {
+        let mut opcode_table = OpCodeTable::new();
+
+        // a .lbc is already bytecode, and has no ast or il left to print
+        if lisp::lbc::is_lbc(&path) {
+            opcode_table = lisp::lbc::read(&path)?;
+        } else {
+            lisp::compile_file(
+                path.as_path(),
+                &mut interpreter.il_compiler,
+                &mut interpreter.ast_compiler,
+                &mut interpreter.vm,
+                &mut opcode_table,
+            )?;
+        }
 
-    for opcode in opcode_table.opcodes() {
-        println!("{indent}{opcode:?}");
+        for dump in interpreter.il_compiler.take_dumps() {
+            match dump {
+                Dump::Ast(ast) => print!(";; ast\n{ast}"),
+                Dump::Il(il) => print!(";; il\n{il}"),
+            }
+        }
+
+        if json {
+            files.push(format!(
+                r#"{{"file":{},"bytecode":{}}}"#,
+                lisp::symbols::json_string(&path.to_string_lossy()),
+                lisp::disasm::to_json(&opcode_table)
+            ));
+            continue;
+        }
 
-        if let OpCode::Lambda { body, .. } = opcode {
-            disasm(body, depth + 1)
+        if options.dump_ast || options.dump_il {
+            println!(";; bytecode");
         }
+
+        print!("{}", lisp::disasm::listing(&opcode_table));
+    }
+
+    if json {
+        println!("[{}]", files.join(","));
     }
+
+    Ok(())
 }
@@ -1,46 +1,68 @@
 #![feature(let_chains)]
 
-use std::{env, path::PathBuf};
-use vm::{OpCodeTable, Vm};
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut il_compiler = compiler::il::Compiler::new();
-    let mut ast_compiler = compiler::ast::Compiler::new();
-    let mut vm = Vm::new();
-    let mut opcode_table = OpCodeTable::new();
-
-    native_functions::load_module(&mut vm);
-
-    lisp::compile_file(
-        PathBuf::from("lib/bootstrap/bootstrap.lisp").as_path(),
-        &mut il_compiler,
-        &mut ast_compiler,
-        &mut vm,
-        &mut opcode_table,
-    )?;
-
-    lisp::compile_file(
-        PathBuf::from("lib/native/decl/native.lisp").as_path(),
-        &mut il_compiler,
-        &mut ast_compiler,
-        &mut vm,
-        &mut opcode_table,
-    )?;
-
-    for arg in env::args().skip(1).take_while(|s| s != "--") {
-        let path = PathBuf::from(arg);
-
-        lisp::compile_file(
-            path.as_path(),
-            &mut il_compiler,
-            &mut ast_compiler,
-            &mut vm,
-            &mut opcode_table,
-        )?;
+use lisp::{Interpreter, Manifest};
+use std::{env, path::PathBuf, process::ExitCode};
+
+// Errors are printed with Display, they render the source they point at.
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = native_functions::Config::default();
+    let mut timings = false;
+    let mut paths = Vec::new();
+
+    let mut args = env::args().skip(1);
+
+    // anything after -- is for the script, see command-line-args
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => break,
+            "--deterministic" => config.deterministic = true,
+            "--timings" => timings = true,
+            "--seed" => {
+                config.seed = args.next().ok_or("--seed expects a value")?.parse()?;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
     }
 
-    match vm.eval(&opcode_table) {
-        Ok(_) => Ok(()),
-        Err((error, sexpr)) => Err(format!("{sexpr:?}:\n{error}").into()),
+    config.args = args.collect();
+
+    // a program compiled by lispc already has the bootstrap in it
+    match paths.as_slice() {
+        [path] if lisp::lbc::is_lbc(path) => {
+            let mut interpreter = Interpreter::with_manifest(&Manifest::default().natives(config))?;
+            interpreter.opcode_table = lisp::lbc::read(path)?;
+            return interpreter.eval();
+        }
+        paths => {
+            if let Some(path) = paths.iter().find(|path| lisp::lbc::is_lbc(path)) {
+                return Err(format!("{} has to be run on its own", path.display()).into());
+            }
+        }
     }
+
+    let mut interpreter = Interpreter::with_manifest(&Manifest::standard().natives(config))?;
+
+    for path in paths {
+        interpreter.compile_file(path.as_path())?;
+    }
+
+    for warning in interpreter.take_warnings() {
+        eprintln!("{warning}");
+    }
+
+    if timings {
+        eprint!("{}", interpreter.report);
+    }
+
+    interpreter.eval()
 }
@@ -0,0 +1,16 @@
+use std::io;
+use std::process::ExitCode;
+
+// lisp-lsp
+//
+// A language server for editors, spoken over stdin and stdout.
+fn main() -> ExitCode {
+    match lisp::lsp::serve(io::stdin().lock(), io::stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use lisp::{Interpreter, Manifest};
+use std::{
+    env, fs,
+    io::{self, IsTerminal},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+const USAGE: &str = "usage: lisp -e <expr> [<arg>...]
+       lisp - [<arg>...]
+       lisp run [--trace] <file>... [-- <arg>...]
+       lisp profile [--out <output>] <file>... [-- <arg>...]
+       lisp symbols [--json] <file>...
+       lisp lint [--json] [--rule <rule>=<off|warning|error>]... <file>...
+       lisp repl [<file>...]
+       lisp build [--release] <file> [-o <output>]
+       lisp test <file or dir>...
+       lisp bench [--warmup <n>] [--iterations <n>] <file or dir>...";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    // a copy made by lisp build runs the program packed onto it, and every
+    // argument is the program's
+    if let Some(program) = lisp::lbc::unpack(&env::current_exe()?)? {
+        let mut interpreter = compiled(env::args().skip(1).collect())?;
+        interpreter.opcode_table = program;
+        return interpreter.eval();
+    }
+
+    let mut args = env::args().skip(1);
+
+    match args.next().as_deref() {
+        Some("-e") => {
+            let expr = args.next().ok_or(USAGE)?;
+            let mut interpreter = script(args.collect())?;
+            interpreter.compile_str(&expr, "-e")?;
+            report_warnings(&mut interpreter);
+            interpreter.eval()
+        }
+        Some("-") => {
+            let mut interpreter = script(args.collect())?;
+            let result = interpreter.run_stream(io::stdin().lock(), "-");
+            report_warnings(&mut interpreter);
+            result
+        }
+        Some("run") => run_files(args.collect()),
+        Some("profile") => profile(args.collect()),
+        Some("symbols") => symbols(args.collect()),
+        Some("lint") => lint(args.collect()),
+        Some("repl") => repl(args.collect()),
+        Some("build") => build(args.collect()),
+        Some("test") => test(args.map(PathBuf::from).collect()),
+        Some("bench") => bench(args.collect()),
+        _ => Err(USAGE.into()),
+    }
+}
+
+// An interpreter for a program given on the command line or piped in, with
+// the arguments after it as its command-line-args.
+fn script(args: Vec<String>) -> Result<Interpreter, Box<dyn std::error::Error>> {
+    let config = native_functions::Config {
+        args,
+        ..Default::default()
+    };
+
+    Interpreter::with_manifest(&Manifest::standard().natives(config))
+}
+
+// An interpreter for a program compiled by lispc or packed by lisp build, which
+// already has the bootstrap in it, so only the natives are loaded.
+fn compiled(args: Vec<String>) -> Result<Interpreter, Box<dyn std::error::Error>> {
+    let config = native_functions::Config {
+        args,
+        ..Default::default()
+    };
+
+    Interpreter::with_manifest(&Manifest::default().natives(config))
+}
+
+fn report_warnings(interpreter: &mut Interpreter) {
+    for warning in interpreter.take_warnings() {
+        eprintln!("{warning}");
+    }
+}
+
+// Runs files one after another as a single program, or a program compiled by
+// lispc when given a .lbc on its own. --trace prints each opcode to stderr as
+// it runs along with the line it was compiled from, which is the way to follow
+// a jump that lands somewhere it shouldn't.
+fn run_files(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut trace = false;
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+
+    for arg in args.by_ref() {
+        match arg.as_str() {
+            "--" => break,
+            "--trace" => trace = true,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut interpreter = match paths.as_slice() {
+        [path] if lisp::lbc::is_lbc(path) => {
+            let mut interpreter = compiled(args.collect())?;
+            interpreter.opcode_table = lisp::lbc::read(path)?;
+            interpreter
+        }
+        paths => {
+            if let Some(path) = paths.iter().find(|path| lisp::lbc::is_lbc(path)) {
+                return Err(format!("{} has to be run on its own", path.display()).into());
+            }
+
+            let mut interpreter = script(args.collect())?;
+
+            for path in paths {
+                interpreter.compile_file(path)?;
+            }
+
+            report_warnings(&mut interpreter);
+            interpreter
+        }
+    };
+
+    if trace {
+        interpreter.vm.set_tracer(Some(|pc, opcode: &_, span: &_| {
+            eprintln!(
+                "{:<24} {pc:>4}  {}",
+                lisp::disasm::location(span),
+                lisp::disasm::instruction(opcode)
+            )
+        }));
+    }
+
+    interpreter.eval()
+}
+
+// Runs files like lisp run while counting the opcodes run under each call
+// stack. The counts are written as folded stacks, to stdout or to --out, which
+// gets an svg flamegraph instead when it ends in .svg.
+fn profile(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut output = None;
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => break,
+            "--out" => output = Some(PathBuf::from(args.next().ok_or(USAGE)?)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut interpreter = script(args.collect())?;
+
+    for path in &paths {
+        interpreter.compile_file(path)?;
+    }
+
+    report_warnings(&mut interpreter);
+
+    interpreter.vm.set_profiling(true);
+    let result = interpreter.eval();
+    let folded = lisp::profile::folded(&interpreter.vm.profile());
+
+    // a program that fails part way is still worth seeing the profile of
+    match output {
+        Some(output)
+            if output
+                .extension()
+                .is_some_and(|extension| extension == "svg") =>
+        {
+            let title = paths[0].display().to_string();
+            fs::write(&output, lisp::profile::flamegraph(&folded, &title)?)?
+        }
+        Some(output) => fs::write(&output, folded)?,
+        None => print!("{folded}"),
+    }
+
+    result
+}
+
+// Compiles a file into a standalone executable, a copy of this one with the
+// program packed onto it. --release leaves the sources out of it.
+fn build(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut release = false;
+    let mut input = None;
+    let mut output = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--release" => release = true,
+            "-o" => output = Some(PathBuf::from(args.next().ok_or(USAGE)?)),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(USAGE.into()),
+        }
+    }
+
+    let input = input.ok_or(USAGE)?;
+    let output = output.unwrap_or_else(|| input.with_extension(env::consts::EXE_EXTENSION));
+
+    if output == input {
+        return Err(format!("{} would overwrite its own source", output.display()).into());
+    }
+
+    let mut interpreter = Interpreter::with_manifest(&Manifest::standard().keep_program())?;
+
+    interpreter.compile_file(input.as_path())?;
+
+    for warning in interpreter.take_warnings() {
+        eprintln!("{warning}");
+    }
+
+    let program = interpreter.program();
+    let header = lisp::lbc::header(&interpreter, &program);
+
+    lisp::lbc::pack(
+        env::current_exe()?.as_path(),
+        output.as_path(),
+        &program,
+        &header,
+        release,
+    )
+    .map_err(|e| format!("failed to write {}: {e}", output.display()).into())
+}
+
+// Loads the files given, then reads forms from stdin, with line editing and
+// tab completion if it's a terminal.
+fn repl(paths: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut interpreter = Interpreter::new()?;
+
+    for path in paths {
+        interpreter.compile_file(PathBuf::from(path).as_path())?;
+    }
+
+    interpreter.eval()?;
+
+    if io::stdin().is_terminal() {
+        let editor = lisp::repl::LineEditor::new()?;
+        lisp::repl::Repl::new(&mut interpreter, editor, io::stdout()).run()?;
+    } else {
+        lisp::repl::Repl::new(&mut interpreter, io::stdin().lock(), io::stdout()).run()?;
+    }
+
+    Ok(())
+}
+
+// Runs the deftests in every .lisp file under the paths given, printing each
+// failure as it happens and a count at the end. Fails if any test did.
+fn test(paths: Vec<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let manifest = Manifest::standard();
+    let mut passed = 0;
+    let mut failed = 0;
+
+    for file in lisp::test_runner::discover(&paths)? {
+        let results = match lisp::test_runner::run_file(&manifest, &file) {
+            Ok(results) => results,
+            Err(e) => {
+                println!("FAIL {}\n{e}", file.display());
+                failed += 1;
+                continue;
+            }
+        };
+
+        for result in results {
+            match result.error {
+                None => {
+                    println!("pass {}: {}", result.file.display(), result.name);
+                    passed += 1;
+                }
+                Some(error) => {
+                    println!("FAIL {}: {}\n{error}", result.file.display(), result.name);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    // a failing run's count is the error main prints, so it isn't printed twice
+    let summary = format!("{passed} passed, {failed} failed");
+
+    println!();
+
+    match failed {
+        0 => {
+            println!("{summary}");
+            Ok(())
+        }
+        _ => Err(summary.into()),
+    }
+}
+
+// Times the defbenches in every .lisp file under the paths given, after a few
+// untimed calls to each to warm up.
+fn bench(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut warmup = 3;
+    let mut iterations = 10;
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--warmup" => warmup = args.next().ok_or(USAGE)?.parse()?,
+            "--iterations" => iterations = args.next().ok_or(USAGE)?.parse()?,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() || iterations == 0 {
+        return Err(USAGE.into());
+    }
+
+    let manifest = Manifest::standard();
+    let mut failed = 0;
+
+    for file in lisp::test_runner::discover(&paths)? {
+        match lisp::bench_runner::run_file(&manifest, &file, warmup, iterations) {
+            Ok(results) => {
+                for result in results {
+                    println!(
+                        "{}: {}: mean {:?}, stddev {:?} over {} iterations",
+                        result.file.display(),
+                        result.name,
+                        result.mean(),
+                        result.stddev(),
+                        result.samples.len()
+                    );
+                }
+            }
+            Err(e) => {
+                println!("FAIL {}\n{e}", file.display());
+                failed += 1;
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{failed} failed").into()),
+    }
+}
+
+// Lints each file on its own, so that what one defines doesn't hide problems
+// in another. Fails if any rule set to error was broken.
+fn lint(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut json = false;
+    let mut config = lisp::lint::Config::default();
+    let mut paths = Vec::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--rule" => config.parse_rule(&args.next().ok_or(USAGE)?)?,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut diagnostics = Vec::new();
+
+    for path in paths {
+        let mut interpreter = Interpreter::new()?;
+
+        diagnostics.extend(lisp::lint::lint(
+            path.as_path(),
+            &config,
+            &mut interpreter.il_compiler,
+            &mut interpreter.ast_compiler,
+            &mut interpreter.vm,
+            &mut interpreter.opcode_table,
+        )?);
+    }
+
+    if json {
+        println!("{}", lisp::lint::to_json(&diagnostics));
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{diagnostic}");
+        }
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == lisp::lint::Severity::Error)
+        .count();
+
+    match errors {
+        0 => Ok(()),
+        1 => Err("1 lint error".into()),
+        errors => Err(format!("{errors} lint errors").into()),
+    }
+}
+
+fn symbols(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut json = false;
+    let mut paths = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut interpreter = Interpreter::new()?;
+
+    let mut symbols = Vec::new();
+
+    for path in paths {
+        symbols.extend(lisp::symbols::collect(
+            path.as_path(),
+            &mut interpreter.il_compiler,
+            &mut interpreter.ast_compiler,
+            &mut interpreter.vm,
+            &mut interpreter.opcode_table,
+        )?);
+    }
+
+    if json {
+        println!("{}", lisp::symbols::to_json(&symbols));
+    } else {
+        for symbol in &symbols {
+            println!(
+                "{}:{}:{}: {} {}",
+                symbol.file,
+                symbol.line,
+                symbol.column,
+                lisp::symbols::kind_name(symbol.kind),
+                symbol.name
+            );
+        }
+    }
+
+    Ok(())
+}
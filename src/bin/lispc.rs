@@ -0,0 +1,54 @@
+use lisp::{Interpreter, Manifest};
+use std::{env, path::PathBuf, process::ExitCode};
+
+const USAGE: &str = "usage: lispc <file> [-o <output>] | lispc --header <file.lbc>";
+
+// Compiles a file and everything it requires into a .lbc that eval runs
+// without compiling anything. --header prints what a .lbc needs from the lisp
+// that runs it instead.
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = None;
+    let mut output = None;
+
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--header" if input.is_none() => {
+                let path = PathBuf::from(args.next().ok_or(USAGE)?);
+                println!("{}", lisp::lbc::read_header(path.as_path())?);
+                return Ok(());
+            }
+            "-o" => output = Some(PathBuf::from(args.next().ok_or(USAGE)?)),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => return Err(USAGE.into()),
+        }
+    }
+
+    let input = input.ok_or(USAGE)?;
+    let output = output.unwrap_or_else(|| input.with_extension("lbc"));
+
+    let mut interpreter = Interpreter::with_manifest(&Manifest::standard().keep_program())?;
+
+    interpreter.compile_file(input.as_path())?;
+
+    for warning in interpreter.take_warnings() {
+        eprintln!("{warning}");
+    }
+
+    let program = interpreter.program();
+    let header = lisp::lbc::header(&interpreter, &program);
+
+    lisp::lbc::write(output.as_path(), &program, &header)
+        .map_err(|e| format!("failed to write {}: {e}", output.display()).into())
+}
@@ -0,0 +1,70 @@
+use std::io::{self, Read, Write};
+use std::{env, fs, path::PathBuf, process::ExitCode};
+
+const USAGE: &str = "usage: lispfmt [--check] [<file>...]";
+
+// lispfmt [--check] [<file>...]
+//
+// Formats each file in place, or stdin to stdout when no files are given. With
+// --check nothing is written, the files that aren't formatted are listed and
+// lispfmt fails if there are any.
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<bool, Box<dyn std::error::Error>> {
+    let mut check = false;
+    let mut files = Vec::new();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with("--") => return Err(USAGE.into()),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+
+    if files.is_empty() {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+
+        let formatted = lisp::formatter::format(&source, "<stdin>")?;
+
+        if check {
+            return Ok(formatted == source);
+        }
+
+        io::stdout().write_all(formatted.as_bytes())?;
+
+        return Ok(true);
+    }
+
+    let mut formatted_all = true;
+
+    for file in files {
+        let source = fs::read_to_string(&file)
+            .map_err(|e| format!("failed to read {}: {e}", file.display()))?;
+        let formatted = lisp::formatter::format(&source, &file.display().to_string())?;
+
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            println!("{}", file.display());
+            formatted_all = false;
+        } else {
+            fs::write(&file, formatted)
+                .map_err(|e| format!("failed to write {}: {e}", file.display()))?;
+        }
+    }
+
+    Ok(formatted_all)
+}
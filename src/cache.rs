@@ -0,0 +1,187 @@
+// Modules compiled by require are kept on disk so the next run can load them
+// instead of compiling them again. An entry holds the module's interface, the
+// code that ran while it compiled, its bytecode, and the sources its debug info
+// points into. It's only used while the module's source and those of the
+// modules it requires hash to what they did when it was written, and by a
+// compiler with the same key, the hash of the manifest that ran before it, since
+// the bootstrap's macros are compiled into the module too. Anything else wrong
+// with an entry just means compiling the module again.
+
+use compiler::interface::Interface;
+use reader::{Context, Span};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use vm::encode::{self, Decoder, Encoder};
+use vm::OpCodeTable;
+
+const MAGIC: &[u8] = b"carpet-lisp module\0";
+
+// Bump when the bytecode or interface format changes.
+const VERSION: u64 = 4;
+
+pub(crate) struct Entry {
+    pub requires: Vec<(String, u64)>,
+    pub interface: Interface,
+    pub compile_time: Vec<OpCodeTable<Span>>,
+    pub opcodes: OpCodeTable<Span>,
+}
+
+// FNV-1a, which unlike the std hasher is the same from one build to the next.
+pub(crate) fn hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Modules with the same name found in different places get different entries.
+pub(crate) fn path(dir: &Path, module: &str, source: &Path) -> PathBuf {
+    let source = source
+        .canonicalize()
+        .unwrap_or_else(|_| source.to_path_buf());
+    let key = hash(&source.to_string_lossy());
+
+    dir.join(format!("{module}-{key:016x}.lispc"))
+}
+
+pub(crate) fn write(path: &Path, source: &str, key: u64, entry: &Entry) -> std::io::Result<()> {
+    let mut encoder = Encoder::new();
+
+    for byte in MAGIC {
+        encoder.u8(*byte);
+    }
+    encoder.u64(VERSION);
+    encoder.u64(key);
+    encoder.u64(hash(source));
+
+    encoder.usize(entry.requires.len());
+    for (module, hash) in &entry.requires {
+        encoder.str(module);
+        encoder.u64(*hash);
+    }
+
+    entry.interface.encode(&mut encoder);
+
+    encode_tables(
+        &mut encoder,
+        entry.compile_time.iter().chain([&entry.opcodes]),
+        false,
+    );
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    fs::write(path, encoder.into_bytes())
+}
+
+// None if there's no usable entry for this version of the source.
+pub(crate) fn read(path: &Path, source: &str, key: u64) -> Option<Entry> {
+    let bytes = fs::read(path).ok()?;
+    let mut decoder = Decoder::new(bytes.strip_prefix(MAGIC)?);
+
+    if decoder.u64().ok()? != VERSION
+        || decoder.u64().ok()? != key
+        || decoder.u64().ok()? != hash(source)
+    {
+        return None;
+    }
+
+    decode(&mut decoder).ok()
+}
+
+fn decode(decoder: &mut Decoder) -> Result<Entry, encode::Error> {
+    let requires = (0..decoder.usize()?)
+        .map(|_| Ok((decoder.str()?.to_string(), decoder.u64()?)))
+        .collect::<Result<_, encode::Error>>()?;
+
+    let interface = Interface::decode(decoder)?;
+
+    let mut compile_time = decode_tables(decoder)?;
+    let opcodes = compile_time
+        .pop()
+        .ok_or_else(|| encode::Error::Other("missing the module's bytecode".into()))?;
+
+    Ok(Entry {
+        requires,
+        interface,
+        compile_time,
+        opcodes,
+    })
+}
+
+// Tables along with the sources their spans point into, each source written
+// once however many spans share it. Stripping the sources leaves their names,
+// so errors still say which file they came from but not where in it.
+pub(crate) fn encode_tables<'a>(
+    encoder: &mut Encoder,
+    tables: impl IntoIterator<Item = &'a OpCodeTable<Span>>,
+    strip_sources: bool,
+) {
+    let mut contexts: Vec<Rc<Context>> = Vec::new();
+    let mut encoded = Encoder::new();
+    let mut count = 0;
+
+    let mut debug = |encoder: &mut Encoder, span: &Span| {
+        let index = match contexts.iter().position(|c| Rc::ptr_eq(c, span.context())) {
+            Some(index) => index,
+            None => {
+                contexts.push(span.context().clone());
+                contexts.len() - 1
+            }
+        };
+        let range = match strip_sources {
+            true => 0..0,
+            false => span.range(),
+        };
+
+        encoder.usize(index);
+        encoder.usize(range.start);
+        encoder.usize(range.end);
+    };
+
+    for table in tables {
+        encoded.table(table, &mut debug);
+        count += 1;
+    }
+
+    encoder.usize(contexts.len());
+    for context in contexts {
+        encoder.str(context.display());
+        encoder.str(if strip_sources { "" } else { context.source() });
+    }
+
+    encoder.usize(count);
+    encoder.append(encoded);
+}
+
+pub(crate) fn decode_tables(
+    decoder: &mut Decoder,
+) -> Result<Vec<OpCodeTable<Span>>, encode::Error> {
+    let contexts = (0..decoder.usize()?)
+        .map(|_| {
+            let display = decoder.str()?;
+            let source = decoder.str()?;
+            Ok(Rc::new(Context::new(source, display)))
+        })
+        .collect::<Result<Vec<_>, encode::Error>>()?;
+
+    let mut debug = |decoder: &mut Decoder| {
+        let context = contexts
+            .get(decoder.usize()?)
+            .ok_or_else(|| encode::Error::Other("debug info refers to a missing source".into()))?;
+        let range = decoder.usize()?..decoder.usize()?;
+
+        if context.source().get(range.clone()).is_none() {
+            return Err(encode::Error::Other(
+                "debug info is out of bounds of its source".into(),
+            ));
+        }
+
+        Ok(Span::new(context.clone(), range))
+    };
+
+    (0..decoder.usize()?)
+        .map(|_| decoder.table(&mut debug))
+        .collect()
+}
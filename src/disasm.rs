@@ -0,0 +1,305 @@
+use crate::symbols::{json_option, json_string};
+use reader::Span;
+use std::fmt::{Debug, Write};
+use vm::{Arity, OpCode, OpCodeTable};
+
+// How much of a lambda's source to show when labelling its body.
+const LABEL_WIDTH: usize = 60;
+
+// One opcode per line, with the bodies of lambdas indented under them.
+pub fn disassemble<D: Debug>(opcode_table: &OpCodeTable<D>) -> String {
+    let mut out = String::new();
+    write_table(opcode_table, 0, &mut out);
+    out
+}
+
+fn write_table<D: Debug>(opcode_table: &OpCodeTable<D>, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+
+    for opcode in opcode_table.opcodes() {
+        writeln!(out, "{indent}{opcode:?}").unwrap();
+
+        if let OpCode::Lambda { body, .. } = opcode {
+            write_table(body, depth + 1, out);
+        }
+    }
+}
+
+// The listing disasm and :disasm print. It starts with the names and strings
+// the code refers to, then gives each opcode with its operands spelled out and
+// the file and line it was compiled from, and labels the body of each lambda
+// with the form that defined it.
+pub fn listing(opcode_table: &OpCodeTable<Span>) -> String {
+    let mut constants = Vec::new();
+    collect_constants(opcode_table, &mut constants);
+
+    let mut out = String::new();
+
+    if !constants.is_empty() {
+        writeln!(out, ";; constants").unwrap();
+
+        for (i, (kind, text)) in constants.iter().enumerate() {
+            match *kind {
+                "string" => writeln!(out, "{i:>4}  {kind:<10} {text:?}").unwrap(),
+                _ => writeln!(out, "{i:>4}  {kind:<10} {text}").unwrap(),
+            }
+        }
+
+        writeln!(out, ";; code").unwrap();
+    }
+
+    write_listing(opcode_table, 0, &mut out);
+
+    out
+}
+
+fn collect_constants(opcode_table: &OpCodeTable<Span>, constants: &mut Vec<(&str, String)>) {
+    for opcode in opcode_table.opcodes() {
+        let constant = match opcode {
+            OpCode::DefGlobal(name) | OpCode::SetGlobal(name) | OpCode::GetGlobal(name) => {
+                ("global", name.to_string())
+            }
+            OpCode::DefModuleVar(name)
+            | OpCode::SetModuleVar(name)
+            | OpCode::GetModuleVar(name) => ("module var", name.to_string()),
+            OpCode::CreateModule(name) => ("module", name.to_string()),
+            OpCode::PushSymbol(symbol) => ("symbol", symbol.to_string()),
+            OpCode::PushString(string) => ("string", string.to_string()),
+            OpCode::Lambda { body, doc, .. } => {
+                if let Some(doc) = doc {
+                    push_constant(constants, ("string", doc.to_string()));
+                }
+                collect_constants(body, constants);
+                continue;
+            }
+            _ => continue,
+        };
+
+        push_constant(constants, constant);
+    }
+}
+
+fn push_constant<'a>(constants: &mut Vec<(&'a str, String)>, constant: (&'a str, String)) {
+    if !constants.contains(&constant) {
+        constants.push(constant);
+    }
+}
+
+fn write_listing(opcode_table: &OpCodeTable<Span>, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let opcodes = opcode_table.opcodes();
+
+    for (i, (opcode, span)) in opcodes.iter().zip(opcode_table.debug()).enumerate() {
+        let instruction = format!("{indent}{i:>4}  {}", instruction(opcode));
+        writeln!(out, "{instruction:<40} ; {}", location(span)).unwrap();
+
+        if let OpCode::Lambda { body, .. } = opcode {
+            let name = match defined_name(opcodes, i) {
+                Some(name) => format!("{name} "),
+                None => String::new(),
+            };
+
+            writeln!(out, "{indent}  ;; {name}{}", label(span)).unwrap();
+            write_listing(body, depth + 1, out);
+        }
+    }
+}
+
+enum Operand {
+    Int(i64),
+    Name(String),
+    String(String),
+    Text(String),
+}
+
+// A lambda defined straight into a variable is labelled with its name as well.
+fn defined_name<D>(opcodes: &[OpCode<D>], lambda: usize) -> Option<&str> {
+    match opcodes.get(lambda + 1) {
+        Some(
+            OpCode::DefGlobal(name)
+            | OpCode::SetGlobal(name)
+            | OpCode::DefModuleVar(name)
+            | OpCode::SetModuleVar(name),
+        ) => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+// The name of the opcode followed by its operands.
+pub fn instruction<D: Debug>(opcode: &OpCode<D>) -> String {
+    let mut operands = operands(opcode)
+        .into_iter()
+        .map(|operand| match operand {
+            Operand::Int(int) => int.to_string(),
+            Operand::Name(text) | Operand::Text(text) => text,
+            Operand::String(string) => format!("{string:?}"),
+        })
+        .collect::<Vec<_>>();
+
+    // the json gives a lambda's arity a field of its own instead
+    if let OpCode::Lambda { arity, .. } = opcode {
+        operands.push(format!("arity {}", arity_text(*arity)));
+    }
+
+    match operands.as_slice() {
+        [] => mnemonic(opcode),
+        operands => format!("{} {}", mnemonic(opcode), operands.join(" ")),
+    }
+}
+
+fn mnemonic<D: Debug>(opcode: &OpCode<D>) -> String {
+    let debug = format!("{opcode:?}");
+
+    debug
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn operands<D>(opcode: &OpCode<D>) -> Vec<Operand> {
+    match opcode {
+        OpCode::DefGlobal(name)
+        | OpCode::SetGlobal(name)
+        | OpCode::GetGlobal(name)
+        | OpCode::DefModuleVar(name)
+        | OpCode::SetModuleVar(name)
+        | OpCode::GetModuleVar(name)
+        | OpCode::CreateModule(name)
+        | OpCode::PushSymbol(name) => vec![Operand::Name(name.to_string())],
+        OpCode::PushString(string) => vec![Operand::String(string.to_string())],
+        OpCode::SetLocal(i)
+        | OpCode::GetLocal(i)
+        | OpCode::SetUpValue(i)
+        | OpCode::GetUpValue(i)
+        | OpCode::Call(i)
+        | OpCode::Tail(i)
+        | OpCode::List(i)
+        | OpCode::Branch(i) => vec![Operand::Int(*i as i64)],
+        OpCode::Jmp(offset) => vec![Operand::Int(*offset as i64)],
+        OpCode::PushInt(int) => vec![Operand::Int(*int)],
+        OpCode::PushChar(char) => vec![Operand::Text(format!("{char:?}"))],
+        OpCode::PushBool(bool) => vec![Operand::Text(bool.to_string())],
+        OpCode::CreateUpValue(upvalue) => vec![Operand::Text(format!("{upvalue:?}"))],
+        OpCode::IsType(ty) => vec![Operand::Text(ty.to_string())],
+        OpCode::JumpTable(table) => vec![
+            Operand::Int(table.min),
+            Operand::Text(format!("{:?}", table.offsets)),
+            Operand::Int(table.default as i64),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+fn arity_text(arity: Arity) -> String {
+    match arity {
+        Arity::Nullary => "0".to_string(),
+        Arity::Nary(n) => n.to_string(),
+        Arity::Variadic(n) => format!("{n}+"),
+        Arity::Optional(required, optional) => format!("{required}-{}", required + optional),
+    }
+}
+
+// The same as listing, as a json object with the constants and the code. Each
+// opcode has its name, operands, and span, and each lambda its arity, the label
+// listing gives it, and the opcodes of its body nested under it.
+pub fn to_json(opcode_table: &OpCodeTable<Span>) -> String {
+    let mut constants = Vec::new();
+    collect_constants(opcode_table, &mut constants);
+
+    let mut json = String::from(r#"{"constants":["#);
+
+    for (i, (kind, text)) in constants.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+
+        write!(
+            json,
+            r#"{{"kind":{},"text":{}}}"#,
+            json_string(kind),
+            json_string(text)
+        )
+        .unwrap();
+    }
+
+    json.push_str(r#"],"code":"#);
+    write_json(opcode_table, &mut json);
+    json.push('}');
+
+    json
+}
+
+fn write_json(opcode_table: &OpCodeTable<Span>, json: &mut String) {
+    let opcodes = opcode_table.opcodes();
+
+    json.push('[');
+
+    for (i, (opcode, span)) in opcodes.iter().zip(opcode_table.debug()).enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+
+        let operands = operands(opcode)
+            .into_iter()
+            .map(|operand| match operand {
+                Operand::Int(int) => int.to_string(),
+                Operand::Name(text) | Operand::String(text) | Operand::Text(text) => {
+                    json_string(&text)
+                }
+            })
+            .collect::<Vec<_>>();
+        let (line, column) = span.context().line_and_column(span.range().start);
+
+        write!(
+            json,
+            r#"{{"index":{i},"op":{},"operands":[{}],"span":{{"file":{},"start":{},"end":{},"line":{line},"column":{column}}}"#,
+            json_string(&mnemonic(opcode)),
+            operands.join(","),
+            json_string(span.context().display()),
+            span.range().start,
+            span.range().end,
+        )
+        .unwrap();
+
+        if let OpCode::Lambda { arity, body, doc } = opcode {
+            let (required, optional, rest) = match *arity {
+                Arity::Nullary => (0, 0, false),
+                Arity::Nary(n) => (n, 0, false),
+                Arity::Variadic(n) => (n, 0, true),
+                Arity::Optional(required, optional) => (required, optional, false),
+            };
+
+            write!(
+                json,
+                r#","lambda":{{"name":{},"label":{},"arity":{{"required":{required},"optional":{optional},"rest":{rest}}},"doc":{},"body":"#,
+                json_option(defined_name(opcodes, i)),
+                json_string(&label(span)),
+                json_option(doc.as_deref().map(String::as_str)),
+            )
+            .unwrap();
+            write_json(body, json);
+            json.push('}');
+        }
+
+        json.push('}');
+    }
+
+    json.push(']');
+}
+
+pub fn location(span: &Span) -> String {
+    let (line, _) = span.context().line_and_column(span.range().start);
+
+    format!("{}:{line}", span.context().display())
+}
+
+// The first line of the source of a lambda, cut short if it's long.
+fn label(span: &Span) -> String {
+    let text = span.text().lines().next().unwrap_or_default();
+
+    match text.char_indices().nth(LABEL_WIDTH) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
@@ -0,0 +1,309 @@
+// What lispfmt prints. Forms are laid out from the sexprs the reader gives
+// back, putting a form on one line when it fits and otherwise breaking it the
+// way lisp usually is: a call lines its arguments up under the first, and a
+// form with a body, like let or def, keeps what it binds or names on the first
+// line and indents its body by two. Atoms and the shorthand the reader expands,
+// such as #(...) and #tag forms, are printed as they were written. Comments
+// come from the reader too, and stay next to whatever they were next to.
+
+use reader::{Context, Reader, Sexpr};
+use std::ops::Range;
+use std::rc::Rc;
+
+const WIDTH: usize = 80;
+const INDENT: usize = 2;
+
+// How many arguments of a form go on the line with its head when it's broken,
+// for the forms whose remaining arguments are a body.
+fn body_args(head: &str) -> Option<usize> {
+    Some(match head {
+        "progn" => 0,
+        "def" | "defconst" | "lambda" | "let" | "let*" | "letrec" | "labels" | "when"
+        | "unless" | "module" | "deftest" | "defbench" | "dolist" | "dotimes" | "match"
+        | "case" | "eval-when-compile" | "with-retry" => 1,
+        "defun" | "defmacro" | "named-let" => 2,
+        _ => return None,
+    })
+}
+
+#[derive(Clone)]
+enum Item<'a> {
+    Sexpr(&'a Sexpr),
+    Comment(Range<usize>),
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: Vec<Range<usize>>,
+}
+
+pub fn format(source: &str, name: &str) -> Result<String, reader::Error> {
+    let context = Rc::new(Context::new(source, name));
+    let mut reader = Reader::new(&context);
+    let sexprs = reader.by_ref().collect::<Result<Vec<_>, _>>()?;
+    let formatter = Formatter {
+        source,
+        comments: reader.comments().to_vec(),
+    };
+
+    let mut out = String::new();
+
+    if source.starts_with("#!") {
+        out.push_str(source.lines().next().unwrap_or_default());
+        out.push('\n');
+    }
+
+    let items = formatter.items(&sexprs.iter().collect::<Vec<_>>(), 0..source.len());
+
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 && formatter.is_trailing(&items[i - 1], item) {
+            out.push(' ');
+        } else if i > 0 {
+            out.push('\n');
+
+            if formatter.blank_line_before(&items[i - 1], item) {
+                out.push('\n');
+            }
+        }
+
+        out.push_str(&formatter.layout(item, 0));
+    }
+
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+impl<'a> Formatter<'a> {
+    // The sexprs with the comments between them, in the order they were
+    // written. Comments inside one of the sexprs are left for it.
+    fn items(&self, sexprs: &[&'a Sexpr], range: Range<usize>) -> Vec<Item<'a>> {
+        let mut items = sexprs
+            .iter()
+            .map(|sexpr| Item::Sexpr(sexpr))
+            .collect::<Vec<_>>();
+
+        items.extend(
+            self.comments
+                .iter()
+                .filter(|comment| range.start <= comment.start && comment.end <= range.end)
+                .filter(|comment| {
+                    !sexprs
+                        .iter()
+                        .any(|sexpr| sexpr.span().contains(&comment.start))
+                })
+                .map(|comment| Item::Comment(comment.clone())),
+        );
+
+        items.sort_by_key(|item| self.span(item).start);
+        items
+    }
+
+    fn span(&self, item: &Item) -> Range<usize> {
+        match item {
+            Item::Sexpr(sexpr) => sexpr.span(),
+            Item::Comment(span) => span.clone(),
+        }
+    }
+
+    // A comment on the same line as what came before it stays there.
+    fn is_trailing(&self, previous: &Item, item: &Item) -> bool {
+        matches!(item, Item::Comment(_))
+            && !self.source[self.span(previous).end..self.span(item).start].contains('\n')
+    }
+
+    fn blank_line_before(&self, previous: &Item, item: &Item) -> bool {
+        self.source[self.span(previous).end..self.span(item).start]
+            .matches('\n')
+            .count()
+            > 1
+    }
+
+    fn has_comments(&self, span: Range<usize>) -> bool {
+        self.comments
+            .iter()
+            .any(|comment| span.start <= comment.start && comment.end <= span.end)
+    }
+
+    fn text(&self, span: Range<usize>) -> &'a str {
+        &self.source[span]
+    }
+
+    // The parts of a list written with parens, brackets or braces: its opening
+    // and closing delimiters and what's between them. None for anything else,
+    // including lists the reader built from shorthand and ones it flattened,
+    // like (a . (b c)), which have to be printed as they were written.
+    fn delimited(&self, sexpr: &'a Sexpr) -> Option<(&'a str, Vec<&'a Sexpr>, &'a str)> {
+        let (open, list, close, span) = match sexpr {
+            Sexpr::List { list, span, .. } if self.source.as_bytes()[span.start] == b'(' => {
+                ("(", list, ")", span)
+            }
+            Sexpr::Vector { list, span, .. } => ("[", list, "]", span),
+            Sexpr::Map { list, span, .. } => ("{", list, "}", span),
+            _ => return None,
+        };
+
+        let elements = list.iter().collect::<Vec<_>>();
+
+        let mut end = span.start + open.len();
+
+        for element in elements
+            .iter()
+            .map(|element| element.span())
+            .chain(std::iter::once(span.end - close.len()..span.end))
+        {
+            if element.start < end || !self.is_blank(end..element.start) {
+                return None;
+            }
+            end = element.end;
+        }
+
+        Some((open, elements, close))
+    }
+
+    // Whether there's nothing but whitespace and comments in the span.
+    fn is_blank(&self, span: Range<usize>) -> bool {
+        let mut chars = self.text(span).chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                ';' => {
+                    chars.by_ref().find(|c| *c == '\n');
+                }
+                c if c.is_whitespace() => (),
+                _ => return false,
+            }
+        }
+
+        true
+    }
+
+    // 'x, `x, ,x and ,@x: the shorthand and what it applies to.
+    fn quoted(&self, sexpr: &'a Sexpr) -> Option<(&'a str, &'a Sexpr)> {
+        let Sexpr::List { list, span, .. } = sexpr else {
+            return None;
+        };
+
+        match list.as_slice() {
+            [shorthand, quoted]
+                if shorthand.span().start == span.start
+                    && matches!(self.source.as_bytes()[span.start], b'\'' | b'`' | b',') =>
+            {
+                Some((self.text(shorthand.span()), quoted))
+            }
+            _ => None,
+        }
+    }
+
+    // The sexpr on a single line, if it can be written as one.
+    fn flat(&self, sexpr: &'a Sexpr) -> Option<String> {
+        if self.has_comments(sexpr.span()) {
+            return None;
+        }
+
+        if let Some((shorthand, quoted)) = self.quoted(sexpr) {
+            return Some(format!("{shorthand}{}", self.flat(quoted)?));
+        }
+
+        if let Some((open, elements, close)) = self.delimited(sexpr) {
+            let elements = elements
+                .into_iter()
+                .map(|element| self.flat(element))
+                .collect::<Option<Vec<_>>>()?;
+
+            return Some(format!("{open}{}{close}", elements.join(" ")));
+        }
+
+        let text = self.text(sexpr.span());
+
+        (!text.contains('\n')).then(|| text.to_string())
+    }
+
+    // The item laid out starting at column. Lines after the first are indented
+    // from the start of the line rather than from column.
+    fn layout(&self, item: &Item<'a>, column: usize) -> String {
+        let sexpr = match item {
+            Item::Sexpr(sexpr) => *sexpr,
+            Item::Comment(span) => return self.text(span.clone()).trim_end().to_string(),
+        };
+
+        if let Some(flat) = self.flat(sexpr) {
+            if column + width(&flat) <= WIDTH {
+                return flat;
+            }
+        }
+
+        if let Some((shorthand, quoted)) = self.quoted(sexpr) {
+            let quoted = self.layout(&Item::Sexpr(quoted), column + width(shorthand));
+            return format!("{shorthand}{quoted}");
+        }
+
+        let Some((open, elements, close)) = self.delimited(sexpr) else {
+            return self.text(sexpr.span()).to_string();
+        };
+
+        let inner = sexpr.span().start + open.len()..sexpr.span().end - close.len();
+        let items = self.items(&elements, inner);
+
+        // how many items go on the first line, and where the rest line up
+        let (first_line, indent) = match items.first() {
+            Some(Item::Sexpr(Sexpr::Symbol { symbol, .. })) if open == "(" => {
+                match body_args(symbol) {
+                    Some(args) => (1 + args, column + INDENT),
+                    None => (2, column + open.len() + width(symbol) + 1),
+                }
+            }
+            _ => (1, column + open.len()),
+        };
+
+        let mut out = open.to_string();
+        let mut line_column = column + open.len();
+
+        for (i, item) in items.iter().enumerate() {
+            let on_first_line = i < first_line
+                && items[..=i]
+                    .iter()
+                    .all(|item| matches!(item, Item::Sexpr(_)));
+
+            if i == 0 {
+                // straight after the open paren
+            } else if on_first_line || self.is_trailing(&items[i - 1], item) {
+                out.push(' ');
+                line_column += 1;
+            } else {
+                out.push('\n');
+
+                if self.blank_line_before(&items[i - 1], item) {
+                    out.push('\n');
+                }
+
+                out.push_str(&" ".repeat(indent));
+                line_column = indent;
+            }
+
+            let text = self.layout(item, line_column);
+
+            line_column = match text.rfind('\n') {
+                Some(i) => width(&text[i + 1..]),
+                None => line_column + width(&text),
+            };
+
+            out.push_str(&text);
+        }
+
+        // a comment runs to the end of its line, so the close can't follow it
+        if let Some(Item::Comment(_)) = items.last() {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        }
+
+        out.push_str(close);
+        out
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
@@ -0,0 +1,95 @@
+use crate::{compile_file, disasm, Interpreter};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use vm::OpCodeTable;
+
+// Snapshot tests for codegen. Every .lisp file in a directory is compiled after
+// the standard library and its disassembly compared with the .disasm file next
+// to it, so a compiler change shows exactly which programs it affected. Setting
+// UPDATE_GOLDEN writes the snapshots instead of comparing against them.
+pub fn check_dir(dir: &Path) -> Result<(), String> {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut paths = fs::read_dir(dir)
+        .map_err(|e| format!("failed to read {}: {e}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(|e| format!("failed to read {}: {e}", dir.display()))?;
+
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "lisp"));
+    paths.sort();
+
+    let mut failures = Vec::new();
+
+    for path in paths {
+        let snapshot = path.with_extension("disasm");
+        let actual = disassemble_file(&path)?;
+
+        if update {
+            fs::write(&snapshot, &actual)
+                .map_err(|e| format!("failed to write {}: {e}", snapshot.display()))?;
+            continue;
+        }
+
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!(
+                "{} changed:\n{}",
+                path.display(),
+                diff(&expected, &actual)
+            )),
+            Err(_) => failures.push(format!(
+                "{} has no snapshot, run with UPDATE_GOLDEN=1 to create it",
+                path.display()
+            )),
+        }
+    }
+
+    match failures.is_empty() {
+        true => Ok(()),
+        false => Err(failures.join("\n")),
+    }
+}
+
+pub fn disassemble_file(path: &Path) -> Result<String, String> {
+    let mut interpreter =
+        Interpreter::new().map_err(|e| format!("failed to load the standard library: {e}"))?;
+
+    let mut opcode_table = OpCodeTable::new();
+
+    compile_file(
+        path,
+        &mut interpreter.il_compiler,
+        &mut interpreter.ast_compiler,
+        &mut interpreter.vm,
+        &mut opcode_table,
+    )
+    .map_err(|e| format!("failed to compile {}: {e}", path.display()))?;
+
+    Ok(disasm::disassemble(&opcode_table))
+}
+
+// Lines that differ, by position. Good enough to see what moved without
+// pulling in a diff algorithm.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    let mut out = String::new();
+
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => (),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push_str(&format!("{:>4} - {e}\n", i + 1));
+                }
+                if let Some(a) = a {
+                    out.push_str(&format!("{:>4} + {a}\n", i + 1));
+                }
+            }
+        }
+    }
+
+    out
+}
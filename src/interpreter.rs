@@ -0,0 +1,405 @@
+use crate::native::NativeFn;
+use crate::{
+    cache, compile_file_with_report, compile_sexpr, compile_str, compile_str_with_report,
+    CompileReport,
+};
+use compiler::{ast, diagnostics::Warning, il};
+use reader::{Span, StreamReader};
+use std::env;
+use std::fs;
+use std::io::BufRead;
+use std::mem;
+use std::path::{Path, PathBuf};
+use vm::{Local, Object, OpCodeTable, Vm};
+
+const BOOTSTRAP: &str = include_str!("../lib/bootstrap/bootstrap.lisp");
+
+const NATIVE_DECLS: &str = include_str!("../lib/native/decl/native.lisp");
+
+#[cfg(feature = "sqlite")]
+const SQLITE_DECLS: &str = include_str!("../lib/native/decl/sqlite.lisp");
+
+// A file to compile before anything else, either from disk or from a string
+// such as one embedded with include_str!.
+#[derive(Clone, Debug)]
+pub enum Source {
+    Path(PathBuf),
+    Str {
+        name: &'static str,
+        source: &'static str,
+    },
+}
+
+fn is_group_decls(name: &str) -> bool {
+    native_functions::GROUPS
+        .iter()
+        .any(|(group, _)| name.ends_with(&format!("native/decl/{group}.lisp")))
+}
+
+// What an interpreter loads when it's created. Sources are compiled and run in
+// order, so the bootstrap should come before anything that uses its macros or
+// functions. Natives are only loaded when a config is given. Modules are
+// required from the search path before CARPET_LISP_PATH, and cached in
+// cache_dir if there is one.
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    pub natives: Option<native_functions::Config>,
+    pub sources: Vec<Source>,
+    pub search_path: Vec<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub keep_program: bool,
+}
+
+impl Manifest {
+    // The bootstrap and native decls. These are embedded in the binary so it
+    // runs from anywhere, but CARPET_LISP_LIB can point at a lib directory on
+    // disk to use instead while working on them.
+    pub fn standard() -> Self {
+        let manifest = Self::default().natives(native_functions::Config::default());
+
+        let manifest = match env::var_os("CARPET_LISP_LIB") {
+            Some(lib) => {
+                let lib = PathBuf::from(lib);
+                manifest
+                    .source(Source::Path(lib.join("bootstrap/bootstrap.lisp")))
+                    .source(Source::Path(lib.join("native/decl/native.lisp")))
+            }
+            None => manifest
+                .source(Source::Str {
+                    name: "lib/bootstrap/bootstrap.lisp",
+                    source: BOOTSTRAP,
+                })
+                .source(Source::Str {
+                    name: "lib/native/decl/native.lisp",
+                    source: NATIVE_DECLS,
+                }),
+        };
+
+        #[cfg(feature = "sqlite")]
+        let manifest = manifest.source(match env::var_os("CARPET_LISP_LIB") {
+            Some(lib) => Source::Path(PathBuf::from(lib).join("native/decl/sqlite.lisp")),
+            None => Source::Str {
+                name: "lib/native/decl/sqlite.lisp",
+                source: SQLITE_DECLS,
+            },
+        });
+
+        manifest
+    }
+
+    // Keeps everything that runs, including what runs while compiling, so that
+    // Interpreter::program can write the whole program out.
+    pub fn keep_program(mut self) -> Self {
+        self.keep_program = true;
+        self
+    }
+
+    pub fn natives(mut self, config: native_functions::Config) -> Self {
+        self.natives = Some(config);
+        self
+    }
+
+    // A hash of what the sources say, which changes with the bootstrap and the
+    // native decls. Compiled programs and cached modules are only used by an
+    // interpreter whose manifest hashes the same. The decls of optional groups
+    // of natives, like sqlite's, are left out: a program calling those says so
+    // itself, and leaving them out lets a build with more natives run what one
+    // with fewer compiled.
+    pub fn hash(&self) -> u64 {
+        let sources = self
+            .sources
+            .iter()
+            .filter_map(|source| match source {
+                Source::Path(path) if !is_group_decls(&path.to_string_lossy()) => {
+                    Some(fs::read_to_string(path).unwrap_or_default())
+                }
+                Source::Str { name, source } if !is_group_decls(name) => Some(source.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        cache::hash(&sources.join("\0"))
+    }
+
+    pub fn source(mut self, source: Source) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    pub fn search_path(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.search_path.push(dir.into());
+        self
+    }
+
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+}
+
+pub struct Interpreter {
+    pub il_compiler: il::Compiler,
+    pub ast_compiler: ast::Compiler,
+    pub vm: Vm<Span>,
+    pub opcode_table: OpCodeTable<Span>,
+    // What the manifest's sources ran, while compiling and after, if the
+    // manifest asked to keep the program.
+    pub prelude: OpCodeTable<Span>,
+    pub report: CompileReport,
+    // The hash of the manifest the interpreter was made with.
+    pub manifest_hash: u64,
+}
+
+// Running lisp from rust takes no more than
+//
+//     let mut interpreter = Interpreter::new()?;
+//     interpreter.eval_str(r#"(def greeting "hello world")"#)?;
+//     let greeting = interpreter.get_global("greeting");
+//
+// The compilers, vm and opcode table are still there for tools that need to
+// drive them a step at a time.
+impl Interpreter {
+    // An interpreter with the standard manifest, the bootstrap and natives.
+    pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_manifest(&Manifest::standard())
+    }
+
+    pub fn with_manifest(manifest: &Manifest) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut interpreter = Self {
+            il_compiler: il::Compiler::new(),
+            ast_compiler: ast::Compiler::new(),
+            vm: Vm::new(),
+            opcode_table: OpCodeTable::new(),
+            prelude: OpCodeTable::new(),
+            report: CompileReport::default(),
+            manifest_hash: manifest.hash(),
+        };
+
+        if let Some(config) = &manifest.natives {
+            interpreter.vm.set_deterministic(config.deterministic);
+            native_functions::load_module_with_config(&mut interpreter.vm, config);
+        }
+
+        for dir in &manifest.search_path {
+            interpreter.il_compiler.add_search_path(dir);
+        }
+
+        interpreter
+            .il_compiler
+            .set_cache_dir(manifest.cache_dir.as_deref());
+        interpreter
+            .il_compiler
+            .set_cache_key(interpreter.manifest_hash);
+
+        interpreter
+            .il_compiler
+            .set_keep_compile_time(manifest.keep_program);
+
+        // each source is run as soon as it's loaded, rather than along with the
+        // program, so that everything it defines is there for the macros and
+        // eval-when-compile code of what comes after it
+        for source in &manifest.sources {
+            interpreter.load(source)?;
+            interpreter.eval()?;
+
+            let opcode_table = mem::replace(&mut interpreter.opcode_table, OpCodeTable::new());
+
+            if manifest.keep_program {
+                for table in interpreter.il_compiler.take_compile_time() {
+                    interpreter.prelude.append(table);
+                }
+                interpreter.prelude.append(opcode_table);
+            }
+        }
+
+        // the standard library is generic over what it's given, there's no point
+        // telling every user that car returns any
+        interpreter.take_warnings();
+
+        Ok(interpreter)
+    }
+
+    pub fn load(&mut self, source: &Source) -> Result<(), Box<dyn std::error::Error>> {
+        match source {
+            Source::Path(path) => self.compile_file(path),
+            Source::Str { name, source } => self.compile_str(source, name),
+        }
+    }
+
+    pub fn compile_file(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        compile_file_with_report(
+            path,
+            &mut self.il_compiler,
+            &mut self.ast_compiler,
+            &mut self.vm,
+            &mut self.opcode_table,
+            &mut self.report,
+        )
+    }
+
+    pub fn compile_str(
+        &mut self,
+        source: &str,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        compile_str_with_report(
+            source,
+            name,
+            &mut self.il_compiler,
+            &mut self.ast_compiler,
+            &mut self.vm,
+            &mut self.opcode_table,
+            &mut self.report,
+        )
+    }
+
+    // Compiles and runs each form as soon as it's read rather than reading all
+    // of the input first, so a program piped in starts before the pipe closes.
+    // Warnings are left for the caller to take.
+    pub fn run_stream(
+        &mut self,
+        input: impl BufRead,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for sexpr in StreamReader::new(input, name) {
+            self.opcode_table = OpCodeTable::new();
+
+            compile_sexpr(
+                &sexpr?,
+                &mut self.il_compiler,
+                &mut self.ast_compiler,
+                &mut self.vm,
+                &mut self.opcode_table,
+            )?;
+
+            let depth = self.vm.depth();
+            self.eval()?;
+            self.vm.unwind(depth);
+        }
+
+        Ok(())
+    }
+
+    // Compiles and runs source on its own, apart from anything compiled before,
+    // and returns the value of its last form. Warnings are left for the caller
+    // to take.
+    pub fn eval_str(&mut self, source: &str) -> Result<Object<Span>, Box<dyn std::error::Error>> {
+        self.opcode_table = OpCodeTable::new();
+        self.compile_str(source, "eval_str")?;
+        self.eval_value()
+    }
+
+    // The same for a file.
+    pub fn eval_file(&mut self, path: &Path) -> Result<Object<Span>, Box<dyn std::error::Error>> {
+        self.opcode_table = OpCodeTable::new();
+        self.compile_file(path)?;
+        self.eval_value()
+    }
+
+    // An error leaves the vm as it was before the eval, so the interpreter can
+    // carry on being used.
+    fn eval_value(&mut self) -> Result<Object<Span>, Box<dyn std::error::Error>> {
+        let depth = self.vm.depth();
+        let result = self.eval().map(|()| match self.vm.depth() > depth {
+            true => self.vm.pop().unwrap().into_object(),
+            false => Object::Nil,
+        });
+
+        self.vm.unwind(depth);
+
+        result
+    }
+
+    // Makes a rust closure a native called name, as in
+    //
+    //     interpreter.register("add2", |a: i64, b: i64| a + b)?;
+    //
+    // Its arguments are checked and converted from what lisp passes it, and
+    // the compiler is told about it as though it had been declared with decl.
+    pub fn register<Args, F: NativeFn<Args>>(
+        &mut self,
+        name: &str,
+        f: F,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let parameters = F::parameter_types()
+            .into_iter()
+            .enumerate()
+            .map(|(i, parameter)| match parameter {
+                Some(t) => format!("(arg{i} {t})"),
+                None => format!("arg{i}"),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let returns = F::return_type()
+            .map(|t| format!(" -> {t}"))
+            .unwrap_or_default();
+        let decl = format!("(decl {name} (lambda ({parameters}){returns}))");
+
+        compile_str(
+            &decl,
+            name,
+            &mut self.il_compiler,
+            &mut self.ast_compiler,
+            &mut self.vm,
+            &mut OpCodeTable::new(),
+        )?;
+
+        let name = name.to_string();
+
+        self.vm
+            .load_native_function(&name.clone(), move |args| f.call(&name, args));
+
+        Ok(())
+    }
+
+    pub fn get_global(&mut self, name: &str) -> Option<Object<Span>> {
+        self.vm.get_global(name).ok()?;
+        self.vm.pop().map(Local::into_object)
+    }
+
+    // Warnings from everything compiled since the last call, in the order the
+    // passes reported them.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        let mut warnings = self.ast_compiler.take_warnings();
+        warnings.extend(self.il_compiler.take_warnings());
+        warnings
+    }
+
+    // Everything that can be referred to by name at the top level: globals,
+    // built in special forms and what modules export, sorted for completion.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self
+            .vm
+            .globals()
+            .chain(ast::BUILT_INS.iter().copied())
+            .map(str::to_string)
+            .chain(self.il_compiler.exports())
+            .collect::<Vec<_>>();
+
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    // The prelude, then what compiling since ran, then what it compiled to.
+    // Run on a vm with nothing but natives loaded it does what eval would, as
+    // long as the manifest kept the program.
+    pub fn program(&mut self) -> OpCodeTable<Span> {
+        let mut program = self.prelude.clone();
+
+        for table in self.il_compiler.take_compile_time() {
+            program.append(table);
+        }
+
+        program.append(self.opcode_table.clone());
+        program
+    }
+
+    // Runs everything compiled so far.
+    pub fn eval(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.vm.eval(&self.opcode_table) {
+            Ok(_) => Ok(()),
+            Err((error, sexpr)) => Err(sexpr.render_diagnostic(&format!("error: {error}")).into()),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// Whole programs compiled ahead of time by lispc. A .lbc file holds the
+// bytecode of the bootstrap, the program and everything it requires, along
+// with the sources the debug info points into, so running one needs no reader
+// or compiler, only a vm with the natives loaded.
+//
+// A program can also be packed onto the end of a copy of the lisp binary,
+// followed by its length and PACKED, which the copy finds and runs instead of
+// doing what lisp normally would. That makes a single executable that can be
+// handed to someone without a rust toolchain or this repo.
+//
+// The header after the version says what the program needs from the lisp that
+// runs it: the optional groups of natives it calls, and the hash of the
+// bootstrap and native decls it was compiled against, which both have to match
+// this build. The modules it required are recorded too, with the hashes of
+// their sources, but being compiled into it they don't have to be around.
+
+use crate::interpreter::{Interpreter, Manifest};
+use crate::{cache, find_module};
+use reader::Span;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use vm::encode::{Decoder, Encoder};
+use vm::{OpCode, OpCodeTable};
+
+const MAGIC: &[u8] = b"carpet-lisp bytecode\0";
+
+const PACKED: &[u8] = b"carpet-lisp packed\0";
+
+// Bump when the bytecode format changes.
+const VERSION: u64 = 2;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Header {
+    pub natives: Vec<String>,
+    pub manifest_hash: u64,
+    pub modules: Vec<(String, u64)>,
+}
+
+// A line for each thing the program needs, as lispc --header prints it.
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.natives.as_slice() {
+            [] => writeln!(f, "natives: none")?,
+            natives => writeln!(f, "natives: {}", natives.join(" "))?,
+        }
+
+        write!(f, "manifest: {:016x}", self.manifest_hash)?;
+
+        for (module, hash) in &self.modules {
+            write!(f, "\nmodule: {module} {hash:016x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+// The header for a program the interpreter compiled.
+pub fn header(interpreter: &Interpreter, program: &OpCodeTable<Span>) -> Header {
+    let mut natives = Vec::new();
+    native_groups(program, &mut natives);
+    natives.sort();
+    natives.dedup();
+
+    let search_path = interpreter.il_compiler.search_path();
+    let modules = interpreter
+        .il_compiler
+        .required_modules()
+        .into_iter()
+        .map(|module| {
+            let hash = find_module(module, search_path)
+                .and_then(|path| fs::read_to_string(path).ok())
+                .map_or(0, |source| cache::hash(&source));
+            (module.to_string(), hash)
+        })
+        .collect();
+
+    Header {
+        natives,
+        manifest_hash: interpreter.manifest_hash,
+        modules,
+    }
+}
+
+fn native_groups(program: &OpCodeTable<Span>, groups: &mut Vec<String>) {
+    for opcode in program.opcodes() {
+        match opcode {
+            OpCode::GetGlobal(name) => {
+                groups.extend(native_functions::group(name).map(str::to_string))
+            }
+            OpCode::Lambda { body, .. } => native_groups(body, groups),
+            _ => (),
+        }
+    }
+}
+
+pub fn write(path: &Path, program: &OpCodeTable<Span>, header: &Header) -> io::Result<()> {
+    fs::write(path, encode(program, header, false))
+}
+
+pub fn read(path: &Path) -> Result<OpCodeTable<Span>, Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+
+    decode(&bytes, &path.display().to_string())
+}
+
+// What a program needs, read without checking it against this build, so that
+// a tool can say what a .lbc wants before trying to run it.
+pub fn read_header(path: &Path) -> Result<Header, Box<dyn Error>> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    let mut decoder = decoder(&bytes, &path.display().to_string())?;
+
+    Ok(decode_header(&mut decoder)?)
+}
+
+pub fn is_lbc(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "lbc")
+}
+
+// Writes a copy of runner with program packed onto it. Stripping the sources
+// keeps them out of the executable, at the cost of errors not showing them.
+pub fn pack(
+    runner: &Path,
+    output: &Path,
+    program: &OpCodeTable<Span>,
+    header: &Header,
+    strip_sources: bool,
+) -> io::Result<()> {
+    let mut bytes = fs::read(runner)?;
+    let program = encode(program, header, strip_sources);
+
+    bytes.extend(&program);
+    bytes.extend((program.len() as u64).to_le_bytes());
+    bytes.extend(PACKED);
+
+    fs::write(output, bytes)?;
+    fs::set_permissions(output, fs::metadata(runner)?.permissions())
+}
+
+// The program packed onto exe, None if nothing is.
+pub fn unpack(exe: &Path) -> Result<Option<OpCodeTable<Span>>, Box<dyn Error>> {
+    let mut file = File::open(exe)?;
+    let trailer = PACKED.len() as u64 + 8;
+
+    if file.metadata()?.len() < trailer {
+        return Ok(None);
+    }
+
+    let mut buffer = vec![0; trailer as usize];
+    file.seek(SeekFrom::End(-(trailer as i64)))?;
+    file.read_exact(&mut buffer)?;
+
+    let (length, magic) = buffer.split_at(8);
+
+    if magic != PACKED {
+        return Ok(None);
+    }
+
+    let length = u64::from_le_bytes(length.try_into().unwrap());
+    let start = file
+        .metadata()?
+        .len()
+        .checked_sub(trailer + length)
+        .ok_or("the packed program is cut short")?;
+
+    let mut bytes = vec![0; length as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut bytes)?;
+
+    decode(&bytes, &exe.display().to_string()).map(Some)
+}
+
+fn encode(program: &OpCodeTable<Span>, header: &Header, strip_sources: bool) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+
+    for byte in MAGIC {
+        encoder.u8(*byte);
+    }
+    encoder.u64(VERSION);
+
+    encoder.usize(header.natives.len());
+    for group in &header.natives {
+        encoder.str(group);
+    }
+
+    encoder.u64(header.manifest_hash);
+
+    encoder.usize(header.modules.len());
+    for (module, hash) in &header.modules {
+        encoder.str(module);
+        encoder.u64(*hash);
+    }
+
+    cache::encode_tables(&mut encoder, [program], strip_sources);
+
+    encoder.into_bytes()
+}
+
+fn decode(bytes: &[u8], name: &str) -> Result<OpCodeTable<Span>, Box<dyn Error>> {
+    let mut decoder = decoder(bytes, name)?;
+    let header = decode_header(&mut decoder)?;
+
+    check(&header, name)?;
+
+    let program = cache::decode_tables(&mut decoder)?
+        .pop()
+        .ok_or_else(|| format!("{name} has no bytecode in it"))?;
+
+    Ok(program)
+}
+
+// A decoder for what follows the magic and the version, once they've been
+// checked.
+fn decoder<'a>(bytes: &'a [u8], name: &str) -> Result<Decoder<'a>, Box<dyn Error>> {
+    let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+        return Err(format!("{name} isn't a compiled lisp program").into());
+    };
+
+    let mut decoder = Decoder::new(bytes);
+
+    match decoder.u64()? {
+        VERSION => (),
+        version => {
+            return Err(format!(
+                "{name} was compiled for bytecode version {version}, this is version {VERSION}"
+            )
+            .into())
+        }
+    }
+
+    Ok(decoder)
+}
+
+fn decode_header(decoder: &mut Decoder) -> Result<Header, vm::encode::Error> {
+    Ok(Header {
+        natives: (0..decoder.usize()?)
+            .map(|_| decoder.str().map(str::to_string))
+            .collect::<Result<_, _>>()?,
+        manifest_hash: decoder.u64()?,
+        modules: (0..decoder.usize()?)
+            .map(|_| Ok((decoder.str()?.to_string(), decoder.u64()?)))
+            .collect::<Result<_, vm::encode::Error>>()?,
+    })
+}
+
+// Whether this build can run a program with the header.
+fn check(header: &Header, name: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(group) = header
+        .natives
+        .iter()
+        .find(|group| !native_functions::has_group(group))
+    {
+        return Err(format!(
+            "{name} calls the {group} natives, which this build of lisp doesn't have, \
+             build it with --features {group}"
+        )
+        .into());
+    }
+
+    if header.manifest_hash != Manifest::standard().hash() {
+        return Err(format!(
+            "{name} was compiled against a different bootstrap or native decls than this \
+             build of lisp has, compile it again with lispc"
+        )
+        .into());
+    }
+
+    Ok(())
+}
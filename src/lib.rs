@@ -1,86 +1,578 @@
+pub mod bench_runner;
+mod cache;
+pub mod disasm;
+pub mod formatter;
+pub mod golden;
+pub mod interpreter;
+pub mod lbc;
+pub mod lint;
+pub mod lsp;
+pub mod native;
+pub mod profile;
+pub mod repl;
+pub mod symbols;
+pub mod test_runner;
+
 use compiler::{
     ast::{self, Ast},
     bytecode, il,
 };
-use reader::{Reader, Sexpr};
+use reader::{Reader, Sexpr, Span};
 use std::env;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::Read;
 use std::path::{Path, PathBuf};
-use vm::{OpCodeTable, Vm};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use vm::{OpCode, OpCodeTable, Vm};
+
+pub use interpreter::{Interpreter, Manifest, Source};
+
+#[derive(Clone, Debug, Default)]
+pub struct CompileReport {
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub read: Duration,
+    pub macro_expansion: Duration,
+    pub ast: Duration,
+    pub il: Duration,
+    pub bytecode: Duration,
+    pub verify: Duration,
+    pub forms: usize,
+    pub il_nodes: usize,
+    pub opcodes: usize,
+}
+
+// Every error found while compiling something, a file keeps going after a form
+// fails to compile so that one typo doesn't hide the next.
+#[derive(Debug)]
+pub struct CompileErrors(pub Vec<Box<dyn std::error::Error>>);
+
+impl CompileErrors {
+    // Errors that are themselves CompileErrors, such as those from a required
+    // file, are flattened into this one.
+    fn push(&mut self, error: Box<dyn std::error::Error>) {
+        match error.downcast::<CompileErrors>() {
+            Ok(errors) => self.0.extend(errors.0),
+            Err(error) => self.0.push(error),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> From<Vec<E>> for CompileErrors {
+    fn from(errors: Vec<E>) -> Self {
+        Self(
+            errors
+                .into_iter()
+                .map(|e| Box::new(e) as Box<dyn std::error::Error>)
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for CompileErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{error}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for CompileErrors {}
+
+impl FileReport {
+    pub fn total(&self) -> Duration {
+        self.read + self.macro_expansion + self.ast + self.il + self.bytecode + self.verify
+    }
+}
+
+impl fmt::Display for CompileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn ms(duration: Duration) -> String {
+            format!("{:.3}ms", duration.as_secs_f64() * 1000.0)
+        }
+
+        writeln!(
+            f,
+            "{:<40} {:>6} {:>8} {:>8} {:>10} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "file",
+            "forms",
+            "il nodes",
+            "opcodes",
+            "read",
+            "macro-expand",
+            "ast",
+            "il",
+            "bytecode",
+            "verify",
+            "total"
+        )?;
+
+        for file in &self.files {
+            writeln!(
+                f,
+                "{:<40} {:>6} {:>8} {:>8} {:>10} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10}",
+                file.path.display(),
+                file.forms,
+                file.il_nodes,
+                file.opcodes,
+                ms(file.read),
+                ms(file.macro_expansion),
+                ms(file.ast),
+                ms(file.il),
+                ms(file.bytecode),
+                ms(file.verify),
+                ms(file.total())
+            )?;
+        }
+
+        Ok(())
+    }
+}
 
 pub fn compile_file(
     path: &Path,
     il_compiler: &mut il::Compiler,
     ast_compiler: &mut ast::Compiler,
-    vm: &mut Vm<&'static Sexpr<'static>>,
-    opcode_table: &mut OpCodeTable<&'static Sexpr<'static>>,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    il_compiler.set_current_module(None);
+    compile_file_with_report(
+        path,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        &mut CompileReport::default(),
+    )
+}
 
+// Like compile_file, but records how long each pass took and how much it
+// produced. Files pulled in through require get their own entry.
+pub fn compile_file_with_report(
+    path: &Path,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+    report: &mut CompileReport,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut source = String::new();
     let mut file = match File::open(path) {
         Ok(f) => f,
         Err(e) => return Err(format!("failed to open {}: {e}", path.to_str().unwrap()).into()),
     };
 
+    let start = Instant::now();
     file.read_to_string(&mut source)?;
 
-    let context = Box::leak(Box::new(reader::Context::new(
+    compile_source(
         source.as_str(),
-        path.to_str().unwrap(),
-    )));
-
-    let reader = Reader::new(context);
-
-    for expr in reader {
-        let sexpr: &'static _ = Box::leak(Box::new(expr?));
-        let ast = ast_compiler.compile(sexpr)?;
-
-        if let Ast::Require(ast::Require { module, .. }) = ast {
-            match find_module(module.as_str()) {
-                Some(Ok(m)) => {
-                    compile_file(m.as_path(), il_compiler, ast_compiler, vm, opcode_table)?;
-                    continue;
-                }
-                Some(Err(e)) => return Err(e),
-                None => return Err(format!("failed to find module: {module}").into()),
+        path,
+        start.elapsed(),
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        report,
+    )
+}
+
+pub fn compile_str(
+    source: &str,
+    name: &str,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    compile_str_with_report(
+        source,
+        name,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        &mut CompileReport::default(),
+    )
+}
+
+// Like compile_file_with_report for source that isn't read from disk, name is
+// used in place of a path in diagnostics and the report.
+pub fn compile_str_with_report(
+    source: &str,
+    name: &str,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+    report: &mut CompileReport,
+) -> Result<(), Box<dyn std::error::Error>> {
+    compile_source(
+        source,
+        Path::new(name),
+        Duration::ZERO,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        report,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compile_source(
+    source: &str,
+    path: &Path,
+    read: Duration,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+    report: &mut CompileReport,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // a required file starts outside of any module, and whatever module it
+    // declares mustn't carry on into the file that required it
+    let module = il_compiler.current_module().map(str::to_string);
+    il_compiler.set_current_module(None);
+    ast_compiler.set_current_module(None);
+    let pragmas = il_compiler.replace_pragmas(Default::default());
+
+    let index = report.files.len();
+
+    report.files.push(FileReport {
+        path: path.to_path_buf(),
+        read,
+        ..Default::default()
+    });
+
+    let context = Rc::new(reader::Context::new(source, path.to_str().unwrap()));
+
+    let mut reader = Reader::new(&context);
+    let mut errors = CompileErrors(Vec::new());
+
+    loop {
+        let start = Instant::now();
+
+        let Some(expr) = reader.next() else {
+            break;
+        };
+
+        // there's no telling where the next form starts after a reader error
+        let sexpr = match expr {
+            Ok(sexpr) => sexpr,
+            Err(e) => {
+                errors.push(e.into());
+                break;
             }
+        };
+
+        report.files[index].read += start.elapsed();
+        report.files[index].forms += 1;
+
+        if let Err(e) = compile_form(
+            &sexpr,
+            index,
+            il_compiler,
+            ast_compiler,
+            vm,
+            opcode_table,
+            report,
+        ) {
+            errors.push(e);
         }
+    }
 
-        let il = il_compiler.compile(&ast, vm, ast_compiler)?;
-        bytecode::compile(&il, opcode_table)?;
+    il_compiler.replace_pragmas(pragmas);
+    il_compiler.set_current_module(module.as_deref());
+    ast_compiler.set_current_module(module.as_deref());
+
+    match errors.0.is_empty() {
+        true => Ok(()),
+        false => Err(errors.into()),
     }
+}
+
+// Compiles a single form without a file around it, for the repl.
+pub fn compile_sexpr(
+    sexpr: &Sexpr,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut report = CompileReport {
+        files: vec![FileReport::default()],
+    };
+
+    compile_form(
+        sexpr,
+        0,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        &mut report,
+    )
+}
+
+// The type of a single form, or None if it's unknown, for the repl's :type.
+// The form is compiled but not run.
+pub fn type_of_sexpr(
+    sexpr: &Sexpr,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let ast = ast_compiler.compile(sexpr).map_err(CompileErrors::from)?;
+    let il = il_compiler
+        .compile(&ast, vm, ast_compiler)
+        .map_err(CompileErrors::from)?;
+
+    Ok(il_compiler.type_of(&il)?)
+}
+
+fn compile_form(
+    sexpr: &Sexpr,
+    index: usize,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+    report: &mut CompileReport,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let ast = ast_compiler.compile(sexpr).map_err(CompileErrors::from)?;
+    report.files[index].ast += start.elapsed();
+
+    if let Ast::Require(require) = &ast {
+        let module = &require.module;
+
+        if il_compiler.mark_required(module.as_str()) {
+            let Some(path) = find_module(module.as_str(), il_compiler.search_path()) else {
+                return Err(format!("failed to find module: {module}").into());
+            };
+
+            compile_module(
+                module,
+                path.as_path(),
+                il_compiler,
+                ast_compiler,
+                vm,
+                opcode_table,
+                report,
+            )?;
+        }
+
+        il_compiler.import(require, ast_compiler)?;
+
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    let il = il_compiler
+        .compile(&ast, vm, ast_compiler)
+        .map_err(CompileErrors::from)?;
+    let elapsed = start.elapsed();
+    let stats = il_compiler.take_stats();
+
+    report.files[index].macro_expansion += stats.macro_expansion;
+    report.files[index].il += elapsed.saturating_sub(stats.macro_expansion);
+    report.files[index].il_nodes += stats.nodes;
+
+    il_compiler.check_types(&il)?;
+
+    let first_opcode = opcode_table.len();
+
+    let start = Instant::now();
+    bytecode::compile(&il, opcode_table)?;
+    report.files[index].bytecode += start.elapsed();
+
+    let opcodes = &opcode_table.opcodes()[first_opcode..];
+
+    let start = Instant::now();
+    bytecode::verify(&il, opcodes)?;
+    report.files[index].verify += start.elapsed();
+
+    report.files[index].opcodes += count_opcodes(opcodes);
 
     Ok(())
 }
 
-pub fn find_module(name: &str) -> Option<Result<PathBuf, Box<dyn std::error::Error>>> {
-    match env::var("CARPET_LISP_PATH") {
-        Ok(paths) => {
-            for path in paths.split(':') {
-                for entry in match fs::read_dir(path) {
-                    Ok(r) => r,
-                    Err(e) => {
-                        return Some(Err(format!("failed to read directory {path}: {e}").into()))
-                    }
-                } {
-                    let entry = match entry {
-                        Ok(entry) => entry,
-                        Err(e) => {
-                            return Some(Err(format!("failed to read dir entry: {e}").into()))
-                        }
-                    };
-
-                    dbg!(&entry.file_name());
-
-                    if entry.path().as_path().file_stem()?.to_str().unwrap() == name {
-                        return Some(Ok(entry.path()));
-                    }
-                }
+// Compiles a required module, or loads it from the cache when there's a cache
+// directory, set on the compiler or through CARPET_LISP_CACHE.
+fn compile_module(
+    module: &str,
+    path: &Path,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+    report: &mut CompileReport,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(dir) = il_compiler
+        .cache_dir()
+        .map(Path::to_path_buf)
+        .or_else(|| env::var_os("CARPET_LISP_CACHE").map(PathBuf::from))
+    else {
+        return compile_file_with_report(path, il_compiler, ast_compiler, vm, opcode_table, report);
+    };
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return Err(format!("failed to open {}: {e}", path.display()).into()),
+    };
+    let cache_path = cache::path(&dir, module, path);
+    let first_opcode = opcode_table.len();
+
+    if let Some(entry) = cache::read(&cache_path, &source, il_compiler.cache_key())
+        .filter(|entry| requires_unchanged(&entry.requires, il_compiler.search_path()))
+    {
+        for (required, _) in &entry.requires {
+            if il_compiler.mark_required(required) {
+                let Some(path) = find_module(required, il_compiler.search_path()) else {
+                    return Err(format!("failed to find module: {required}").into());
+                };
+
+                compile_module(
+                    required,
+                    path.as_path(),
+                    il_compiler,
+                    ast_compiler,
+                    vm,
+                    opcode_table,
+                    report,
+                )?;
             }
-            None
         }
-        Err(e) => Some(Err(format!("failed to read CARPET_LISP_PATH: {e}").into())),
+
+        il_compiler.load_interface(&entry.interface, ast_compiler);
+
+        for table in &entry.compile_time {
+            vm.eval(table)
+                .map_err(|(error, span)| span.render_diagnostic(&format!("error: {error}")))?;
+            il_compiler.keep_compile_time(table);
+        }
+
+        let start = opcode_table.len();
+        opcode_table.append(entry.opcodes);
+
+        if let Some(recording) = il_compiler.recording_mut() {
+            recording.nested.push(start..opcode_table.len());
+        }
+
+        return Ok(());
+    }
+
+    let outer = il_compiler.replace_recording(Some(il::Recording::default()));
+    let result = compile_source(
+        source.as_str(),
+        path,
+        Duration::ZERO,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        report,
+    );
+    let recording = il_compiler.replace_recording(outer).unwrap_or_default();
+
+    if let Some(recording) = il_compiler.recording_mut() {
+        recording.nested.push(first_opcode..opcode_table.len());
     }
+
+    result?;
+
+    let Some(interface) = il_compiler.interface(module, &recording, ast_compiler) else {
+        return Ok(());
+    };
+
+    let Some(requires) = recording
+        .requires
+        .iter()
+        .map(|required| {
+            let path = find_module(required, il_compiler.search_path())?;
+            let source = fs::read_to_string(path).ok()?;
+            Some((required.clone(), cache::hash(&source)))
+        })
+        .collect::<Option<Vec<_>>>()
+    else {
+        return Ok(());
+    };
+
+    // the code of the modules this one required isn't part of it
+    let mut opcodes = OpCodeTable::new();
+    let mut nested = recording.nested.iter().peekable();
+    let mut i = first_opcode;
+
+    while i < opcode_table.len() {
+        match nested.peek() {
+            Some(range) if range.start == i => {
+                i = range.end;
+                nested.next();
+            }
+            _ => {
+                opcodes.push(
+                    opcode_table.opcodes()[i].clone(),
+                    opcode_table.debug()[i].clone(),
+                );
+                i += 1;
+            }
+        }
+    }
+
+    let entry = cache::Entry {
+        requires,
+        interface,
+        compile_time: recording.compile_time,
+        opcodes,
+    };
+
+    // not being able to write the cache only costs compiling it again next time
+    let _ = cache::write(&cache_path, &source, il_compiler.cache_key(), &entry);
+
+    Ok(())
+}
+
+// Whether the modules a cached module required are the same as when it was
+// compiled, if one changed so might the macros and types it was compiled with.
+fn requires_unchanged(requires: &[(String, u64)], search_path: &[PathBuf]) -> bool {
+    requires.iter().all(|(module, hash)| {
+        find_module(module, search_path)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .is_some_and(|source| cache::hash(&source) == *hash)
+    })
+}
+
+fn count_opcodes<D>(opcodes: &[OpCode<D>]) -> usize {
+    opcodes
+        .iter()
+        .map(|opcode| match opcode {
+            OpCode::Lambda { body, .. } => 1 + count_opcodes(body.opcodes()),
+            _ => 1,
+        })
+        .sum()
+}
+
+// Looks for <name>.lisp in each directory of the search path, then in those
+// listed in CARPET_LISP_PATH.
+pub fn find_module(name: &str, search_path: &[PathBuf]) -> Option<PathBuf> {
+    let env_path = env::var_os("CARPET_LISP_PATH").unwrap_or_default();
+
+    search_path
+        .iter()
+        .cloned()
+        .chain(env::split_paths(&env_path))
+        .map(|dir| dir.join(format!("{name}.lisp")))
+        .find(|path| path.is_file())
 }
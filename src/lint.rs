@@ -0,0 +1,440 @@
+// What lisp lint checks. A file is compiled the way lisp symbols compiles it,
+// then its il is walked once it's all compiled, so that a check can see every
+// definition in the file and not just the ones before the form it's looking
+// at. Code a macro expanded to is checked too, but reported at the form that
+// invoked the macro, and the checks that would trip over what macros expand to,
+// like cond's (if true ...), leave it alone.
+
+use crate::symbols::compile_forms;
+use compiler::{
+    ast,
+    il::{self, DefinitionKind, Il, VarRef},
+};
+use reader::{Context, Sexpr, Span};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+use vm::{OpCodeTable, Vm};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rule {
+    // A function or variable the file defines and never refers to.
+    UnusedDefinition,
+    // A branch of an if whose predicate is a constant.
+    UnreachableBranch,
+    // A set! of a global the file doesn't define or declare, which is usually a
+    // typo that happens to name a builtin.
+    UndeclaredSet,
+    // A call with a number of arguments the function it calls doesn't take.
+    Arity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+// How severe each rule is, set from --rule rule=severity.
+#[derive(Clone, Debug)]
+pub struct Config {
+    severities: HashMap<Rule, Severity>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+    pub file: String,
+    pub span: Range<usize>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 4] = [
+        Rule::UnusedDefinition,
+        Rule::UnreachableBranch,
+        Rule::UndeclaredSet,
+        Rule::Arity,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedDefinition => "unused-definition",
+            Rule::UnreachableBranch => "unreachable-branch",
+            Rule::UndeclaredSet => "undeclared-set",
+            Rule::Arity => "arity",
+        }
+    }
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Off => "off",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            severities: HashMap::from([
+                (Rule::UnusedDefinition, Severity::Warning),
+                (Rule::UnreachableBranch, Severity::Warning),
+                (Rule::UndeclaredSet, Severity::Warning),
+                (Rule::Arity, Severity::Error),
+            ]),
+        }
+    }
+}
+
+impl Config {
+    pub fn set(&mut self, rule: Rule, severity: Severity) {
+        self.severities.insert(rule, severity);
+    }
+
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.severities[&rule]
+    }
+
+    // rule=severity, as --rule takes it.
+    pub fn parse_rule(&mut self, setting: &str) -> Result<(), String> {
+        let (rule, severity) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("expected rule=severity, found {setting}"))?;
+
+        let rule = Rule::ALL
+            .into_iter()
+            .find(|r| r.name() == rule)
+            .ok_or_else(|| format!("unknown lint rule: {rule}"))?;
+
+        let severity = [Severity::Off, Severity::Warning, Severity::Error]
+            .into_iter()
+            .find(|s| s.name() == severity)
+            .ok_or_else(|| {
+                format!("unknown severity: {severity}, expected off, warning or error")
+            })?;
+
+        self.set(rule, severity);
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}: {} [{}]",
+            self.file,
+            self.line,
+            self.column,
+            self.severity.name(),
+            self.message,
+            self.rule.name()
+        )
+    }
+}
+
+pub fn lint(
+    path: &Path,
+    config: &Config,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+) -> Result<Vec<Diagnostic>, Box<dyn std::error::Error>> {
+    let source =
+        fs::read_to_string(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+
+    lint_str(
+        &source,
+        path.to_str().unwrap(),
+        config,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+    )
+}
+
+pub fn lint_str(
+    source: &str,
+    name: &str,
+    config: &Config,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+) -> Result<Vec<Diagnostic>, Box<dyn std::error::Error>> {
+    let mut forms = Vec::new();
+    let mut definitions = Vec::new();
+    let mut rejected = Vec::new();
+
+    let file = compile_forms(
+        source,
+        name,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        |_, form, il, defined| {
+            definitions.extend(
+                defined
+                    .into_iter()
+                    .map(|definition| (form.clone(), definition)),
+            );
+            forms.push((form.clone(), il.clone()));
+        },
+        // the compiler already refuses a call with the wrong number of
+        // arguments or a set! of an unknown global when it can tell, so those
+        // are reported under their rules rather than failing the file
+        |form, errors| {
+            let (checked, errors): (Vec<_>, Vec<_>) = errors.into_iter().partition(|error| {
+                matches!(
+                    error,
+                    il::Error::Arity { .. } | il::Error::UndeclaredSet { .. }
+                )
+            });
+
+            rejected.extend(checked.into_iter().map(|error| (form.clone(), error)));
+            errors
+        },
+    )?;
+
+    let mut linter = Linter {
+        config,
+        file,
+        diagnostics: Vec::new(),
+    };
+
+    for (form, error) in &rejected {
+        match error {
+            il::Error::Arity { span, message, .. } => {
+                linter.report(Rule::Arity, span, form, message.clone())
+            }
+            il::Error::UndeclaredSet { span, name } => linter.report(
+                Rule::UndeclaredSet,
+                span,
+                form,
+                format!("set! of {name}, which this file doesn't define or declare"),
+            ),
+            _ => (),
+        }
+    }
+
+    let mut referenced = HashSet::new();
+    let mut declared = HashSet::new();
+
+    for (_, il) in &forms {
+        walk(il, &mut |il| {
+            if let Il::VarRef(var_ref @ (VarRef::Global { .. } | VarRef::Module { .. })) = il {
+                referenced.insert(key(var_ref));
+            }
+        });
+    }
+
+    for (form, definition) in &definitions {
+        let key = (definition.module.clone(), definition.name.clone());
+
+        if definition.kind != DefinitionKind::Constant {
+            declared.insert(key.clone());
+        }
+
+        let exported = definition.module.as_ref().is_some_and(|module| {
+            il_compiler
+                .exports()
+                .contains(&format!("{module}::{}", definition.name))
+        });
+
+        if matches!(
+            definition.kind,
+            DefinitionKind::Function | DefinitionKind::Variable
+        ) && !referenced.contains(&key)
+            && !exported
+            && !definition.name.starts_with('_')
+        {
+            let kind = match definition.kind {
+                DefinitionKind::Function => "function",
+                _ => "variable",
+            };
+
+            linter.report(
+                Rule::UnusedDefinition,
+                definition.source.source_span(),
+                form,
+                format!("{kind} {} is never used", definition.name),
+            );
+        }
+    }
+
+    for (form, il) in &forms {
+        walk(il, &mut |il| match il {
+            Il::If(r#if) => linter.check_if(r#if, form),
+            Il::Set(set) => linter.check_set(set, &declared, form),
+            Il::FnCall(fncall) => linter.check_call(fncall, il_compiler, form),
+            _ => (),
+        });
+    }
+
+    linter
+        .diagnostics
+        .sort_by_key(|diagnostic| diagnostic.span.start);
+
+    Ok(linter.diagnostics)
+}
+
+struct Linter<'a> {
+    config: &'a Config,
+    file: Rc<Context>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    // Reports at span when it's in the file, and at the form it came from when
+    // a macro expanded to it.
+    fn report(&mut self, rule: Rule, span: &Span, form: &Sexpr, message: String) {
+        let severity = self.config.severity(rule);
+
+        if severity == Severity::Off {
+            return;
+        }
+
+        let span = if self.is_in_file(span) {
+            span.range()
+        } else {
+            form.span()
+        };
+        let (line, column) = self.file.line_and_column(span.start);
+
+        self.diagnostics.push(Diagnostic {
+            rule,
+            severity,
+            message,
+            file: self.file.display().to_string(),
+            span,
+            line,
+            column,
+        });
+    }
+
+    fn is_in_file(&self, span: &Span) -> bool {
+        Rc::ptr_eq(span.context(), &self.file)
+    }
+
+    fn check_if(&mut self, r#if: &il::If, form: &Sexpr) {
+        if !self.is_in_file(r#if.source.source_span()) {
+            return;
+        }
+
+        let Il::Constant(constant) = &*r#if.predicate else {
+            return;
+        };
+
+        let (unreachable, branch) = match constant {
+            il::Constant::Bool { bool: false, .. } | il::Constant::Nil { .. } => {
+                (&r#if.then, "then")
+            }
+            _ => (&r#if.r#else, "else"),
+        };
+
+        self.report(
+            Rule::UnreachableBranch,
+            unreachable.source_ast().source_span(),
+            form,
+            format!("the {branch} branch never runs, the predicate is always the same"),
+        );
+    }
+
+    fn check_set(
+        &mut self,
+        set: &il::Set,
+        declared: &HashSet<(Option<String>, String)>,
+        form: &Sexpr,
+    ) {
+        let VarRef::Global { name, .. } = &set.target else {
+            return;
+        };
+
+        if !declared.contains(&(None, name.clone())) {
+            self.report(
+                Rule::UndeclaredSet,
+                set.source.source_span(),
+                form,
+                format!("set! of {name}, which this file doesn't define or declare"),
+            );
+        }
+    }
+
+    fn check_call(&mut self, fncall: &il::FnCall, il_compiler: &il::Compiler, form: &Sexpr) {
+        let Il::VarRef(var_ref @ (VarRef::Global { .. } | VarRef::Module { .. })) =
+            &*fncall.function
+        else {
+            return;
+        };
+
+        let (module, name) = key(var_ref);
+
+        let Some(arity) = il_compiler.signature_arity(module.as_deref(), &name) else {
+            return;
+        };
+
+        if !il::accepts(arity, fncall.args.len()) {
+            self.report(
+                Rule::Arity,
+                fncall.source.source_span(),
+                form,
+                format!(
+                    "{name} takes {} arguments, called with {}",
+                    il::describe_arity(arity),
+                    fncall.args.len()
+                ),
+            );
+        }
+    }
+}
+
+fn key(var_ref: &VarRef) -> (Option<String>, String) {
+    match var_ref {
+        VarRef::Module { name, module, .. } => (Some(module.clone()), name.clone()),
+        VarRef::Local { name, .. } | VarRef::UpValue { name, .. } | VarRef::Global { name, .. } => {
+            (None, name.clone())
+        }
+    }
+}
+
+fn walk(il: &Il, f: &mut impl FnMut(&Il)) {
+    f(il);
+
+    for child in il.children() {
+        walk(child, f);
+    }
+}
+
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    Value::from_iter(diagnostics.iter().map(|diagnostic| {
+        json!({
+            "rule": diagnostic.rule.name(),
+            "severity": diagnostic.severity.name(),
+            "message": diagnostic.message,
+            "file": diagnostic.file,
+            "span": {
+                "start": diagnostic.span.start,
+                "end": diagnostic.span.end,
+                "line": diagnostic.line,
+                "column": diagnostic.column,
+            },
+        })
+    }))
+    .to_string()
+}
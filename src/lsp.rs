@@ -0,0 +1,680 @@
+// What lisp-lsp serves: a language server speaking json-rpc over stdin and
+// stdout. A document is compiled from scratch with the standard library each
+// time it's opened or saved, and its errors and warnings are published as
+// diagnostics. Definitions are looked up in the document and in the modules it
+// requires, and completion offers everything defined at the top level along
+// with the parameters and let bindings around the cursor. What's defined is
+// worked out once for each version of a document, past any forms that don't
+// compile.
+
+use crate::symbols::{self, Symbol};
+use crate::{find_module, CompileErrors, Interpreter, Manifest};
+use compiler::il::{self, DefinitionKind};
+use compiler::{ast, bytecode};
+use reader::{Reader, Sexpr, Span};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// https://microsoft.github.io/language-server-protocol/specifications/specification-current/
+const METHOD_NOT_FOUND: i64 = -32601;
+const PARSE_ERROR: i64 = -32700;
+const SEVERITY_ERROR: i64 = 1;
+const SEVERITY_WARNING: i64 = 2;
+const COMPLETION_FUNCTION: i64 = 3;
+const COMPLETION_VARIABLE: i64 = 6;
+const COMPLETION_KEYWORD: i64 = 14;
+const SYNC_FULL: i64 = 1;
+
+#[derive(Default)]
+pub struct Server {
+    // Each open document by uri.
+    documents: HashMap<String, Document>,
+    // What each document defined the last time all of it compiled, standing
+    // in for the forms after one that an edit leaves unreadable.
+    definitions: HashMap<String, Vec<Symbol>>,
+    // Globals, built-ins and module exports of the standard library.
+    names: Option<Vec<String>>,
+    shutdown: bool,
+    exit: bool,
+}
+
+// The text of a document as of the last change, and what it and the modules
+// it requires define once something has asked. Any change replaces the lot.
+#[derive(Default)]
+struct Document {
+    text: String,
+    analysis: Option<Analysis>,
+}
+
+struct Analysis {
+    definitions: Vec<Symbol>,
+    modules: Vec<Symbol>,
+}
+
+// Handles messages until the client sends exit or closes the input, and says
+// whether it asked to shut down first, as it should have.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::default();
+
+    while let Some(message) = read_message(&mut input)? {
+        let replies = match serde_json::from_slice::<Value>(&message) {
+            Ok(message) => server.handle(&message),
+            Err(e) => vec![error_response(Value::Null, PARSE_ERROR, &e.to_string())],
+        };
+
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+
+        if server.exit {
+            break;
+        }
+    }
+
+    Ok(server.shutdown)
+}
+
+// The body of the next message, None at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length",
+        )
+    })?;
+    let mut body = vec![0; length];
+
+    input.read_exact(&mut body)?;
+
+    Ok(Some(body))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn response(id: Value, result: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "result": result})
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+impl Server {
+    // The messages to send back, a response for a request and any
+    // notifications it led to.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let id = message.get("id").cloned().unwrap_or(Value::Null);
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+
+        match message["method"].as_str().unwrap_or_default() {
+            "initialize" => vec![response(
+                id,
+                json!({
+                    "capabilities": {
+                        "textDocumentSync": {
+                            "openClose": true,
+                            "change": SYNC_FULL,
+                            "save": {"includeText": true},
+                        },
+                        "definitionProvider": true,
+                        "completionProvider": {},
+                    },
+                    "serverInfo": {"name": "lisp-lsp"},
+                }),
+            )],
+            "shutdown" => {
+                self.shutdown = true;
+                vec![response(id, Value::Null)]
+            }
+            "exit" => {
+                self.exit = true;
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.open(uri, text);
+                vec![self.publish_diagnostics(uri)]
+            }
+            // changes only update the text, compiling on every keystroke would
+            // run whatever the document does at compile time just as often
+            "textDocument/didChange" => {
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.open(uri, text);
+                }
+                Vec::new()
+            }
+            "textDocument/didSave" => {
+                if let Some(text) = params["text"].as_str() {
+                    self.open(uri, text);
+                }
+                vec![self.publish_diagnostics(uri)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.definitions.remove(uri);
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({"uri": uri, "diagnostics": []}),
+                )]
+            }
+            "textDocument/definition" => {
+                let definition = self.definition(uri, &params["position"]);
+                vec![response(id, definition.unwrap_or(Value::Null))]
+            }
+            "textDocument/completion" => {
+                let completion = self.completion(uri, &params["position"]);
+                vec![response(id, Value::Array(completion))]
+            }
+            method if message.get("id").is_some() => vec![error_response(
+                id,
+                METHOD_NOT_FOUND,
+                &format!("unsupported method: {method}"),
+            )],
+            _ => Vec::new(),
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: &str) {
+        self.documents.insert(
+            uri.to_string(),
+            Document {
+                text: text.to_string(),
+                analysis: None,
+            },
+        );
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> Value {
+        let text = self
+            .documents
+            .get(uri)
+            .map(|document| document.text.clone())
+            .unwrap_or_default();
+        let diagnostics = diagnostics(&uri_to_path(uri), &text);
+
+        self.analysis(uri);
+
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({"uri": uri, "diagnostics": diagnostics}),
+        )
+    }
+
+    // What the document and the modules it requires define, compiled the first
+    // time it's asked for since the document last changed.
+    fn analysis(&mut self, uri: &str) -> Option<&Analysis> {
+        let document = self.documents.get_mut(uri)?;
+
+        if document.analysis.is_none() {
+            let path = uri_to_path(uri);
+            let (mut defined, error) = definitions(&path, &document.text);
+
+            match error {
+                None => {
+                    self.definitions.insert(uri.to_string(), defined.clone());
+                }
+                // the forms past whatever stopped it are taken from the last
+                // version that compiled all the way through
+                Some(_) => {
+                    let known = defined
+                        .iter()
+                        .map(|symbol| symbol.name.clone())
+                        .collect::<HashSet<_>>();
+
+                    defined.extend(
+                        self.definitions
+                            .get(uri)
+                            .into_iter()
+                            .flatten()
+                            .filter(|symbol| !known.contains(&symbol.name))
+                            .cloned(),
+                    );
+                }
+            }
+
+            let mut files = Vec::new();
+            required_modules(&path, &document.text, &mut HashSet::new(), &mut files);
+
+            let modules = files
+                .iter()
+                .flat_map(|module| {
+                    fs::read_to_string(module)
+                        .map(|source| definitions(module, &source).0)
+                        .unwrap_or_default()
+                })
+                .collect();
+
+            document.analysis = Some(Analysis {
+                definitions: defined,
+                modules,
+            });
+        }
+
+        document.analysis.as_ref()
+    }
+
+    fn definition(&mut self, uri: &str, position: &Value) -> Option<Value> {
+        let text = self.documents.get(uri)?.text.clone();
+        let path = uri_to_path(uri);
+        let offset = offset(&text, position);
+        let word = word_at(&text, offset)?;
+        let analysis = self.analysis(uri)?;
+
+        for symbol in analysis.definitions.iter().chain(&analysis.modules) {
+            let qualified = symbol
+                .module
+                .as_ref()
+                .map(|module| format!("{module}::{}", symbol.name));
+
+            if symbol.name != word && qualified.as_deref() != Some(word) {
+                continue;
+            }
+
+            let source = match Path::new(&symbol.file) == path {
+                true => text.clone(),
+                false => fs::read_to_string(&symbol.file).ok()?,
+            };
+
+            return Some(json!({
+                "uri": path_to_uri(Path::new(&symbol.file)),
+                "range": range(&source, symbol.span.start, symbol.span.end),
+            }));
+        }
+
+        None
+    }
+
+    fn completion(&mut self, uri: &str, position: &Value) -> Vec<Value> {
+        let Some(document) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let text = document.text.clone();
+        let offset = offset(&text, position);
+        let prefix = &text[word_start(&text, offset)..offset];
+
+        let mut items = Vec::new();
+
+        for local in locals(&text, offset) {
+            items.push((local, COMPLETION_VARIABLE));
+        }
+
+        if let Some(analysis) = self.analysis(uri) {
+            for symbol in &analysis.definitions {
+                let kind = match symbol.kind {
+                    DefinitionKind::Function => COMPLETION_FUNCTION,
+                    DefinitionKind::Macro => COMPLETION_KEYWORD,
+                    _ => COMPLETION_VARIABLE,
+                };
+                items.push((symbol.name.clone(), kind));
+            }
+
+            for symbol in &analysis.modules {
+                if let Some(module) = &symbol.module {
+                    items.push((format!("{module}::{}", symbol.name), COMPLETION_FUNCTION));
+                }
+            }
+        }
+
+        let names = self.names.get_or_insert_with(|| {
+            Interpreter::new()
+                .map(|interpreter| interpreter.names())
+                .unwrap_or_default()
+        });
+
+        for name in names.iter() {
+            let kind = match ast::BUILT_INS.contains(&name.as_str()) {
+                true => COMPLETION_KEYWORD,
+                false => COMPLETION_FUNCTION,
+            };
+            items.push((name.clone(), kind));
+        }
+
+        let mut seen = HashSet::new();
+
+        items
+            .into_iter()
+            .filter(|(label, _)| label.starts_with(prefix) && seen.insert(label.clone()))
+            .map(|(label, kind)| json!({"label": label, "kind": kind}))
+            .collect()
+    }
+}
+
+// Errors and warnings from compiling a document, each put at the span it's
+// about.
+pub fn diagnostics(path: &Path, text: &str) -> Vec<Value> {
+    let search_path = path.parent().unwrap_or(Path::new("."));
+    let mut interpreter =
+        match Interpreter::with_manifest(&Manifest::standard().search_path(search_path)) {
+            Ok(interpreter) => interpreter,
+            Err(e) => return vec![diagnostic(text, 0..0, SEVERITY_ERROR, &e.to_string())],
+        };
+    let name = path.to_string_lossy();
+    let mut diagnostics = Vec::new();
+
+    if let Err(e) = interpreter.compile_str(text, &name) {
+        let errors = match e.downcast::<CompileErrors>() {
+            Ok(errors) => errors.0,
+            Err(e) => vec![e],
+        };
+
+        for error in errors {
+            diagnostics.push(error_diagnostic(&name, text, error.as_ref()));
+        }
+    }
+
+    for warning in interpreter.take_warnings() {
+        if warning.file() == name {
+            let mut diagnostic = diagnostic(
+                text,
+                warning.span.range(),
+                SEVERITY_WARNING,
+                &warning.message,
+            );
+            diagnostic["code"] = json!(warning.kind.name());
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    diagnostics
+}
+
+// An error is put at the span it's about, with the first line of it, its
+// message, as the diagnostic's. One without a span, or in another file such as
+// a module the document requires, is put at the start of the document in full.
+fn error_diagnostic(name: &str, text: &str, error: &(dyn std::error::Error + 'static)) -> Value {
+    let rendered = error.to_string();
+
+    match error_span(error) {
+        Some(span) if span.context().display() == name => {
+            let message = rendered.lines().next().unwrap_or_default();
+            let message = message.strip_prefix("error: ").unwrap_or(message);
+
+            diagnostic(text, span.range(), SEVERITY_ERROR, message)
+        }
+        _ => diagnostic(text, 0..0, SEVERITY_ERROR, &rendered),
+    }
+}
+
+// The span of a compile error, for those that have one.
+fn error_span<'a>(error: &'a (dyn std::error::Error + 'static)) -> Option<&'a Span> {
+    if let Some(error) = error.downcast_ref::<il::Error>() {
+        error.span()
+    } else if let Some(error) = error.downcast_ref::<ast::Error>() {
+        Some(error.span())
+    } else if let Some(error) = error.downcast_ref::<bytecode::Error>() {
+        Some(error.span())
+    } else {
+        error
+            .downcast_ref::<reader::Error>()
+            .and_then(reader::Error::span)
+    }
+}
+
+fn diagnostic(text: &str, span: std::ops::Range<usize>, severity: i64, message: &str) -> Value {
+    json!({
+        "range": range(text, span.start, span.end),
+        "severity": severity,
+        "source": "lisp",
+        "message": message,
+    })
+}
+
+// What a document defines, compiled on top of the standard library, and what
+// stopped it compiling before the end if anything did.
+fn definitions(path: &Path, text: &str) -> (Vec<Symbol>, Option<Box<dyn std::error::Error>>) {
+    let search_path = path.parent().unwrap_or(Path::new("."));
+    let mut interpreter =
+        match Interpreter::with_manifest(&Manifest::standard().search_path(search_path)) {
+            Ok(interpreter) => interpreter,
+            Err(e) => return (Vec::new(), Some(e)),
+        };
+
+    symbols::collect_recovering(
+        text,
+        &path.to_string_lossy(),
+        &mut interpreter.il_compiler,
+        &mut interpreter.ast_compiler,
+        &mut interpreter.vm,
+        &mut interpreter.opcode_table,
+    )
+}
+
+// The files of the modules a document requires, and those they require in
+// turn.
+fn required_modules(
+    path: &Path,
+    text: &str,
+    seen: &mut HashSet<String>,
+    modules: &mut Vec<PathBuf>,
+) {
+    let search_path = [path.parent().unwrap_or(Path::new(".")).to_path_buf()];
+    let context = Rc::new(reader::Context::new(text, &path.to_string_lossy()));
+
+    for sexpr in Reader::new(&context).map_while(Result::ok) {
+        let Some([require, module, ..]) = sexpr.as_list() else {
+            continue;
+        };
+
+        let (Some("require"), Some(module)) = (require.as_symbol(), module.as_symbol()) else {
+            continue;
+        };
+
+        if !seen.insert(module.to_string()) {
+            continue;
+        }
+
+        if let Some(file) = find_module(module, &search_path) {
+            if let Ok(source) = fs::read_to_string(&file) {
+                required_modules(&file, &source, seen, modules);
+            }
+            modules.push(file);
+        }
+    }
+}
+
+// The parameters and let bindings of the forms around offset, innermost last.
+fn locals(text: &str, offset: usize) -> Vec<String> {
+    let context = Rc::new(reader::Context::new(text, ""));
+    let mut locals = Vec::new();
+
+    for sexpr in Reader::new(&context).map_while(Result::ok) {
+        if sexpr.span().contains(&offset) {
+            collect_locals(&sexpr, offset, &mut locals);
+        }
+    }
+
+    locals
+}
+
+fn collect_locals(sexpr: &Sexpr, offset: usize, locals: &mut Vec<String>) {
+    let list = match sexpr {
+        Sexpr::List { list, .. } | Sexpr::Vector { list, .. } | Sexpr::Map { list, .. } => {
+            list.as_slice()
+        }
+        _ => return,
+    };
+
+    let names = |sexpr: &Sexpr| {
+        sexpr
+            .as_list()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|binding| match binding.as_list() {
+                Some([name, ..]) => name.as_symbol(),
+                _ => binding.as_symbol(),
+            })
+            .filter(|name| !name.starts_with('&'))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    match list {
+        [head, parameters, ..] if head.as_symbol() == Some("lambda") => {
+            locals.extend(names(parameters))
+        }
+        [head, bindings, ..] if matches!(head.as_symbol(), Some("let" | "let*" | "letrec")) => {
+            locals.extend(names(bindings))
+        }
+        [head, name, bindings, ..] if head.as_symbol() == Some("named-let") => {
+            locals.extend(name.as_symbol().map(str::to_string));
+            locals.extend(names(bindings));
+        }
+        _ => (),
+    }
+
+    for child in list {
+        if child.span().contains(&offset) {
+            collect_locals(child, offset, locals);
+        }
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '(' | ')' | '\'' | '`' | ',' | '"' | ';')
+}
+
+fn word_start(text: &str, offset: usize) -> usize {
+    text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_symbol_char(*c))
+        .last()
+        .map_or(offset, |(i, _)| i)
+}
+
+// The symbol the cursor is in or just after.
+fn word_at(text: &str, offset: usize) -> Option<&str> {
+    let start = word_start(text, offset);
+    let end = text[offset..]
+        .char_indices()
+        .find(|(_, c)| !is_symbol_char(*c))
+        .map_or(text.len(), |(i, _)| offset + i);
+
+    (start < end).then(|| &text[start..end])
+}
+
+// Positions count lines from zero and characters in utf-16 code units.
+fn position(text: &str, offset: usize) -> Value {
+    let offset = floor_char_boundary(text, offset);
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+
+    json!({
+        "line": text[..offset].matches('\n').count(),
+        "character": text[line_start..offset].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, start: usize, end: usize) -> Value {
+    json!({"start": position(text, start), "end": position(text, end)})
+}
+
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or_default() as usize;
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+
+    let line_start = text
+        .split_inclusive('\n')
+        .take(line)
+        .map(str::len)
+        .sum::<usize>();
+    let mut units = 0;
+
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+
+    text.len()
+}
+
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    offset
+}
+
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut bytes = Vec::new();
+    let mut chars = path.bytes();
+
+    while let Some(byte) = chars.next() {
+        let escaped = match byte {
+            b'%' => {
+                let hex = [chars.next(), chars.next()];
+                hex.iter()
+                    .flatten()
+                    .map(|digit| (*digit as char).to_digit(16))
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|digits| digits.len() == 2)
+                    .map(|digits| (digits[0] * 16 + digits[1]) as u8)
+            }
+            _ => None,
+        };
+
+        bytes.push(escaped.unwrap_or(byte));
+    }
+
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    uri
+}
@@ -0,0 +1,240 @@
+// Natives written as plain rust closures, for Interpreter::register. The
+// arguments a closure takes and the value it returns say how to convert them
+// from and to objects, so the arity and type checks natives otherwise do by
+// hand with check_arity! and check_type! come for free, and so does the decl
+// that tells the compiler about it. The conversions are the vm's, from
+// vm::convert; what these traits add is the type each is declared with, and
+// Option, which the vm can't convert to.
+
+use reader::Span;
+use std::collections::HashMap;
+use std::hash::Hash;
+use vm::object::HashMapKey;
+use vm::{Error, Local, Object};
+
+// A rust value that can be made from an object passed to a native.
+pub trait FromObject: Sized {
+    // The type of the parameter in the native's decl, None if it takes any.
+    fn decl_type() -> Option<String> {
+        None
+    }
+
+    fn from_object(object: Object<Span>) -> Result<Self, Error>;
+}
+
+// A rust value that a native can return.
+pub trait IntoObject {
+    // The return type in the native's decl, None if it could be anything.
+    fn decl_type() -> Option<String> {
+        None
+    }
+
+    fn into_object(self) -> Object<Span>;
+}
+
+// What a native's closure returns, a value or an error to raise with it.
+pub trait IntoResult {
+    fn decl_type() -> Option<String>;
+
+    fn into_result(self) -> Result<Object<Span>, Error>;
+}
+
+// A closure taking Args that can be registered as a native.
+pub trait NativeFn<Args>: 'static {
+    fn parameter_types() -> Vec<Option<String>>;
+
+    fn return_type() -> Option<String>;
+
+    fn call(&self, name: &str, args: &mut [Local<Span>]) -> Result<Object<Span>, Error>;
+}
+
+impl FromObject for Object<Span> {
+    fn from_object(object: Object<Span>) -> Result<Self, Error> {
+        Ok(object)
+    }
+}
+
+// The scalars convert the way the vm's TryFrom and From impls do.
+macro_rules! scalar {
+    ($rust:ty, $type:literal) => {
+        impl FromObject for $rust {
+            fn decl_type() -> Option<String> {
+                Some($type.to_string())
+            }
+
+            fn from_object(object: Object<Span>) -> Result<Self, Error> {
+                Self::try_from(object)
+            }
+        }
+
+        impl IntoObject for $rust {
+            fn decl_type() -> Option<String> {
+                Some($type.to_string())
+            }
+
+            fn into_object(self) -> Object<Span> {
+                self.into()
+            }
+        }
+    };
+}
+
+scalar!(i64, "int");
+scalar!(bool, "bool");
+scalar!(char, "char");
+scalar!(String, "string");
+
+// Lists, maps and options are converted an element at a time, so that they can
+// hold anything a native can take, options included.
+impl<T: FromObject> FromObject for Vec<T> {
+    fn decl_type() -> Option<String> {
+        Some("(union cons nil)".to_string())
+    }
+
+    fn from_object(object: Object<Span>) -> Result<Self, Error> {
+        Vec::<Object<Span>>::try_from(object)?
+            .into_iter()
+            .map(T::from_object)
+            .collect()
+    }
+}
+
+impl<K: FromObject + Eq + Hash, V: FromObject> FromObject for HashMap<K, V> {
+    fn from_object(object: Object<Span>) -> Result<Self, Error> {
+        HashMap::<HashMapKey, Object<Span>>::try_from(object)?
+            .into_iter()
+            .map(|(key, value)| Ok((K::from_object(Object::from(&key))?, V::from_object(value)?)))
+            .collect()
+    }
+}
+
+// nil or a T.
+impl<T: FromObject> FromObject for Option<T> {
+    fn decl_type() -> Option<String> {
+        T::decl_type().map(|t| format!("(union {t} nil)"))
+    }
+
+    fn from_object(object: Object<Span>) -> Result<Self, Error> {
+        object.into_option().map(T::from_object).transpose()
+    }
+}
+
+impl IntoObject for Object<Span> {
+    fn into_object(self) -> Object<Span> {
+        self
+    }
+}
+
+impl IntoObject for () {
+    fn decl_type() -> Option<String> {
+        Some("nil".to_string())
+    }
+
+    fn into_object(self) -> Object<Span> {
+        Object::Nil
+    }
+}
+
+impl IntoObject for &str {
+    fn decl_type() -> Option<String> {
+        Some("string".to_string())
+    }
+
+    fn into_object(self) -> Object<Span> {
+        self.into()
+    }
+}
+
+impl<T: IntoObject> IntoObject for Vec<T> {
+    fn decl_type() -> Option<String> {
+        Some("(union cons nil)".to_string())
+    }
+
+    fn into_object(self) -> Object<Span> {
+        self.into_iter().map(T::into_object).collect()
+    }
+}
+
+impl<K: Into<HashMapKey>, V: IntoObject> IntoObject for HashMap<K, V> {
+    fn into_object(self) -> Object<Span> {
+        self.into_iter()
+            .map(|(key, value)| (key.into(), value.into_object()))
+            .collect::<HashMap<HashMapKey, _>>()
+            .into()
+    }
+}
+
+impl<T: IntoObject> IntoObject for Option<T> {
+    fn decl_type() -> Option<String> {
+        T::decl_type().map(|t| format!("(union {t} nil)"))
+    }
+
+    fn into_object(self) -> Object<Span> {
+        self.map_or(Object::Nil, T::into_object)
+    }
+}
+
+impl<T: IntoObject> IntoResult for T {
+    fn decl_type() -> Option<String> {
+        T::decl_type()
+    }
+
+    fn into_result(self) -> Result<Object<Span>, Error> {
+        Ok(self.into_object())
+    }
+}
+
+impl<T: IntoObject> IntoResult for Result<T, Error> {
+    fn decl_type() -> Option<String> {
+        T::decl_type()
+    }
+
+    fn into_result(self) -> Result<Object<Span>, Error> {
+        self.map(IntoObject::into_object)
+    }
+}
+
+macro_rules! native_fn {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> NativeFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoResult,
+            $($arg: FromObject),*
+        {
+            fn parameter_types() -> Vec<Option<String>> {
+                vec![$($arg::decl_type()),*]
+            }
+
+            fn return_type() -> Option<String> {
+                R::decl_type()
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(&self, name: &str, args: &mut [Local<Span>]) -> Result<Object<Span>, Error> {
+                let arity = Self::parameter_types().len();
+
+                if args.len() != arity {
+                    return Err(Error::Parameters(format!(
+                        "{name} expects {arity} parameters, received {}",
+                        args.len()
+                    )));
+                }
+
+                let mut args = args.iter().cloned().map(Local::into_object);
+
+                $(let $arg = $arg::from_object(args.next().unwrap())?;)*
+
+                self($($arg),*).into_result()
+            }
+        }
+    };
+}
+
+native_fn!();
+native_fn!(A);
+native_fn!(A, B);
+native_fn!(A, B, C);
+native_fn!(A, B, C, D);
+native_fn!(A, B, C, D, E);
+native_fn!(A, B, C, D, E, G);
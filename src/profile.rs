@@ -0,0 +1,60 @@
+// What lisp profile writes. The vm counts the opcodes run under each call
+// stack, and a stack is written as the functions called to get there, each one
+// named by the head of the call and the file and line it was called from. That
+// is the folded format flamegraph tools read, one stack per line with the
+// count after it, and lisp profile can render it to an svg itself.
+
+use crate::disasm::location;
+use reader::Span;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub fn folded(profile: &[(Vec<Span>, u64)]) -> String {
+    // different calls can look the same once named, like two calls to the same
+    // function on one line
+    let mut stacks = BTreeMap::new();
+
+    for (call_sites, count) in profile {
+        let stack = std::iter::once("main".to_string())
+            .chain(call_sites.iter().map(frame))
+            .collect::<Vec<_>>()
+            .join(";");
+
+        *stacks.entry(stack).or_insert(0) += count;
+    }
+
+    let mut out = String::new();
+
+    for (stack, count) in stacks {
+        writeln!(out, "{stack} {count}").unwrap();
+    }
+
+    out
+}
+
+pub fn flamegraph(folded: &str, title: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut options = inferno::flamegraph::Options::default();
+    options.title = title.to_string();
+    options.count_name = "opcodes".to_string();
+
+    let mut svg = Vec::new();
+    inferno::flamegraph::from_lines(&mut options, folded.lines(), &mut svg)?;
+
+    Ok(svg)
+}
+
+// A call is named after what it calls when that's a name, as in (fib n), and
+// is just a call otherwise, like ((lambda (x) x) 1).
+fn frame(call_site: &Span) -> String {
+    let name = call_site
+        .text()
+        .strip_prefix('(')
+        .and_then(|call| {
+            call.split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .next()
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or("call");
+
+    format!("{name} ({})", location(call_site))
+}
@@ -0,0 +1,522 @@
+use crate::{compile_sexpr, disasm, type_of_sexpr, Interpreter, Manifest};
+use reader::{Reader, Sexpr, Span};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Editor, Helper,
+};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+use vm::{Object, OpCodeTable};
+
+// What the user can do about a form that failed. Retry and use-value only make
+// sense once a form has compiled, so compile errors only offer the last two,
+// and use-value is only offered when the operation that failed can be carried
+// on from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Restart {
+    Retry,
+    UseValue,
+    SkipForm,
+    Abort,
+}
+
+impl Restart {
+    pub fn name(self) -> &'static str {
+        match self {
+            Restart::Retry => "retry",
+            Restart::UseValue => "use-value",
+            Restart::SkipForm => "skip-form",
+            Restart::Abort => "abort",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Restart::Retry => "evaluate the form again",
+            Restart::UseValue => "read a form and use its value for the failed call",
+            Restart::SkipForm => "skip the form and carry on with the rest of the line",
+            Restart::Abort => "return to the top level",
+        }
+    }
+}
+
+const RUNTIME_RESTARTS: &[Restart] = &[
+    Restart::Retry,
+    Restart::UseValue,
+    Restart::SkipForm,
+    Restart::Abort,
+];
+
+const UNRESUMABLE_RESTARTS: &[Restart] = &[Restart::Retry, Restart::SkipForm, Restart::Abort];
+
+const COMPILE_RESTARTS: &[Restart] = &[Restart::SkipForm, Restart::Abort];
+
+// Lines starting with one of these are handled by the repl rather than being
+// evaluated, so :time (fib 20) times (fib 20). Anything else starting with a
+// colon is read as lisp as usual.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Disasm,
+    Time,
+    Type,
+    Load,
+    Reset,
+}
+
+impl Command {
+    const ALL: &[Command] = &[
+        Command::Disasm,
+        Command::Time,
+        Command::Type,
+        Command::Load,
+        Command::Reset,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Disasm => ":disasm",
+            Command::Time => ":time",
+            Command::Type => ":type",
+            Command::Load => ":load",
+            Command::Reset => ":reset",
+        }
+    }
+
+    // The command a line starts with, and the rest of the line.
+    fn parse(line: &str) -> Option<(Command, &str)> {
+        let line = line.trim_start();
+        let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+        Command::ALL
+            .iter()
+            .copied()
+            .find(|command| command.name() == name)
+            .map(|command| (command, rest))
+    }
+}
+
+// Where the repl gets its lines from. Anything BufRead works, and LineEditor
+// adds editing, history and completion when reading from a terminal.
+pub trait Input {
+    fn read_line(&mut self, prompt: &str, output: &mut dyn Write) -> io::Result<Option<String>>;
+
+    // The names to complete from, updated before each top level prompt.
+    fn set_names(&mut self, _names: Vec<String>) {}
+}
+
+impl<R: BufRead> Input for R {
+    fn read_line(&mut self, prompt: &str, output: &mut dyn Write) -> io::Result<Option<String>> {
+        write!(output, "{prompt}")?;
+        output.flush()?;
+
+        let mut line = String::new();
+
+        Ok(match BufRead::read_line(self, &mut line)? {
+            0 => None,
+            _ => Some(line),
+        })
+    }
+}
+
+pub struct LineEditor(Editor<Names, DefaultHistory>);
+
+impl LineEditor {
+    pub fn new() -> io::Result<Self> {
+        let mut editor = Editor::new().map_err(io::Error::other)?;
+        editor.set_helper(Some(Names(Vec::new())));
+        Ok(Self(editor))
+    }
+}
+
+impl Input for LineEditor {
+    fn read_line(&mut self, prompt: &str, _: &mut dyn Write) -> io::Result<Option<String>> {
+        match self.0.readline(prompt) {
+            Ok(line) => {
+                self.0
+                    .add_history_entry(line.as_str())
+                    .map_err(io::Error::other)?;
+                Ok(Some(line + "\n"))
+            }
+            // ctrl-c throws the line away rather than leaving the repl
+            Err(ReadlineError::Interrupted) => Ok(Some("\n".to_string())),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+
+    fn set_names(&mut self, names: Vec<String>) {
+        if let Some(helper) = self.0.helper_mut() {
+            helper.0 = names;
+        }
+    }
+}
+
+pub struct Names(Vec<String>);
+
+impl Completer for Names {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(&self.0, line, pos))
+    }
+}
+
+impl Hinter for Names {
+    type Hint = String;
+}
+
+impl Highlighter for Names {}
+
+impl Validator for Names {}
+
+impl Helper for Names {}
+
+// The start of the symbol under the cursor and the names it could be the
+// start of, in the order they're given.
+pub fn complete(names: &[String], line: &str, pos: usize) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .rfind(|c: char| c.is_whitespace() || "()[]{}'`,\"".contains(c))
+        .map_or(0, |i| i + 1);
+    let prefix = &line[start..pos];
+
+    let candidates = names
+        .iter()
+        .filter(|name| name.starts_with(prefix))
+        .cloned()
+        .collect();
+
+    (start, candidates)
+}
+
+pub struct Repl<'a, I, W> {
+    interpreter: &'a mut Interpreter,
+    manifest: Manifest,
+    input: I,
+    output: W,
+}
+
+impl<'a, I: Input, W: Write> Repl<'a, I, W> {
+    pub fn new(interpreter: &'a mut Interpreter, input: I, output: W) -> Self {
+        Self {
+            interpreter,
+            manifest: Manifest::standard(),
+            input,
+            output,
+        }
+    }
+
+    // What :reset creates the new interpreter from, the standard manifest if
+    // this isn't called.
+    pub fn manifest(mut self, manifest: Manifest) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    // Reads a line at a time until the input runs out, printing the value of
+    // every form in it.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.input.set_names(self.interpreter.names());
+
+            let Some(line) = self.prompt("> ")? else {
+                break;
+            };
+
+            let input = self.continue_input(line)?;
+
+            match Command::parse(&input) {
+                Some((command, rest)) => self.run_command(command, rest)?,
+                None => self.eval_line(input)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_command(&mut self, command: Command, rest: &str) -> io::Result<()> {
+        match command {
+            Command::Disasm => self.for_each_form(rest, Self::disasm),
+            Command::Time => self.for_each_form(rest, Self::time),
+            Command::Type => self.for_each_form(rest, Self::type_of),
+            Command::Load => self.load(Path::new(rest.trim())),
+            Command::Reset => self.reset(),
+        }
+    }
+
+    fn for_each_form(
+        &mut self,
+        input: &str,
+        mut f: impl FnMut(&mut Self, &Sexpr) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let context = Rc::new(reader::Context::new(input, "repl"));
+
+        for sexpr in Reader::new(&context) {
+            match sexpr {
+                Ok(sexpr) => f(self, &sexpr)?,
+                Err(e) => {
+                    writeln!(self.output, "{e}")?;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Compiles without running, so anything the form defines is known to the
+    // compiler but not yet to the vm.
+    fn disasm(&mut self, sexpr: &Sexpr) -> io::Result<()> {
+        let mut opcode_table = OpCodeTable::new();
+
+        match compile_sexpr(
+            sexpr,
+            &mut self.interpreter.il_compiler,
+            &mut self.interpreter.ast_compiler,
+            &mut self.interpreter.vm,
+            &mut opcode_table,
+        ) {
+            Ok(()) => write!(self.output, "{}", disasm::listing(&opcode_table)),
+            Err(e) => writeln!(self.output, "{e}"),
+        }
+    }
+
+    // Compiling is timed along with running, but a form that fails isn't.
+    fn time(&mut self, sexpr: &Sexpr) -> io::Result<()> {
+        let start = Instant::now();
+
+        if self.eval_form(sexpr)?.is_none() {
+            writeln!(self.output, "time: {:?}", start.elapsed())?;
+        }
+
+        Ok(())
+    }
+
+    fn type_of(&mut self, sexpr: &Sexpr) -> io::Result<()> {
+        match type_of_sexpr(
+            sexpr,
+            &mut self.interpreter.il_compiler,
+            &mut self.interpreter.ast_compiler,
+            &mut self.interpreter.vm,
+        ) {
+            Ok(r#type) => writeln!(self.output, "{}", r#type.as_deref().unwrap_or("unknown")),
+            Err(e) => writeln!(self.output, "{e}"),
+        }
+    }
+
+    fn load(&mut self, path: &Path) -> io::Result<()> {
+        let depth = self.interpreter.vm.depth();
+
+        self.interpreter.opcode_table = OpCodeTable::new();
+
+        let result = self
+            .interpreter
+            .compile_file(path)
+            .and_then(|()| self.interpreter.eval());
+
+        self.report_warnings()?;
+
+        if let Err(e) = result {
+            self.interpreter.vm.unwind(depth);
+            writeln!(self.output, "{e}")?;
+        }
+
+        Ok(())
+    }
+
+    // Starts over with a new interpreter, keeping the old one if that fails.
+    fn reset(&mut self) -> io::Result<()> {
+        match Interpreter::with_manifest(&self.manifest) {
+            Ok(interpreter) => *self.interpreter = interpreter,
+            Err(e) => writeln!(self.output, "{e}")?,
+        }
+
+        Ok(())
+    }
+
+    // Keeps reading lines onto the input while it ends in the middle of a
+    // form, so a defun can be typed over several lines. Running out of input
+    // leaves it for eval_line to report.
+    fn continue_input(&mut self, mut input: String) -> io::Result<String> {
+        while incomplete(&input) {
+            match self.prompt(".. ")? {
+                Some(line) => input.push_str(&line),
+                None => break,
+            }
+        }
+
+        Ok(input)
+    }
+
+    fn eval_line(&mut self, line: String) -> io::Result<()> {
+        let context = Rc::new(reader::Context::new(line.as_str(), "repl"));
+
+        for sexpr in Reader::new(&context) {
+            let sexpr = match sexpr {
+                Ok(sexpr) => sexpr,
+                Err(e) => {
+                    writeln!(self.output, "{e}")?;
+                    return Ok(());
+                }
+            };
+
+            match self.eval_form(&sexpr)? {
+                Some(Restart::Abort) => return Ok(()),
+                _ => continue,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the restart that ended the form, if it failed.
+    fn eval_form(&mut self, sexpr: &Sexpr) -> io::Result<Option<Restart>> {
+        let mut opcode_table = OpCodeTable::new();
+
+        if let Err(e) = compile_sexpr(
+            sexpr,
+            &mut self.interpreter.il_compiler,
+            &mut self.interpreter.ast_compiler,
+            &mut self.interpreter.vm,
+            &mut opcode_table,
+        ) {
+            writeln!(self.output, "{e}")?;
+            return self.choose(COMPILE_RESTARTS).map(Some);
+        }
+
+        self.report_warnings()?;
+
+        let depth = self.interpreter.vm.depth();
+
+        loop {
+            match self.interpreter.vm.eval(&opcode_table) {
+                Ok(()) => {
+                    self.print_result(depth)?;
+                    return Ok(None);
+                }
+                Err((e, at)) => {
+                    writeln!(
+                        self.output,
+                        "{}",
+                        at.render_diagnostic(&format!("error: {e}"))
+                    )?;
+
+                    // the failed form stays suspended under anything evaluated
+                    // for use-value
+                    let Some(suspended) = self.interpreter.vm.suspend() else {
+                        self.interpreter.vm.unwind(depth);
+
+                        match self.choose(UNRESUMABLE_RESTARTS)? {
+                            Restart::Retry => continue,
+                            restart => return Ok(Some(restart)),
+                        }
+                    };
+
+                    match self.choose(RUNTIME_RESTARTS)? {
+                        Restart::UseValue => match self.read_value()? {
+                            Some(value) => self.interpreter.vm.resume(suspended, value),
+                            None => {
+                                self.interpreter.vm.unwind(depth);
+                                return Ok(Some(Restart::Abort));
+                            }
+                        },
+                        restart => {
+                            self.interpreter.vm.unwind(depth);
+
+                            if restart != Restart::Retry {
+                                return Ok(Some(restart));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Asks for a form for use-value until one evaluates, None if the input
+    // runs out first.
+    fn read_value(&mut self) -> io::Result<Option<Object<Span>>> {
+        loop {
+            let Some(line) = self.prompt("value> ")? else {
+                return Ok(None);
+            };
+
+            let input = self.continue_input(line)?;
+
+            let result = self.interpreter.eval_str(&input);
+
+            self.report_warnings()?;
+
+            match result {
+                Ok(value) => return Ok(Some(value)),
+                Err(e) => writeln!(self.output, "{e}")?,
+            }
+        }
+    }
+
+    fn print_result(&mut self, depth: usize) -> io::Result<()> {
+        if self.interpreter.vm.depth() > depth {
+            let value: Object<_> = self.interpreter.vm.pop().unwrap().into_object();
+            writeln!(self.output, "{value}")?;
+        }
+
+        Ok(())
+    }
+
+    fn report_warnings(&mut self) -> io::Result<()> {
+        for warning in self.interpreter.take_warnings() {
+            writeln!(self.output, "{warning}")?;
+        }
+
+        Ok(())
+    }
+
+    // Lists the restarts and asks for one by number or name until it gets an
+    // answer. Running out of input aborts.
+    fn choose(&mut self, restarts: &[Restart]) -> io::Result<Restart> {
+        writeln!(self.output, "restarts:")?;
+
+        for (i, restart) in restarts.iter().enumerate() {
+            writeln!(
+                self.output,
+                "  {i}: [{}] {}",
+                restart.name(),
+                restart.description()
+            )?;
+        }
+
+        loop {
+            let Some(line) = self.prompt("restart> ")? else {
+                return Ok(Restart::Abort);
+            };
+
+            let answer = line.trim();
+
+            let restart = match answer.parse::<usize>() {
+                Ok(i) => restarts.get(i).copied(),
+                Err(_) => restarts.iter().copied().find(|r| r.name() == answer),
+            };
+
+            match restart {
+                Some(restart) => return Ok(restart),
+                None => writeln!(self.output, "no such restart: {answer}")?,
+            }
+        }
+    }
+
+    fn prompt(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        self.input.read_line(prompt, &mut self.output)
+    }
+}
+
+fn incomplete(input: &str) -> bool {
+    let context = Rc::new(reader::Context::new(input, "repl"));
+
+    Reader::new(&context).any(|sexpr| matches!(sexpr, Err(reader::Error::UnexpectedEof(_))))
+}
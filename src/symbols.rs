@@ -0,0 +1,380 @@
+use crate::{compile_file, find_module, CompileErrors};
+use compiler::{
+    ast::{self, Ast},
+    il::{self, Definition, DefinitionKind, Il},
+};
+use reader::{Context, Reader, Sexpr, Span};
+use std::fmt::Write;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+use vm::{OpCodeTable, Vm};
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    pub name: String,
+    pub module: Option<String>,
+    pub kind: DefinitionKind,
+    pub arity: Option<(usize, usize, bool)>,
+    pub parameters: Vec<(Option<String>, Option<String>)>,
+    pub r#type: Option<String>,
+    pub return_type: Option<String>,
+    pub doc: Option<String>,
+    pub file: String,
+    pub span: Range<usize>,
+    pub line: usize,
+    pub column: usize,
+}
+
+// Compiles a file and returns everything it defines after macro expansion.
+// Definitions produced by a macro are located at the form that invoked it.
+pub fn collect(
+    path: &Path,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+) -> Result<Vec<Symbol>, Box<dyn std::error::Error>> {
+    let source =
+        fs::read_to_string(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+
+    collect_str(
+        &source,
+        path.to_str().unwrap(),
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+    )
+}
+
+// The same for source that isn't on disk, or not as it is on disk, such as a
+// file open in an editor.
+pub fn collect_str(
+    source: &str,
+    name: &str,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+) -> Result<Vec<Symbol>, Box<dyn std::error::Error>> {
+    let mut symbols = Vec::new();
+
+    collect_into(
+        &mut symbols,
+        source,
+        name,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        |_, errors| errors,
+    )?;
+
+    Ok(symbols)
+}
+
+// The same for source in the middle of being edited: a form that doesn't
+// compile is left out rather than failing the lot. Whatever stops compiling
+// altogether, like a form that can't be read, comes back along with what was
+// collected before it.
+pub fn collect_recovering(
+    source: &str,
+    name: &str,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+) -> (Vec<Symbol>, Option<Box<dyn std::error::Error>>) {
+    let mut symbols = Vec::new();
+
+    let error = collect_into(
+        &mut symbols,
+        source,
+        name,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        |_, _| Vec::new(),
+    )
+    .err();
+
+    (symbols, error)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn collect_into<R>(
+    symbols: &mut Vec<Symbol>,
+    source: &str,
+    name: &str,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+    recover: R,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    R: FnMut(&Sexpr, Vec<il::Error>) -> Vec<il::Error>,
+{
+    compile_forms(
+        source,
+        name,
+        il_compiler,
+        ast_compiler,
+        vm,
+        opcode_table,
+        |context, form, _, definitions| {
+            for definition in definitions {
+                let source = definition.source.source_span();
+                let span = if Rc::ptr_eq(source.context(), context) {
+                    source.range()
+                } else {
+                    form.span()
+                };
+                let (line, column) = context.line_and_column(span.start);
+
+                symbols.push(Symbol {
+                    name: definition.name,
+                    module: definition.module,
+                    kind: definition.kind,
+                    arity: definition
+                        .parameters
+                        .as_ref()
+                        .map(|parameters| match parameters {
+                            ast::Parameters::Normal(parameters) => (parameters.len(), 0, false),
+                            ast::Parameters::Rest(parameters, _) => (parameters.len(), 0, true),
+                            ast::Parameters::Optional(parameters, optional) => {
+                                (parameters.len(), optional.len(), false)
+                            }
+                        }),
+                    parameters: definition
+                        .parameters
+                        .as_ref()
+                        .map(|parameters| parameter_list(definition.kind, parameters))
+                        .unwrap_or_default(),
+                    r#type: definition.r#type.map(|t| t.to_string()),
+                    return_type: definition.return_type.map(|t| t.to_string()),
+                    doc: definition.doc,
+                    file: context.display().to_string(),
+                    span,
+                    line,
+                    column,
+                });
+            }
+        },
+        recover,
+    )?;
+
+    Ok(())
+}
+
+// Compiles source form by form, handing each form to f along with the il it
+// compiled to and what it defined. Modules it requires are compiled as they
+// would be for running it, but aren't handed to f. A form the ast or il
+// compiler rejects is handed to recover with its errors instead, the ast ones
+// as il::Error::Ast, and compiling carries on past it unless recover gives any
+// of them back. Returns the context the source was read with.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn compile_forms<F, R>(
+    source: &str,
+    name: &str,
+    il_compiler: &mut il::Compiler,
+    ast_compiler: &mut ast::Compiler,
+    vm: &mut Vm<Span>,
+    opcode_table: &mut OpCodeTable<Span>,
+    mut f: F,
+    mut recover: R,
+) -> Result<Rc<Context>, Box<dyn std::error::Error>>
+where
+    F: FnMut(&Rc<Context>, &Sexpr, &Il, Vec<Definition>),
+    R: FnMut(&Sexpr, Vec<il::Error>) -> Vec<il::Error>,
+{
+    il_compiler.set_current_module(None);
+    ast_compiler.set_current_module(None);
+    il_compiler.take_definitions();
+
+    let context = Rc::new(Context::new(source, name));
+
+    for expr in Reader::new(&context) {
+        let form = expr?;
+        let ast = match ast_compiler.compile(&form) {
+            Ok(ast) => ast,
+            Err(errors) => {
+                let errors = recover(&form, errors.into_iter().map(il::Error::Ast).collect());
+
+                if !errors.is_empty() {
+                    return Err(unrecovered(errors));
+                }
+
+                continue;
+            }
+        };
+
+        if let Ast::Require(require) = &ast {
+            let module = &require.module;
+
+            if il_compiler.mark_required(module.as_str()) {
+                let Some(path) = find_module(module.as_str(), il_compiler.search_path()) else {
+                    return Err(format!("failed to find module: {module}").into());
+                };
+
+                compile_file(path.as_path(), il_compiler, ast_compiler, vm, opcode_table)?;
+                il_compiler.take_definitions();
+            }
+
+            il_compiler.import(require, ast_compiler)?;
+            continue;
+        }
+
+        let il = match il_compiler.compile(&ast, vm, ast_compiler) {
+            Ok(il) => il,
+            Err(errors) => {
+                let errors = recover(&form, errors);
+
+                if !errors.is_empty() {
+                    return Err(unrecovered(errors));
+                }
+
+                // what the form defined before it failed goes with it
+                il_compiler.take_definitions();
+                continue;
+            }
+        };
+        compiler::bytecode::compile(&il, opcode_table)?;
+
+        f(&context, &form, &il, il_compiler.take_definitions());
+    }
+
+    Ok(context)
+}
+
+// The errors recover gave back, with the ast ones unwrapped again so they read
+// the way they do when the file is compiled to run.
+fn unrecovered(errors: Vec<il::Error>) -> Box<dyn std::error::Error> {
+    CompileErrors(
+        errors
+            .into_iter()
+            .map(|error| match error {
+                il::Error::Ast(error) => Box::new(error) as Box<dyn std::error::Error>,
+                error => Box::new(error),
+            })
+            .collect(),
+    )
+    .into()
+}
+
+// The parameters of a decl are the types of its arguments rather than names.
+fn parameter_list(
+    kind: DefinitionKind,
+    parameters: &ast::Parameters,
+) -> Vec<(Option<String>, Option<String>)> {
+    let parameters = match parameters {
+        ast::Parameters::Normal(parameters) => parameters.iter().collect::<Vec<_>>(),
+        ast::Parameters::Rest(parameters, rest) => parameters.iter().chain([rest]).collect(),
+        ast::Parameters::Optional(parameters, optional) => parameters
+            .iter()
+            .chain(optional.iter().map(|(parameter, _)| parameter))
+            .collect(),
+    };
+
+    parameters
+        .into_iter()
+        .map(|parameter| match kind {
+            DefinitionKind::Decl => (None, Some(parameter.name.clone())),
+            _ => (
+                Some(parameter.name.clone()),
+                parameter.r#type.as_ref().map(|t| t.to_string()),
+            ),
+        })
+        .collect()
+}
+
+pub fn kind_name(kind: DefinitionKind) -> &'static str {
+    match kind {
+        DefinitionKind::Function => "function",
+        DefinitionKind::Variable => "variable",
+        DefinitionKind::Macro => "macro",
+        DefinitionKind::Decl => "decl",
+        DefinitionKind::Constant => "constant",
+    }
+}
+
+pub fn to_json(symbols: &[Symbol]) -> String {
+    let mut json = String::from("[");
+
+    for (i, symbol) in symbols.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+
+        write!(
+            json,
+            r#"{{"name":{},"module":{},"kind":"{}","arity":{},"parameters":["#,
+            json_string(&symbol.name),
+            json_option(symbol.module.as_deref()),
+            kind_name(symbol.kind),
+            match symbol.arity {
+                Some((required, optional, rest)) =>
+                    format!(r#"{{"required":{required},"optional":{optional},"rest":{rest}}}"#),
+                None => "null".to_string(),
+            },
+        )
+        .unwrap();
+
+        for (i, (name, r#type)) in symbol.parameters.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+
+            write!(
+                json,
+                r#"{{"name":{},"type":{}}}"#,
+                json_option(name.as_deref()),
+                json_option(r#type.as_deref())
+            )
+            .unwrap();
+        }
+
+        write!(
+            json,
+            r#"],"type":{},"return_type":{},"doc":{},"file":{},"span":{{"start":{},"end":{},"line":{},"column":{}}}}}"#,
+            json_option(symbol.r#type.as_deref()),
+            json_option(symbol.return_type.as_deref()),
+            json_option(symbol.doc.as_deref()),
+            json_string(&symbol.file),
+            symbol.span.start,
+            symbol.span.end,
+            symbol.line,
+            symbol.column,
+        )
+        .unwrap();
+    }
+
+    json.push(']');
+    json
+}
+
+pub fn json_option(s: Option<&str>) -> String {
+    s.map_or_else(|| "null".to_string(), json_string)
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
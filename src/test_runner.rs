@@ -0,0 +1,132 @@
+// What lisp test runs. Every .lisp file under the paths given is a test file,
+// loaded into an interpreter of its own so that files can't see each other's
+// definitions. Each deftest in a file is then called on its own, and a test
+// that errors, such as with a failed assert, fails with the error pointing at
+// where in the test it happened.
+
+use crate::{compile_str, Interpreter, Manifest};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use vm::{Object, OpCodeTable};
+
+pub struct TestResult {
+    pub file: PathBuf,
+    pub name: String,
+    // The rendered error, None if the test passed.
+    pub error: Option<String>,
+}
+
+// The .lisp files under each path, or the path itself if it's a file, sorted
+// so the order is the same from run to run.
+pub fn discover(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            walk(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("failed to read {}: {e}", dir.display()))?;
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "lisp")
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+// The tests in a file in the order they're defined. A file that fails to
+// compile or to run its top level has no results, just the error.
+pub fn run_file(manifest: &Manifest, file: &Path) -> Result<Vec<TestResult>, Box<dyn Error>> {
+    let mut interpreter = Interpreter::with_manifest(manifest)?;
+
+    interpreter.compile_file(file)?;
+    interpreter.eval()?;
+
+    let mut results = Vec::new();
+
+    for name in registered(&mut interpreter, "*tests*")? {
+        let error = run_test(&mut interpreter, &name)
+            .err()
+            .map(|e| e.to_string());
+
+        results.push(TestResult {
+            file: file.to_path_buf(),
+            name,
+            error,
+        });
+    }
+
+    Ok(results)
+}
+
+// The names in a registry such as *tests*, oldest first.
+pub(crate) fn registered(
+    interpreter: &mut Interpreter,
+    registry: &str,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    interpreter.vm.get_global(registry)?;
+
+    let tests = interpreter.vm.pop().unwrap().into_object();
+    let mut names = match tests {
+        Object::Cons(cons) => cons
+            .borrow()
+            .iter_cars()
+            .map(|name| match name {
+                Object::Symbol(name) => Ok(name.to_string()),
+                object => Err(format!(
+                    "{registry} should only hold symbols, found {object}"
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Object::Nil => Vec::new(),
+        object => return Err(format!("{registry} should be a list, found {object}").into()),
+    };
+
+    names.reverse();
+
+    Ok(names)
+}
+
+// Calling the test from a form of its own, rather than from rust, gives an
+// error the span of whatever in the test failed.
+fn run_test(interpreter: &mut Interpreter, name: &str) -> Result<(), Box<dyn Error>> {
+    let depth = interpreter.vm.depth();
+
+    interpreter.opcode_table = OpCodeTable::new();
+
+    compile_str(
+        &format!("({name})"),
+        name,
+        &mut interpreter.il_compiler,
+        &mut interpreter.ast_compiler,
+        &mut interpreter.vm,
+        &mut interpreter.opcode_table,
+    )?;
+
+    let result = interpreter.eval();
+
+    interpreter.vm.unwind(depth);
+
+    result
+}
@@ -0,0 +1,10 @@
+use std::path::Path;
+
+#[test]
+fn test_golden_disassembly() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+
+    if let Err(e) = lisp::golden::check_dir(&dir) {
+        panic!("{e}");
+    }
+}
@@ -0,0 +1,2 @@
+(if (= 1 2) "yes" "no")
+(let ((a 1) (b 2)) (list a b))
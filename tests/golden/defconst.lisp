@@ -0,0 +1,4 @@
+(defconst size (* 4 1024))
+(defconst color 'red)
+
+(if (= color 'red) size 0)
@@ -0,0 +1,2 @@
+(def add (lambda (a b) (+ a b)))
+(add 1 2)
@@ -1,6 +1,7 @@
 use compiler::{ast, bytecode, il};
 use core::fmt;
-use reader::{Reader, Sexpr};
+use reader::{Reader, Span};
+use std::rc::Rc;
 use vm::{OpCode, OpCodeTable, Vm};
 
 macro_rules! deftest {
@@ -14,14 +15,30 @@ macro_rules! deftest {
     };
 }
 
-macro_rules! leak {
-    ($e:expr) => {
-        Box::leak(Box::new($e))
+// Runs input with the natives loaded, then each of the failing inputs, which
+// have to fail with an error containing the message given with them.
+macro_rules! defnativetest {
+    ($name:tt, $input:literal, [$($failing:expr),* $(,)?]) => {
+        defnativetest!($name, $input, native_functions::Config::default(), [$($failing),*]);
+    };
+    ($name:tt, $input:literal, $config:expr, [$($failing:expr),* $(,)?]) => {
+        #[test]
+        fn $name() {
+            let config = $config;
+            eval_with_natives(include_str!($input), &config).unwrap();
+
+            for (input, message) in [$($failing),*] {
+                let error = eval_with_natives(input, &config).unwrap_err().to_string();
+                assert!(error.contains(message), "{input}: {error}");
+            }
+            gc::collect();
+        }
     };
 }
 
 static BOOTSTRAP_SOURCE: &str = include_str!("../lib/bootstrap/bootstrap.lisp");
 static LIST_UTILS_SOURCE: &str = include_str!("../lib/lisp/list.lisp");
+static NATIVE_DECL_SOURCE: &str = include_str!("../lib/native/decl/native.lisp");
 
 fn disasm<D: fmt::Debug>(opcode_table: &OpCodeTable<D>, depth: usize) {
     let indent = "  ".repeat(depth);
@@ -38,20 +55,24 @@ fn compile(
     context: &str,
     il_compiler: &mut il::Compiler,
     ast_compiler: &mut ast::Compiler,
-    opcode_table: &mut OpCodeTable<&'static Sexpr>,
-    vm: &mut Vm<&Sexpr>,
+    opcode_table: &mut OpCodeTable<Span>,
+    vm: &mut Vm<Span>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let context: &'static reader::Context = leak!(reader::Context::new(input, context));
-    let reader = Reader::new(context);
+    let context = Rc::new(reader::Context::new(input, context));
+    let reader = Reader::new(&context);
 
     for sexpr in reader {
-        let sexpr: &'static _ = Box::leak(Box::new(sexpr?));
+        let ast = ast_compiler
+            .compile(&sexpr?)
+            .map_err(lisp::CompileErrors::from)?;
 
-        let ast: &'static _ = Box::leak(Box::new(ast_compiler.compile(sexpr)?));
+        let il = il_compiler
+            .compile(&ast, vm, ast_compiler)
+            .map_err(lisp::CompileErrors::from)?;
 
-        let il: &'static _ = Box::leak(Box::new(il_compiler.compile(ast, vm, ast_compiler)?));
+        il_compiler.check_types(&il)?;
 
-        bytecode::compile(il, opcode_table)?;
+        bytecode::compile(&il, opcode_table)?;
     }
 
     Ok(())
@@ -59,7 +80,7 @@ fn compile(
 
 fn eval_with_bootstrap(
     input: &str,
-) -> Result<Option<vm::Object<&'static Sexpr>>, Box<dyn std::error::Error>> {
+) -> Result<Option<vm::Object<Span>>, Box<dyn std::error::Error>> {
     let mut il_compiler = il::Compiler::new();
     let mut ast_compiler = ast::Compiler::new();
     let mut opcode_table = OpCodeTable::new();
@@ -94,14 +115,63 @@ fn eval_with_bootstrap(
 
     match vm.eval(&opcode_table) {
         Ok(_) => Ok(vm.pop().map(|local| local.into_object())),
-        Err((e, sexpr)) => {
-            eprintln!("error in: {}:\nat: {sexpr}", sexpr.context().display());
+        Err((e, span)) => {
+            eprintln!("error in: {}:\nat: {span}", span.context().display());
+            Err(Box::new(e))
+        }
+    }
+}
+
+fn eval_with_natives(
+    input: &str,
+    config: &native_functions::Config,
+) -> Result<Option<vm::Object<Span>>, Box<dyn std::error::Error>> {
+    let mut il_compiler = il::Compiler::new();
+    let mut ast_compiler = ast::Compiler::new();
+    let mut opcode_table = OpCodeTable::new();
+    let mut vm = Vm::new();
+
+    vm.set_deterministic(config.deterministic);
+
+    native_functions::load_module_with_config(&mut vm, config);
+
+    compile(
+        BOOTSTRAP_SOURCE,
+        "bootstrap.lisp",
+        &mut il_compiler,
+        &mut ast_compiler,
+        &mut opcode_table,
+        &mut vm,
+    )?;
+
+    compile(
+        NATIVE_DECL_SOURCE,
+        "native.lisp",
+        &mut il_compiler,
+        &mut ast_compiler,
+        &mut opcode_table,
+        &mut vm,
+    )?;
+
+    compile(
+        input,
+        "test input",
+        &mut il_compiler,
+        &mut ast_compiler,
+        &mut opcode_table,
+        &mut vm,
+    )?;
+
+    match vm.eval(&opcode_table) {
+        Ok(_) => Ok(vm.pop().map(|local| local.into_object())),
+        Err((e, span)) => {
+            eprintln!("error in: {}:\nat: {span}", span.context().display());
             Err(Box::new(e))
         }
     }
 }
 
-fn eval(input: &'static str) -> Result<Option<vm::Object<&Sexpr>>, Box<dyn std::error::Error>> {
+fn eval(input: &'static str) -> Result<Option<vm::Object<Span>>, Box<dyn std::error::Error>> {
     let mut il_compiler = il::Compiler::new();
     let mut ast_compiler = ast::Compiler::new();
     let mut opcode_table = OpCodeTable::new();
@@ -118,8 +188,8 @@ fn eval(input: &'static str) -> Result<Option<vm::Object<&Sexpr>>, Box<dyn std::
 
     match vm.eval(&opcode_table) {
         Ok(_) => Ok(vm.pop().map(|local| local.into_object())),
-        Err((e, sexpr)) => {
-            eprintln!("error in: {}:\nat: {sexpr}", sexpr.context().display());
+        Err((e, span)) => {
+            eprintln!("error in: {}:\nat: {span}", span.context().display());
             Err(Box::new(e))
         }
     }
@@ -184,6 +254,393 @@ fn test_defmacro() {
     gc::collect();
 }
 
+#[test]
+fn test_macros_use_standard_library() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    // fold is defined by the bootstrap outside of eval-when-compile, and
+    // string-split-whitespace is a native
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(defmacro sum (&rest xs) (fold (lambda (a b) (+ a b)) xs))
+                     (defmacro words (s) (cons 'list (string-split-whitespace s)))
+                     (eval-when-compile (def six (sum 1 2 3)))
+                     (defmacro get-six () six)
+                     (if (= (get-six) 6) (car (words \"a b\")) (car 1))",
+        })
+        .unwrap();
+    interpreter.eval().unwrap();
+    gc::collect();
+}
+
+#[test]
+fn test_defconst() {
+    let input = "
+(defconst size (* 4 1024))
+(defconst color 'red)
+((lambda (size) (if (= color 'red) size 0)) (+ size 1))";
+    assert!(matches!(
+        eval(input).unwrap().unwrap(),
+        vm::Object::Int(4097)
+    ));
+
+    assert!(eval("(defconst x 1) (set! x 2)").is_err());
+    assert!(eval("(defconst x 1) (def x 2)").is_err());
+    assert!(eval("(defconst x (lambda () 1))").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_quote_shorthand_spans() {
+    let context = Rc::new(reader::Context::new("'x `(a ,b ,@c)", "test input"));
+    let sexprs = Reader::new(&context)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    let [quote, quasiquote] = sexprs.as_slice() else {
+        panic!("expected two forms, got {sexprs:?}");
+    };
+
+    assert_eq!(quote.to_string(), "(quote x)");
+    assert_eq!(quote.source_span().text(), "'x");
+
+    assert_eq!(
+        quasiquote.to_string(),
+        "(quasiquote (a (unquote b) (unquote-splice c)))"
+    );
+    assert_eq!(quasiquote.source_span().text(), "`(a ,b ,@c)");
+
+    let list = quasiquote.as_list().unwrap()[1].as_list().unwrap();
+
+    assert_eq!(list[1].source_span().text(), ",b");
+    assert_eq!(list[2].source_span().text(), ",@c");
+}
+
+#[test]
+fn test_char_literals() {
+    let context = Rc::new(reader::Context::new(
+        r"'a' #\b #\newline #\space #\tab #\x41 #\( #\é (#\x3bb #\))",
+        "test input",
+    ));
+    let sexprs = Reader::new(&context)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    let chars = sexprs[..8]
+        .iter()
+        .chain(sexprs[8].as_list().unwrap())
+        .map(|sexpr| sexpr.as_char().unwrap())
+        .collect::<String>();
+
+    assert_eq!(chars, "ab\n \tA(éλ)");
+
+    for bad in [r"#\nope", r"(#\x110000)"] {
+        let context = Rc::new(reader::Context::new(bad, "test input"));
+        assert!(Reader::new(&context).next().unwrap().is_err());
+    }
+}
+
+deftest!(test_radix, "lisp/radix.lisp");
+
+deftest!(test_dotted, "lisp/dotted.lisp");
+
+deftest!(test_literals, "lisp/literals.lisp");
+
+#[test]
+fn test_stream_reader() {
+    let input = "(a\n b) ; comment\n\n\"x\" 'c\n(d]\n(e)\n(f\n";
+    let mut reader = reader::StreamReader::new(input.as_bytes(), "stream");
+
+    let list = reader.next().unwrap().unwrap();
+    assert_eq!(list.to_string(), "(a b)");
+
+    let string = reader.next().unwrap().unwrap();
+    assert_eq!(string.to_string(), "\"x\"");
+    assert_eq!(
+        string.context().line_and_column(string.span().start),
+        (4, 1)
+    );
+
+    let quote = reader.next().unwrap().unwrap();
+    assert_eq!(quote.to_string(), "(quote c)");
+    assert_eq!(quote.source_span().text(), "'c");
+
+    assert!(reader.next().unwrap().is_err());
+
+    let list = reader.next().unwrap().unwrap();
+    assert_eq!(list.to_string(), "(e)");
+    assert_eq!(list.context().line_and_column(list.span().start), (6, 1));
+
+    assert!(matches!(
+        reader.next(),
+        Some(Err(reader::Error::UnexpectedEof(_)))
+    ));
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn test_dispatch() {
+    let context = Rc::new(reader::Context::new(
+        "#u8(1 2) (#date\"2024-06-01\" #x1f #:g)",
+        "test input",
+    ));
+    let sexprs = Reader::new(&context)
+        .with_dispatch("u8", |form| {
+            let mut list = vec![reader::Sexpr::Symbol {
+                symbol: "bytes".to_string(),
+                context: form.context().clone(),
+                span: form.span(),
+            }];
+            list.extend(form.as_list().ok_or("expected a list")?.iter().cloned());
+
+            Ok(reader::Sexpr::List {
+                list,
+                context: form.context().clone(),
+                span: form.span(),
+            })
+        })
+        .with_dispatch("date", |form| match form.as_string() {
+            Some(date) if date.len() == 10 => Ok(form.clone()),
+            _ => Err("expected a yyyy-mm-dd string".to_string()),
+        })
+        .map(|sexpr| sexpr.unwrap().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(sexprs, ["(bytes 1 2)", "(\"2024-06-01\" 31 #:g)"]);
+
+    let context = Rc::new(reader::Context::new("#date 1 #nope", "test input"));
+    let errors = Reader::new(&context)
+        .with_dispatch("date", |_| Err("expected a string".to_string()))
+        .map(|sexpr| sexpr.unwrap_err().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        errors,
+        [
+            "error: #date: expected a string
+ --> test input:1:1
+  |
+1 | #date 1 #nope
+  | ^^^^^^^",
+            "error: #nope: nothing is registered to read this
+ --> test input:1:9
+  |
+1 | #date 1 #nope
+  |         ^^^^^",
+        ]
+    );
+}
+
+#[test]
+fn test_shebang() {
+    let script = "#!/usr/bin/env lisp\n(+ 1 2)";
+
+    assert!(matches!(eval(script).unwrap().unwrap(), vm::Object::Int(3)));
+
+    let mut reader = reader::StreamReader::new(script.as_bytes(), "script");
+    let sexpr = reader.next().unwrap().unwrap();
+    assert_eq!(sexpr.context().line_and_column(sexpr.span().start), (2, 1));
+
+    // only the first line of the source
+    assert!(eval("1\n#!/usr/bin/env lisp").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_raw_strings() {
+    let context = Rc::new(reader::Context::new(
+        r##"#"say "hi""# #"^"[a-z]+"$"# #""# #"line
+"line""# "plain""##,
+        "test input",
+    ));
+    let strings = Reader::new(&context)
+        .map(|sexpr| sexpr.unwrap().as_string().unwrap().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        strings,
+        [
+            r#"say "hi""#,
+            r#"^"[a-z]+"$"#,
+            "",
+            "line\n\"line\"",
+            "plain"
+        ]
+    );
+
+    let input = r##"
+(defmacro greeting () #"she said "hello""#)
+(assert (= (greeting) #"she said "hello""#))
+(assert (= #"plain"# "plain"))"##;
+    eval_with_bootstrap(input).unwrap();
+
+    let context = Rc::new(reader::Context::new(r#"#"unterminated"#, "test input"));
+    assert!(matches!(
+        Reader::new(&context).next(),
+        Some(Err(reader::Error::UnexpectedEof(_)))
+    ));
+    gc::collect();
+}
+
+#[test]
+fn test_incomplete_input() {
+    for incomplete in [
+        "(defun foo (x)",
+        "(a \"b",
+        "\"abc",
+        "'",
+        "#(+ %",
+        "[1 {a",
+        "(a . ",
+    ] {
+        let context = Rc::new(reader::Context::new(incomplete, "test input"));
+        assert!(
+            matches!(
+                Reader::new(&context).next(),
+                Some(Err(reader::Error::UnexpectedEof(_)))
+            ),
+            "{incomplete}"
+        );
+    }
+
+    for wrong in ["(a))", "(a]", "(. a)", "(a . b c"] {
+        let context = Rc::new(reader::Context::new(wrong, "test input"));
+        assert!(
+            !matches!(
+                Reader::new(&context).last(),
+                Some(Err(reader::Error::UnexpectedEof(_)))
+            ),
+            "{wrong}"
+        );
+    }
+
+    let mut reader = reader::StreamReader::new("(a \"b\nc\")\n".as_bytes(), "stream");
+    assert_eq!(reader.next().unwrap().unwrap().to_string(), "(a \"b\nc\")");
+}
+
+#[test]
+fn test_literal_syntax() {
+    let context = Rc::new(reader::Context::new("{a 1 b [2]} []", "test input"));
+    let sexprs = Reader::new(&context)
+        .map(|sexpr| sexpr.unwrap().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(sexprs, ["{a 1 b [2]}", "[]"]);
+
+    for bad in ["{a 1)", "(a]", "[a}", "}", "{a 1 b}"] {
+        let context = Rc::new(reader::Context::new(bad, "test input"));
+        assert!(Reader::new(&context).next().unwrap().is_err(), "{bad}");
+    }
+
+    assert!(eval_with_bootstrap("{:a 1 :b}").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_dotted_list_syntax() {
+    let context = Rc::new(reader::Context::new(
+        "(a . b) (a . (b . c)) (a . (b c)) (a . ())",
+        "test input",
+    ));
+    let sexprs = Reader::new(&context)
+        .map(|sexpr| sexpr.unwrap().to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(sexprs, ["(a . b)", "(a b . c)", "(a b c)", "(a)"]);
+
+    for bad in ["(. a)", "(a . b c)", "(a .)", ". a"] {
+        let context = Rc::new(reader::Context::new(bad, "test input"));
+        assert!(Reader::new(&context).next().unwrap().is_err(), "{bad}");
+    }
+
+    assert!(eval_with_bootstrap("(a . b)").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_int_literal_overflow() {
+    assert!(eval_with_bootstrap("#x8000000000000000").is_err());
+    assert!(eval_with_bootstrap("(list 99999999999999999999)").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_compiled_forms_release_their_source() {
+    let context = Rc::new(reader::Context::new(
+        "(def f (lambda (x) (list x x))) (def xs (f 1))",
+        "test input",
+    ));
+
+    let mut il_compiler = il::Compiler::new();
+    let mut ast_compiler = ast::Compiler::new();
+    let mut opcode_table = OpCodeTable::new();
+    let mut vm = Vm::new();
+
+    for sexpr in Reader::new(&context) {
+        let ast = ast_compiler.compile(&sexpr.unwrap()).unwrap();
+        let il = il_compiler
+            .compile(&ast, &mut vm, &mut ast_compiler)
+            .unwrap();
+        bytecode::compile(&il, &mut opcode_table).unwrap();
+    }
+
+    // the compiler's debug info is all that's left holding on to the source
+    assert!(Rc::strong_count(&context) > 1);
+
+    drop((il_compiler, ast_compiler, opcode_table));
+    gc::collect();
+
+    assert_eq!(Rc::strong_count(&context), 1);
+}
+
+#[test]
+fn test_dump_ast_and_il() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    interpreter.il_compiler.set_options(il::CompilerOptions {
+        dump_ast: true,
+        dump_il: true,
+    });
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(def f (lambda (x) (let ((y 1)) (+ x y))))",
+        })
+        .unwrap();
+
+    let dumps = interpreter.il_compiler.take_dumps();
+
+    let [il::Dump::Ast(ast), il::Dump::Il(il)] = dumps.as_slice() else {
+        panic!("expected an ast and an il dump, got {dumps:?}");
+    };
+
+    assert_eq!(
+        ast,
+        "def f
+  lambda (x)
+    macro-call let
+      quote ((y 1))
+      quote (+ x y)
+"
+    );
+    assert_eq!(
+        il,
+        "def global f
+  lambda (x) Nary(1)
+    call
+      lambda (y) Nary(1)
+        upvalues Local(0)
+        +
+          upvalue 0 x
+          local 0 y
+      int 1
+"
+    );
+    gc::collect();
+}
+
 #[test]
 fn test_car() {
     let input = "(car (list 1 2 3))";
@@ -417,6 +874,159 @@ fn test_find() {
     gc::collect();
 }
 
+#[test]
+fn test_deterministic() {
+    let input = "
+(def hm (map-create))
+(map-insert! hm 'a (random 1000))
+(map-insert! hm 'b (random 1000))
+(map-insert! hm 'c (time-monotonic))
+(map-items hm)";
+    let config = native_functions::Config {
+        deterministic: true,
+        seed: 42,
+        ..Default::default()
+    };
+    let a = eval_with_natives(input, &config).unwrap().unwrap();
+    let b = eval_with_natives(input, &config).unwrap().unwrap();
+    assert_eq!(a.to_string(), b.to_string());
+    gc::collect();
+}
+
+#[test]
+fn test_compile_report() {
+    let mut il_compiler = il::Compiler::new();
+    let mut ast_compiler = ast::Compiler::new();
+    let mut opcode_table = OpCodeTable::new();
+    let mut vm = Vm::new();
+    let mut report = lisp::CompileReport::default();
+
+    for path in ["lib/bootstrap/bootstrap.lisp", "tests/lisp/let.lisp"] {
+        lisp::compile_file_with_report(
+            std::path::Path::new(path),
+            &mut il_compiler,
+            &mut ast_compiler,
+            &mut vm,
+            &mut opcode_table,
+            &mut report,
+        )
+        .unwrap();
+    }
+
+    let file = &report.files[1];
+    assert_eq!(file.forms, 1);
+    assert!(file.il_nodes > 0);
+    assert!(file.opcodes > 0);
+    assert!(file.macro_expansion > std::time::Duration::ZERO);
+    gc::collect();
+}
+
+#[test]
+fn test_interpreter_manifest() {
+    let manifest = lisp::Manifest::default()
+        .source(lisp::Source::Str {
+            name: "bootstrap.lisp",
+            source: BOOTSTRAP_SOURCE,
+        })
+        .source(lisp::Source::Str {
+            name: "prelude.lisp",
+            source: "(def answer 42)",
+        });
+
+    let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(assert (= (let ((x answer)) x) 42))",
+        })
+        .unwrap();
+    interpreter.eval().unwrap();
+
+    let files = interpreter
+        .report
+        .files
+        .iter()
+        .map(|file| file.path.to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(files, ["bootstrap.lisp", "prelude.lisp", "test input"]);
+
+    // natives aren't loaded unless asked for
+    assert!(interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(print \"hello\")",
+        })
+        .is_err());
+
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(string->int \"1\")",
+        })
+        .unwrap();
+    gc::collect();
+}
+
+#[test]
+fn test_symbols() {
+    let mut il_compiler = il::Compiler::new();
+    let mut ast_compiler = ast::Compiler::new();
+    let mut opcode_table = OpCodeTable::new();
+    let mut vm = Vm::new();
+
+    lisp::compile_file(
+        std::path::Path::new("lib/bootstrap/bootstrap.lisp"),
+        &mut il_compiler,
+        &mut ast_compiler,
+        &mut vm,
+        &mut opcode_table,
+    )
+    .unwrap();
+
+    let symbols = lisp::symbols::collect(
+        std::path::Path::new("tests/lisp/symbols.lisp"),
+        &mut il_compiler,
+        &mut ast_compiler,
+        &mut vm,
+        &mut opcode_table,
+    )
+    .unwrap();
+
+    let summary = symbols
+        .iter()
+        .map(|symbol| {
+            (
+                symbol.name.as_str(),
+                lisp::symbols::kind_name(symbol.kind),
+                symbol.arity,
+                symbol.line,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        summary,
+        [
+            ("defun", "macro", Some((2, 0, true)), 1),
+            ("x", "variable", None, 4),
+            ("add", "function", Some((2, 0, true)), 6),
+            ("foo", "decl", Some((2, 0, false)), 10),
+        ]
+    );
+
+    assert_eq!(
+        symbols[2].parameters[0],
+        (Some("a".to_string()), Some("int".to_string()))
+    );
+    assert_eq!(symbols[3].parameters[0], (None, Some("string".to_string())));
+    assert_eq!(symbols[2].doc.as_deref(), Some("Adds a and b."));
+    assert!(lisp::symbols::to_json(&symbols)
+        .starts_with(r#"[{"name":"defun","module":null,"kind":"macro""#));
+    gc::collect();
+}
+
 deftest!(test_hashmap, "lisp/hashmap.lisp");
 
 deftest!(test_quasiquote, "lisp/quasiquote.lisp");
@@ -438,3 +1048,2423 @@ deftest!(test_list_drop, "lisp/list/drop.lisp");
 deftest!(test_list_sort, "lisp/list/sort.lisp");
 
 deftest!(test_quote_eq_list, "lisp/quote-eq-list.lisp");
+
+deftest!(test_gensym, "lisp/gensym.lisp");
+
+deftest!(test_hygiene, "lisp/hygiene.lisp");
+
+deftest!(test_macroexpand, "lisp/macroexpand.lisp");
+
+deftest!(test_destructuring, "lisp/destructuring.lisp");
+
+#[test]
+fn test_malformed_pattern() {
+    assert!(eval("(lambda ((a 1)) a)").is_err());
+    assert!(eval("(lambda ((a &rest)) a)").is_err());
+    assert!(eval("(lambda ((a a)) a)").is_err());
+    gc::collect();
+}
+
+deftest!(test_optional, "lisp/optional.lisp");
+
+#[test]
+fn test_malformed_optional() {
+    assert!(eval("(lambda (a &optional b &rest c) a)").is_err());
+    assert!(eval("(lambda (&optional a &optional b) a)").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_optional_apply_arity() {
+    for (input, expected) in [
+        (
+            "(def f (lambda (a &optional (b 5)) (list a b))) (apply f (list 1 2 3 4))",
+            "expected 1 to 2 parameters, received 4",
+        ),
+        (
+            "(def f (lambda (a b &optional (c 5)) (list a b c))) (apply f (list 1))",
+            "expected 2 to 3 parameters, received 1",
+        ),
+    ] {
+        let error = eval(input).unwrap_err().to_string();
+        assert!(error.contains(expected), "{error}");
+    }
+
+    assert_eq!(
+        eval("(def f (lambda (a &optional (b 5)) (list a b))) (apply f (list 1))")
+            .unwrap()
+            .unwrap()
+            .to_string(),
+        "(1 5)"
+    );
+    gc::collect();
+}
+
+deftest!(test_anonymous_function, "lisp/anonymous-function.lisp");
+
+deftest!(test_documentation, "lisp/documentation.lisp");
+
+deftest!(test_match, "lisp/match.lisp");
+
+#[test]
+fn test_malformed_match() {
+    assert!(eval("(match 1 ((vector a) a))").is_err());
+    assert!(eval("(match 1 ((list a a) a))").is_err());
+    assert!(eval("(match 1 (a))").is_err());
+    gc::collect();
+}
+
+deftest!(test_case, "lisp/case.lisp");
+
+#[test]
+fn test_malformed_case() {
+    assert!(eval("(case 1 (else 1) (1 2))").is_err());
+    assert!(eval("(case 1 (1))").is_err());
+    assert!(eval("(case 1 2)").is_err());
+    gc::collect();
+}
+
+deftest!(test_iteration, "lisp/iteration.lisp");
+
+deftest!(test_labels, "lisp/labels.lisp");
+
+#[test]
+fn test_labels_scope() {
+    assert!(eval_with_bootstrap("(labels ((f () 1)) (f)) (f)").is_err());
+    gc::collect();
+}
+
+deftest!(test_setf, "lisp/setf.lisp");
+
+deftest!(test_typecheck, "lisp/typecheck.lisp");
+
+#[test]
+fn test_type_errors() {
+    assert!(eval("(+ 1 \"a\")").is_err());
+    assert!(eval("(if 1 2 3)").is_err());
+    assert!(eval("(def f (lambda ((x int)) x)) (f \"a\")").is_err());
+    assert!(eval("(decl f (lambda (string))) (f 1)").is_err());
+    assert!(eval("((lambda ((x string)) x) 1)").is_err());
+    assert!(eval("(def f (lambda (x) -> int \"a\"))").is_err());
+    assert!(eval("(def f (lambda ((x int)) (set! x 'a)))").is_err());
+    assert!(eval("(def (x int) (if true 1 nil))").is_err());
+    assert!(eval("(1 2)").is_err());
+    gc::collect();
+}
+
+deftest!(test_inference, "lisp/inference.lisp");
+
+#[test]
+fn test_inferred_type_errors() {
+    assert!(eval_with_bootstrap("(let ((x 1)) (if x 1 2))").is_err());
+    assert!(eval_with_bootstrap("(let ((x 1)) (set! x 'a) x)").is_ok());
+    assert!(eval_with_bootstrap("(def inc (lambda (x) (+ x 1))) (if (inc 1) 1 2)").is_err());
+    assert!(eval_with_bootstrap(
+        "(decl f (lambda (int) -> string)) (def f (lambda (x) \"a\")) (+ (f 1) 1)"
+    )
+    .is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_inference_warnings() {
+    let mut interpreter = lisp::Interpreter::with_manifest(&lisp::Manifest::default()).unwrap();
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "warn.lisp",
+            source: "(def id (lambda (x) x)) (def (one int) 1)",
+        })
+        .unwrap();
+
+    let warnings = interpreter.take_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].message.contains("id"));
+
+    assert!(interpreter
+        .load(&lisp::Source::Str {
+            name: "strict.lisp",
+            source: "(declaim (warnings-as-errors t)) (def id (lambda (x) x))",
+        })
+        .is_err());
+
+    assert!(interpreter
+        .load(&lisp::Source::Str {
+            name: "declared.lisp",
+            source:
+                "(declaim (warnings-as-errors t)) (decl id (lambda (int))) (def id (lambda (x) x))",
+        })
+        .is_ok());
+}
+
+#[test]
+fn test_unchecked_int_opcodes() {
+    fn opcodes(input: &'static str) -> Vec<OpCode<Span>> {
+        fn flatten<D: Clone>(table: &OpCodeTable<D>, out: &mut Vec<OpCode<D>>) {
+            for opcode in table.opcodes() {
+                if let OpCode::Lambda { body, .. } = opcode {
+                    flatten(body, out);
+                }
+                out.push(opcode.clone());
+            }
+        }
+
+        let mut opcode_table = OpCodeTable::new();
+
+        compile(
+            input,
+            "test input",
+            &mut il::Compiler::new(),
+            &mut ast::Compiler::new(),
+            &mut opcode_table,
+            &mut Vm::new(),
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        flatten(&opcode_table, &mut out);
+        out
+    }
+
+    let typed = opcodes("(def f (lambda ((x int)) (* (+ x 1) 2)))");
+    assert!(typed.iter().any(|opcode| matches!(opcode, OpCode::AddInt)));
+    assert!(typed.iter().any(|opcode| matches!(opcode, OpCode::MulInt)));
+
+    let untyped = opcodes("(def f (lambda (x) (+ x 1)))");
+    assert!(untyped.iter().any(|opcode| matches!(opcode, OpCode::Add)));
+    assert!(!untyped
+        .iter()
+        .any(|opcode| matches!(opcode, OpCode::AddInt)));
+
+    assert!(matches!(
+        eval("(def f (lambda ((x int)) (- x 1))) (f 3)"),
+        Ok(Some(vm::Object::Int(2)))
+    ));
+
+    // the decl is trusted, but an untyped caller can still break it
+    assert!(
+        eval("(def f (lambda ((x int)) (+ x 1))) (def g (lambda (y) (f y))) (g \"a\")").is_err()
+    );
+    gc::collect();
+}
+
+#[test]
+fn test_compile_warnings() {
+    use compiler::diagnostics::WarningKind;
+
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "warn.lisp",
+            source: "(def f (lambda (a b) -> int a))
+(def g (lambda (x _unused) -> int
+  (let ((x 1) (y 2)) x)))
+(def h (lambda (q q) -> int q))",
+        })
+        .unwrap();
+
+    let warnings = interpreter
+        .take_warnings()
+        .into_iter()
+        .map(|warning| (warning.kind, warning.line_and_column().0))
+        .collect::<Vec<_>>();
+
+    assert!(warnings.contains(&(WarningKind::UnusedParameter, 1)));
+    assert!(warnings.contains(&(WarningKind::UnusedParameter, 2)));
+    assert!(warnings.contains(&(WarningKind::Shadowing, 3)));
+    assert!(warnings.contains(&(WarningKind::UnusedVariable, 3)));
+    assert!(warnings.contains(&(WarningKind::Shadowing, 4)));
+    assert_eq!(warnings.len(), 6);
+
+    assert!(interpreter
+        .load(&lisp::Source::Str {
+            name: "strict.lisp",
+            source: "(declaim (warnings-as-errors t)) (def f (lambda (a b) -> int a))",
+        })
+        .is_err());
+}
+
+// A guarded match clause binds its pattern's names for the guard and for the
+// body, a name is unused only if neither of them uses it.
+#[test]
+fn test_match_guard_warnings() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "match.lisp",
+            source: "(match '(1 2)
+  ((list a b) :when (= a 1) b)
+  ((list c d e) :when (= c 1) c)
+  (_ 0))",
+        })
+        .unwrap();
+
+    let warnings = interpreter
+        .take_warnings()
+        .into_iter()
+        .map(|warning| warning.message)
+        .collect::<Vec<_>>();
+
+    assert_eq!(warnings, ["unused variable: d", "unused variable: e"]);
+}
+
+#[test]
+fn test_multiple_compile_errors() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    let error = interpreter
+        .load(&lisp::Source::Str {
+            name: "typos.lisp",
+            source: "(def f (lambda (x) (+ x undefined-a)))
+(def g (lambda () (list undefined-b (undefined-c 1))))
+(def h (lambda () (if)))
+(def ok (lambda () 1))",
+        })
+        .unwrap_err();
+
+    let errors = error.downcast::<lisp::CompileErrors>().unwrap();
+    let messages = errors.to_string();
+
+    assert_eq!(errors.0.len(), 4);
+    assert!(messages.contains("undefined-a"));
+    assert!(messages.contains("undefined-b"));
+    assert!(messages.contains("undefined-c"));
+
+    // everything that did compile is still defined
+    assert!(interpreter
+        .load(&lisp::Source::Str {
+            name: "after.lisp",
+            source: "(ok)",
+        })
+        .is_ok());
+}
+
+#[test]
+fn test_error_diagnostics() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    let error = interpreter
+        .load(&lisp::Source::Str {
+            name: "typo.lisp",
+            source: "(def f (lambda (x)\n  (+ x undefined-a)))",
+        })
+        .unwrap_err();
+
+    assert_eq!(
+        error.to_string(),
+        "error: unknown variable referenced: undefined-a
+ --> typo.lisp:2:8
+  |
+2 |   (+ x undefined-a)))
+  |        ^^^^^^^^^^^"
+    );
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "runtime.lisp",
+            source: "(car (cdr (list 1)))",
+        })
+        .unwrap();
+
+    assert_eq!(
+        interpreter.eval().unwrap_err().to_string(),
+        "error: type error: expected cons: received: nil
+ --> runtime.lisp:1:1
+  |
+1 | (car (cdr (list 1)))
+  | ^^^^^^^^^^^^^^^^^^^^"
+    );
+    gc::collect();
+}
+
+#[test]
+fn test_reader_diagnostics() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    let mut error = |source| {
+        interpreter
+            .load(&lisp::Source::Str {
+                name: "parens.lisp",
+                source,
+            })
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert_eq!(
+        error("(def x 1)\n(def f (lambda (x)\n  (list x]))"),
+        "error: unbalanced parens
+ --> parens.lisp:3:10
+  |
+3 |   (list x]))
+  |          ^"
+    );
+
+    assert_eq!(
+        error("(def x 1)\n(def f\n  (lambda (x) x)"),
+        "error: unexpected end of file before this was closed
+ --> parens.lisp:2:1
+  |
+2 | (def f
+  | ^"
+    );
+
+    assert_eq!(
+        error("(print \"unterminated)"),
+        "error: unexpected end of file before this was closed
+ --> parens.lisp:1:8
+  |
+1 | (print \"unterminated)
+  |        ^^^^^^^^^^^^^^"
+    );
+    gc::collect();
+}
+
+deftest!(test_declaim, "lisp/declaim.lisp");
+
+#[test]
+fn test_declaim_strict_arity() {
+    // calls to functions that are only declared are checked with strict-arity
+    assert!(eval("(decl f (lambda (int int))) (def g (lambda () (f 1)))").is_ok());
+    assert!(eval("(declaim (strict-arity t)) (def f (lambda (a b) a)) (f 1)").is_err());
+    assert!(
+        eval("(declaim (strict-arity t)) (def f (lambda (a &optional b) a)) (f 1 2 3)").is_err()
+    );
+    assert!(eval("(declaim (strict-arity t)) (decl f (lambda (int))) (f)").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_declaim_require_decl() {
+    assert!(eval("(declaim (require-decl t)) (def f (lambda () 1))").is_err());
+    assert!(eval("(declaim (require-decl t)) (def x 1)").is_ok());
+    assert!(eval("(declaim (strict-arity maybe))").is_err());
+    assert!(eval("(declaim (pedantic t))").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_declaim_is_per_file() {
+    let mut interpreter = lisp::Interpreter::with_manifest(&lisp::Manifest::default()).unwrap();
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "strict.lisp",
+            source: "(declaim (strict-arity t)) (decl f (lambda (int)))",
+        })
+        .unwrap();
+
+    assert!(interpreter
+        .load(&lisp::Source::Str {
+            name: "lax.lisp",
+            source: "(def g (lambda () (f 1 2)))",
+        })
+        .is_ok());
+    gc::collect();
+}
+
+#[test]
+fn test_arity_of_defined_functions() {
+    assert!(eval("(def f (lambda (a b) a)) (f 1 2)").is_ok());
+    assert!(eval("(def f (lambda (a &optional b) a)) (f 1)").is_ok());
+    assert!(eval("(def f (lambda (a &rest b) a)) (f 1 2 3)").is_ok());
+    assert!(eval("((lambda (a) a) 1 2)").is_err());
+    assert!(eval("(def f (lambda (a &rest b) a)) (f)").is_err());
+    // f could be anything once it's been set!
+    assert!(eval("(def f (lambda (a) a)) (set! f (lambda (a b) a)) (f 1 2)").is_ok());
+
+    let error = eval("(def f (lambda (a b) a))\n(f 1)").unwrap_err();
+
+    assert_eq!(
+        error.to_string(),
+        "error: wrong number of arguments: expected 2, received 1
+ --> test input:2:1
+  |
+2 | (f 1)
+  | ^^^^^
+note: defined here
+ --> test input:1:1
+  |
+1 | (def f (lambda (a b) a))
+  | ^^^^^^^^^^^^^^^^^^^^^^^^"
+    );
+    gc::collect();
+}
+
+deftest!(test_threading, "lisp/threading.lisp");
+
+#[test]
+fn test_malformed_threading() {
+    assert!(eval("(-> 1 2)").is_err());
+    assert!(eval("(->> 1 ())").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_malformed_setf() {
+    assert!(eval_with_bootstrap("(let ((x (list 1))) (set! (length x) 1))").is_err());
+    assert!(eval("(set! (car) 1)").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_malformed_iteration() {
+    assert!(eval("(dolist x x)").is_err());
+    assert!(eval("(dotimes (1 2) 1)").is_err());
+    gc::collect();
+}
+
+defnativetest!(
+    test_string_to_int_checked,
+    "lisp/string-to-int-checked.lisp",
+    [
+        (
+            "(string->int? \"1\" 37)",
+            "invalid parameters: string->int? expects a radix between 2 and 36"
+        ),
+        (
+            "(string->int? \"1\" 10 0)",
+            "invalid parameters: string->int? expects 1, 2 or 4 parameters"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_int_string_conversion,
+    "lisp/int-string.lisp",
+    [
+        (
+            "(int->string 1 1)",
+            "invalid parameters: int->string expects a radix between 2 and 36"
+        ),
+        (
+            "(int->string 1 37)",
+            "invalid parameters: int->string expects a radix between 2 and 36"
+        ),
+        (
+            "(string->int \"z\" 37)",
+            "invalid parameters: string->int expects a radix between 2 and 36"
+        ),
+        (
+            "(string->int \"12x\")",
+            r#"other error: failed to parse "12x": 'x' at position 2 is not a base 10 digit"#
+        ),
+        ("(string->int \"1_000\")", "'_' at position 1"),
+    ]
+);
+
+defnativetest!(
+    test_string_natives,
+    "lisp/string.lisp",
+    [
+        (
+            "(string-replace \"abc\" \"\" \"x\")",
+            "invalid parameters: string-replace expects a non-empty string to replace"
+        ),
+        (
+            "(string-ref \"abc\" 3)",
+            "invalid parameters: string-ref index 3 is out of bounds of a string of length 3"
+        ),
+        (
+            "(substring \"abc\" 2 1)",
+            "invalid parameters: substring expects start 2 to be before end 1"
+        ),
+        (
+            "(substring \"abc\" 0 4)",
+            "invalid parameters: substring index 4 is out of bounds of a string of length 3"
+        ),
+        (
+            "(string-append \"a\" 'b)",
+            "type error: expected string: received: symbol"
+        ),
+        (
+            "(string-ref \"abc\" (- 0 1))",
+            "invalid parameters: string-ref index -1 is out of bounds of a string of length 3"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_csv,
+    "lisp/csv.lisp",
+    [
+        (
+            r##"(csv-parse #"a,"b"#)"##,
+            "invalid parameters: csv-parse found a quoted field on line 1 that isn't closed"
+        ),
+        (
+            r##"(csv-parse #""a"b,c"#)"##,
+            "invalid parameters: csv-parse expects a delimiter after the quoted field on line 1"
+        ),
+        (
+            "(csv-parse \"a,b\n1\" :header true)",
+            "invalid parameters: csv-parse expects 2 fields in row 2 to match the header, found 1"
+        ),
+        (
+            "(csv-parse \"a\" :delimiter \";\")",
+            r#"invalid parameters: csv-parse expects a char for :delimiter, received ";""#
+        ),
+        (
+            "(csv-write '((a)))",
+            "invalid parameters: csv-write can't write 'a as a field"
+        ),
+        (
+            "(csv-write '(1 2))",
+            "invalid parameters: csv-write expects a list, received 1"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_format,
+    "lisp/format.lisp",
+    [
+        (
+            "(format \"~a ~a\" 1)",
+            "invalid parameters: format ran out of arguments for ~a"
+        ),
+        (
+            "(format \"~a\" 1 2)",
+            "invalid parameters: format was given 1 more arguments than its directives use"
+        ),
+        (
+            "(format \"~d\" \"one\")",
+            "type error: expected int: received: string"
+        ),
+        (
+            "(format \"~q\" 1)",
+            "invalid parameters: format does not know the directive ~q"
+        ),
+        (
+            "(format \"trailing ~\")",
+            "invalid parameters: format string ends in a lone ~"
+        ),
+        (
+            "(format 'not-a-string)",
+            "type error: argument 1 expected string, found symbol"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_time,
+    "lisp/time.lisp",
+    native_functions::Config {
+        deterministic: true,
+        seed: 0,
+        ..Default::default()
+    },
+    [
+        (
+            "(time-format 0 \"%q\")",
+            "invalid parameters: time-format does not know the directive %q"
+        ),
+        (
+            "(time-format 0 \"%\")",
+            "invalid parameters: time-format string ends in a lone %"
+        ),
+        (
+            "(time-format \"today\" \"%Y\")",
+            "type error: expected int: received: string"
+        ),
+        (
+            "(time-parse \"1\" \"%q\")",
+            "invalid parameters: time-parse does not know the directive %q"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_list_natives,
+    "lisp/list-natives.lisp",
+    [
+        (
+            "(length 1)",
+            "invalid parameters: length expects a list, received 1"
+        ),
+        (
+            "(append 1 '(2))",
+            "invalid parameters: append expects a list, received 1"
+        ),
+        (
+            "(nth '(1) 'a)",
+            "type error: expected int: received: symbol"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_higher_order_natives,
+    "lisp/higher-order.lisp",
+    [
+        (
+            "(map car 1)",
+            "invalid parameters: map expects a list, received 1"
+        ),
+        (
+            "(filter (lambda (x) x) '(1 2))",
+            "type error: expected bool: received: int"
+        ),
+        (
+            "(fold-left + 0 '(1 a))",
+            "type error: expected int: received: symbol"
+        ),
+        ("(fold-right + 0)", "invalid parameters: fold-right expects"),
+        (
+            "(for-each 1 '(1))",
+            "type error: argument 1 expected function, found int"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_deque,
+    "lisp/deque.lisp",
+    [
+        (
+            "(push-back! '(1) 2)",
+            "type error: expected deque: received: cons"
+        ),
+        (
+            "(pop-front! nil)",
+            "type error: expected deque: received: nil"
+        ),
+        (
+            "(push-front! (queue-create))",
+            "invalid parameters: push-front! expects"
+        ),
+        (
+            "(queue-length 1)",
+            "type error: expected deque: received: int"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_set,
+    "lisp/set.lisp",
+    [
+        (
+            "(set-create '(1))",
+            "invalid parameters: set-create can't hold (1), it can't be hashed"
+        ),
+        (
+            "(set-insert! (set-create) {:a 1})",
+            "invalid parameters: set-insert! can't hold ':a => 1,"
+        ),
+        (
+            "(set-contains? '(1) 1)",
+            "type error: expected set: received: cons"
+        ),
+        (
+            "(set-remove! (queue-create) 1)",
+            "type error: expected set: received: deque"
+        ),
+        ("(set->list 1)", "type error: expected set: received: int"),
+        ("(set-length)", "invalid parameters: set-length expects"),
+    ]
+);
+
+defnativetest!(
+    test_heap,
+    "lisp/heap.lisp",
+    [
+        ("(heap-push! (heap-create) 'a)", "invalid parameters: heap-push! expects a heap, an item and a priority, or no priority if the heap has a less function"),
+        ("(heap-push! (heap-create) 'a 'b)", "type error: argument 3 expected int, found symbol"),
+        ("(heap-push! (heap-create (lambda (a b) true)) 'a 1)", "invalid parameters: heap-push! expects a heap, an item and a priority, or no priority if the heap has a less function"),
+        ("(heap-push! (queue-create) 'a 1)", "type error: expected heap: received: deque"),
+        ("(def h (heap-create (lambda (a b) 1))) (heap-push! h 1) (heap-push! h 2)", "type error: expected bool: received: int"),
+        ("(heap-pop! nil)", "type error: expected heap: received: nil"),
+        ("(heap-create 1 2)", "type error: argument 1 expected function, found int"),
+    ]
+);
+
+defnativetest!(
+    test_alist,
+    "lisp/alist.lisp",
+    [
+        (
+            "(assoc 'a '(1 2))",
+            "invalid parameters: assoc expects a list of pairs, received 1"
+        ),
+        (
+            "(assq 'a 'b)",
+            "invalid parameters: assq expects a list, received 'b"
+        ),
+        (
+            "(alist->map '((a . 1) b))",
+            "invalid parameters: alist->map expects a list of pairs, received 'b"
+        ),
+        (
+            "(alist->map (list (cons '(a) 1)))",
+            "invalid parameters: alist->map can't use ('a) as a map key"
+        ),
+        (
+            "(map->alist '((a . 1)))",
+            "type error: expected map: received: cons"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_read_string,
+    "lisp/read-string.lisp",
+    [
+        (
+            r#"(read-string "(1 2")"#,
+            "other error: error: unexpected end of file before this was closed"
+        ),
+        (
+            r#"(read-string ")")"#,
+            "other error: error: unbalanced parens"
+        ),
+        (
+            r#"(read-string "")"#,
+            "invalid parameters: read-string expects a form to read"
+        ),
+        (
+            r#"(read-string "1 2")"#,
+            "invalid parameters: read-string expects only one form"
+        ),
+        (
+            "(read-string 'a)",
+            "type error: argument 1 expected string, found symbol"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_error_handler,
+    "lisp/error.lisp",
+    [
+        (r#"(error "failed")"#, "error: failed"),
+        (
+            r#"(error "failed" 1 2)"#,
+            "invalid parameters: error expects a message and optional data"
+        ),
+        (
+            "(error 'failed)",
+            "type error: argument 1 expected string, found symbol"
+        ),
+        (
+            "(with-error-handler (lambda (c) c))",
+            "invalid parameters: with-error-handler expects"
+        ),
+        (
+            r#"(with-error-handler 1 (lambda () (error "failed")))"#,
+            "type error: argument 1 expected function, found int"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_char_natives,
+    "lisp/char.lisp",
+    [
+        (
+            "(char->int 97)",
+            "type error: argument 1 expected char, found int"
+        ),
+        (
+            "(int->char (- 0 1))",
+            "invalid parameters: int->char expects a code point, received -1"
+        ),
+        (
+            "(int->char 55296)",
+            "invalid parameters: int->char expects a code point, received 55296"
+        ),
+        (
+            "(int->char 1114112)",
+            "invalid parameters: int->char expects a code point, received 1114112"
+        ),
+        (
+            r#"(char-upcase "a")"#,
+            "type error: argument 1 expected char, found string"
+        ),
+        (
+            "(char-alpha? 'a)",
+            "type error: argument 1 expected char, found symbol"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_symbol_string_conversion,
+    "lisp/symbol-string.lisp",
+    [
+        (
+            r#"(symbol->string "a")"#,
+            "type error: argument 1 expected symbol, found string"
+        ),
+        (
+            "(string->symbol 'a)",
+            "type error: argument 1 expected string, found symbol"
+        ),
+        (
+            r#"(string->symbol "")"#,
+            "invalid parameters: string->symbol expects a non-empty string"
+        ),
+    ]
+);
+
+defnativetest!(
+    test_hash_natives,
+    "lisp/hash.lisp",
+    [
+        (
+            "(sha256 'abc)",
+            "invalid parameters: sha256 expects a string or bytes, received 'abc"
+        ),
+        (
+            "(md5 1)",
+            "invalid parameters: md5 expects a string or bytes, received 1"
+        ),
+        (
+            r#"(base64-decode "YQ=")"#,
+            r#"invalid parameters: base64-decode failed to decode "YQ=": its length isn't a multiple of 4"#
+        ),
+        (
+            r#"(base64-decode "Y===")"#,
+            r#"invalid parameters: base64-decode failed to decode "Y===": it has = where there should be data"#
+        ),
+        (
+            r#"(base64-decode "YQ==YQ==")"#,
+            r#"invalid parameters: base64-decode failed to decode "YQ==YQ==": it has = where there should be data"#
+        ),
+        (
+            r#"(base64-decode "YQ!=")"#,
+            r#"invalid parameters: base64-decode failed to decode "YQ!=": '!' isn't a base64 character"#
+        ),
+        // 0xff isn't utf-8
+        (
+            r#"(base64-decode "/w==")"#,
+            "invalid parameters: base64-decode expects text, use base64-decode-bytes for binary data"
+        ),
+        (
+            r#"(base64-decode-bytes "YQ!=")"#,
+            r#"invalid parameters: base64-decode-bytes failed to decode "YQ!=": '!' isn't a base64 character"#
+        ),
+        (
+            "(base64-decode-bytes (bytes 1))",
+            "type error: expected string: received: bytes"
+        ),
+    ]
+);
+
+// Keeps what each test thread logs so tests running at the same time don't
+// see each other's records.
+struct TestLogger;
+
+thread_local! {
+    static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+impl log::Log for TestLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let line = format!("{} {}: {}", record.level(), record.target(), record.args());
+        LOGGED.with(|logged| logged.borrow_mut().push(line));
+    }
+
+    fn flush(&self) {}
+}
+
+#[test]
+fn test_logging() {
+    static LOGGER: TestLogger = TestLogger;
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let config = native_functions::Config::default();
+    let input = r#"
+        (log-debug "starting")
+        (log-info '(1 2))
+        (log-level :warn)
+        (log-info "dropped")
+        (log-warn "careful")
+        (log-error "failed")
+        (log-level :off)
+        (log-error "also dropped")"#;
+    eval_with_natives(input, &config).unwrap();
+
+    assert_eq!(
+        LOGGED.with(|logged| logged.take()),
+        [
+            "DEBUG lisp: starting",
+            "INFO lisp: (1 2)",
+            "WARN lisp: careful",
+            "ERROR lisp: failed",
+        ]
+    );
+
+    for input in [
+        "(log-info)",
+        r#"(log-info "a" "b")"#,
+        "(log-level :trace)",
+        r#"(log-level "warn")"#,
+    ] {
+        assert!(eval_with_natives(input, &config).is_err(), "{input}");
+    }
+    gc::collect();
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite() {
+    let decls = include_str!("../lib/native/decl/sqlite.lisp");
+    let config = native_functions::Config::default();
+
+    let input = format!(
+        r#"{decls}
+        (def db (sqlite-open ":memory:"))
+        (sqlite-exec db "create table people (name text, age int, photo blob)")
+        (assert (= (sqlite-exec db "insert into people values (?, ?, ?)" "ann" 31 (bytes 1 2)) 1))
+        (assert (= (sqlite-exec db "insert into people values (?, ?, ?)" "bob" 25 nil) 1))
+        (assert (= (sqlite-query db "select name, age from people order by age")
+                   '(("bob" 25) ("ann" 31))))
+        (assert (= (sqlite-query db "select photo from people where name = ?" "ann")
+                   (list (list (bytes 1 2)))))
+        (assert (nil? (sqlite-query db "select * from people where age > ?" 40)))
+        (assert (= (sqlite-query db "select 1.5") '(("1.5"))))
+        (assert (= (sqlite-exec db "update people set age = age + 1") 2))
+        (sqlite-close db)"#
+    );
+    eval_with_natives(&input, &config).unwrap();
+
+    for input in [
+        r#"(sqlite-query (sqlite-open ":memory:") "select * from missing")"#,
+        r#"(sqlite-exec (sqlite-open ":memory:") "not sql")"#,
+        r#"(sqlite-query (sqlite-open ":memory:") "select ?" 'a)"#,
+        r#"(sqlite-query 7 "select 1")"#,
+        r#"(let ((db (sqlite-open ":memory:"))) (sqlite-close db) (sqlite-close db))"#,
+    ] {
+        let input = format!("{decls}\n{input}");
+        assert!(eval_with_natives(&input, &config).is_err(), "{input}");
+    }
+    gc::collect();
+}
+
+defnativetest!(
+    test_terminal,
+    "lisp/terminal.lisp",
+    native_functions::Config {
+        deterministic: true,
+        ..Default::default()
+    },
+    [
+        ("(ansi-code :purple)", "invalid parameters: ansi-code does not know the style ':purple, expected one of :bold :dim :italic :underline :black :red :green :yellow :blue :magenta :cyan :white"),
+        (r#"(ansi-code "red")"#, r#"invalid parameters: ansi-code does not know the style "red", expected one of :bold :dim :italic :underline :black :red :green :yellow :blue :magenta :cyan :white"#),
+        ("(ansi-style 'text :bold)", "type error: argument 1 expected string, found symbol"),
+        (r#"(ansi-style "text" :blink)"#, "invalid parameters: ansi-style does not know the style ':blink, expected one of :bold :dim :italic :underline :black :red :green :yellow :blue :magenta :cyan :white"),
+        ("(is-tty? 1)", "invalid parameters: is-tty? expects"),
+    ]
+);
+
+defnativetest!(
+    test_sort,
+    "lisp/sort.lisp",
+    native_functions::Config {
+        deterministic: true,
+        seed: 7,
+        ..Default::default()
+    },
+    [
+        (
+            "(sort '(2 1) (lambda (a b) 1))",
+            "type error: expected bool: received: int"
+        ),
+        (
+            "(sort '(2 a) (lambda (a b) (< a b)))",
+            "cannot compare this combination of types: symbol int"
+        ),
+        (
+            "(sort 1 (lambda (a b) (< a b)))",
+            "invalid parameters: sort expects a list, received 1"
+        ),
+        (
+            "(sort '(2 1) 1)",
+            "type error: argument 2 expected function, found int"
+        ),
+    ]
+);
+
+#[test]
+fn test_retry() {
+    let input = include_str!("lisp/retry.lisp");
+    let config = native_functions::Config {
+        deterministic: true,
+        seed: 0,
+        ..Default::default()
+    };
+    eval_with_natives(input, &config).unwrap();
+    gc::collect();
+}
+
+#[test]
+fn test_keywords() {
+    assert!(matches!(
+        eval("(= :mode (quote :mode))").unwrap().unwrap(),
+        vm::Object::Bool(true)
+    ));
+    gc::collect();
+}
+
+#[test]
+fn test_write_file_options() {
+    let path = std::env::temp_dir().join(format!("lisp-write-file-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let config = native_functions::Config::default();
+
+    let input = format!(
+        "(write-file \"{path}\" \"a\")
+         (write-file \"{path}\" \"b\" :mode :append :create false)
+         (read-file \"{path}\")"
+    );
+    let contents = eval_with_natives(&input, &config).unwrap().unwrap();
+    assert_eq!(contents.as_string().unwrap().as_str(), "ab");
+
+    for options in [
+        ":mode :sideways",
+        ":mode \"append\"",
+        ":create 1",
+        ":colour :red",
+        ":mode :append :mode :truncate",
+        ":mode",
+        "mode :append",
+    ] {
+        let input = format!("(write-file \"{path}\" \"c\" {options})");
+        assert!(eval_with_natives(&input, &config).is_err(), "{options}");
+    }
+
+    std::fs::remove_file(path).unwrap();
+
+    let input = format!("(write-file \"{path}\" \"c\" :create false)");
+    assert!(eval_with_natives(&input, &config).is_err());
+    gc::collect();
+}
+
+defnativetest!(
+    test_bytes,
+    "lisp/bytes.lisp",
+    [
+        (
+            "(bytes 256)",
+            "invalid parameters: bytes expects ints from 0 to 255, received 256"
+        ),
+        (
+            "(bytes (- 0 1))",
+            "invalid parameters: bytes expects ints from 0 to 255, received -1"
+        ),
+        ("(bytes 'a)", "type error: expected int: received: symbol"),
+        (
+            "(bytes-ref (bytes 1) 1)",
+            "invalid parameters: bytes-ref index 1 is out of range for 1 bytes"
+        ),
+        (
+            "(bytes-slice (bytes 1 2) 1 3)",
+            "invalid parameters: bytes-slice range 1..3 is out of range for 2 bytes"
+        ),
+        (
+            "(bytes-slice (bytes 1 2) 2 1)",
+            "invalid parameters: bytes-slice range 2..1 is out of range for 2 bytes"
+        ),
+        (
+            "(utf8->string (bytes 255))",
+            "invalid parameters: utf8->string found invalid utf-8 at byte 0"
+        ),
+        (
+            r#"(bytes-length "abc")"#,
+            "type error: expected bytes: received: string"
+        ),
+        (
+            "(sha256 'abc)",
+            "invalid parameters: sha256 expects a string or bytes, received 'abc"
+        ),
+    ]
+);
+
+// bytes that aren't utf-8 survive a trip through a file
+#[test]
+fn test_bytes_file() {
+    let config = native_functions::Config::default();
+    let path = std::env::temp_dir().join(format!("lisp-bytes-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let input = format!(
+        "(write-file-bytes \"{path}\" (bytes 0 159 146 150))
+         (read-file-bytes \"{path}\")"
+    );
+    let result = eval_with_natives(&input, &config).unwrap().unwrap();
+    assert_eq!(result.to_string(), "#u8(0 159 146 150)");
+    std::fs::remove_file(path).unwrap();
+    gc::collect();
+}
+
+#[test]
+fn test_filesystem() {
+    let dir = std::env::temp_dir().join(format!("lisp-filesystem-{}", std::process::id()));
+    let dir = dir.to_str().unwrap();
+    let config = native_functions::Config::default();
+
+    let input = format!(
+        "(create-dir \"{dir}/a/b\")
+         (create-dir \"{dir}/a/b\")
+         (write-file \"{dir}/a/one\" \"12345\")
+         (copy-file \"{dir}/a/one\" \"{dir}/a/two\")
+         (rename-file \"{dir}/a/two\" \"{dir}/a/three\")
+         (def file (file-metadata \"{dir}/a/three\"))
+         (def dir (file-metadata \"{dir}/a/b\"))
+         (list (list-dir \"{dir}/a\")
+               (map-retrieve file :size)
+               (map-retrieve file :is-dir)
+               (map-retrieve dir :is-dir)
+               (> (map-retrieve file :mtime) 0))"
+    );
+    let result = eval_with_natives(&input, &config).unwrap().unwrap();
+    assert_eq!(
+        result.to_string(),
+        r#"(("b" "one" "three") 5 false true true)"#
+    );
+
+    // without :recursive the directory has to be empty
+    let input = format!("(remove-dir \"{dir}/a\")");
+    assert!(eval_with_natives(&input, &config).is_err());
+
+    let input = format!("(remove-dir \"{dir}\" :recursive true)");
+    eval_with_natives(&input, &config).unwrap();
+    assert!(!std::path::Path::new(dir).exists());
+
+    for input in [
+        format!("(list-dir \"{dir}\")"),
+        format!("(file-metadata \"{dir}\")"),
+        format!("(copy-file \"{dir}/missing\" \"{dir}/copy\")"),
+    ] {
+        assert!(eval_with_natives(&input, &config).is_err(), "{input}");
+    }
+    gc::collect();
+}
+
+#[test]
+fn test_ports() {
+    let path = std::env::temp_dir().join(format!("lisp-ports-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let config = native_functions::Config::default();
+
+    let input = format!(
+        "(def out (open \"{path}\" :mode :write))
+         (write-string out \"first\nsecond\")
+         (flush out)
+         (close out)
+         (def out (open \"{path}\" :mode :append))
+         (write-string out \"\n\u{e9}t\u{e9}\")
+         (close out)
+         (def in (open \"{path}\"))
+         (def lines (list (read-line in) (read-line in)))
+         (def chars (list (read-char in) (read-char in) (read-char in)))
+         (def end (list (read-char in) (read-line in)))
+         (close in)
+         (list lines chars end)"
+    );
+    let result = eval_with_natives(&input, &config).unwrap().unwrap();
+    assert_eq!(
+        result.to_string(),
+        "((\"first\" \"second\") ('\u{e9}' 't' '\u{e9}') (nil nil))"
+    );
+
+    for input in [
+        format!("(write-string (open \"{path}\") \"x\")"),
+        format!("(read-line (open \"{path}\" :mode :append))"),
+        format!("(let ((in (open \"{path}\"))) (close in) (read-char in))"),
+        "(read-line \"not a port\")".to_string(),
+    ] {
+        assert!(eval_with_natives(&input, &config).is_err(), "{input}");
+    }
+
+    std::fs::remove_file(path).unwrap();
+
+    assert!(eval_with_natives(&format!("(open \"{path}\")"), &config).is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_environment() {
+    let config = native_functions::Config {
+        args: vec!["-v".to_string(), "input.txt".to_string()],
+        ..Default::default()
+    };
+    let args = eval_with_natives("(command-line-args)", &config)
+        .unwrap()
+        .unwrap();
+    assert_eq!(args.to_string(), r#"("-v" "input.txt")"#);
+
+    let name = format!("LISP_TEST_ENVIRONMENT_{}", std::process::id());
+    let input = format!(
+        "(assert (nil? (getenv \"{name}\")))
+         (setenv \"{name}\" \"set\")
+         (getenv \"{name}\")"
+    );
+    let value = eval_with_natives(&input, &config).unwrap().unwrap();
+    assert_eq!(value.as_string().unwrap().as_str(), "set");
+
+    assert!(eval_with_natives("(setenv \"A=B\" \"c\")", &config).is_err());
+
+    // the host environment is left out of deterministic runs
+    let config = native_functions::Config {
+        deterministic: true,
+        ..Default::default()
+    };
+    assert!(eval_with_natives(&format!("(getenv \"{name}\")"), &config).is_err());
+    assert!(matches!(
+        eval_with_natives("(command-line-args)", &config).unwrap(),
+        Some(vm::Object::Nil)
+    ));
+    gc::collect();
+}
+
+#[test]
+fn test_ids() {
+    let input = "(list (ulid) (ulid) (ulid) (next-id 'a) (next-id 'a) (next-id 'b))";
+    let config = native_functions::Config {
+        deterministic: true,
+        seed: 42,
+        ..Default::default()
+    };
+    let ids = eval_with_natives(input, &config).unwrap().unwrap();
+    let ids = ids
+        .as_cons()
+        .unwrap()
+        .borrow()
+        .iter_cars()
+        .collect::<Vec<_>>();
+    let ulids = ids[..3]
+        .iter()
+        .map(|id| id.as_string().unwrap().to_string())
+        .collect::<Vec<_>>();
+
+    assert!(ulids.iter().all(|ulid| ulid.len() == 26));
+    assert!(ulids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(matches!(
+        ids[3..],
+        [vm::Object::Int(1), vm::Object::Int(2), vm::Object::Int(1)]
+    ));
+
+    let again = eval_with_natives(input, &config).unwrap().unwrap();
+    assert_eq!(
+        again
+            .as_cons()
+            .unwrap()
+            .borrow()
+            .iter_cars()
+            .next()
+            .unwrap()
+            .to_string(),
+        ids[0].to_string()
+    );
+    gc::collect();
+}
+
+#[test]
+fn test_repl_restarts() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    let input = "(car 1) (+ 1 1)\nskip-form\n(car 1) (+ 2 2)\nabort\n(+ 10 (car 1))\nuse-value\n(car 2)\n(+ 3 3)\n(def f (lambda (x) (* 2 (car x))))\n(f 1)\n1\n4\nnope\n";
+    let mut output = Vec::new();
+
+    lisp::repl::Repl::new(&mut interpreter, input.as_bytes(), &mut output)
+        .run()
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.contains("[retry]"));
+    assert!(output.contains("2\n"));
+    assert!(!output.contains("4\n"));
+    // use-value carries on with the failed call's value, after a value form
+    // that fails itself
+    assert!(output.contains("16\n"));
+    assert!(output.contains("8\n"));
+    assert_eq!(output.matches("value> ").count(), 3);
+    // nope doesn't compile, so there's nothing to retry
+    assert!(output.contains("0: [skip-form]"));
+}
+
+#[test]
+fn test_repl_multiline() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    let input = "(def add\n  (lambda (a b)\n    (+ a b)))\n(add 1\n 2) \"a\nb\"\n(car\n";
+    let mut output = Vec::new();
+
+    lisp::repl::Repl::new(&mut interpreter, input.as_bytes(), &mut output)
+        .run()
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+
+    assert_eq!(output.matches(".. ").count(), 5);
+    assert!(output.contains("3\n"));
+    assert!(output.contains("\"a\nb\"\n"));
+    assert!(output.contains("unexpected end of file"));
+    gc::collect();
+}
+
+#[test]
+fn test_repl_completion() {
+    let manifest = lisp::Manifest::standard().search_path("tests/lisp/modules");
+    let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(def greeting-loads 0) (require greeting) (def my-global 1)",
+        })
+        .unwrap();
+    interpreter.eval().unwrap();
+
+    let names = interpreter.names();
+    let complete = |line: &str| lisp::repl::complete(&names, line, line.len());
+
+    assert_eq!(complete("(my-gl"), (1, vec!["my-global".to_string()]));
+    assert_eq!(complete("(defcon"), (1, vec!["defconst".to_string()]));
+    assert!(complete("(+ 1 (greeting::")
+        .1
+        .contains(&"greeting::double".to_string()));
+    assert!(complete("'(set-ins").1.contains(&"set-insert!".to_string()));
+    assert_eq!(complete("(car nope-").1, Vec::<String>::new());
+    assert_eq!(complete("").0, 0);
+    gc::collect();
+}
+
+#[test]
+fn test_repl_commands() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    let input = r#"(def greeting-loads 0)
+:type (+ greeting-loads 1)
+:disasm (+ 1 2)
+:time (+ 20 1)
+:load tests/lisp/modules/greeting.lisp
+(greeting::double greeting-loads)
+:load nonexistent.lisp
+:reset
+greeting-loads
+skip-form
+:type (+ 1
+  "a")
+"#;
+    let mut output = Vec::new();
+
+    lisp::repl::Repl::new(&mut interpreter, input.as_bytes(), &mut output)
+        .run()
+        .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.contains("> int\n"));
+    assert!(output.contains("   2  AddInt                             ; repl:1\n"));
+    assert!(output.contains("21\ntime: "));
+    assert!(output.contains("> 2\n"));
+    assert!(output.contains("failed to open nonexistent.lisp"));
+    // reset forgot greeting-loads
+    assert!(output.contains("unknown variable referenced: greeting-loads"));
+    assert!(output.contains(".. type error: arithmetic operand expected int, found string"));
+    assert!(!output.contains("error: error:"));
+    gc::collect();
+}
+
+#[test]
+fn test_module_isolation() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    let load = |interpreter: &mut lisp::Interpreter, source: &'static str| {
+        interpreter.load(&lisp::Source::Str {
+            name: "test input",
+            source,
+        })
+    };
+
+    load(
+        &mut interpreter,
+        "(module counter)
+         (def count 0)
+         (def next (lambda () (set! count (+ counter::count 1)) count))
+         (export next)",
+    )
+    .unwrap();
+
+    load(&mut interpreter, "(counter::next) (counter::next)").unwrap();
+    interpreter.eval().unwrap();
+
+    // the module ended with its file
+    assert!(load(&mut interpreter, "next").is_err());
+    assert!(load(&mut interpreter, "counter::count").is_err());
+    assert!(load(&mut interpreter, "(module other) (export nope)").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_require_search_path() {
+    let manifest = lisp::Manifest::standard().search_path("tests/lisp/modules");
+    let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(def greeting-loads 0)
+                     (require greeting)
+                     (require greeting)
+                     (if (= greeting-loads 1) (greeting::double 2) (car 1))",
+        })
+        .unwrap();
+
+    interpreter.eval().unwrap();
+
+    assert!(interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(require nonexistent)",
+        })
+        .is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_require_alias_and_only() {
+    let manifest = lisp::Manifest::standard().search_path("tests/lisp/modules");
+    let load = |source: &'static str| {
+        let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+        for source in ["(def greeting-loads 0)", source] {
+            interpreter.load(&lisp::Source::Str {
+                name: "test input",
+                source,
+            })?;
+        }
+        interpreter.eval()
+    };
+
+    load("(require greeting :as g) (g::double (greeting::triple 1))").unwrap();
+    load("(require greeting :only (double)) (double (greeting::double 1))").unwrap();
+
+    assert!(load("(require greeting :only (double)) (triple 1)").is_err());
+    assert!(load("(require greeting :only (double)) (greeting::triple 1)").is_err());
+    assert!(load("(require greeting :only (nope))").is_err());
+    assert!(load("(require greeting :as)").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_require_macros() {
+    let manifest = lisp::Manifest::standard().search_path("tests/lisp/modules");
+    let load = |source: &'static str| {
+        let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+        interpreter.load(&lisp::Source::Str {
+            name: "test input",
+            source,
+        })?;
+        interpreter.eval()
+    };
+
+    load("(require unless) (my-unless (= 1 2) unless::one)").unwrap();
+    load("(require unless :only (my-unless)) (my-unless (= 1 2) 1)").unwrap();
+    load("(require unless) (unless::my-unless (= 1 2) 1)").unwrap();
+    load("(require unless :as u) (u::my-unless (= 1 2) (unless::my-unless (= 1 2) 1))").unwrap();
+    load("(require unless :as u :only (my-unless)) (my-unless (= 1 2) 1)").unwrap();
+
+    // private-unless is compiled as a function call to an unknown variable
+    assert!(load("(require unless) (private-unless (= 1 2) 1)").is_err());
+    assert!(load("(require unless) (unless::private-unless (= 1 2) 1)").is_err());
+    // required under an alias, the macro is only usable through it
+    assert!(load("(require unless :as u) (my-unless (= 1 2) 1)").is_err());
+    assert!(load("(require unless :only (one)) (unless::my-unless (= 1 2) 1)").is_err());
+    // nothing was required
+    assert!(load("(my-unless (= 1 2) 1)").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_require_cache() {
+    let cache_dir = std::env::temp_dir().join(format!("lisp-test-cache-{}", std::process::id()));
+    let manifest = lisp::Manifest::standard()
+        .search_path("tests/lisp/modules")
+        .cache_dir(&cache_dir);
+
+    // the first run writes the cache and the second loads from it
+    for _ in 0..2 {
+        let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+        interpreter
+            .load(&lisp::Source::Str {
+                name: "test input",
+                source: "(def greeting-loads 0)
+                         (require greeting)
+                         (require unless)
+                         (my-unless (= greeting-loads 1) (car 1))
+                         (my-unless (= (greeting::double unless::one) 2) (car 1))",
+            })
+            .unwrap();
+        interpreter.eval().unwrap();
+    }
+
+    let entries = std::fs::read_dir(&cache_dir).unwrap().count();
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+
+    assert_eq!(entries, 2);
+    gc::collect();
+}
+
+#[test]
+fn test_require_cache_prelude() {
+    let cache_dir =
+        std::env::temp_dir().join(format!("lisp-test-cache-prelude-{}", std::process::id()));
+
+    // a cached module isn't reused after the macros it was compiled with change
+    for (prelude, value) in [
+        ("(defmacro prelude-value () 1)", 1),
+        ("(defmacro prelude-value () 2)", 2),
+        ("(defmacro prelude-value () 2)", 2),
+    ] {
+        let manifest = lisp::Manifest::standard()
+            .source(lisp::Source::Str {
+                name: "prelude",
+                source: prelude,
+            })
+            .search_path("tests/lisp/modules")
+            .cache_dir(&cache_dir);
+        let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+
+        assert_eq!(
+            interpreter
+                .eval_str("(require prelude) prelude::value")
+                .unwrap(),
+            vm::Object::Int(value)
+        );
+    }
+
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+    gc::collect();
+}
+
+#[test]
+fn test_lbc() {
+    let path = std::env::temp_dir().join(format!("lisp-test-{}.lbc", std::process::id()));
+    let manifest = lisp::Manifest::standard()
+        .search_path("tests/lisp/modules")
+        .keep_program();
+    let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(def greeting-loads 0)
+                     (require greeting)
+                     (require unless)
+                     (def result (map greeting::double (list unless::one 2)))
+                     (my-unless (= greeting-loads 1) (car 1))",
+        })
+        .unwrap();
+
+    let program = interpreter.program();
+    let header = lisp::lbc::header(&interpreter, &program);
+    assert_eq!(
+        header
+            .modules
+            .iter()
+            .map(|(module, _)| module.as_str())
+            .collect::<Vec<_>>(),
+        ["greeting", "unless"]
+    );
+    assert!(header.natives.is_empty());
+    lisp::lbc::write(&path, &program, &header).unwrap();
+
+    // nothing but natives, everything else comes from the file
+    let manifest = lisp::Manifest::default().natives(native_functions::Config::default());
+    let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+    interpreter.opcode_table = lisp::lbc::read(&path).unwrap();
+    interpreter.eval().unwrap();
+    interpreter.vm.get_global("result").unwrap();
+    let result = interpreter.vm.pop().unwrap().into_object();
+
+    // a program needing natives or a bootstrap this build doesn't have is
+    // refused before it runs
+    let missing = lisp::lbc::Header {
+        natives: vec!["sqlite".to_string()],
+        ..header.clone()
+    };
+    lisp::lbc::write(&path, &program, &missing).unwrap();
+    assert_eq!(lisp::lbc::read_header(&path).unwrap(), missing);
+    assert!(missing
+        .to_string()
+        .starts_with("natives: sqlite\nmanifest: "));
+    assert!(missing.to_string().contains("\nmodule: greeting "));
+    match lisp::lbc::read(&path) {
+        Ok(_) => assert!(native_functions::has_group("sqlite")),
+        Err(error) => {
+            assert!(!native_functions::has_group("sqlite"));
+            assert!(
+                error.to_string().contains("calls the sqlite natives"),
+                "{error}"
+            );
+        }
+    }
+
+    let stale = lisp::lbc::Header {
+        manifest_hash: header.manifest_hash ^ 1,
+        ..header
+    };
+    lisp::lbc::write(&path, &program, &stale).unwrap();
+    let error = lisp::lbc::read(&path).err().unwrap().to_string();
+    assert!(error.contains("different bootstrap"), "{error}");
+
+    std::fs::write(&path, "(def not-bytecode 1)").unwrap();
+    assert!(lisp::lbc::read(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(result.to_string(), "(2 4)");
+    gc::collect();
+}
+
+#[test]
+fn test_pack() {
+    let dir = std::env::temp_dir().join(format!("lisp-test-pack-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let runner = dir.join("runner");
+    let packed = dir.join("packed");
+    std::fs::write(&runner, "not really an executable").unwrap();
+
+    let mut interpreter =
+        lisp::Interpreter::with_manifest(&lisp::Manifest::standard().keep_program()).unwrap();
+    interpreter
+        .load(&lisp::Source::Str {
+            name: "test input",
+            source: "(def result (reverse (list 1 2 3)))",
+        })
+        .unwrap();
+    let program = interpreter.program();
+    let header = lisp::lbc::header(&interpreter, &program);
+
+    let manifest = lisp::Manifest::default().natives(native_functions::Config::default());
+
+    for release in [false, true] {
+        lisp::lbc::pack(&runner, &packed, &program, &header, release).unwrap();
+
+        let unpacked = lisp::lbc::unpack(&packed).unwrap().unwrap();
+        let span = &unpacked.debug()[0];
+        assert_eq!(span.context().source().is_empty(), release);
+
+        let mut interpreter = lisp::Interpreter::with_manifest(&manifest).unwrap();
+        interpreter.opcode_table = unpacked;
+        interpreter.eval().unwrap();
+        interpreter.vm.get_global("result").unwrap();
+        let result = interpreter.vm.pop().unwrap().into_object();
+        assert_eq!(result.to_string(), "(3 2 1)");
+    }
+
+    assert!(lisp::lbc::unpack(&runner).unwrap().is_none());
+    std::fs::remove_dir_all(&dir).unwrap();
+    gc::collect();
+}
+
+#[test]
+fn test_deftest() {
+    let files =
+        lisp::test_runner::discover(&[std::path::PathBuf::from("tests/lisp/deftest")]).unwrap();
+    assert_eq!(
+        files,
+        [
+            "tests/lisp/deftest/arithmetic.lisp",
+            "tests/lisp/deftest/nested/broken.lisp",
+            "tests/lisp/deftest/nested/lists.lisp",
+        ]
+        .map(std::path::PathBuf::from)
+    );
+
+    let manifest = lisp::Manifest::standard();
+    let results = lisp::test_runner::run_file(&manifest, &files[0]).unwrap();
+    let names = results.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["doubles", "fails", "errors"]);
+
+    assert!(results[0].error.is_none());
+    // failures point into the test file, not at an expansion
+    let error = results[1].error.as_ref().unwrap();
+    assert!(error.contains("arithmetic.lisp:7:3"), "{error}");
+    assert!(results[2].error.is_some());
+
+    assert!(lisp::test_runner::run_file(&manifest, &files[1]).is_err());
+
+    let results = lisp::test_runner::run_file(&manifest, &files[2]).unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| r.error.is_none()));
+
+    for input in ["(deftest)", "(deftest 1 2)", "(deftest (a) 1)"] {
+        assert!(eval(input).is_err(), "{input}");
+    }
+    gc::collect();
+}
+
+#[test]
+fn test_defbench() {
+    // the virtual clock moves on a millisecond each time it's read, so every
+    // iteration takes exactly that long
+    let config = native_functions::Config {
+        deterministic: true,
+        ..Default::default()
+    };
+    let manifest = lisp::Manifest::default()
+        .natives(config)
+        .source(lisp::Source::Path("lib/bootstrap/bootstrap.lisp".into()))
+        .source(lisp::Source::Path("lib/native/decl/native.lisp".into()));
+
+    let results =
+        lisp::bench_runner::run_file(&manifest, "tests/lisp/defbench/sums.lisp".as_ref(), 2, 5)
+            .unwrap();
+    let names = results.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["small", "large", "loop", "start"]);
+
+    for result in &results {
+        assert_eq!(result.samples, [1_000_000; 5]);
+        assert_eq!(result.mean(), std::time::Duration::from_millis(1));
+        assert_eq!(result.stddev(), std::time::Duration::ZERO);
+    }
+
+    let error =
+        lisp::bench_runner::run_file(&manifest, "tests/lisp/defbench/broken.lisp".as_ref(), 2, 5)
+            .err()
+            .unwrap()
+            .to_string();
+    assert!(error.contains("broken.lisp:2:3"), "{error}");
+
+    for input in ["(defbench)", "(defbench 1 2)", "(defbench (a) 1)"] {
+        assert!(eval(input).is_err(), "{input}");
+    }
+    gc::collect();
+}
+
+#[test]
+fn test_disasm_listing() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    let mut opcode_table = OpCodeTable::new();
+    let input = r#"(def add (lambda (a b)
+  "adds"
+  (+ a b)))
+(add 1 (length '(x)))
+(map (lambda (x) (print "hi")) nil)"#;
+
+    lisp::compile_str(
+        input,
+        "listing.lisp",
+        &mut interpreter.il_compiler,
+        &mut interpreter.ast_compiler,
+        &mut interpreter.vm,
+        &mut opcode_table,
+    )
+    .unwrap();
+
+    let listing = lisp::disasm::listing(&opcode_table);
+    let lines = listing.lines().collect::<Vec<_>>();
+
+    assert_eq!(
+        lines[..7],
+        [
+            ";; constants",
+            "   0  string     \"adds\"",
+            "   1  global     add",
+            "   2  global     length",
+            "   3  symbol     x",
+            "   4  global     map",
+            "   5  global     print",
+        ]
+    );
+    assert!(lines.contains(&"   0  Lambda arity 2                     ; listing.lisp:1"));
+    assert!(lines.contains(&"  ;; add (lambda (a b)"));
+    assert!(lines.contains(&"     2  Add                              ; listing.lisp:3"));
+    assert!(lines.contains(&"   1  DefGlobal add                      ; listing.lisp:1"));
+    assert!(lines.contains(&"  ;; (lambda (x) (print \"hi\"))"));
+    gc::collect();
+}
+
+#[test]
+fn test_disasm_json() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    let mut opcode_table = OpCodeTable::new();
+
+    lisp::compile_str(
+        r#"(def f (lambda (a &rest b) "say \ hi" a))"#,
+        "json.lisp",
+        &mut interpreter.il_compiler,
+        &mut interpreter.ast_compiler,
+        &mut interpreter.vm,
+        &mut opcode_table,
+    )
+    .unwrap();
+
+    let json = lisp::disasm::to_json(&opcode_table);
+
+    assert!(json.starts_with(
+        r#"{"constants":[{"kind":"string","text":"say \\ hi"},{"kind":"global","text":"f"}],"code":[{"index":0,"op":"Lambda","operands":[],"span":{"file":"json.lisp","start":7,"end":40,"line":1,"column":8},"lambda":{"name":"f","label":"(lambda (a &rest b) \"say \\ hi\" a)","arity":{"required":1,"optional":0,"rest":true},"doc":"say \\ hi","body":[{"index":0,"op":"GetLocal","operands":[0],"#
+    ), "{json}");
+    assert!(json.ends_with(r#"{"index":1,"op":"DefGlobal","operands":["f"],"span":{"file":"json.lisp","start":0,"end":41,"line":1,"column":1}}]}"#), "{json}");
+    gc::collect();
+}
+
+#[test]
+fn test_run_stream() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    interpreter.compile_str("(def x 2)", "-e").unwrap();
+    interpreter.eval().unwrap();
+
+    // each form runs before the next is read, so a later error doesn't stop
+    // the ones before it
+    let input = "(def y (* x\n  21))\n(set! x y)\n(car 1)\n(set! x 0)\n";
+    let error = interpreter
+        .run_stream(input.as_bytes(), "-")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("-:4:1"), "{error}");
+
+    interpreter.opcode_table = OpCodeTable::new();
+    interpreter.compile_str("(assert (= x 42))", "-e").unwrap();
+    interpreter.eval().unwrap();
+
+    assert!(interpreter.run_stream("(car".as_bytes(), "-").is_err());
+    gc::collect();
+}
+
+#[test]
+fn test_tracer() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    let lines = Rc::new(std::cell::RefCell::new(Vec::new()));
+
+    interpreter
+        .compile_str(
+            "(def f (lambda (n) (if (= n 0) 'done (f (- n 1)))))\n(f 1)",
+            "trace.lisp",
+        )
+        .unwrap();
+
+    let trace = lines.clone();
+    interpreter
+        .vm
+        .set_tracer(Some(move |pc, opcode: &OpCode<Span>, span: &Span| {
+            trace.borrow_mut().push(format!(
+                "{} {pc} {}",
+                lisp::disasm::location(span),
+                lisp::disasm::instruction(opcode)
+            ))
+        }));
+    interpreter.eval().unwrap();
+
+    let trace = lines.borrow();
+    assert_eq!(trace[0], "trace.lisp:1 0 Lambda arity 1");
+    assert_eq!(trace[2], "trace.lisp:2 2 GetGlobal f");
+    // the body of f runs twice, the second time taking the other branch
+    assert_eq!(
+        trace
+            .iter()
+            .filter(|line| line.ends_with(" 3 Branch 2"))
+            .count(),
+        2
+    );
+    assert!(trace.contains(&"trace.lisp:1 4 PushSymbol done".to_string()));
+    assert_eq!(trace.last().unwrap(), "trace.lisp:1 11 Return");
+    let len = trace.len();
+    drop(trace);
+
+    // without a tracer nothing more is recorded
+    interpreter
+        .vm
+        .set_tracer(None::<fn(usize, &OpCode<Span>, &Span)>);
+    interpreter.eval().unwrap();
+    assert_eq!(lines.borrow().len(), len);
+    gc::collect();
+}
+
+#[test]
+fn test_lsp() {
+    use serde_json::json;
+
+    let dir = std::fs::canonicalize("tests/lisp/lsp").unwrap();
+    let uri = lisp::lsp::path_to_uri(&dir.join("main.lisp"));
+    let text = std::fs::read_to_string(dir.join("main.lisp")).unwrap();
+    let mut server = lisp::lsp::Server::default();
+
+    let replies = server.handle(&json!({"jsonrpc": "2.0", "id": 1, "method": "initialize"}));
+    assert_eq!(
+        replies[0]["result"]["capabilities"]["definitionProvider"],
+        true
+    );
+
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {"textDocument": {"uri": uri, "text": text}},
+    }));
+    assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+
+    // shapes::area goes to the module that defines it
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "textDocument/definition",
+        "params": {"textDocument": {"uri": uri}, "position": {"line": 3, "character": 20}},
+    }));
+    assert_eq!(
+        replies[0]["result"],
+        json!({
+            "uri": lisp::lsp::path_to_uri(&dir.join("shapes.lisp")),
+            "range": {"start": {"line": 2, "character": 0}, "end": {"line": 2, "character": 51}},
+        })
+    );
+
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 3,
+        "method": "textDocument/completion",
+        "params": {"textDocument": {"uri": uri}, "position": {"line": 3, "character": 16}},
+    }));
+    let labels = replies[0]["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(labels[..3], ["side", "square", "shapes::area"]);
+    assert!(labels.contains(&"set!"));
+    assert!(labels.iter().all(|label| label.starts_with('s')));
+
+    // an edit in progress still has what the rest of the document defines,
+    // past a form that doesn't compile and up to one that can't be read
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didChange",
+        "params": {
+            "textDocument": {"uri": uri, "version": 2},
+            "contentChanges": [{"text": "(def x (car))\n(def helper (lambda () 1))\n(helper (hel"}],
+        },
+    }));
+    assert!(replies.is_empty());
+
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 4,
+        "method": "textDocument/definition",
+        "params": {"textDocument": {"uri": uri}, "position": {"line": 2, "character": 3}},
+    }));
+    assert_eq!(
+        replies[0]["result"]["range"],
+        json!({"start": {"line": 1, "character": 0}, "end": {"line": 1, "character": 26}})
+    );
+
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": 5,
+        "method": "textDocument/completion",
+        "params": {"textDocument": {"uri": uri}, "position": {"line": 2, "character": 12}},
+    }));
+    let labels = replies[0]["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, ["helper"]);
+
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didSave",
+        "params": {
+            "textDocument": {"uri": uri},
+            "text": "(def x (car))\n(def y (lambda (unused) 1))\n(+ 1",
+        },
+    }));
+    let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diagnostics.len(), 3);
+    assert_eq!(diagnostics[0]["message"], "invalid expression");
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({"start": {"line": 0, "character": 7}, "end": {"line": 0, "character": 12}})
+    );
+    assert_eq!(
+        diagnostics[1]["range"]["start"],
+        json!({"line": 2, "character": 0})
+    );
+    assert_eq!(diagnostics[2]["severity"], 2);
+    assert_eq!(diagnostics[2]["code"], "unused-parameter");
+
+    // the range is the error's span, all of it when it runs over lines
+    let replies = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didSave",
+        "params": {"textDocument": {"uri": uri}, "text": "(def x (car\n))"},
+    }));
+    assert_eq!(
+        replies[0]["params"]["diagnostics"][0]["range"],
+        json!({"start": {"line": 0, "character": 7}, "end": {"line": 1, "character": 1}})
+    );
+
+    let replies = server.handle(&json!({"jsonrpc": "2.0", "id": 6, "method": "hover"}));
+    assert_eq!(replies[0]["error"]["code"], -32601);
+
+    assert_eq!(
+        lisp::lsp::uri_to_path("file:///a%20b/c.lisp"),
+        std::path::PathBuf::from("/a b/c.lisp")
+    );
+    assert_eq!(
+        lisp::lsp::path_to_uri("/a b/c.lisp".as_ref()),
+        "file:///a%20b/c.lisp"
+    );
+    gc::collect();
+}
+
+#[test]
+fn test_lispfmt() {
+    let messy = std::fs::read_to_string("tests/lisp/fmt/messy.lisp").unwrap();
+    let formatted = std::fs::read_to_string("tests/lisp/fmt/formatted.lisp").unwrap();
+
+    assert_eq!(
+        lisp::formatter::format(&messy, "messy.lisp").unwrap(),
+        formatted
+    );
+    assert_eq!(
+        lisp::formatter::format(&formatted, "formatted.lisp").unwrap(),
+        formatted
+    );
+
+    let context = Rc::new(reader::Context::new(&formatted, "formatted.lisp"));
+    let mut reader = Reader::new(&context);
+    reader.by_ref().for_each(drop);
+    assert_eq!(
+        reader
+            .comments()
+            .iter()
+            .map(|comment| &formatted[comment.clone()])
+            .collect::<Vec<_>>(),
+        [
+            ";; squares, from a list of numbers",
+            "; one per x",
+            ";; the sum",
+            "; fallback"
+        ]
+    );
+
+    // formatting never changes what a file reads as
+    for entry in std::fs::read_dir("tests/lisp").unwrap() {
+        let path = entry.unwrap().path();
+
+        if path.extension().is_none_or(|extension| extension != "lisp") {
+            continue;
+        }
+
+        let source = std::fs::read_to_string(&path).unwrap();
+        let formatted = lisp::formatter::format(&source, "source.lisp").unwrap();
+        let read = |source: &str| {
+            let context = Rc::new(reader::Context::new(source, "source.lisp"));
+            Reader::new(&context)
+                .map(|sexpr| sexpr.unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(read(&source), read(&formatted), "{}", path.display());
+    }
+    gc::collect();
+}
+
+#[test]
+fn test_profile() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+    let opcodes = Rc::new(std::cell::Cell::new(0));
+
+    interpreter
+        .compile_str(
+            "(def fib (lambda (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))))
+             (def sq (lambda (x) (* x x)))
+             (fib 3)
+             (map sq (list 1 2))",
+            "profile.lisp",
+        )
+        .unwrap();
+
+    let counter = opcodes.clone();
+    interpreter
+        .vm
+        .set_tracer(Some(move |_, _: &OpCode<Span>, _: &Span| {
+            counter.set(counter.get() + 1)
+        }));
+    interpreter.vm.set_profiling(true);
+    interpreter.eval().unwrap();
+
+    let profile = interpreter.vm.profile();
+    let folded = lisp::profile::folded(&profile);
+    let lines = folded.lines().collect::<Vec<_>>();
+
+    // every opcode is counted under exactly one stack
+    assert_eq!(
+        profile.iter().map(|(_, count)| count).sum::<u64>(),
+        opcodes.get()
+    );
+    assert!(lines.contains(&"main;fib (profile.lisp:3) 16"));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("main;fib (profile.lisp:3);fib (profile.lisp:1) ")));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("main;map (profile.lisp:4)")));
+    assert!(!lines.iter().any(|line| line
+        .starts_with("main;fib (profile.lisp:3);fib (profile.lisp:1);fib (profile.lisp:1);fib")));
+
+    let svg = lisp::profile::flamegraph(&folded, "profile.lisp").unwrap();
+    assert!(String::from_utf8(svg)
+        .unwrap()
+        .contains("fib (profile.lisp:3)"));
+
+    interpreter.vm.set_profiling(false);
+    assert!(interpreter.vm.profile().is_empty());
+    gc::collect();
+}
+
+#[test]
+fn test_lint() {
+    let lint = |config: &lisp::lint::Config| {
+        let mut interpreter = lisp::Interpreter::new().unwrap();
+
+        lisp::lint::lint(
+            "tests/lisp/lint/shapes.lisp".as_ref(),
+            config,
+            &mut interpreter.il_compiler,
+            &mut interpreter.ast_compiler,
+            &mut interpreter.vm,
+            &mut interpreter.opcode_table,
+        )
+        .unwrap()
+    };
+
+    let diagnostics = lint(&lisp::lint::Config::default());
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<_>>(),
+        [
+            "tests/lisp/lint/shapes.lisp:3:27: error: area takes 2 arguments, called with 1 [arity]",
+            "tests/lisp/lint/shapes.lisp:7:1: warning: variable scale is never used [unused-definition]",
+            "tests/lisp/lint/shapes.lisp:11:20: warning: the else branch never runs, the predicate is always the same [unreachable-branch]",
+            "tests/lisp/lint/shapes.lisp:13:1: warning: function perimeter is never used [unused-definition]",
+            "tests/lisp/lint/shapes.lisp:17:1: warning: set! of length, which this file doesn't define or declare [undeclared-set]",
+            "tests/lisp/lint/shapes.lisp:23:1: error: wrong number of arguments: expected 2, received 3 [arity]",
+            "tests/lisp/lint/shapes.lisp:24:1: warning: set! of nowhere, which this file doesn't define or declare [undeclared-set]",
+        ]
+    );
+
+    let mut config = lisp::lint::Config::default();
+    config.parse_rule("unused-definition=off").unwrap();
+    config.parse_rule("arity=warning").unwrap();
+    assert!(config.parse_rule("arity=fatal").is_err());
+    assert!(config.parse_rule("unknown=off").is_err());
+
+    let diagnostics = lint(&config);
+    assert_eq!(diagnostics.len(), 5);
+    assert!(diagnostics
+        .iter()
+        .all(|diagnostic| diagnostic.severity == lisp::lint::Severity::Warning));
+
+    let json =
+        serde_json::from_str::<serde_json::Value>(&lisp::lint::to_json(&diagnostics)).unwrap();
+    assert_eq!(
+        json[0],
+        serde_json::json!({
+            "rule": "arity",
+            "severity": "warning",
+            "message": "area takes 2 arguments, called with 1",
+            "file": "tests/lisp/lint/shapes.lisp",
+            "span": {"start": 65, "end": 73, "line": 3, "column": 27},
+        })
+    );
+    assert_eq!(json[3]["rule"], "arity");
+    assert_eq!(json[3]["span"]["line"], 23);
+
+    config.parse_rule("arity=off").unwrap();
+    config.parse_rule("undeclared-set=off").unwrap();
+
+    let diagnostics = lint(&config);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].rule, lisp::lint::Rule::UnreachableBranch);
+    gc::collect();
+}
+
+#[test]
+fn test_interpreter_eval_str() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    assert_eq!(interpreter.eval_str("(+ 1 2)").unwrap(), vm::Object::Int(3));
+    assert_eq!(
+        interpreter
+            .eval_str("(def greeting \"hello world\") (list 1 2)")
+            .unwrap()
+            .to_string(),
+        "(1 2)"
+    );
+    assert_eq!(
+        interpreter.get_global("greeting").unwrap().to_string(),
+        "\"hello world\""
+    );
+    assert!(interpreter.get_global("farewell").is_none());
+
+    // an error leaves the interpreter usable
+    assert!(interpreter.eval_str("(car 1)").is_err());
+    assert!(interpreter.eval_str("(undefined-function)").is_err());
+    assert_eq!(
+        interpreter.eval_str("greeting").unwrap().to_string(),
+        "\"hello world\""
+    );
+
+    assert_eq!(
+        interpreter
+            .eval_file("tests/lisp/fact.lisp".as_ref())
+            .unwrap(),
+        vm::Object::Int(3628800)
+    );
+    assert!(interpreter.get_global("fact").is_some());
+    gc::collect();
+}
+
+#[test]
+fn test_interpreter_register() {
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    interpreter
+        .register("add2", |a: i64, b: i64| a + b)
+        .unwrap();
+    interpreter
+        .register("shout", |s: String, c: char| {
+            format!("{}{c}", s.to_uppercase())
+        })
+        .unwrap();
+    interpreter
+        .register("checked-div", |a: i64, b: i64| match b {
+            0 => Err(vm::Error::Other("division by zero".into())),
+            _ => Ok(a / b),
+        })
+        .unwrap();
+    interpreter.register("nothing", || ()).unwrap();
+
+    assert_eq!(
+        interpreter.eval_str("(add2 1 2)").unwrap(),
+        vm::Object::Int(3)
+    );
+    assert_eq!(
+        interpreter
+            .eval_str("(shout \"hello\" #\\!)")
+            .unwrap()
+            .to_string(),
+        "\"HELLO!\""
+    );
+    assert_eq!(
+        interpreter.eval_str("(checked-div 6 3)").unwrap(),
+        vm::Object::Int(2)
+    );
+    assert!(interpreter.eval_str("(checked-div 6 0)").is_err());
+    assert_eq!(interpreter.eval_str("(nothing)").unwrap(), vm::Object::Nil);
+
+    // the decl catches what it can when compiling, the native the rest
+    assert!(interpreter.eval_str("(add2 1)").is_err());
+    assert!(interpreter.eval_str("(add2 1 \"2\")").is_err());
+    let error = interpreter
+        .eval_str("(apply add2 (list 1 \"2\"))")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("type error"), "{error}");
+    let error = interpreter
+        .eval_str("(apply add2 (list 1 2 3))")
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("add2 expects 2 parameters"), "{error}");
+    gc::collect();
+}
+
+#[test]
+fn test_object_conversions() {
+    use std::collections::HashMap;
+
+    let list = vm::Object::<()>::from(vec![1i64, 2, 3]);
+    assert_eq!(list.to_string(), "(1 2 3)");
+    assert_eq!(Vec::<i64>::try_from(list.clone()).unwrap(), vec![1, 2, 3]);
+    assert!(Vec::<String>::try_from(list).is_err());
+    assert_eq!(
+        Vec::<bool>::try_from(vm::Object::<()>::Nil).unwrap(),
+        Vec::<bool>::new()
+    );
+
+    let map = vm::Object::<()>::from(HashMap::from([("a", 'x'), ("b", 'y')]));
+    assert_eq!(
+        HashMap::<String, char>::try_from(map).unwrap(),
+        HashMap::from([("a".to_string(), 'x'), ("b".to_string(), 'y')])
+    );
+
+    assert_eq!(vm::Object::<()>::from(None::<i64>), vm::Object::Nil);
+    assert_eq!(vm::Object::<()>::from(Some(true)), vm::Object::Bool(true));
+    assert_eq!(vm::Object::<()>::Nil.into_option(), None);
+    assert_eq!(
+        String::try_from(vm::Object::<()>::from("hi")).unwrap(),
+        "hi"
+    );
+    assert!(i64::try_from(vm::Object::<()>::Char('c')).is_err());
+
+    let mut interpreter = lisp::Interpreter::new().unwrap();
+
+    interpreter
+        .register("sum", |ints: Vec<i64>| ints.iter().sum::<i64>())
+        .unwrap();
+    interpreter
+        .register("lookup", |map: HashMap<String, i64>, key: String| {
+            map.get(&key).copied()
+        })
+        .unwrap();
+    interpreter
+        .register("or-zero", |int: Option<i64>| int.unwrap_or(0))
+        .unwrap();
+    interpreter
+        .register("count-chars", |strings: Vec<String>| {
+            strings
+                .into_iter()
+                .map(|string| (string.clone(), string.chars().count() as i64))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap();
+
+    assert_eq!(
+        interpreter.eval_str("(sum (list 1 2 3))").unwrap(),
+        vm::Object::Int(6)
+    );
+    assert_eq!(
+        interpreter.eval_str("(sum nil)").unwrap(),
+        vm::Object::Int(0)
+    );
+    assert!(interpreter.eval_str("(sum (list 1 #\\a))").is_err());
+    assert_eq!(
+        interpreter
+            .eval_str("(lookup {\"a\" 1 \"b\" 2} \"b\")")
+            .unwrap(),
+        vm::Object::Int(2)
+    );
+    assert_eq!(
+        interpreter.eval_str("(lookup {} \"b\")").unwrap(),
+        vm::Object::Nil
+    );
+    assert_eq!(
+        interpreter.eval_str("(or-zero 5)").unwrap(),
+        vm::Object::Int(5)
+    );
+    assert_eq!(
+        interpreter.eval_str("(or-zero nil)").unwrap(),
+        vm::Object::Int(0)
+    );
+    assert_eq!(
+        HashMap::<String, i64>::try_from(
+            interpreter
+                .eval_str("(count-chars (list \"ab\" \"abc\"))")
+                .unwrap()
+        )
+        .unwrap(),
+        HashMap::from([("ab".to_string(), 2), ("abc".to_string(), 3)])
+    );
+    gc::collect();
+}
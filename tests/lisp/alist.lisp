@@ -0,0 +1,26 @@
+(def colors '((red . 1) (green . 2) (blue . 3) (red . 4)))
+
+(assert (= (assoc 'green colors) '(green . 2)))
+(assert (= (assoc 'red colors) '(red . 1)))
+(assert (nil? (assoc 'pink colors)))
+(assert (nil? (assoc 'red nil)))
+
+;; assoc compares with =, assq wants the very same object
+(def key "name")
+(def people (list (cons key "ann") (cons '(1 2) "bob")))
+
+(assert (= (assoc "name" people) (cons key "ann")))
+(assert (= (assoc '(1 2) people) (cons '(1 2) "bob")))
+(assert (= (assq key people) (cons key "ann")))
+(assert (nil? (assq "name" people)))
+(assert (nil? (assq '(1 2) people)))
+(assert (= (assq 'blue colors) '(blue . 3)))
+(assert (= (assq 3 '((1 . a) (3 . c))) '(3 . c)))
+
+(def table (alist->map colors))
+(assert (= (map-retrieve table 'red) 1))
+(assert (= (map-retrieve table 'blue) 3))
+
+(assert (= (map->alist {:b 2 :a 1}) '((:a . 1) (:b . 2))))
+(assert (nil? (map->alist (alist->map nil))))
+(assert (= (map->alist (alist->map '((3 . c) (1 . a)))) '((1 . a) (3 . c))))
@@ -0,0 +1,9 @@
+(assert (= (#(+ %1 %2) 1 2) 3))
+
+(assert (= (map #(+ % 1) (list 1 2 3)) '(2 3 4)))
+
+(assert (= (#(cons %2 %&) 1 2 3 4) '(2 3 4)))
+
+(assert (= (#(list 1)) '(1)))
+
+(assert (= (#(map #(* % 2) %) (list 1 2)) '(2 4)))
@@ -0,0 +1,22 @@
+(def b (bytes 0 1 127 255))
+(assert (bytes? b))
+(assert (= (bytes? "abc") false))
+(assert (= (bytes-length b) 4))
+(assert (= (bytes-ref b 3) 255))
+(assert (= b (bytes 0 1 127 255)))
+(assert (= (bytes-length (bytes)) 0))
+
+(assert (= (bytes-slice b 1 3) (bytes 1 127)))
+(assert (= (bytes-slice b 2) (bytes 127 255)))
+(assert (= (bytes-length (bytes-slice b 4)) 0))
+
+(assert (= (string->utf8 "hé") (bytes 104 195 169)))
+(assert (= (utf8->string (bytes 104 195 169)) "hé"))
+(assert (= (utf8->string (string->utf8 "round trip")) "round trip"))
+
+;; the hashes and base64 take bytes as well as strings
+(assert (= (sha256 (string->utf8 "abc")) (sha256 "abc")))
+(assert (= (md5 (bytes)) "d41d8cd98f00b204e9800998ecf8427e"))
+(assert (= (base64-encode (bytes 255 0)) "/wA="))
+
+(assert (= (format "~s" (bytes 1 2 255)) "#u8(1 2 255)"))
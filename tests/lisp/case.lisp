@@ -0,0 +1,57 @@
+(def weekday (lambda (n)
+               (case n
+                 (0 'sunday)
+                 (1 'monday)
+                 (2 'tuesday)
+                 (3 'wednesday)
+                 (4 'thursday)
+                 (5 'friday)
+                 (6 'saturday)
+                 (else 'unknown))))
+
+(assert (= (weekday 0) 'sunday))
+(assert (= (weekday 3) 'wednesday))
+(assert (= (weekday 6) 'saturday))
+(assert (= (weekday 7) 'unknown))
+(assert (= (weekday (- 0 1)) 'unknown))
+(assert (= (weekday "3") 'unknown))
+
+(def kind (lambda (x)
+            (case x
+              ((a e i o u) 'vowel)
+              ((1 2 3) 'small)
+              ("hi" 'greeting)
+              (nil 'empty))))
+
+(assert (= (kind 'e) 'vowel))
+(assert (= (kind 'u) 'vowel))
+(assert (= (kind 2) 'small))
+(assert (= (kind "hi") 'greeting))
+(assert (= (kind nil) 'empty))
+(assert (nil? (kind 'z)))
+
+;; the first clause with a key wins, even when dispatching through a table
+(def first-wins (lambda (n)
+                  (case n
+                    ((1 2) 'first)
+                    ((2 3 4) 'second))))
+
+(assert (= (first-wins 2) 'first))
+(assert (= (first-wins 4) 'second))
+
+;; the scrutinee is only evaluated once
+(def counter 0)
+
+(assert (= (case (progn (set! counter (+ counter 1)) 'b)
+             (a 1)
+             (b 2)
+             (else 3))
+           2))
+
+(assert (= counter 1))
+
+(assert (= (case 10
+             ((1 2 3 4) 'low)
+             (else (set! counter 0)
+                   'high))
+           'high))
@@ -0,0 +1,26 @@
+(assert (= (char->int #\a) 97))
+(assert (= (char->int #\é) 233))
+(assert (= (int->char 955) #\λ))
+(assert (= (int->char (char->int #\z)) #\z))
+
+(assert (= (char-upcase #\a) #\A))
+(assert (= (char-upcase #\é) #\É))
+(assert (= (char-upcase #\1) #\1))
+(assert (= (char-upcase #\ß) #\ß))
+(assert (= (char-downcase #\Q) #\q))
+
+(assert (char-alpha? #\a))
+(assert (char-alpha? #\λ))
+(assert (= (char-alpha? #\1) false))
+(assert (char-whitespace? #\space))
+(assert (char-whitespace? #\newline))
+(assert (= (char-whitespace? #\a) false))
+
+;; enough to scan an identifier off the front of a string
+(def scan-word
+  (lambda (chars)
+    (if (and (cons? chars) (char-alpha? (car chars)))
+        (cons (char-upcase (car chars)) (scan-word (cdr chars)))
+        nil)))
+
+(assert (= (list->string (scan-word (string->list "abc def"))) "ABC"))
@@ -2,3 +2,15 @@
               ((= 1 2) false)
               ((= 1 1) true)))
 
+
+(assert (= (cond ((= 1 1) :when (= 2 3) 'a)
+                 (true 'b))
+           'b))
+
+(assert (= (cond ((= 1 1) :when (= 2 2) 'a)
+                 (true 'b))
+           'a))
+
+(assert (= (cond ((= 1 2) :when (= 2 2) 'a)
+                 (true :when (= 1 1) 'b))
+           'b))
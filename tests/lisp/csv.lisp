@@ -0,0 +1,34 @@
+(assert (= (csv-parse "a,b,c
+1,2,3
+") '(("a" "b" "c") ("1" "2" "3"))))
+
+;; quoted fields can hold the delimiter, quotes and newlines
+(assert (= (csv-parse #"name,quote
+"Smith, J","said ""hi""
+twice"
+,"#)
+           '(("name" "quote") ("Smith, J" #"said "hi"
+twice"#) ("" ""))))
+
+(assert (= (csv-parse "a;b
+1;2" :delimiter #\;)
+           '(("a" "b") ("1" "2"))))
+
+(def people (csv-parse "name,age
+ann,30
+bob,25" :header true))
+(assert (= (map-retrieve (car people) :name) "ann"))
+(assert (= (map-retrieve (cadr people) :age) "25"))
+
+(assert (nil? (csv-parse "")))
+
+(assert (= (csv-write '(("a" "b") (1 #\c) ("x,y" #"say "hi""#)))
+           #"a,b
+1,c
+"x,y","say ""hi"""
+"#))
+(assert (= (csv-write '(("a" "b")) :delimiter #\tab) "a	b
+"))
+
+(def rows '(("1" "two, three") ("" #"fo"ur"#)))
+(assert (= (csv-parse (csv-write rows)) rows))
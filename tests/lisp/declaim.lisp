@@ -0,0 +1,18 @@
+(declaim (strict-arity t) (warnings-as-errors nil) (require-decl t))
+
+(decl add (lambda (int int)))
+(def add (lambda (a b) (+ a b)))
+
+(decl sum (lambda (&rest int)))
+(def sum (lambda (&rest xs) (fold add xs)))
+
+(def answer 42)
+
+(assert (= (add 1 2) 3))
+(assert (= (sum 1 2 3) 6))
+(assert (= (add answer 0) 42))
+
+(declaim (require-decl nil))
+
+(def double (lambda (x) (* x 2)))
+(assert (= (double 2) 4))
@@ -0,0 +1,2 @@
+(defbench broken
+  (car 1))
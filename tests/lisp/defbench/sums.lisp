@@ -0,0 +1,18 @@
+(def sum (lambda (n)
+           (named-let loop ((i 0) (total 0))
+             (if (= i n)
+                 total
+                 (loop (+ i 1) (+ total i))))))
+
+(defbench small
+  (sum 10))
+
+(defbench large
+  (assert (= (sum 1000) 499500)))
+
+;; named like the locals lisp bench times with
+(defbench loop
+  (sum 10))
+
+(defbench start
+  (sum 10))
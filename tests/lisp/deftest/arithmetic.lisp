@@ -0,0 +1,10 @@
+(def double (lambda (x) (* x 2)))
+
+(deftest doubles
+  (assert (= (double 2) 4)))
+
+(deftest fails
+  (assert (= (double 2) 5)))
+
+(deftest errors
+  (car (double 1)))
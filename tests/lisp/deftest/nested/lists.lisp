@@ -0,0 +1,7 @@
+;; doesn't see double from arithmetic.lisp
+(deftest reverses
+  (assert (= (reverse '(1 2 3)) '(3 2 1))))
+
+(deftest isolated
+  (let ((double 1))
+    (assert (= double 1))))
@@ -0,0 +1,41 @@
+(def q (queue-create 1 2 3))
+(assert (= (queue-length q) 3))
+(assert (= (queue->list q) '(1 2 3)))
+
+(push-back! q 4)
+(push-front! q 0)
+(assert (= (queue->list q) '(0 1 2 3 4)))
+
+(assert (= (pop-front! q) 0))
+(assert (= (pop-back! q) 4))
+(assert (= (queue->list q) '(1 2 3)))
+
+(def empty (queue-create))
+(assert (= (queue-length empty) 0))
+(assert (nil? (pop-front! empty)))
+(assert (nil? (pop-back! empty)))
+(assert (= (queue-create 1 2) (queue-create 1 2)))
+
+;; breadth first over a tree of (value children...)
+(def tree '(1 (2 (4)) (3 (5) (6))))
+(def bfs
+  (lambda (tree)
+    (let ((queue (queue-create tree)))
+      (named-let loop ((seen nil))
+        (if (= (queue-length queue) 0)
+            (reverse seen)
+            (let ((node (pop-front! queue)))
+              (for-each (lambda (child) (push-back! queue child)) (cdr node))
+              (loop (cons (car node) seen))))))))
+(assert (= (bfs tree) '(1 2 3 4 5 6)))
+
+;; lots of pushes and pops stay cheap
+(def big (queue-create))
+(dotimes (i 100000)
+  (push-back! big i))
+(dotimes (i 99999)
+  (pop-front! big))
+(assert (= (queue->list big) '(99999)))
+
+;; a deque is written with its items in order
+(assert (= (format "~s" (queue-create 1 'a "b")) #"#deque(1 'a "b")"#))
@@ -0,0 +1,23 @@
+(def f (lambda ((a b) c)
+         (+ (+ a b) c)))
+
+(assert (= (f (list 1 2) 3) 6))
+
+(def g (lambda ((a (b c)) &rest rest)
+         (list a b c rest)))
+
+(assert (= (g (list 1 (list 2 3)) 4 5) '(1 2 3 (4 5))))
+
+(def h (lambda ((head &rest tail))
+         tail))
+
+(assert (= (h (list 1 2 3)) '(2 3)))
+
+(def typed (lambda ((x int)) x))
+
+(assert (= (typed 1) 1))
+
+(assert (= (let (((a b) (list 1 2))
+                 (c 3))
+             (list a b c))
+           '(1 2 3)))
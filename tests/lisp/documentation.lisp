@@ -0,0 +1,29 @@
+(def square
+  "Multiplies x by itself."
+  (lambda (x) (* x x)))
+
+(assert (= (documentation 'square) "Multiplies x by itself."))
+(assert (= (documentation square) "Multiplies x by itself."))
+
+(def cube (lambda (x)
+            "Multiplies x by itself twice."
+            (* x (square x))))
+
+(assert (= (documentation 'cube) "Multiplies x by itself twice."))
+(assert (= (cube 2) 8))
+
+(defmacro swap (a b)
+  "Expands to the list (b a)."
+  (list 'list b a))
+
+(assert (= (documentation 'swap) "Expands to the list (b a)."))
+(assert (= (swap 1 2) '(2 1)))
+
+(def answer (lambda () "42"))
+
+(assert (= (answer) "42"))
+(assert (= (documentation 'answer) ()))
+
+(def x "A variable." 1)
+
+(assert (= (documentation 'x) ()))
@@ -0,0 +1,24 @@
+(def pair '(a . b))
+(assert (= (car pair) 'a))
+(assert (= (cdr pair) 'b))
+
+(def alist '((a . 1) (b . 2)))
+(assert (= (cdr (car (cdr alist))) 2))
+
+(def improper '(1 2 . 3))
+(assert (= (cdr (cdr improper)) 3))
+(assert (= '(1 . (2 3)) '(1 2 3)))
+(assert (= '(1 . ()) '(1)))
+
+(def x 5)
+(assert (= (cdr `(a . ,x)) 5))
+(assert (= (cdr `(a . b)) 'b))
+
+(def xs '(1 2))
+(def spliced `(0 ,@xs ,x . end))
+(assert (= (car (cdr (cdr (cdr spliced)))) 5))
+(assert (= (cdr (cdr (cdr (cdr spliced)))) 'end))
+
+(defmacro pair-of (a b)
+  `(quote (,a . ,b)))
+(assert (= (cdr (pair-of 1 2)) 2))
@@ -0,0 +1,36 @@
+(def handled (lambda (condition) condition))
+
+(def condition (with-error-handler handled
+                 (lambda () (error "no such user" '(user 7)))))
+(assert (= (map-retrieve condition :message) "no such user"))
+(assert (= (map-retrieve condition :data) '(user 7)))
+
+(assert (nil? (map-retrieve (with-error-handler handled (lambda () (error "oops")))
+                            :data)))
+
+;; the thunk's value is returned when nothing goes wrong
+(assert (= (with-error-handler handled (lambda () (+ 1 2))) 3))
+
+;; errors raised by natives and the vm are handled too
+(assert (string? (map-retrieve (with-error-handler handled (lambda () (car 1)))
+                               :message)))
+
+;; errors from deep inside other calls unwind back to the handler, which can
+;; carry on with its own calls afterwards
+(def find-negative
+  (lambda (list)
+    (with-error-handler (lambda (condition) (map-retrieve condition :data))
+      (lambda ()
+        (for-each (lambda (x) (if (< x 0) (error "negative" x) nil)) list)
+        nil))))
+
+(assert (= (find-negative (list 1 2 (- 0 3) 4)) (- 0 3)))
+(assert (nil? (find-negative '(1 2 3))))
+(assert (= (map (lambda (l) (find-negative l)) (list '(1) (list (- 0 1)))) (list nil (- 0 1))))
+
+;; handlers nest, and a handler can raise to the one outside it
+(assert (= (with-error-handler (lambda (c) (string-append "outer " (map-retrieve c :message)))
+             (lambda ()
+               (with-error-handler (lambda (c) (error (string-append "inner " (map-retrieve c :message))))
+                 (lambda () (error "failed")))))
+           "outer inner failed"))
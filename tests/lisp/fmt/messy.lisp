@@ -0,0 +1,19 @@
+;; squares, from a list of numbers
+(def squares (lambda (xs)   ; one per x
+  (map (lambda (x) (* x x)) xs)))
+
+
+
+(defmacro unless-nil (x &rest body) `(if (nil? ,x) nil (progn ,@body)))
+(let ((a 1)
+  (b 2))
+  ;; the sum
+  (+ a b))
+(if (= (length (squares '(1 2 3 4 5 6 7 8 9 10))) 10) (print "ten squares, as expected") (print "something is wrong"))
+(def table {"one" 1 "two" 2})
+(def xs [1 2
+  3])
+(def pair '(a . b))
+(cond ((nil? xs) 'empty)
+  (true 'full) ; fallback
+  )
@@ -0,0 +1,9 @@
+(assert (= (format "x=~a y=~s~%" "hi" "hi") #"x=hi y="hi"
+"#))
+
+(assert (= (format "~a ~s" 'sym 'sym) "sym 'sym"))
+(assert (= (format "~a ~s" #\a #\a) "a 'a'"))
+(assert (= (format "~d/~d" 1 2) "1/2"))
+(assert (= (format "~a" (list 1 "two" 'three)) #"(1 "two" 'three)"#))
+(assert (= (format "100~~") "100~"))
+(assert (= (format "no directives") "no directives"))
@@ -0,0 +1,11 @@
+(assert (symbol? (gensym)))
+(assert (= (= (gensym) (gensym)) false))
+
+(defmacro my-or (a b)
+  (let ((g (gensym)))
+    `(let ((,g ,a))
+       (if ,g ,g ,b))))
+
+(def g false)
+(assert (= (my-or g true) true))
+(assert (= (my-or false g) false))
@@ -0,0 +1,29 @@
+(assert (= (sha256 "") "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"))
+(assert (= (sha256 "abc") "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"))
+(assert (= (md5 "") "d41d8cd98f00b204e9800998ecf8427e"))
+(assert (= (md5 "abc") "900150983cd24fb0d6963f7d28e17f72"))
+
+(def fox "The quick brown fox jumps over the lazy dog")
+(assert (= (sha256 fox) "d7a8fbb307d7809469ca9abcb0082e4f8d5651e46d3cdb762d02d0bf37c9e592"))
+(assert (= (md5 fox) "9e107d9d372bb6826bd81d3542a419d6"))
+
+;; more than one block, and not all ascii
+(def long "héllo wörld héllo wörld héllo wörld héllo wörld héllo wörld héllo wörld héllo wörld héllo wörld héllo wörld héllo wörld ")
+(assert (= (sha256 long) "3a7018cfc3a3b05e7abe43f6c4488c99bed710c9cc4bfa0f4c41247eac82d0b8"))
+(assert (= (md5 long) "f5aa2ccd9d817186adab83c7b0de8fd7"))
+
+(assert (= (base64-encode "") ""))
+(assert (= (base64-encode "a") "YQ=="))
+(assert (= (base64-encode "ab") "YWI="))
+(assert (= (base64-encode "abc") "YWJj"))
+(assert (= (base64-encode fox) "VGhlIHF1aWNrIGJyb3duIGZveCBqdW1wcyBvdmVyIHRoZSBsYXp5IGRvZw=="))
+
+(assert (= (base64-decode "YQ==") "a"))
+(assert (= (base64-decode "YWI=") "ab"))
+(assert (= (base64-decode (base64-encode long)) long))
+
+;; binary payloads decode to bytes
+(assert (= (base64-decode-bytes "/wA=") (bytes 255 0)))
+(assert (= (base64-decode-bytes "") (bytes)))
+(assert (= (base64-decode-bytes (base64-encode (bytes 0 159 146 150))) (bytes 0 159 146 150)))
+(assert (= (utf8->string (base64-decode-bytes "YWI=")) "ab"))
@@ -0,0 +1,60 @@
+(def h (heap-create))
+(heap-push! h 'c 3)
+(heap-push! h 'a 1)
+(heap-push! h 'd 4)
+(heap-push! h 'b 2)
+(assert (= (heap-length h) 4))
+(assert (= (heap-pop! h) 'a))
+(assert (= (heap-pop! h) 'b))
+(heap-push! h 'z 0)
+(assert (= (heap-pop! h) 'z))
+(assert (= (heap-pop! h) 'c))
+(assert (= (heap-pop! h) 'd))
+(assert (nil? (heap-pop! h)))
+(assert (= (heap-length h) 0))
+
+;; a max-heap through a less function
+(def max-heap (heap-create (lambda (a b) (< b a))))
+(for-each (lambda (x) (heap-push! max-heap x)) '(5 1 4 2 3))
+(def drain
+  (lambda (heap)
+    (named-let loop ((items nil))
+      (if (= (heap-length heap) 0)
+          (reverse items)
+          (loop (cons (heap-pop! heap) items))))))
+(assert (= (drain max-heap) '(5 4 3 2 1)))
+
+;; heap sort agrees with sort
+(def numbers '(9 3 7 1 8 2 6 4 5 0 3 7))
+(def sorted (heap-create (lambda (a b) (< a b))))
+(for-each (lambda (x) (heap-push! sorted x)) numbers)
+(assert (= (drain sorted) (sort numbers (lambda (a b) (< a b)))))
+
+;; shortest paths over a small weighted graph
+(def graph '((a . ((b . 7) (c . 9) (f . 14)))
+             (b . ((a . 7) (c . 10) (d . 15)))
+             (c . ((a . 9) (b . 10) (d . 11) (f . 2)))
+             (d . ((b . 15) (c . 11) (e . 6)))
+             (e . ((d . 6) (f . 9)))
+             (f . ((a . 14) (c . 2) (e . 9)))))
+(def dijkstra
+  (lambda (graph start)
+    (let ((seen (set-create))
+          (queue (heap-create)))
+      (heap-push! queue (cons start 0) 0)
+      (named-let loop ((dist nil))
+        (let ((entry (heap-pop! queue)))
+          (cond ((nil? entry) (reverse dist))
+                ((set-insert! seen (car entry))
+                 (progn
+                  (for-each
+                   (lambda (edge)
+                     (let ((d (+ (cdr entry) (cdr edge))))
+                       (heap-push! queue (cons (car edge) d) d)))
+                   (cdr (assq (car entry) graph)))
+                  (loop (cons entry dist))))
+                (true (loop dist))))))))
+(def paths (dijkstra graph 'a))
+(assert (= (length paths) 6))
+(assert (= (map (lambda (node) (cdr (assq node paths))) '(a b c d e f))
+           '(0 7 9 20 20 11)))
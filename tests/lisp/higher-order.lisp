@@ -0,0 +1,29 @@
+(assert (= (map (lambda (x) (* x 2)) '(1 2 3)) '(2 4 6)))
+(assert (= (map car '((a 1) (b 2))) '(a b)))
+(assert (nil? (map car nil)))
+
+(assert (= (filter (lambda (x) (< 1 x)) '(1 2 3)) '(2 3)))
+(assert (nil? (filter (lambda (x) false) '(1 2 3))))
+
+(assert (= (fold-left (lambda (acc e) (cons e acc)) nil '(1 2 3)) '(3 2 1)))
+(assert (= (fold-right (lambda (e acc) (cons e acc)) nil '(1 2 3)) '(1 2 3)))
+(assert (= (fold-left (lambda (a b) (- a b)) 10 '(1 2 3)) 4))
+(assert (= (fold-right (lambda (a b) (- a b)) 0 '(1 2 3)) 2))
+(assert (= (fold-left + 0 nil) 0))
+
+(def total 0)
+(assert (nil? (for-each (lambda (x) (set! total (+ total x))) '(1 2 3))))
+(assert (= total 6))
+
+;; closures called from natives can call natives that call closures
+(assert (= (map (lambda (l) (fold-left + 0 (map (lambda (x) (* x x)) l)))
+                '((1 2) (3 4)))
+           '(5 25)))
+
+(def big nil)
+(dotimes (i 100000)
+  (set! big (cons i big)))
+
+(assert (= (length (map (lambda (x) (+ x 1)) big)) 100000))
+(assert (= (length (filter (lambda (x) (< x 10)) big)) 10))
+(assert (= (fold-right (lambda (e acc) (+ e acc)) 0 big) 4999950000))
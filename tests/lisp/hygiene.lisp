@@ -0,0 +1,20 @@
+(defmacro swap! (a b)
+  `(let ((tmp ,a))
+     (set! ,a ,b)
+     (set! ,b tmp)))
+
+(def tmp 1)
+(def other 2)
+
+(swap! tmp other)
+
+(assert (= tmp 2))
+(assert (= other 1))
+
+(defmacro with-x (&rest body)
+  `(let ((x 10))
+     (+ x ,@body)))
+
+(def x 1)
+
+(assert (= (with-x x) 11))
@@ -0,0 +1,17 @@
+(def inc (lambda (x) (+ x 1)))
+
+(def twice (lambda (f x) (f (f x))))
+
+(def describe (lambda (n)
+                (let ((small (< n 10)))
+                  (if small "small" "big"))))
+
+(decl first-or (lambda (list int) -> int))
+(def first-or (lambda (xs default)
+                (if (nil? xs) default (car xs))))
+
+(assert (= (inc 1) 2))
+(assert (= (+ (inc 1) (first-or (list 5) 0)) 7))
+(assert (= (describe 3) "small"))
+(assert (= (describe (inc 41)) "big"))
+(assert (= (first-or () 3) 3))
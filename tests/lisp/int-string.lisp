@@ -0,0 +1,15 @@
+(assert (= (int->string 42) "42"))
+(assert (= (int->string (- 0 42)) "-42"))
+(assert (= (int->string 0 2) "0"))
+(assert (= (int->string 255 16) "ff"))
+(assert (= (int->string 5 2) "101"))
+(assert (= (int->string 35 36) "z"))
+(assert (= (int->string (- (- 0 9223372036854775807) 1) 16) "-8000000000000000"))
+
+(assert (= (string->int "ff" 16) 255))
+(assert (= (string->int (int->string 12345 7) 7) 12345))
+
+(assert (= (string->int? "12x")
+           '(err #"failed to parse "12x": 'x' at position 2 is not a base 10 digit"#)))
+(assert (= (string->int? "-19" 8)
+           '(err #"failed to parse "-19": '9' at position 2 is not a base 8 digit"#)))
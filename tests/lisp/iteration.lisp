@@ -0,0 +1,47 @@
+(def sum-list (lambda (list)
+                (let ((sum 0))
+                  (dolist (x list sum)
+                    (set! sum (+ sum x))))))
+
+(assert (= (sum-list (list 1 2 3 4)) 10))
+(assert (= (sum-list nil) 0))
+
+(def squares (lambda (n)
+               (let ((squares nil))
+                 (dotimes (i n)
+                   (set! squares (cons (* i i) squares)))
+                 squares)))
+
+(assert (= (squares 4) '(9 4 1 0)))
+(assert (nil? (squares 0)))
+
+;; without a result form both evaluate to nil
+(assert (nil? (dolist (x (list 1 2)) x)))
+(assert (nil? (dotimes (i 2) i)))
+
+;; nested loops, and the count is only evaluated once
+(def count 0)
+
+(dotimes (i (progn (set! count (+ count 1)) 3))
+  (dolist (x (list 1 2))
+    (set! count (+ count x))))
+
+(assert (= count 10))
+
+;; loops run in constant stack space
+(def total 0)
+
+(dotimes (i 100000)
+  (set! total (+ total 1)))
+
+(assert (= total 100000))
+
+;; a loop variable introduced by a macro doesn't capture the caller's
+(defmacro repeat (n &rest body)
+  `(dotimes (i ,n) ,@body))
+
+(def i 0)
+
+(repeat 3 (set! i (+ i 1)))
+
+(assert (= i 3))
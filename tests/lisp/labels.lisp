@@ -0,0 +1,23 @@
+(def parity (lambda (n)
+              (labels ((even? (n) (if (= n 0) true (odd? (- n 1))))
+                       (odd? (n) (if (= n 0) false (even? (- n 1)))))
+                (if (even? n) 'even 'odd))))
+
+(assert (= (parity 10) 'even))
+(assert (= (parity 7) 'odd))
+
+;; local functions can see the variables around them
+(def scale (lambda (factor list)
+             (labels ((scale-all (list)
+                        (if (nil? list)
+                            nil
+                            (cons (* factor (car list)) (scale-all (cdr list))))))
+               (scale-all list))))
+
+(assert (= (scale 2 (list 1 2 3)) '(2 4 6)))
+
+;; with multiple expressions in the body and an optional parameter
+(assert (= (labels ((add (a &optional (b 1)) (+ a b)))
+             (add 1 2)
+             (add 1))
+           2))
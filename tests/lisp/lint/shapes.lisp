@@ -0,0 +1,24 @@
+(decl area (lambda (int int) -> int))
+
+(def describe (lambda (w) (area w)))
+
+(def area (lambda (w h) (* w h)))
+
+(def scale 2)
+(def _ignored 0)
+
+(def kind (lambda (shape)
+  (if true 'square 'circle)))
+
+(def perimeter (lambda (w h)
+  (cond ((= w 0) 0)
+        (true (* 2 (+ w h))))))
+
+(set! length 0)
+
+(describe 3)
+(kind 'square)
+
+;; the compiler rejects these itself, lint reports them under their rules
+(area 1 2 3)
+(set! nowhere 1)
@@ -0,0 +1,27 @@
+(assert (= (length '(1 2 3)) 3))
+(assert (= (length nil) 0))
+
+(assert (= (reverse '(1 2 3)) '(3 2 1)))
+(assert (nil? (reverse nil)))
+
+(assert (= (append '(1) '(2 3) nil '(4)) '(1 2 3 4)))
+(assert (= (append '(1 2) '(3 . 4)) '(1 2 3 . 4)))
+(assert (= (append '(1) 2) '(1 . 2)))
+(assert (= (append '(1)) '(1)))
+(assert (nil? (append)))
+
+(assert (= (nth '(a b c) 1) 'b))
+(assert (nil? (nth '(a b c) 3)))
+(assert (nil? (nth '(a b c) (- 0 1))))
+
+(assert (= (last '(a b c)) 'c))
+(assert (nil? (last nil)))
+
+;; long enough that the recursive versions would run out of stack
+(def big nil)
+(dotimes (i 100000)
+  (set! big (cons i big)))
+
+(assert (= (length (append big '(end))) 100001))
+(assert (= (last (append big '(end))) 'end))
+(assert (= (nth (reverse big) 99999) 99999))
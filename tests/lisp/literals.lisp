@@ -0,0 +1,27 @@
+(def x 2)
+
+(def config {:name "lisp" :size (+ x 1) "nested" {:a [1 2]}})
+(assert (= (map-retrieve config :name) "lisp"))
+(assert (= (map-retrieve config :size) 3))
+(assert (= (map-retrieve (map-retrieve config "nested") :a) '(1 2)))
+
+(def empty {})
+(map-insert! empty 'a 1)
+(assert (= (map-retrieve empty 'a) 1))
+
+(assert (= [1 x (+ x 1)] '(1 2 3)))
+(assert (nil? []))
+(assert (= (length [[1] [2]]) 2))
+
+;; quoted literals are data, the elements aren't evaluated
+(assert (= '[1 x] '(1 x)))
+(assert (= (length '[1 2]) 2))
+(assert (= (map-retrieve '{:a x} :a) 'x))
+(assert (= (map-retrieve (car '({:a [1 2]})) :a) '(1 2)))
+
+;; and macros are handed code that still builds them
+(defmacro first-of (form) (list 'car form))
+(defmacro same (form) form)
+(assert (= (first-of [x 3]) 2))
+(assert (= (map-retrieve (same {:a x}) :a) 2))
+(assert (= (map-retrieve (same '{:a x}) :a) 'x))
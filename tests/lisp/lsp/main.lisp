@@ -0,0 +1,4 @@
+(require shapes)
+
+(def square (lambda (side)
+              (shapes::area side side)))
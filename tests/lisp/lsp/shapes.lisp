@@ -0,0 +1,5 @@
+(module shapes)
+
+(def area (lambda (width height) (* width height)))
+
+(export area)
@@ -0,0 +1,10 @@
+(defmacro my-unless (p body)
+  `(if ,p nil ,body))
+
+(defmacro my-unless-2 (p body)
+  `(my-unless ,p ,body))
+
+(assert (= (macroexpand-1 '(my-unless false 1)) '(if false nil 1)))
+(assert (= (macroexpand-1 '(my-unless-2 false 1)) '(my-unless false 1)))
+(assert (= (macroexpand '(my-unless-2 false 1)) '(if false nil 1)))
+(assert (= (macroexpand '(+ 1 2)) '(+ 1 2)))
@@ -0,0 +1,36 @@
+(def describe (lambda (x)
+                (match x
+                  (0 'zero)
+                  ("hello" 'greeting)
+                  ('foo 'foo)
+                  (nil 'empty)
+                  ((list a) (list 'one a))
+                  ((list a b) (list 'two a b))
+                  ((cons 1 rest) (list 'starts-with-one rest))
+                  ((list _ _ &rest rest) (list 'many rest))
+                  (n :when (> n 100) 'big)
+                  (_ 'other))))
+
+(assert (= (describe 0) 'zero))
+(assert (= (describe "hello") 'greeting))
+(assert (= (describe 'foo) 'foo))
+(assert (= (describe nil) 'empty))
+(assert (= (describe (list 5)) '(one 5)))
+(assert (= (describe (list 5 6)) '(two 5 6)))
+(assert (= (describe (list 1 2 3)) '(starts-with-one (2 3))))
+(assert (= (describe (list 2 3 4 5)) '(many (4 5))))
+(assert (= (describe 101) 'big))
+(assert (= (describe 7) 'other))
+
+(assert (= (match (list 1 (list 2 3))
+             ((list a (list b c)) (+ a (+ b c))))
+           6))
+
+(assert (= (match 1 (2 'two)) ()))
+
+(def y 10)
+
+(assert (= (match (list 1 2)
+             ((list a b) :when (= (+ a b) y) 'ten)
+             ((list a b) (+ (+ a b) y)))
+           13))
@@ -0,0 +1,11 @@
+(module greeting)
+
+;; counts how many times this file was compiled, in a global the requiring
+;; file defines
+(set! greeting-loads (+ greeting-loads 1))
+
+(def double (lambda (x) (* x 2)))
+(def triple (lambda (x) (* x 3)))
+
+(export double)
+(export triple)
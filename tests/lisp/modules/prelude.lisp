@@ -0,0 +1,7 @@
+(module prelude)
+
+;; prelude-value is a macro the test defines in the manifest, which changes
+;; between runs
+(def value (prelude-value))
+
+(export value)
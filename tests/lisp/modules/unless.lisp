@@ -0,0 +1,13 @@
+(module unless)
+
+(defmacro my-unless (condition body)
+  (list 'if condition nil body))
+
+(defmacro private-unless (condition body)
+  (list 'if condition nil body))
+
+(export my-unless)
+
+(def one (private-unless (= 1 2) 1))
+
+(export one)
@@ -0,0 +1,24 @@
+(def f (lambda (a &optional (b 2) c)
+         (list a b c)))
+
+(assert (= (f 1) '(1 2 ())))
+(assert (= (f 1 3) '(1 3 ())))
+(assert (= (f 1 3 4) '(1 3 4)))
+
+(def g (lambda (a &optional (b (+ a 1)))
+         (+ a b)))
+
+(assert (= (g 1) 3))
+(assert (= (g 1 10) 11))
+
+(def h (lambda (&optional (a 1))
+         (if (= a 1) (h 2) a)))
+
+(assert (= (h) 2))
+
+(defmacro or-else (test &optional (else nil))
+  `(if ,test true ,else))
+
+(assert (= (or-else false) ()))
+(assert (= (or-else false 1) 1))
+(assert (or-else true))
@@ -1,3 +1,7 @@
 (let ((a 1))
   (assert (= `(,a (,a) ,@(list a 'a) a)
              '(1 (1) 1 a a))))
+
+;; a bracketed template is a list, and what's unquoted in it is still code
+(let ((a 1))
+  (assert (= `[,a a ,[a 2]] '(1 a (1 2)))))
@@ -0,0 +1,8 @@
+(assert (= #x1F 31))
+(assert (= #XfF 255))
+(assert (= #o17 15))
+(assert (= #b1010 10))
+(assert (= 0x1f #x1f))
+(assert (= 0o17 #o17))
+(assert (= 0b1010 #b1010))
+(assert (= (+ #b1 0) 1))
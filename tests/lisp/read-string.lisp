@@ -0,0 +1,15 @@
+(assert (= (read-string "(1 2 3)") '(1 2 3)))
+(assert (= (read-string "  42 ") 42))
+(assert (= (read-string "hello") 'hello))
+(assert (= (read-string #""a string""#) "a string"))
+(assert (= (read-string "#\a") #\a))
+(assert (= (read-string "(a (b . c) () true)") '(a (b . c) nil true)))
+(assert (= (read-string "'x") '(quote x)))
+(assert (= (read-string "[1 2]") '(1 2)))
+(assert (= (map-retrieve (read-string "{:a [1 2] b c}") :a) '(1 2)))
+(assert (= (map-retrieve (read-string "{:a [1 2] b c}") 'b) 'c))
+
+;; settings read from a config file come back as data to look through
+(def settings (read-string #"((name . "my app") (port . 8080)) ; trailing comment"#))
+(assert (= (cdr (assoc 'name settings)) "my app"))
+(assert (= (cdr (assoc 'port settings)) 8080))
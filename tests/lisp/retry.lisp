@@ -0,0 +1,42 @@
+(def calls 0)
+
+(def flaky (lambda ()
+             (set! calls (+ calls 1))
+             (if (< calls 3)
+                 (list 'err "not yet")
+                 (list 'ok calls))))
+
+(def start (time-monotonic))
+
+(assert (= (with-retry (:attempts 5 :backoff-ms 100) (flaky))
+           '(ok 3)))
+(assert (= calls 3))
+
+;; the virtual clock skipped ahead 100ms and then 200ms
+(assert (> (- (time-monotonic) start) 299999999))
+
+(set! calls 0)
+
+(assert (= (car (with-retry (:attempts 2) (flaky))) 'err))
+(assert (= calls 2))
+
+;; anything other than an err is returned straight away
+(assert (= (with-retry () 1) 1))
+;; errors raised with error are retried too, and the last one is let through
+(set! calls 0)
+
+(def raising (lambda ()
+               (set! calls (+ calls 1))
+               (if (< calls 3)
+                   (error "boom" nil)
+                   calls)))
+
+(assert (= (with-retry (:attempts 3) (raising)) 3))
+(assert (= calls 3))
+
+(set! calls 0)
+
+(assert (= (with-error-handler (lambda (condition) (map-retrieve condition :message))
+             (lambda () (with-retry (:attempts 2) (raising))))
+           "boom"))
+(assert (= calls 2))
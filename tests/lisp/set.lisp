@@ -0,0 +1,31 @@
+(def s (set-create 1 2 2 3))
+(assert (= (set-length s) 3))
+(assert (= (set->list s) '(1 2 3)))
+
+(assert (set-insert! s 4))
+(assert (= (set-insert! s 4) false))
+(assert (set-contains? s 4))
+(assert (= (set-contains? s 5) false))
+(assert (= (set-contains? s '(1)) false))
+
+(assert (set-remove! s 1))
+(assert (= (set-remove! s 1) false))
+(assert (= (set->list s) '(2 3 4)))
+
+(def words (set-create "b" "a" 'c #\d nil true))
+(assert (set-contains? words "a"))
+(assert (set-contains? words 'c))
+(assert (set-contains? words nil))
+(assert (= (set-contains? words 'a) false))
+(assert (= (set-create 1 2) (set-create 2 1)))
+(assert (= (set-length (set-create)) 0))
+
+;; dedup a list while keeping its order
+(def dedup
+  (lambda (list)
+    (let ((seen (set-create)))
+      (filter (lambda (x) (set-insert! seen x)) list))))
+(assert (= (dedup '(3 1 3 2 1 4)) '(3 1 2 4)))
+
+;; a set is written with its items sorted, duplicates gone
+(assert (= (format "~s" (set-create 2 1 2)) "#set(1 2)"))
@@ -0,0 +1,18 @@
+(let ((xs (list 1 2 3 4)))
+  (set! (car xs) 5)
+  (assert (= xs (list 5 2 3 4)))
+  (set! (cdr (cdr xs)) nil)
+  (assert (= xs (list 5 2)))
+  (assert (= (set! (car (cdr xs)) 7) 7))
+  (assert (= xs (list 5 7))))
+
+(let ((hm (map-create)))
+  (set! (map-retrieve hm "hello") 'world)
+  (assert (= (map-retrieve hm "hello") 'world))
+  (assert (= (set! (map-retrieve hm "hello") 'there) 'there))
+  (assert (= (map-retrieve hm "hello") 'there)))
+
+(let ((xs (list (list 1) (list 2) (list 3))))
+  (dolist (x xs)
+    (set! (car x) (* (car x) 10)))
+  (assert (= xs (list (list 10) (list 20) (list 30)))))
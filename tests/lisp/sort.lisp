@@ -0,0 +1,32 @@
+(assert (= (sort '(3 1 2) (lambda (a b) (< a b))) '(1 2 3)))
+(assert (= (sort '(3 1 2) (lambda (a b) (> a b))) '(3 2 1)))
+(assert (nil? (sort nil (lambda (a b) (< a b)))))
+(assert (= (sort '(1) (lambda (a b) (< a b))) '(1)))
+
+;; equal elements stay in the order they were in
+(assert (= (sort '((2 a) (1 b) (2 c) (1 d)) (lambda (a b) (< (car a) (car b))))
+           '((1 b) (1 d) (2 a) (2 c))))
+
+;; the comparator can close over things and call sort itself
+(def calls 0)
+(def by-sum (lambda (a b)
+              (set! calls (+ calls 1))
+              (< (fold + (sort a (lambda (x y) (< x y))))
+                 (fold + (sort b (lambda (x y) (< x y)))))))
+(assert (= (sort '((5 5) (1 2) (3 1)) by-sum) '((1 2) (3 1) (5 5))))
+(assert (> calls 0))
+
+(def numbers nil)
+(dotimes (i 1000)
+  (set! numbers (cons (random 100) numbers)))
+
+(def sorted? (lambda (xs)
+               (if (nil? (cdr xs))
+                   true
+                   (if (> (car xs) (cadr xs))
+                       false
+                       (sorted? (cdr xs))))))
+
+(def sorted (sort numbers (lambda (a b) (< a b))))
+(assert (sorted? sorted))
+(assert (= (length sorted) 1000))
@@ -0,0 +1,17 @@
+(assert (= (string->int? "42") '(ok 42)))
+
+(assert (= (string->int? "-7") (list 'ok (- 0 7))))
+
+(assert (= (string->int? "ff" 16) '(ok 255)))
+
+(assert (= (string->int? "101" 2) '(ok 5)))
+
+(assert (= (car (string->int? "abc")) 'err))
+
+(assert (= (car (string->int? "2" 2)) 'err))
+
+(assert (= (string->int? "50" 10 0 100) '(ok 50)))
+
+(assert (= (car (string->int? "500" 10 0 100)) 'err))
+
+(assert (string? (cadr (string->int? ""))))
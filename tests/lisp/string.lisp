@@ -0,0 +1,33 @@
+(assert (= (string-trim "  padded	
+") "padded"))
+(assert (= (string-trim "") ""))
+
+(assert (= (string-replace "a-b-c" "-" "+") "a+b+c"))
+(assert (= (string-replace "aaa" "aa" "b") "ba"))
+(assert (= (string-replace "abc" "x" "y") "abc"))
+
+(assert (string-contains? "haystack" "st"))
+(assert (= (string-contains? "haystack" "needle") false))
+(assert (string-contains? "anything" ""))
+
+(assert (string-starts-with? "prefix" "pre"))
+(assert (= (string-starts-with? "prefix" "fix") false))
+(assert (string-ends-with? "suffix" "fix"))
+(assert (= (string-ends-with? "suffix" "suf") false))
+
+(assert (= (string-upcase "Mixed Case ß") "MIXED CASE SS"))
+(assert (= (string-downcase "Mixed CASE") "mixed case"))
+
+(assert (= (string-length "héllo") 5))
+(assert (= (string-length "") 0))
+
+(assert (= (string-ref "héllo" 1) #\é))
+(assert (= (string-ref "héllo" 4) #\o))
+
+(assert (= (substring "héllo" 1 3) "él"))
+(assert (= (substring "héllo" 2) "llo"))
+(assert (= (substring "héllo" 5) ""))
+(assert (= (substring "héllo" 0 0) ""))
+
+(assert (= (string-append "a" "é" "" "c") "aéc"))
+(assert (= (string-append) ""))
@@ -0,0 +1,15 @@
+(assert (= (symbol->string 'hello) "hello"))
+(assert (= (symbol->string :key) ":key"))
+(assert (= (string->symbol "hello") 'hello))
+(assert (= (string->symbol (symbol->string 'round-trip)) 'round-trip))
+
+;; names made at runtime can be used like any other symbol
+(def field (lambda (name) (string->symbol (string-append "get-" (symbol->string name)))))
+(assert (= (field 'x) 'get-x))
+(assert (= (cdr (assoc (field 'x) '((get-x . 1)))) 1))
+
+;; gensyms are symbols too, and never the same as one made from a string
+(def g (gensym))
+(assert (symbol? g))
+(assert (= (string->symbol (symbol->string g)) g))
+(assert (= (= (gensym) (string->symbol "g1")) false))
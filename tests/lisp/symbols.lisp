@@ -0,0 +1,10 @@
+(defmacro defun (name params &rest body)
+  `(def ,name (lambda ,params ,@body)))
+
+(def x 1)
+
+(defun add ((a int) b &rest more)
+  "Adds a and b."
+  (+ a b))
+
+(decl foo (lambda (string int)))
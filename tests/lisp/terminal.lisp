@@ -0,0 +1,14 @@
+;; run deterministically, so stdout is never a terminal
+(assert (= (is-tty?) false))
+(assert (= (terminal-width) 80))
+(assert (= (ansi-style "plain" :bold :red) "plain"))
+
+(def esc (list->string (list (int->char 27) #\[)))
+(assert (= (ansi-code :bold :red) (string-append esc "1;31m")))
+(assert (= (ansi-code :underline) (string-append esc "4m")))
+(assert (= (ansi-code) (string-append esc "0m")))
+
+(def styled (string-append (ansi-code :green) "ok" (ansi-code) " done"))
+(assert (= (strip-ansi styled) "ok done"))
+(assert (= (strip-ansi (string-append esc "2K" esc "10;3H" "moved")) "moved"))
+(assert (= (strip-ansi "nothing to strip") "nothing to strip"))
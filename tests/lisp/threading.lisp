@@ -0,0 +1,10 @@
+(def add (lambda (a b) (+ a b)))
+(def sub (lambda (a b) (- a b)))
+(def double (lambda (x) (* x 2)))
+
+(assert (= (-> 10) 10))
+(assert (= (-> 10 (sub 3) double) 14))
+(assert (= (->> 10 (sub 3) double) (- 0 14)))
+(assert (= (-> (list 1 2 3) cdr car) 2))
+(assert (= (->> (list 1 2 3) (map double) (fold add)) 12))
+(assert (= (-> 1 (add 2) (->> (sub 10))) 7))
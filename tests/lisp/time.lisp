@@ -0,0 +1,25 @@
+;; the virtual clock starts at the epoch
+(def now (time-now))
+(assert (= (map-retrieve now :year) 1970))
+(assert (= (time-format now "%Y-%m-%d") "1970-01-01"))
+
+(assert (= (time-format 0 "%Y-%m-%dT%H:%M:%S.%L") "1970-01-01T00:00:00.000"))
+(assert (= (time-format 951782400000 "%Y-%m-%d") "2000-02-29"))
+(assert (= (time-format (- 0 1) "%Y-%m-%d %H:%M:%S.%L") "1969-12-31 23:59:59.999"))
+(assert (= (time-format 1710510330000 "%s 100%%") "1710510330 100%"))
+
+(def parsed (time-parse "2024-03-15 13:45:30" "%Y-%m-%d %H:%M:%S"))
+(assert (= (car parsed) 'ok))
+(assert (= (map-retrieve (cadr parsed) :unix-ms) 1710510330000))
+(assert (= (map-retrieve (cadr parsed) :month) 3))
+(assert (= (time-format (cadr parsed) "%d/%m/%Y %H:%M") "15/03/2024 13:45"))
+
+;; fields left out of the format are from the epoch
+(assert (= (map-retrieve (cadr (time-parse "12:30" "%H:%M")) :unix-ms) 45000000))
+
+(assert (= (time-parse "2024-1-01" "%Y-%m-%d")
+           '(err #""2024-1-01" doesn't match the format at position 5, expected 2 digits"#)))
+(assert (= (time-parse "2023-02-29" "%Y-%m-%d")
+           '(err #"29 is not a valid day in "2023-02-29""#)))
+(assert (= (car (time-parse "2024-01-01 extra" "%Y-%m-%d")) 'err))
+(assert (= (car (time-parse "2024/01/01" "%Y-%m-%d")) 'err))
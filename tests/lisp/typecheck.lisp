@@ -0,0 +1,14 @@
+(decl add (lambda (int int) -> int))
+(def add (lambda ((a int) (b int)) -> int (+ a b)))
+
+(def (answer int) (add 40 2))
+
+(def pick (lambda ((flag bool) x) -> (union int nil)
+            (if flag 1 nil)))
+
+(def len (lambda (xs) -> int
+           (if (nil? xs) 0 (+ 1 (len (cdr xs))))))
+
+(assert (= (add answer (len (list 1 2))) 44))
+(assert (= (pick true 0) 1))
+(assert (nil? (pick false 0)))
@@ -3,4 +3,12 @@
                    ((lambda ()
                       test))))))
 
-(= (upvalues 1) 1)
+;; two closures capturing the same local share it, even when one is made while
+;; the other's call is still being set up, as (let ((y (f))) x) does
+(def shared (lambda (x)
+              ((lambda (y) x)
+               ((lambda () (set! x true))))))
+
+(if (shared false)
+    (= (upvalues 1) 1)
+    false)